@@ -108,8 +108,8 @@ impl Serialize for Setting {
     where
         S: Serializer,
     {
-        let s = self.to_string();
-        serializer.collect_str(&s)
+        // Serialized with the type prefix, so it can be deserialized again
+        serializer.collect_str(&format!("{self}"))
     }
 }
 
@@ -137,7 +137,7 @@ impl Display for Setting {
                     result.push(',');
                 }
                 result.pop();
-                write!(f, "m: {result}")
+                write!(f, "m:{result}")
             }
         }
     }
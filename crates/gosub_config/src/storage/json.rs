@@ -59,6 +59,10 @@ impl StorageAdapter for JsonStorageAdapter {
         // self.write_file()
     }
 
+    fn remove(&self, key: &str) {
+        self.elements.lock().unwrap().remove(key);
+    }
+
     fn all(&self) -> Result<HashMap<String, Setting>> {
         let lock = self.elements.lock().unwrap();
 
@@ -67,6 +71,11 @@ impl StorageAdapter for JsonStorageAdapter {
 }
 
 impl JsonStorageAdapter {
+    /// Writes all settings to the json file
+    pub fn flush(&self) {
+        self.write_file()
+    }
+
     /// Read whole json file and stores the data into self.elements
     fn read_file(&mut self) {
         // @TODO: We should have some kind of OS file lock here
@@ -96,16 +105,45 @@ impl JsonStorageAdapter {
 
     /// Write the self.elements hashmap back to the file by truncating the file and writing the
     /// data again.
-    #[allow(dead_code)]
-    fn write_file(&mut self) {
+    fn write_file(&self) {
         // @TODO: We need some kind of OS lock file here. We should protect against concurrent threads but also
         // against concurrent processes.
-        let mut file = File::open(&self.path).expect("failed to open json file");
+        let mut file = File::options()
+            .write(true)
+            .open(&self.path)
+            .expect("failed to open json file");
 
-        let json = serde_json::to_string_pretty(&self.elements).expect("failed to serialize");
+        let json = serde_json::to_string_pretty(&*self.elements.lock().unwrap()).expect("failed to serialize");
 
         file.set_len(0).expect("failed to truncate file");
         file.seek(std::io::SeekFrom::Start(0)).expect("failed to seek");
         file.write_all(json.as_bytes()).expect("failed to write file");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn persists_settings() {
+        let path = std::env::temp_dir().join(format!("gosub-json-storage-{}.json", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        _ = fs::remove_file(&path);
+
+        let adapter = JsonStorageAdapter::try_from(&path).unwrap();
+        adapter.set("foo.string", Setting::String("hello, world".into()));
+        adapter.set("foo.uint", Setting::UInt(42));
+        adapter.set("foo.map", Setting::Map(vec!["a".into(), "b".into()]));
+        adapter.remove("foo.uint");
+        adapter.flush();
+
+        let adapter = JsonStorageAdapter::try_from(&path).unwrap();
+        assert_eq!(adapter.get("foo.string"), Some(Setting::String("hello, world".into())));
+        assert_eq!(adapter.get("foo.map"), Some(Setting::Map(vec!["a".into(), "b".into()])));
+        assert_eq!(adapter.get("foo.uint"), None);
+        assert_eq!(adapter.all().unwrap().len(), 2);
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -38,7 +38,7 @@ impl StorageAdapter for SqliteStorageAdapter {
         let mut statement = db_lock.prepare(query).unwrap();
         let val: String = statement
             .query_row(named_params! { ":key": key }, |row| row.get(0))
-            .unwrap();
+            .ok()?;
 
         match Setting::from_str(&val) {
             Ok(setting) => Some(setting),
@@ -52,16 +52,29 @@ impl StorageAdapter for SqliteStorageAdapter {
     fn set(&self, key: &str, value: Setting) {
         let db_lock = self.connection.lock().unwrap();
 
-        let query = "INSERT OR REPLACE INTO settings (key, value) VALUES (:key, :value)";
+        // The key column is not unique, so remove the current value first
+        let query = "DELETE FROM settings WHERE key = :key";
+        let mut statement = db_lock.prepare(query).unwrap();
+        let _ = statement.execute(named_params! { ":key": key }).unwrap();
+
+        let query = "INSERT INTO settings (key, value) VALUES (:key, :value)";
         let mut statement = db_lock.prepare(query).unwrap();
         let _ = statement
             .execute(named_params! {
                 ":key": &key.to_string(),
-                ":value": &value.to_string(),
+                ":value": &format!("{value}"),
             })
             .unwrap();
     }
 
+    fn remove(&self, key: &str) {
+        let db_lock = self.connection.lock().unwrap();
+
+        let query = "DELETE FROM settings WHERE key = :key";
+        let mut statement = db_lock.prepare(query).unwrap();
+        let _ = statement.execute(named_params! { ":key": key }).unwrap();
+    }
+
     fn all(&self) -> Result<HashMap<String, Setting>> {
         let mut settings = HashMap::new();
 
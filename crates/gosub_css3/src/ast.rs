@@ -1,12 +1,19 @@
+use cow_utils::CowUtils;
 use log::warn;
 
+use crate::matcher::property_definitions::get_css_definitions;
+use crate::matcher::styling::selector_is_supported;
+use crate::media::{
+    dimension_to_media_value, Comparison, MediaCondition, MediaFeature, MediaQuery, MediaQueryList, MediaValue,
+};
 use crate::node::{Node as CssNode, NodeType};
 use crate::stylesheet::{
     AttributeSelector, Combinator, CssDeclaration, CssRule, CssSelector, CssSelectorPart, CssStylesheet, CssValue,
-    MatcherType,
+    MatcherType, PseudoClassArgument, PseudoClassFunction,
 };
 use gosub_interface::css3::CssOrigin;
 use gosub_shared::errors::{CssError, CssResult};
+use std::slice;
 
 /*
 
@@ -71,158 +78,565 @@ pub fn convert_ast_to_stylesheet(css_ast: &CssNode, origin: CssOrigin, url: &str
         origin,
         url: url.to_string(),
         parse_log: vec![],
+        layers: vec![],
     };
 
-    for node in css_ast.as_stylesheet() {
-        if !node.is_rule() {
-            continue;
-        }
+    convert_rules(css_ast.as_stylesheet(), &[], None, &mut sheet)?;
 
-        let mut rule = CssRule {
-            selectors: vec![],
-            declarations: vec![],
+    Ok(sheet)
+}
+
+/// Converts all (at-)rules into the stylesheet. Rules found inside `@media` blocks get the media query lists of all
+/// their enclosing `@media` rules, and rules inside `@layer` blocks get the name of their layer. Rules inside
+/// `@supports` blocks are only converted when the condition is supported.
+fn convert_rules(
+    nodes: &[CssNode],
+    media: &[MediaQueryList],
+    layer: Option<&str>,
+    sheet: &mut CssStylesheet,
+) -> CssResult<()> {
+    for node in nodes {
+        let NodeType::AtRule { name, prelude, block } = &*node.node_type else {
+            if node.is_rule() {
+                sheet.rules.push(convert_rule(node, media, layer)?);
+            }
+            continue;
         };
 
-        let (prelude, declarations) = node.as_rule();
-        for node in prelude.iter() {
-            if !node.is_selector_list() {
-                continue;
+        let block = block.as_ref().filter(|block| block.is_block());
+
+        match name.cow_to_ascii_lowercase().as_ref() {
+            "media" => {
+                let mut media = media.to_vec();
+                if let Some(prelude) = prelude {
+                    media.push(convert_media_query_list(prelude));
+                }
+
+                if let Some(block) = block {
+                    convert_rules(block.as_block(), &media, layer, sheet)?;
+                }
             }
+            "supports" => {
+                let supported = prelude.as_ref().is_some_and(supports_condition_matches);
+                if let Some(block) = block.filter(|_| supported) {
+                    convert_rules(block.as_block(), media, layer, sheet)?;
+                }
+            }
+            "layer" => {
+                let names = match prelude.as_ref().map(|prelude| &*prelude.node_type) {
+                    Some(NodeType::LayerList { layers }) if !layers.is_empty() => layers
+                        .iter()
+                        .filter_map(|layer| match &*layer.node_type {
+                            NodeType::Ident { value } => Some(value.clone()),
+                            _ => None,
+                        })
+                        .collect(),
+                    // Anonymous layers are numbered per stylesheet. Every one of them is a new layer, so the number
+                    // of anonymous layers declared so far is the number of layers with an anonymous name.
+                    _ => vec![format!(
+                        "#anonymous-{}",
+                        sheet
+                            .layers
+                            .iter()
+                            .filter(|name| name.rsplit('.').next().is_some_and(|name| name.starts_with('#')))
+                            .count()
+                    )],
+                };
 
-            let mut selector = CssSelector { parts: vec![vec![]] };
-            for node in node.as_selector_list().iter() {
-                if !node.is_selector() {
-                    continue;
+                let names: Vec<String> = names
+                    .into_iter()
+                    .map(|name| match layer {
+                        Some(parent) => format!("{}.{}", parent, name),
+                        None => name,
+                    })
+                    .collect();
+
+                for name in &names {
+                    if !sheet.layers.contains(name) {
+                        sheet.layers.push(name.clone());
+                    }
                 }
 
-                for node in node.as_selector() {
-                    let part = match &*node.node_type {
-                        NodeType::Ident { value } => CssSelectorPart::Type(value.clone()),
-                        NodeType::ClassSelector { value } => CssSelectorPart::Class(value.clone()),
-                        NodeType::Combinator { value } => {
-                            let combinator = match value.as_str() {
-                                ">" => Combinator::Child,
-                                "+" => Combinator::NextSibling,
-                                "~" => Combinator::SubsequentSibling,
-                                " " => Combinator::Descendant,
-                                "||" => Combinator::Column,
-                                "|" => Combinator::Namespace,
-                                _ => return Err(CssError::new(format!("Unknown combinator: {}", value).as_str())),
-                            };
-
-                            CssSelectorPart::Combinator(combinator)
-                        }
-                        NodeType::IdSelector { value } => CssSelectorPart::Id(value.clone()),
-                        NodeType::TypeSelector { value, .. } if value == "*" => CssSelectorPart::Universal,
-                        NodeType::PseudoClassSelector { value, .. } => CssSelectorPart::PseudoClass(value.to_string()),
-                        NodeType::PseudoElementSelector { value, .. } => {
-                            CssSelectorPart::PseudoElement(value.to_string())
-                        }
-                        NodeType::TypeSelector { value, .. } => CssSelectorPart::Type(value.clone()),
-                        NodeType::AttributeSelector {
-                            name,
-                            value,
-                            flags,
-                            matcher,
-                        } => {
-                            let matcher = match matcher {
-                                None => MatcherType::None,
-
-                                Some(matcher) => match &*matcher.node_type {
-                                    NodeType::Operator(op) => match op.as_str() {
-                                        "=" => MatcherType::Equals,
-                                        "~=" => MatcherType::Includes,
-                                        "|=" => MatcherType::DashMatch,
-                                        "^=" => MatcherType::PrefixMatch,
-                                        "$=" => MatcherType::SuffixMatch,
-                                        "*=" => MatcherType::SubstringMatch,
-                                        _ => {
-                                            warn!("Unsupported matcher: {:?}", matcher);
-                                            MatcherType::Equals
-                                        }
-                                    },
-                                    _ => {
-                                        warn!("Unsupported matcher: {:?}", matcher);
-                                        MatcherType::Equals
-                                    }
-                                },
-                            };
-
-                            CssSelectorPart::Attribute(Box::new(AttributeSelector {
-                                name: name.clone(),
-                                matcher,
-                                value: value.clone(),
-                                case_insensitive: flags.eq_ignore_ascii_case("i"),
-                            }))
-                        }
-                        NodeType::Comma => {
-                            selector.parts.push(vec![]);
-                            continue;
-                        }
-                        _ => {
-                            return Err(CssError::new(
-                                format!("Unsupported selector part: {:?}", node.node_type).as_str(),
-                            ));
-                        }
+                // Only a layer statement can declare multiple layers, a layer block has a single name
+                if let (Some(block), [name]) = (block, names.as_slice()) {
+                    convert_rules(block.as_block(), media, Some(name), sheet)?;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Evaluates the condition of a `@supports` rule. Declarations are checked against the property definitions, and
+/// `selector()` against the selectors we can match.
+fn supports_condition_matches(node: &CssNode) -> bool {
+    match &*node.node_type {
+        NodeType::Condition { list } => match list.as_slice() {
+            [keyword, term] if is_keyword(keyword, "not") => !supports_condition_matches(term),
+            [first, rest @ ..] => {
+                let mut matches = supports_condition_matches(first);
+                for pair in rest.chunks(2) {
+                    let [keyword, term] = pair else {
+                        return false;
                     };
-                    if let Some(x) = selector.parts.last_mut() {
-                        x.push(part)
+
+                    if is_keyword(keyword, "or") {
+                        matches = matches || supports_condition_matches(term);
                     } else {
-                        selector.parts.push(vec![part]); //unreachable, but still, we handle it
+                        matches = matches && supports_condition_matches(term);
                     }
                 }
+
+                matches
             }
-            rule.selectors.push(selector);
+            [] => false,
+        },
+        NodeType::SupportsDeclaration { term } => supports_declaration(term),
+        NodeType::Function { name, arguments } if name == "selector" => arguments
+            .first()
+            .and_then(|selector| convert_selector_list(selector).ok())
+            .is_some_and(|selector| selector_is_supported(&selector)),
+        _ => false,
+    }
+}
+
+fn is_keyword(node: &CssNode, keyword: &str) -> bool {
+    matches!(&*node.node_type, NodeType::Ident { value } if value == keyword)
+}
+
+/// Returns true when the property of the declaration is known, and its value matches the property definition
+fn supports_declaration(node: &CssNode) -> bool {
+    let NodeType::Declaration { property, value, .. } = &*node.node_type else {
+        return false;
+    };
+
+    // Custom properties accept any value
+    if property.starts_with("--") {
+        return true;
+    }
+
+    let Some(definition) = get_css_definitions().find_property(&property.cow_to_ascii_lowercase()) else {
+        return false;
+    };
+
+    let mut values = vec![];
+    for node in value.iter() {
+        match CssValue::parse_ast_node(node) {
+            Ok(value) => values.push(value),
+            Err(_) => return false,
+        }
+    }
+
+    match values.as_slice() {
+        [CssValue::Initial | CssValue::Inherit] => true,
+        [CssValue::String(keyword)] if matches!(keyword.as_str(), "unset" | "revert" | "revert-layer") => true,
+        // Values with variables can only be checked once the variables are known
+        _ if values
+            .iter()
+            .any(|value| matches!(value, CssValue::Function(name, _) if name == "var")) =>
+        {
+            true
+        }
+        _ => definition.matches(&values),
+    }
+}
+
+/// Converts a single rule node into a rule
+fn convert_rule(node: &CssNode, media: &[MediaQueryList], layer: Option<&str>) -> CssResult<CssRule> {
+    let mut rule = CssRule {
+        selectors: vec![],
+        declarations: vec![],
+        media: media.to_vec(),
+        layer: layer.map(str::to_string),
+    };
+
+    let (prelude, declarations) = node.as_rule();
+    for node in prelude.iter() {
+        if !node.is_selector_list() {
+            continue;
+        }
+
+        rule.selectors.push(convert_selector_list(node)?);
+    }
+
+    for declaration in declarations.iter() {
+        if !declaration.is_block() {
+            continue;
         }
 
-        for declaration in declarations.iter() {
-            if !declaration.is_block() {
+        let block = declaration.as_block();
+        for declaration in block.iter() {
+            if !declaration.is_declaration() {
                 continue;
             }
 
-            let block = declaration.as_block();
-            for declaration in block.iter() {
-                if !declaration.is_declaration() {
-                    continue;
+            let (property, nodes, important) = declaration.as_declaration();
+
+            // Convert the nodes into CSS Values
+            let mut css_values = vec![];
+            for node in nodes.iter() {
+                if let Ok(value) = CssValue::parse_ast_node(node) {
+                    css_values.push(value);
                 }
+            }
 
-                let (property, nodes, important) = declaration.as_declaration();
+            if css_values.is_empty() {
+                continue;
+            }
 
-                // Convert the nodes into CSS Values
-                let mut css_values = vec![];
-                for node in nodes.iter() {
-                    if let Ok(value) = CssValue::parse_ast_node(node) {
-                        css_values.push(value);
-                    }
-                }
+            let value = if css_values.len() == 1 {
+                css_values.pop().expect("unreachable")
+            } else {
+                CssValue::List(css_values)
+            };
+
+            rule.declarations.push(CssDeclaration {
+                property: property.clone(),
+                value,
+                important: *important,
+            });
+        }
+    }
 
-                if css_values.is_empty() {
-                    continue;
-                }
+    Ok(rule)
+}
 
-                let value = if css_values.len() == 1 {
-                    css_values.pop().expect("unreachable")
-                } else {
-                    CssValue::List(css_values)
-                };
+/// Converts the prelude of a `@media` rule into a media query list
+fn convert_media_query_list(node: &CssNode) -> MediaQueryList {
+    let NodeType::MediaQueryList { media_queries } = &*node.node_type else {
+        return MediaQueryList::default();
+    };
 
-                rule.declarations.push(CssDeclaration {
-                    property: property.clone(),
-                    value,
-                    important: *important,
+    let mut list = MediaQueryList::default();
+
+    for query in media_queries {
+        let NodeType::MediaQuery {
+            modifier,
+            media_type,
+            condition,
+        } = &*query.node_type
+        else {
+            continue;
+        };
+
+        list.queries.push(MediaQuery {
+            negated: modifier.eq_ignore_ascii_case("not"),
+            media_type: (!media_type.is_empty()).then(|| media_type.cow_to_ascii_lowercase().to_string()),
+            condition: condition.as_ref().map(convert_media_condition),
+        });
+    }
+
+    list
+}
+
+/// Converts a media condition node. The parser keeps a condition as a flat list of features separated by `and`,
+/// `or` and `not` keywords.
+fn convert_media_condition(node: &CssNode) -> MediaCondition {
+    let terms = match &*node.node_type {
+        NodeType::Condition { list } => list.as_slice(),
+        _ => slice::from_ref(node),
+    };
+
+    let is_keyword = |node: &CssNode, keyword: &str| node.is_ident() && node.as_ident().eq_ignore_ascii_case(keyword);
+
+    if let Some((first, rest)) = terms.split_first() {
+        if is_keyword(first, "not") {
+            let rest = rest.iter().map(convert_media_term).collect::<Vec<_>>();
+            return match rest.len() {
+                1 => MediaCondition::Not(Box::new(rest.into_iter().next().expect("unreachable"))),
+                _ => MediaCondition::Unknown,
+            };
+        }
+    }
+
+    let has_and = terms.iter().any(|term| is_keyword(term, "and"));
+    let has_or = terms.iter().any(|term| is_keyword(term, "or"));
+
+    let conditions = terms
+        .iter()
+        .filter(|term| !is_keyword(term, "and") && !is_keyword(term, "or"))
+        .map(convert_media_term)
+        .collect::<Vec<_>>();
+
+    match (has_and, has_or) {
+        // Mixing "and" and "or" without parenthesis is not allowed
+        (true, true) => MediaCondition::Unknown,
+        (_, true) => MediaCondition::Or(conditions),
+        _ if conditions.len() == 1 => conditions.into_iter().next().expect("unreachable"),
+        _ => MediaCondition::And(conditions),
+    }
+}
+
+/// Converts a single term (feature, range or nested condition) of a media condition
+fn convert_media_term(node: &CssNode) -> MediaCondition {
+    match &*node.node_type {
+        NodeType::Feature { name, value, .. } => {
+            let name = name.cow_to_ascii_lowercase();
+
+            let Some(value) = value else {
+                return MediaCondition::Feature(MediaFeature {
+                    name: name.to_string(),
+                    comparison: None,
                 });
+            };
+
+            let Some(value) = convert_media_value(value) else {
+                return MediaCondition::Unknown;
+            };
+
+            let (name, comparison) = if let Some(name) = name.strip_prefix("min-") {
+                (name, Comparison::Ge)
+            } else if let Some(name) = name.strip_prefix("max-") {
+                (name, Comparison::Le)
+            } else {
+                (name.as_ref(), Comparison::Eq)
+            };
+
+            MediaCondition::Feature(MediaFeature {
+                name: name.to_string(),
+                comparison: Some((comparison, value)),
+            })
+        }
+        NodeType::Range {
+            left,
+            left_comparison,
+            middle,
+            right_comparison,
+            right,
+        } => match (right_comparison, right) {
+            // value < name < value
+            (Some(right_comparison), Some(right)) => MediaCondition::And(vec![
+                convert_media_range(left, left_comparison, middle),
+                convert_media_range(middle, right_comparison, right),
+            ]),
+            _ => convert_media_range(left, left_comparison, middle),
+        },
+        NodeType::Condition { .. } => convert_media_condition(node),
+        _ => MediaCondition::Unknown,
+    }
+}
+
+/// Converts a range with a single comparison, like `(width < 600px)` or `(600px <= width)`
+fn convert_media_range(left: &CssNode, comparison: &CssNode, right: &CssNode) -> MediaCondition {
+    let Some(comparison) = convert_comparison(comparison) else {
+        return MediaCondition::Unknown;
+    };
+
+    let (name, comparison, value) = if left.is_ident() {
+        (left.as_ident(), comparison, right)
+    } else if right.is_ident() {
+        (right.as_ident(), comparison.flip(), left)
+    } else {
+        return MediaCondition::Unknown;
+    };
+
+    let Some(value) = convert_media_value(value) else {
+        return MediaCondition::Unknown;
+    };
+
+    MediaCondition::Feature(MediaFeature {
+        name: name.cow_to_ascii_lowercase().to_string(),
+        comparison: Some((comparison, value)),
+    })
+}
+
+fn convert_comparison(node: &CssNode) -> Option<Comparison> {
+    let NodeType::Operator(op) = &*node.node_type else {
+        return None;
+    };
+
+    match op.as_str() {
+        "=" => Some(Comparison::Eq),
+        "<" => Some(Comparison::Lt),
+        "<=" => Some(Comparison::Le),
+        ">" => Some(Comparison::Gt),
+        ">=" => Some(Comparison::Ge),
+        _ => None,
+    }
+}
+
+fn convert_media_value(node: &CssNode) -> Option<MediaValue> {
+    match &*node.node_type {
+        NodeType::Number { value } => Some(MediaValue::Number(*value)),
+        NodeType::Dimension { value, unit } => dimension_to_media_value(*value, unit),
+        NodeType::Ident { value } => Some(MediaValue::Ident(value.cow_to_ascii_lowercase().to_string())),
+        _ => None,
+    }
+}
+
+/// Converts a selector list node into a selector
+pub(crate) fn convert_selector_list(node: &CssNode) -> CssResult<CssSelector> {
+    convert_selectors(node.as_selector_list())
+}
+
+/// Converts one or more selector nodes into a single selector. Each node (and each comma inside a node) starts a
+/// new alternative of the selector.
+fn convert_selectors(nodes: &[CssNode]) -> CssResult<CssSelector> {
+    let mut selector = CssSelector { parts: vec![vec![]] };
+    for (idx, node) in nodes.iter().enumerate() {
+        if !node.is_selector() {
+            continue;
+        }
+
+        if idx > 0 {
+            selector.parts.push(vec![]);
+        }
+
+        for node in node.as_selector() {
+            let part = match &*node.node_type {
+                NodeType::Ident { value } => CssSelectorPart::Type(value.clone()),
+                NodeType::ClassSelector { value } => CssSelectorPart::Class(value.clone()),
+                NodeType::Combinator { value } => {
+                    let combinator = match value.as_str() {
+                        ">" => Combinator::Child,
+                        "+" => Combinator::NextSibling,
+                        "~" => Combinator::SubsequentSibling,
+                        " " => Combinator::Descendant,
+                        "||" => Combinator::Column,
+                        "|" => Combinator::Namespace,
+                        _ => return Err(CssError::new(format!("Unknown combinator: {}", value).as_str())),
+                    };
+
+                    CssSelectorPart::Combinator(combinator)
+                }
+                NodeType::IdSelector { value } => CssSelectorPart::Id(value.clone()),
+                NodeType::TypeSelector { value, .. } if value == "*" => CssSelectorPart::Universal,
+                NodeType::PseudoClassSelector { value, .. } => convert_pseudo_class(value)?,
+                NodeType::PseudoElementSelector { value, .. } => CssSelectorPart::PseudoElement(value.to_string()),
+                NodeType::TypeSelector { value, .. } => CssSelectorPart::Type(value.clone()),
+                NodeType::AttributeSelector {
+                    name,
+                    value,
+                    flags,
+                    matcher,
+                } => {
+                    let matcher = match matcher {
+                        None => MatcherType::None,
+
+                        Some(matcher) => match &*matcher.node_type {
+                            NodeType::Operator(op) => match op.as_str() {
+                                "=" => MatcherType::Equals,
+                                "~=" => MatcherType::Includes,
+                                "|=" => MatcherType::DashMatch,
+                                "^=" => MatcherType::PrefixMatch,
+                                "$=" => MatcherType::SuffixMatch,
+                                "*=" => MatcherType::SubstringMatch,
+                                _ => {
+                                    warn!("Unsupported matcher: {:?}", matcher);
+                                    MatcherType::Equals
+                                }
+                            },
+                            _ => {
+                                warn!("Unsupported matcher: {:?}", matcher);
+                                MatcherType::Equals
+                            }
+                        },
+                    };
+
+                    CssSelectorPart::Attribute(Box::new(AttributeSelector {
+                        name: name.clone(),
+                        matcher,
+                        value: value.clone(),
+                        case_insensitive: flags.eq_ignore_ascii_case("i"),
+                    }))
+                }
+                NodeType::Comma => {
+                    selector.parts.push(vec![]);
+                    continue;
+                }
+                _ => {
+                    return Err(CssError::new(
+                        format!("Unsupported selector part: {:?}", node.node_type).as_str(),
+                    ));
+                }
+            };
+            if let Some(x) = selector.parts.last_mut() {
+                x.push(part)
+            } else {
+                selector.parts.push(vec![part]); //unreachable, but still, we handle it
             }
         }
+    }
 
-        sheet.rules.push(rule);
+    // Whitespace around commas and parenthesis is seen as a descendant combinator by the parser, which does not
+    // mean anything at the start or end of a selector.
+    for parts in selector.parts.iter_mut() {
+        while parts.last() == Some(&CssSelectorPart::Combinator(Combinator::Descendant)) {
+            parts.pop();
+        }
+        while parts.first() == Some(&CssSelectorPart::Combinator(Combinator::Descendant)) {
+            parts.remove(0);
+        }
     }
-    Ok(sheet)
+
+    Ok(selector)
+}
+
+/// Converts the value of a pseudo class selector node into a selector part
+fn convert_pseudo_class(value: &CssNode) -> CssResult<CssSelectorPart> {
+    let (name, arguments) = match &*value.node_type {
+        NodeType::Ident { value } => {
+            let name = value.cow_to_lowercase().to_string();
+            // CSS2 pseudo elements can still be written with a single colon
+            return Ok(match name.as_str() {
+                "before" | "after" | "first-line" | "first-letter" => CssSelectorPart::PseudoElement(name),
+                _ => CssSelectorPart::PseudoClass(name),
+            });
+        }
+        NodeType::Function { name, arguments } => (name, arguments),
+        _ => {
+            return Err(CssError::new(
+                format!("Unsupported pseudo class: {:?}", value.node_type).as_str(),
+            ))
+        }
+    };
+
+    let Some(argument) = arguments.first() else {
+        return Err(CssError::new(format!("Missing argument for :{}()", name).as_str()));
+    };
+
+    let argument = match &*argument.node_type {
+        NodeType::SelectorList { selectors } => PseudoClassArgument::Selector(convert_selectors(selectors)?),
+        NodeType::Selector { .. } => PseudoClassArgument::Selector(convert_selectors(slice::from_ref(argument))?),
+        NodeType::Ident { value } => PseudoClassArgument::Ident(value.clone()),
+        NodeType::Nth { nth, selector } => {
+            let (a, b) = match &*nth.node_type {
+                NodeType::AnPlusB { a, b } => match (a.parse::<i32>(), b.parse::<i32>()) {
+                    (Ok(a), Ok(b)) => (a, b),
+                    _ => return Err(CssError::new(format!("Invalid An+B value: {}n+{}", a, b).as_str())),
+                },
+                NodeType::Number { value } if value.fract() == 0.0 => (0, *value as i32),
+                _ => return Err(CssError::new(format!("Invalid An+B value: {}", nth).as_str())),
+            };
+
+            let of = match selector {
+                Some(selector) => Some(convert_selector_list(selector)?),
+                None => None,
+            };
+
+            PseudoClassArgument::Nth { a, b, of }
+        }
+        _ => {
+            return Err(CssError::new(
+                format!("Unsupported argument for :{}(): {:?}", name, argument.node_type).as_str(),
+            ))
+        }
+    };
+
+    Ok(CssSelectorPart::PseudoClassFunction(Box::new(PseudoClassFunction {
+        name: name.clone(),
+        argument,
+    })))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::stylesheet::Specificity;
     use crate::Css3;
+    use gosub_interface::css3::MediaEnvironment;
     use gosub_shared::config::ParserConfig;
 
     #[test]
@@ -282,4 +696,192 @@ mod tests {
             ])
         );
     }
+    #[test]
+    fn convert_pseudo_classes() {
+        let stylesheet = Css3::parse_str(
+            r#"
+            li:first-child { color: red; }
+            li:nth-child(2n+1 of .item) { color: red; }
+            a:not(.foo, #bar) { color: red; }
+            :where(.foo) p, div:has(> img) { color: red; }
+            "#,
+            ParserConfig::default(),
+            CssOrigin::User,
+            "test.css",
+        )
+        .unwrap();
+
+        let selector = |idx: usize| stylesheet.rules.get(idx).unwrap().selectors.first().unwrap().clone();
+
+        assert_eq!(
+            selector(0).parts,
+            vec![vec![
+                CssSelectorPart::Type("li".into()),
+                CssSelectorPart::PseudoClass("first-child".into()),
+            ]]
+        );
+
+        let CssSelectorPart::PseudoClassFunction(func) = &selector(1).parts[0][1] else {
+            panic!("expected pseudo class function");
+        };
+        assert_eq!(func.name, "nth-child");
+        assert_eq!(
+            func.argument,
+            PseudoClassArgument::Nth {
+                a: 2,
+                b: 1,
+                of: Some(CssSelector {
+                    parts: vec![vec![CssSelectorPart::Class("item".into())]]
+                }),
+            }
+        );
+
+        let CssSelectorPart::PseudoClassFunction(func) = &selector(2).parts[0][1] else {
+            panic!("expected pseudo class function");
+        };
+        assert_eq!(func.name, "not");
+        assert_eq!(
+            func.argument,
+            PseudoClassArgument::Selector(CssSelector {
+                parts: vec![
+                    vec![CssSelectorPart::Class("foo".into())],
+                    vec![CssSelectorPart::Id("bar".into())],
+                ]
+            })
+        );
+
+        let CssSelectorPart::PseudoClassFunction(func) = &selector(3).parts[1][1] else {
+            panic!("expected pseudo class function");
+        };
+        assert_eq!(func.name, "has");
+        assert_eq!(
+            func.argument,
+            PseudoClassArgument::Selector(CssSelector {
+                parts: vec![vec![
+                    CssSelectorPart::Combinator(Combinator::Child),
+                    CssSelectorPart::Type("img".into()),
+                ]]
+            })
+        );
+
+        assert_eq!(selector(0).specificity(), vec![Specificity::new(0, 1, 1)]);
+        assert_eq!(selector(1).specificity(), vec![Specificity::new(0, 2, 1)]);
+        assert_eq!(selector(2).specificity(), vec![Specificity::new(1, 0, 1)]);
+        assert_eq!(
+            selector(3).specificity(),
+            vec![Specificity::new(0, 0, 1), Specificity::new(0, 0, 2)]
+        );
+    }
+    #[test]
+    fn convert_media_rules() {
+        let stylesheet = Css3::parse_str(
+            r#"
+              p { color: black; }
+              @media screen and (min-width: 600px), print {
+                p { color: red; }
+                @media (400px <= width < 700px) {
+                  p { color: blue; }
+                }
+              }
+              @media not all and (orientation: portrait) {
+                a { color: green; }
+              }
+              @media (max-aspect-ratio: 16/9) or (prefers-color-scheme: dark) {
+                b { color: green; }
+              }
+            "#,
+            ParserConfig::default(),
+            CssOrigin::Author,
+            "test.css",
+        )
+        .unwrap();
+
+        assert_eq!(stylesheet.rules.len(), 5);
+        assert!(stylesheet.rules[0].media.is_empty());
+        assert_eq!(stylesheet.rules[1].media.len(), 1);
+        assert_eq!(stylesheet.rules[2].media.len(), 2);
+
+        let wide = MediaEnvironment::default().with_size(1000.0, 500.0);
+        let medium = MediaEnvironment::default().with_size(650.0, 500.0);
+        let narrow = MediaEnvironment::default().with_size(500.0, 800.0);
+
+        let matches = |env: &MediaEnvironment| {
+            stylesheet
+                .rules
+                .iter()
+                .map(|rule| rule.matches_media(env))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(matches(&wide), vec![true, true, false, true, false]);
+        assert_eq!(matches(&medium), vec![true, true, true, true, true]);
+        assert_eq!(matches(&narrow), vec![true, false, false, false, true]);
+
+        use gosub_interface::css3::CssStylesheet as _;
+        assert!(stylesheet.media_changed(&wide, &medium));
+        assert!(!stylesheet.media_changed(&wide, &wide.with_size(1200.0, 400.0)));
+    }
+
+    #[test]
+    fn convert_nonlocal_dependencies() {
+        use gosub_interface::css3::CssStylesheet as _;
+
+        let parse = |css: &str| Css3::parse_str(css, ParserConfig::default(), CssOrigin::Author, "test.css").unwrap();
+
+        assert!(!parse("p + p, p ~ p, p:empty, li:nth-child(2 of .a) { color: red; }").has_nonlocal_dependencies());
+        assert!(
+            !parse("ol { counter-reset: list-item; } li { counter-increment: list-item 2; }")
+                .has_nonlocal_dependencies()
+        );
+        assert!(!parse("p { counter-reset: none; }").has_nonlocal_dependencies());
+
+        assert!(parse("div:has(> img) { color: red; }").has_nonlocal_dependencies());
+        assert!(parse("p:not(:has(+ .a)) { color: red; }").has_nonlocal_dependencies());
+        assert!(parse("@media screen { li:nth-child(odd of :has(b)) { color: red; } }").has_nonlocal_dependencies());
+        assert!(parse("h2 { counter-increment: chapter; }").has_nonlocal_dependencies());
+        assert!(parse("body { counter-reset: list-item section 2; }").has_nonlocal_dependencies());
+    }
+
+    #[test]
+    fn convert_supports_and_layers() {
+        let stylesheet = Css3::parse_str(
+            r#"
+              @layer reset, theme;
+              @supports (display: flex) { a { color: red; } }
+              @supports (display: banana) { b { color: red; } }
+              @supports (not (display: banana)) and selector(p > a:first-child) { c { color: red; } }
+              @supports (display: banana) or selector(p:unknown) or font-tech(color-COLRv1) { d { color: red; } }
+              @layer theme { e { color: red; } @layer dark { f { color: red; } } }
+              @layer { g { color: red; } }
+              @layer reset { @supports (--foo: bar) { h { color: red; } } }
+            "#,
+            ParserConfig::default(),
+            CssOrigin::Author,
+            "test.css",
+        )
+        .unwrap();
+
+        let rules = stylesheet
+            .rules
+            .iter()
+            .map(|rule| {
+                let CssSelectorPart::Type(name) = &rule.selectors[0].parts[0][0] else {
+                    panic!("expected type selector");
+                };
+                (name.as_str(), rule.layer.as_deref())
+            })
+            .collect::<Vec<_>>();
+
+        let names = rules.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+        assert_eq!(names, vec!["a", "c", "e", "f", "g", "h"]);
+
+        assert_eq!(rules[0].1, None);
+        assert_eq!(rules[2].1, Some("theme"));
+        assert_eq!(rules[3].1, Some("theme.dark"));
+        assert_eq!(rules[4].1, Some("#anonymous-0"));
+        assert_eq!(rules[5].1, Some("reset"));
+
+        assert_eq!(stylesheet.layers[..3], ["reset", "theme", "theme.dark"]);
+        assert_eq!(stylesheet.layers.len(), 4);
+    }
 }
@@ -25,6 +25,11 @@ pub fn resolve_attr<C: HasDocument>(values: &[CssValue], node: &C::Node) -> Vec<
         return vec![];
     };
 
+    // Without a type, the attribute value is used as a string (for instance in the `content` property)
+    if values.len() == 1 {
+        return vec![CssValue::String(attr_value.clone())];
+    }
+
     let Ok(value) = CssValue::parse_str(attr_value) else {
         return vec![];
     };
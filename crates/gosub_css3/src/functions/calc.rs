@@ -1,7 +1,644 @@
 use crate::stylesheet::CssValue;
+use cow_utils::CowUtils;
+use gosub_interface::css3::MediaEnvironment;
 
-#[allow(dead_code)]
-pub fn resolve_calc(values: &[CssValue]) -> Vec<CssValue> {
-    println!("Calc called with {values:?}");
-    vec![CssValue::None]
+/// Context in which the units of a math function are resolved. Units that can't be resolved in the context are
+/// kept, so the expression can be evaluated later on (during layout) when more is known.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CalcContext {
+    /// Size of the viewport, used for the viewport-percentage units (`vw`, `vh`, `vmin` and `vmax`)
+    pub viewport: Option<(f32, f32)>,
+    /// Size that percentages are resolved against
+    pub percentage_basis: Option<f32>,
+    /// Font size of the element, that the font-relative units (`em`, `ex` and `ch`) are resolved against
+    pub font_size: Option<f32>,
+    /// Font size of the root element, that `rem` is resolved against
+    pub root_font_size: Option<f32>,
+}
+
+impl CalcContext {
+    /// Context for computed values: only the viewport is known
+    pub fn for_media(media: &MediaEnvironment) -> Self {
+        Self {
+            viewport: Some((media.width, media.height)),
+            ..Default::default()
+        }
+    }
+}
+
+/// Returns true when the value is a math function that is evaluated by [`resolve_calc`]
+pub fn is_math_function(value: &CssValue) -> bool {
+    matches!(value, CssValue::Function(name, _) if matches!(name.as_str(), "calc" | "min" | "max" | "clamp"))
+}
+
+/// Evaluates a math function (`calc()`, `min()`, `max()` or `clamp()`) as far as possible in the given context.
+/// When the result is a single value, that value is returned. Otherwise, a simplified `calc()` function is
+/// returned that holds the parts that could not be resolved yet (like `calc(50% - 8px)`). Returns `None` when the
+/// expression is invalid, for instance when it adds a length to a number.
+pub fn resolve_calc(value: &CssValue, ctx: &CalcContext) -> Option<CssValue> {
+    let calc = parse_function(value, ctx)?;
+    calc.category()?;
+
+    Some(match calc {
+        Calc::Sum(ref terms) if terms.len() == 1 => calc.to_value(),
+        calc => CssValue::Function("calc".into(), calc.to_args()),
+    })
+}
+
+/// Kind of value of a calc expression. Values can only be added when they are of the same kind.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Category {
+    Number,
+    /// Percentages can be combined with any kind of dimension (like `<length-percentage>`)
+    Percentage,
+    Length,
+    Angle,
+    Time,
+    Frequency,
+    Resolution,
+    Flex,
+}
+
+impl Category {
+    fn of(unit: &str) -> Option<Self> {
+        Some(match unit {
+            "" => Category::Number,
+            "%" => Category::Percentage,
+            "px" | "em" | "rem" | "ex" | "ch" | "vw" | "vh" | "vmin" | "vmax" => Category::Length,
+            "deg" => Category::Angle,
+            "s" => Category::Time,
+            "hz" => Category::Frequency,
+            "dppx" => Category::Resolution,
+            "fr" => Category::Flex,
+            _ => return None,
+        })
+    }
+
+    fn combine(self, other: Self) -> Option<Self> {
+        match (self, other) {
+            (a, b) if a == b => Some(a),
+            (Category::Number, _) | (_, Category::Number) => None,
+            (Category::Percentage, other) | (other, Category::Percentage) => Some(other),
+            _ => None,
+        }
+    }
+}
+
+/// A (partially) evaluated calc expression
+#[derive(Clone, Debug, PartialEq)]
+enum Calc {
+    /// Sum of values. Terms with the same (canonical) unit are combined, so `10px + 1in + 5%` becomes `106px + 5%`.
+    /// Numbers have an empty unit.
+    Sum(Vec<(String, f32)>),
+    /// Sum of expressions that can't be combined yet, as some of them are `min()` or `max()` functions
+    Add(Vec<Calc>),
+    /// Expression multiplied by a number
+    Scale(Box<Calc>, f32),
+    Min(Vec<Calc>),
+    Max(Vec<Calc>),
+}
+
+impl Calc {
+    fn number(value: f32) -> Self {
+        Calc::Sum(vec![(String::new(), value)])
+    }
+
+    /// Returns the value when the expression is a plain number
+    fn as_number(&self) -> Option<f32> {
+        match self {
+            Calc::Sum(terms) if terms.iter().all(|(unit, _)| unit.is_empty()) => Some(terms.iter().map(|t| t.1).sum()),
+            _ => None,
+        }
+    }
+
+    /// Returns the value and unit when the expression is a single value
+    fn as_single(&self) -> Option<(&str, f32)> {
+        match self {
+            Calc::Sum(terms) if terms.len() == 1 => Some((&terms[0].0, terms[0].1)),
+            _ => None,
+        }
+    }
+
+    fn category(&self) -> Option<Category> {
+        let mut categories: Box<dyn Iterator<Item = Option<Category>>> = match self {
+            Calc::Sum(terms) => Box::new(terms.iter().map(|(unit, _)| Category::of(unit))),
+            Calc::Add(list) | Calc::Min(list) | Calc::Max(list) => Box::new(list.iter().map(Calc::category)),
+            Calc::Scale(calc, _) => Box::new(std::iter::once(calc.category())),
+        };
+
+        let first = categories.next()??;
+        categories.try_fold(first, |acc, category| acc.combine(category?))
+    }
+
+    fn add(self, other: Calc) -> Option<Calc> {
+        self.category()?.combine(other.category()?)?;
+
+        Some(match (self, other) {
+            (Calc::Sum(mut terms), Calc::Sum(other)) => {
+                for (unit, value) in other {
+                    match terms.iter_mut().find(|(u, _)| *u == unit) {
+                        Some(term) => term.1 += value,
+                        None => terms.push((unit, value)),
+                    }
+                }
+                Calc::Sum(terms)
+            }
+            (Calc::Add(mut list), Calc::Add(other)) => {
+                list.extend(other);
+                Calc::Add(list)
+            }
+            (Calc::Add(mut list), other) => {
+                list.push(other);
+                Calc::Add(list)
+            }
+            (this, Calc::Add(mut list)) => {
+                list.insert(0, this);
+                Calc::Add(list)
+            }
+            (this, other) => Calc::Add(vec![this, other]),
+        })
+    }
+
+    fn scale(self, factor: f32) -> Calc {
+        match self {
+            Calc::Sum(terms) => Calc::Sum(terms.into_iter().map(|(unit, value)| (unit, value * factor)).collect()),
+            Calc::Add(list) => Calc::Add(list.into_iter().map(|calc| calc.scale(factor)).collect()),
+            Calc::Scale(calc, f) => Calc::Scale(calc, f * factor),
+            calc => Calc::Scale(Box::new(calc), factor),
+        }
+    }
+
+    fn mul(self, other: Calc) -> Option<Calc> {
+        if let Some(factor) = other.as_number() {
+            return Some(self.scale(factor));
+        }
+
+        let factor = self.as_number()?;
+        Some(other.scale(factor))
+    }
+
+    fn div(self, other: Calc) -> Option<Calc> {
+        let divisor = other.as_number()?;
+        Some(self.scale(1.0 / divisor))
+    }
+
+    /// Creates a `min()` (or `max()` when `max` is set) of the arguments. When all arguments are single values of
+    /// the same unit, the function is evaluated right away.
+    fn min_max(args: Vec<Calc>, max: bool) -> Option<Calc> {
+        let first = args.first()?.category()?;
+        args.iter().try_fold(first, |acc, arg| acc.combine(arg.category()?))?;
+
+        let singles: Option<Vec<(&str, f32)>> = args.iter().map(Calc::as_single).collect();
+        if let Some(singles) = singles {
+            let unit = singles[0].0;
+            if singles.iter().all(|(u, _)| *u == unit) {
+                let values = singles.iter().map(|(_, value)| *value);
+                let value = if max {
+                    values.fold(f32::NEG_INFINITY, f32::max)
+                } else {
+                    values.fold(f32::INFINITY, f32::min)
+                };
+
+                return Some(Calc::Sum(vec![(unit.to_string(), value)]));
+            }
+        }
+
+        Some(if max { Calc::Max(args) } else { Calc::Min(args) })
+    }
+
+    /// Converts the expression into a single CSS value
+    fn to_value(&self) -> CssValue {
+        match self {
+            Calc::Sum(terms) if terms.len() == 1 => {
+                let (unit, value) = &terms[0];
+                match unit.as_str() {
+                    "" if *value == 0.0 => CssValue::Zero,
+                    "" => CssValue::Number(*value),
+                    "%" => CssValue::Percentage(*value),
+                    unit => CssValue::Unit(*value, unit.to_string()),
+                }
+            }
+            Calc::Min(args) | Calc::Max(args) => {
+                let name = if matches!(self, Calc::Min(_)) { "min" } else { "max" };
+
+                let mut values = Vec::with_capacity(args.len() * 2);
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        values.push(CssValue::Comma);
+                    }
+                    values.push(arg.to_value());
+                }
+
+                CssValue::Function(name.into(), values)
+            }
+            calc => CssValue::Function("calc".into(), calc.to_args()),
+        }
+    }
+
+    /// Converts the expression into the arguments of a `calc()` function
+    fn to_args(&self) -> Vec<CssValue> {
+        let terms: Vec<Vec<CssValue>> = match self {
+            Calc::Sum(terms) => terms
+                .iter()
+                .map(|term| vec![Calc::Sum(vec![term.clone()]).to_value()])
+                .collect(),
+            Calc::Add(list) => list.iter().map(|calc| vec![calc.to_value()]).collect(),
+            Calc::Scale(calc, factor) => {
+                vec![vec![
+                    calc.to_value(),
+                    CssValue::String("*".into()),
+                    CssValue::Number(*factor),
+                ]]
+            }
+            calc => vec![vec![calc.to_value()]],
+        };
+
+        // Negative values are subtracted, so `50% + -10px` is serialized as `50% - 10px`
+        let mut args = Vec::new();
+        for (i, mut term) in terms.into_iter().enumerate() {
+            if i > 0 {
+                let negated = match term.as_mut_slice() {
+                    [CssValue::Unit(value, _) | CssValue::Percentage(value) | CssValue::Number(value)]
+                        if *value < 0.0 =>
+                    {
+                        *value = -*value;
+                        true
+                    }
+                    _ => false,
+                };
+                args.push(CssValue::String(if negated { "-" } else { "+" }.into()));
+            }
+            args.extend(term);
+        }
+
+        args
+    }
+}
+
+/// Parses a math function into an expression
+fn parse_function(value: &CssValue, ctx: &CalcContext) -> Option<Calc> {
+    let CssValue::Function(name, args) = value else {
+        return None;
+    };
+
+    match name.as_str() {
+        "calc" => Parser { args, pos: 0, ctx }.parse_all(),
+        "min" | "max" => {
+            let args = split_args(args)
+                .map(|arg| Parser { args: arg, pos: 0, ctx }.parse_all())
+                .collect::<Option<Vec<_>>>()?;
+
+            Calc::min_max(args, name == "max")
+        }
+        "clamp" => {
+            let args = split_args(args).collect::<Vec<_>>();
+            let [min, value, max] = args.as_slice() else {
+                return None;
+            };
+
+            // `none` can be used for the lower or upper bound
+            let bound = |arg: &[CssValue]| match arg {
+                [CssValue::None] => Some(None),
+                [CssValue::String(s)] if s.eq_ignore_ascii_case("none") => Some(None),
+                arg => Parser { args: arg, pos: 0, ctx }.parse_all().map(Some),
+            };
+
+            let mut calc = Parser {
+                args: value,
+                pos: 0,
+                ctx,
+            }
+            .parse_all()?;
+            if let Some(max) = bound(max)? {
+                calc = Calc::min_max(vec![calc, max], false)?;
+            }
+            if let Some(min) = bound(min)? {
+                calc = Calc::min_max(vec![min, calc], true)?;
+            }
+
+            Some(calc)
+        }
+        _ => None,
+    }
+}
+
+/// Splits the arguments of a function on commas
+fn split_args(args: &[CssValue]) -> impl Iterator<Item = &[CssValue]> {
+    args.split(|arg| *arg == CssValue::Comma)
+}
+
+/// Recursive descent parser for the arguments of a `calc()` function
+struct Parser<'a> {
+    args: &'a [CssValue],
+    pos: usize,
+    ctx: &'a CalcContext,
+}
+
+impl Parser<'_> {
+    /// Parses all arguments as a single sum
+    fn parse_all(mut self) -> Option<Calc> {
+        let calc = self.parse_sum()?;
+        if self.pos != self.args.len() {
+            return None;
+        }
+
+        Some(calc)
+    }
+
+    fn operator(&self) -> Option<&str> {
+        match self.args.get(self.pos) {
+            Some(CssValue::String(op)) if matches!(op.as_str(), "+" | "-" | "*" | "/") => Some(op),
+            _ => None,
+        }
+    }
+
+    fn parse_sum(&mut self) -> Option<Calc> {
+        let mut calc = self.parse_product()?;
+
+        while let Some(op @ ("+" | "-")) = self.operator() {
+            let negate = op == "-";
+            self.pos += 1;
+
+            let rhs = self.parse_product()?;
+            calc = calc.add(if negate { rhs.scale(-1.0) } else { rhs })?;
+        }
+
+        Some(calc)
+    }
+
+    fn parse_product(&mut self) -> Option<Calc> {
+        let mut calc = self.parse_value()?;
+
+        while let Some(op @ ("*" | "/")) = self.operator() {
+            let divide = op == "/";
+            self.pos += 1;
+
+            let rhs = self.parse_value()?;
+            calc = if divide { calc.div(rhs)? } else { calc.mul(rhs)? };
+        }
+
+        Some(calc)
+    }
+
+    fn parse_value(&mut self) -> Option<Calc> {
+        let value = self.args.get(self.pos)?;
+        self.pos += 1;
+
+        match value {
+            CssValue::Zero => Some(Calc::number(0.0)),
+            CssValue::Number(value) => Some(Calc::number(*value)),
+            CssValue::Percentage(value) => Some(canonical(*value, "%", self.ctx)),
+            CssValue::Unit(value, unit) => Some(canonical(*value, &unit.cow_to_ascii_lowercase(), self.ctx)),
+            CssValue::String(ident) => match ident.cow_to_ascii_lowercase().as_ref() {
+                "e" => Some(Calc::number(std::f32::consts::E)),
+                "pi" => Some(Calc::number(std::f32::consts::PI)),
+                "infinity" => Some(Calc::number(f32::INFINITY)),
+                "-infinity" => Some(Calc::number(f32::NEG_INFINITY)),
+                "nan" => Some(Calc::number(f32::NAN)),
+                _ => None,
+            },
+            value @ CssValue::Function(..) => parse_function(value, self.ctx),
+            _ => None,
+        }
+    }
+}
+
+/// Converts a value into its canonical unit. Absolute lengths are converted to pixels, angles to degrees, times to
+/// seconds, frequencies to hertz and resolutions to dppx. Relative units are converted to pixels when the context
+/// allows it, and are kept as-is otherwise.
+fn canonical(value: f32, unit: &str, ctx: &CalcContext) -> Calc {
+    let (factor, unit) = match (unit, ctx) {
+        ("px", _) => (1.0, "px"),
+        ("in", _) => (96.0, "px"),
+        ("cm", _) => (96.0 / 2.54, "px"),
+        ("mm", _) => (96.0 / 25.4, "px"),
+        ("q", _) => (96.0 / 101.6, "px"),
+        ("pt", _) => (96.0 / 72.0, "px"),
+        ("pc", _) => (16.0, "px"),
+        ("deg", _) => (1.0, "deg"),
+        ("grad", _) => (0.9, "deg"),
+        ("rad", _) => (180.0 / std::f32::consts::PI, "deg"),
+        ("turn", _) => (360.0, "deg"),
+        ("s", _) => (1.0, "s"),
+        ("ms", _) => (0.001, "s"),
+        ("hz", _) => (1.0, "hz"),
+        ("khz", _) => (1000.0, "hz"),
+        ("dppx" | "x", _) => (1.0, "dppx"),
+        ("dpi", _) => (1.0 / 96.0, "dppx"),
+        ("dpcm", _) => (2.54 / 96.0, "dppx"),
+        (
+            "%",
+            CalcContext {
+                percentage_basis: Some(basis),
+                ..
+            },
+        ) => (basis / 100.0, "px"),
+        (
+            "em",
+            CalcContext {
+                font_size: Some(size), ..
+            },
+        ) => (*size, "px"),
+        (
+            "rem",
+            CalcContext {
+                root_font_size: Some(size),
+                ..
+            },
+        ) => (*size, "px"),
+        (
+            "ex" | "ch",
+            CalcContext {
+                font_size: Some(size), ..
+            },
+        ) => (size / 2.0, "px"),
+        (
+            "vw",
+            CalcContext {
+                viewport: Some((width, _)),
+                ..
+            },
+        ) => (width / 100.0, "px"),
+        (
+            "vh",
+            CalcContext {
+                viewport: Some((_, height)),
+                ..
+            },
+        ) => (height / 100.0, "px"),
+        (
+            "vmin",
+            CalcContext {
+                viewport: Some((width, height)),
+                ..
+            },
+        ) => (width.min(*height) / 100.0, "px"),
+        (
+            "vmax",
+            CalcContext {
+                viewport: Some((width, height)),
+                ..
+            },
+        ) => (width.max(*height) / 100.0, "px"),
+        (unit, _) => (1.0, unit),
+    };
+
+    Calc::Sum(vec![(unit.to_string(), value * factor)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn px(value: f32) -> CssValue {
+        CssValue::Unit(value, "px".into())
+    }
+
+    fn op(op: &str) -> CssValue {
+        CssValue::String(op.into())
+    }
+
+    fn func(name: &str, args: Vec<CssValue>) -> CssValue {
+        CssValue::Function(name.into(), args)
+    }
+
+    #[test]
+    fn resolve_sums_and_products() {
+        let ctx = CalcContext::default();
+
+        // calc(1in + 4px * 2 - 10px)
+        let value = func(
+            "calc",
+            vec![
+                CssValue::Unit(1.0, "in".into()),
+                op("+"),
+                px(4.0),
+                op("*"),
+                CssValue::Number(2.0),
+                op("-"),
+                px(10.0),
+            ],
+        );
+        assert_eq!(resolve_calc(&value, &ctx), Some(px(94.0)));
+
+        // calc((10px + 20px) / 3 * 2)
+        let value = func(
+            "calc",
+            vec![
+                func("calc", vec![px(10.0), op("+"), px(20.0)]),
+                op("/"),
+                CssValue::Number(3.0),
+                op("*"),
+                CssValue::Number(2.0),
+            ],
+        );
+        assert_eq!(resolve_calc(&value, &ctx), Some(px(20.0)));
+
+        // calc(2 * pi)
+        let value = func("calc", vec![CssValue::Number(2.0), op("*"), op("pi")]);
+        assert_eq!(
+            resolve_calc(&value, &ctx),
+            Some(CssValue::Number(2.0 * std::f32::consts::PI))
+        );
+
+        // Lengths can't be added to numbers or multiplied with each other
+        assert_eq!(
+            resolve_calc(&func("calc", vec![px(1.0), op("+"), CssValue::Number(1.0)]), &ctx),
+            None
+        );
+        assert_eq!(resolve_calc(&func("calc", vec![px(1.0), op("*"), px(1.0)]), &ctx), None);
+        assert_eq!(resolve_calc(&func("calc", vec![px(1.0), op("/"), px(1.0)]), &ctx), None);
+        assert_eq!(resolve_calc(&func("calc", vec![px(1.0), px(1.0)]), &ctx), None);
+    }
+
+    #[test]
+    fn keep_unresolved_units() {
+        let ctx = CalcContext {
+            viewport: Some((800.0, 600.0)),
+            ..Default::default()
+        };
+
+        // calc(100% - 2 * 10px + 10vw) keeps the percentage
+        let value = func(
+            "calc",
+            vec![
+                CssValue::Percentage(100.0),
+                op("-"),
+                CssValue::Number(2.0),
+                op("*"),
+                px(10.0),
+                op("+"),
+                CssValue::Unit(10.0, "vw".into()),
+            ],
+        );
+        let resolved = resolve_calc(&value, &ctx).unwrap();
+        assert_eq!(
+            resolved,
+            func("calc", vec![CssValue::Percentage(100.0), op("+"), px(60.0)])
+        );
+
+        // ... which can be resolved later on, once the percentage basis is known
+        let ctx = CalcContext {
+            percentage_basis: Some(200.0),
+            ..ctx
+        };
+        assert_eq!(resolve_calc(&resolved, &ctx), Some(px(260.0)));
+
+        // Negative terms are subtracted
+        let value = func("calc", vec![CssValue::Percentage(50.0), op("-"), px(10.0)]);
+        assert_eq!(
+            resolve_calc(&value, &CalcContext::default()),
+            Some(func("calc", vec![CssValue::Percentage(50.0), op("-"), px(10.0)]))
+        );
+    }
+
+    #[test]
+    fn resolve_min_max_clamp() {
+        let ctx = CalcContext::default();
+
+        let value = func("min", vec![px(10.0), CssValue::Comma, CssValue::Unit(1.0, "in".into())]);
+        assert_eq!(resolve_calc(&value, &ctx), Some(px(10.0)));
+
+        let value = func("max", vec![px(10.0), CssValue::Comma, CssValue::Unit(1.0, "in".into())]);
+        assert_eq!(resolve_calc(&value, &ctx), Some(px(96.0)));
+
+        let value = func(
+            "clamp",
+            vec![px(10.0), CssValue::Comma, px(50.0), CssValue::Comma, px(20.0)],
+        );
+        assert_eq!(resolve_calc(&value, &ctx), Some(px(20.0)));
+
+        let value = func(
+            "clamp",
+            vec![op("none"), CssValue::Comma, px(50.0), CssValue::Comma, px(80.0)],
+        );
+        assert_eq!(resolve_calc(&value, &ctx), Some(px(50.0)));
+
+        // min(100%, 600px) / 2 + 1em can only be resolved during layout
+        let value = func(
+            "calc",
+            vec![
+                func("min", vec![CssValue::Percentage(100.0), CssValue::Comma, px(600.0)]),
+                op("/"),
+                CssValue::Number(2.0),
+                op("+"),
+                CssValue::Unit(1.0, "em".into()),
+            ],
+        );
+        let resolved = resolve_calc(&value, &ctx).unwrap();
+        assert!(is_math_function(&resolved));
+
+        let layout = |basis: f32| CalcContext {
+            percentage_basis: Some(basis),
+            font_size: Some(16.0),
+            root_font_size: Some(20.0),
+            ..Default::default()
+        };
+        assert_eq!(resolve_calc(&resolved, &layout(400.0)), Some(px(216.0)));
+        assert_eq!(resolve_calc(&resolved, &layout(2000.0)), Some(px(316.0)));
+
+        // Arguments of different kinds can't be compared
+        let value = func("min", vec![px(10.0), CssValue::Comma, CssValue::Number(2.0)]);
+        assert_eq!(resolve_calc(&value, &ctx), None);
+    }
 }
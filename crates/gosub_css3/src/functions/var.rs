@@ -1,60 +1,280 @@
 use crate::stylesheet::CssValue;
-use gosub_interface::config::HasDocument;
 use std::collections::HashMap;
 
-#[allow(dead_code)]
+/// Custom properties (`--*`) that are available to a node. These are inherited from the parent node, and
+/// overridden by the custom properties declared on the node itself.
 #[derive(Clone, Debug, Default)]
 pub struct VariableEnvironment {
     pub values: HashMap<String, CssValue>,
 }
 
-#[allow(dead_code)]
 impl VariableEnvironment {
-    pub fn get<C: HasDocument>(&self, name: &str, _doc: &C::Document, _node: &C::Node) -> Option<CssValue> {
-        let mut current = Some(self);
+    pub fn get(&self, name: &str) -> Option<&CssValue> {
+        self.values.get(name)
+    }
+
+    /// Creates the environment of a node from the inherited environment and the custom properties declared on the
+    /// node. References between the declared custom properties are substituted. Custom properties that are part of
+    /// a reference cycle, or that reference an unknown variable without fallback, are invalid at computed-value
+    /// time and are removed from the environment.
+    pub fn with_declared(inherited: &VariableEnvironment, declared: HashMap<String, CssValue>) -> Self {
+        let mut resolver = Resolver {
+            inherited,
+            declared: &declared,
+            resolved: HashMap::new(),
+        };
+
+        for name in declared.keys() {
+            resolver.resolve(name);
+        }
+
+        let mut env = inherited.clone();
+        for (name, value) in resolver.resolved {
+            match value {
+                Some(value) => env.values.insert(name, value),
+                None => env.values.remove(&name),
+            };
+        }
+
+        env
+    }
+}
+
+/// Resolves the custom properties declared on a single node
+struct Resolver<'a> {
+    inherited: &'a VariableEnvironment,
+    declared: &'a HashMap<String, CssValue>,
+    /// Resolved custom properties. `None` means the property is invalid at computed-value time
+    resolved: HashMap<String, Option<CssValue>>,
+}
+
+impl Resolver<'_> {
+    fn resolve(&mut self, name: &str) -> Option<CssValue> {
+        if let Some(value) = self.resolved.get(name) {
+            return value.clone();
+        }
+
+        let Some(value) = self.declared.get(name) else {
+            return self.inherited.get(name).cloned();
+        };
+
+        let resolved = match value {
+            CssValue::Initial => None,
+            CssValue::Inherit => self.inherited.get(name).cloned(),
+            // Properties that are part of a reference cycle are invalid. As cycles are detected up front, the
+            // recursion below always ends.
+            _ if self.in_cycle(name) => None,
+            value => substitute(value, &mut |name| self.resolve(name)),
+        };
+
+        self.resolved.insert(name.to_string(), resolved.clone());
+        resolved
+    }
+
+    /// Returns true when the declared value of the given property (transitively) references itself
+    fn in_cycle(&self, name: &str) -> bool {
+        let mut seen: Vec<String> = Vec::new();
+        let mut todo = vec![name.to_string()];
+
+        while let Some(current) = todo.pop() {
+            let Some(value) = self.declared.get(&current) else {
+                continue;
+            };
+
+            for reference in references(value) {
+                if reference == name {
+                    return true;
+                }
+
+                if !seen.contains(&reference) {
+                    seen.push(reference.clone());
+                    todo.push(reference);
+                }
+            }
+        }
+
+        false
+    }
+}
+
+/// Returns true when the value contains a `var()` function
+pub fn contains_var(value: &CssValue) -> bool {
+    match value {
+        CssValue::Function(name, _) if name.eq_ignore_ascii_case("var") => true,
+        CssValue::Function(_, args) | CssValue::List(args) => args.iter().any(contains_var),
+        _ => false,
+    }
+}
+
+/// Substitutes all `var()` functions in the value with the values from the environment. Returns `None` when the
+/// value is invalid at computed-value time (a variable is unknown and has no fallback).
+pub fn substitute_vars(value: &CssValue, env: &VariableEnvironment) -> Option<CssValue> {
+    substitute(value, &mut |name| env.get(name).cloned())
+}
+
+/// Returns the names of all variables referenced by the value
+fn references(value: &CssValue) -> Vec<String> {
+    match value {
+        CssValue::Function(name, args) if name.eq_ignore_ascii_case("var") => {
+            let mut refs: Vec<String> = args.first().map(|name| vec![name.to_string()]).unwrap_or_default();
+            refs.extend(args.iter().skip(1).flat_map(references));
+            refs
+        }
+        CssValue::Function(_, args) | CssValue::List(args) => args.iter().flat_map(references).collect(),
+        _ => Vec::new(),
+    }
+}
 
-        while let Some(env) = current {
-            if let Some(value) = env.values.get(name) {
-                return Some(value.clone());
+fn substitute(value: &CssValue, lookup: &mut impl FnMut(&str) -> Option<CssValue>) -> Option<CssValue> {
+    match value {
+        CssValue::Function(name, args) if name.eq_ignore_ascii_case("var") => {
+            let name = args.first()?.to_string();
+            if let Some(value) = lookup(&name) {
+                return Some(value);
             }
 
-            current = None;
+            // The fallback is everything after the first comma
+            let fallback = args.iter().position(|arg| *arg == CssValue::Comma)?;
+            let fallback = substitute_list(&args[fallback + 1..], lookup)?;
 
-            //TODO: give node a variable env
-            // let node = doc.get_parent(node);
-            // current =  node.get_variable_env();
+            Some(match fallback.len() {
+                0 => CssValue::List(vec![]),
+                1 => fallback.into_iter().next().expect("unreachable"),
+                _ => CssValue::List(fallback),
+            })
         }
+        CssValue::Function(name, args) => Some(CssValue::Function(name.clone(), substitute_list(args, lookup)?)),
+        CssValue::List(list) => {
+            let list = substitute_list(list, lookup)?;
+            if list.len() == 1 {
+                list.into_iter().next()
+            } else {
+                Some(CssValue::List(list))
+            }
+        }
+        _ => Some(value.clone()),
+    }
+}
 
-        None
+/// Substitutes the variables in all values. Variables that are substituted with a list of values are spliced into
+/// the resulting list.
+fn substitute_list(values: &[CssValue], lookup: &mut impl FnMut(&str) -> Option<CssValue>) -> Option<Vec<CssValue>> {
+    let mut result = Vec::with_capacity(values.len());
+
+    for value in values {
+        let is_var = matches!(value, CssValue::Function(name, _) if name.eq_ignore_ascii_case("var"));
+
+        match substitute(value, lookup)? {
+            CssValue::List(list) if is_var => result.extend(list),
+            value => result.push(value),
+        }
     }
+
+    Some(result)
 }
 
-#[allow(dead_code)]
-pub fn resolve_var<C: HasDocument>(values: &[CssValue], doc: &C::Document, node: &C::Node) -> Vec<CssValue> {
-    let Some(name) = values.first().map(|v| {
-        let mut str = v.to_string();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        if str.starts_with("--") {
-            str.remove(0);
-            str.remove(0);
+    fn var(name: &str, fallback: Option<CssValue>) -> CssValue {
+        let mut args = vec![CssValue::String(name.into())];
+        if let Some(fallback) = fallback {
+            args.push(CssValue::Comma);
+            args.push(fallback);
         }
 
-        str
-    }) else {
-        return vec![];
-    };
+        CssValue::Function("var".into(), args)
+    }
 
-    // let environment = doc.get_variable_env(node);
+    #[test]
+    fn substitute_variables() {
+        let mut env = VariableEnvironment::default();
+        env.values.insert("--gap".into(), CssValue::Unit(4.0, "px".into()));
+        env.values.insert(
+            "--border".into(),
+            CssValue::List(vec![CssValue::Unit(1.0, "px".into()), CssValue::String("solid".into())]),
+        );
 
-    let environment = VariableEnvironment::default(); //TODO: get from node
+        assert_eq!(
+            substitute_vars(&var("--gap", None), &env),
+            Some(CssValue::Unit(4.0, "px".into()))
+        );
+        assert_eq!(substitute_vars(&var("--unknown", None), &env), None);
+        assert_eq!(
+            substitute_vars(&var("--unknown", Some(CssValue::Zero)), &env),
+            Some(CssValue::Zero)
+        );
+        assert_eq!(
+            substitute_vars(
+                &CssValue::List(vec![var("--border", None), CssValue::String("red".into())]),
+                &env
+            ),
+            Some(CssValue::List(vec![
+                CssValue::Unit(1.0, "px".into()),
+                CssValue::String("solid".into()),
+                CssValue::String("red".into()),
+            ]))
+        );
+        assert!(contains_var(&CssValue::Function(
+            "calc".into(),
+            vec![var("--gap", None)]
+        )));
+    }
 
-    let Some(value) = environment.get::<C>(&name, doc, node) else {
-        let Some(default) = values.get(1).cloned() else {
-            return vec![];
-        };
+    #[test]
+    fn declared_custom_properties() {
+        let mut inherited = VariableEnvironment::default();
+        inherited
+            .values
+            .insert("--color".into(), CssValue::String("red".into()));
+        inherited.values.insert("--size".into(), CssValue::Number(1.0));
 
-        return vec![default];
-    };
+        let mut declared = HashMap::new();
+        declared.insert("--a".into(), var("--b", None));
+        declared.insert("--b".into(), var("--color", None));
+        declared.insert("--size".into(), CssValue::Number(2.0));
+        declared.insert("--cycle-1".into(), var("--cycle-2", None));
+        declared.insert("--cycle-2".into(), var("--cycle-1", Some(CssValue::Zero)));
+        declared.insert("--self".into(), var("--self", Some(CssValue::Zero)));
+        declared.insert("--initial".into(), CssValue::Initial);
+        declared.insert("--color".into(), CssValue::Inherit);
 
-    vec![value.clone()]
+        let env = VariableEnvironment::with_declared(&inherited, declared);
+
+        assert_eq!(env.get("--a"), Some(&CssValue::String("red".into())));
+        assert_eq!(env.get("--b"), Some(&CssValue::String("red".into())));
+        assert_eq!(env.get("--size"), Some(&CssValue::Number(2.0)));
+        assert_eq!(env.get("--color"), Some(&CssValue::String("red".into())));
+        assert_eq!(env.get("--cycle-1"), None);
+        assert_eq!(env.get("--cycle-2"), None);
+        assert_eq!(env.get("--self"), None);
+        assert_eq!(env.get("--initial"), None);
+    }
+
+    #[test]
+    fn substitute_parsed_fallback() {
+        let sheet = crate::Css3::parse_str(
+            "a { margin: var(--gap, 1px 2px) var(--other); }",
+            Default::default(),
+            gosub_interface::css3::CssOrigin::Author,
+            "test.css",
+        )
+        .unwrap();
+
+        let value = &sheet.rules[0].declarations[0].value;
+        assert!(contains_var(value));
+
+        let mut env = VariableEnvironment::default();
+        env.values.insert("--other".into(), CssValue::Zero);
+
+        assert_eq!(
+            substitute_vars(value, &env),
+            Some(CssValue::List(vec![
+                CssValue::Unit(1.0, "px".into()),
+                CssValue::Unit(2.0, "px".into()),
+                CssValue::Zero,
+            ]))
+        );
+    }
 }
@@ -0,0 +1,169 @@
+use crate::stylesheet::CssStylesheet;
+use gosub_interface::css3::CssOrigin;
+use std::collections::HashMap;
+
+/// Order of the cascade layers of one or more stylesheets. Layers are ordered per origin by their first declaration,
+/// and the sublayers of a layer come before the styles of the layer itself. Layers later in the order win over
+/// earlier layers, and unlayered styles win over all layers.
+#[derive(Debug, Default)]
+pub struct LayerOrder {
+    ranks: HashMap<(CssOrigin, String), usize>,
+}
+
+#[derive(Default)]
+struct LayerNode {
+    name: String,
+    children: Vec<LayerNode>,
+}
+
+impl LayerNode {
+    fn insert(&mut self, path: &[&str]) {
+        let Some((first, rest)) = path.split_first() else {
+            return;
+        };
+
+        let name = if self.name.is_empty() {
+            first.to_string()
+        } else {
+            format!("{}.{}", self.name, first)
+        };
+
+        let child = match self.children.iter().position(|child| child.name == name) {
+            Some(idx) => &mut self.children[idx],
+            None => {
+                self.children.push(LayerNode {
+                    name,
+                    children: Vec::new(),
+                });
+                self.children.last_mut().expect("unreachable")
+            }
+        };
+
+        child.insert(rest);
+    }
+
+    fn rank(&self, origin: CssOrigin, ranks: &mut HashMap<(CssOrigin, String), usize>, next: &mut usize) {
+        for child in &self.children {
+            child.rank(origin, ranks, next);
+            ranks.insert((origin, child.name.clone()), *next);
+            *next += 1;
+        }
+    }
+}
+
+impl LayerOrder {
+    /// Creates the layer order from the (full, dotted) layer names of each origin in order of declaration
+    pub fn new<'a>(names: impl IntoIterator<Item = (CssOrigin, &'a str)>) -> Self {
+        let mut roots: Vec<(CssOrigin, LayerNode)> = Vec::new();
+        for (origin, name) in names {
+            let idx = match roots.iter().position(|(o, _)| *o == origin) {
+                Some(idx) => idx,
+                None => {
+                    roots.push((origin, LayerNode::default()));
+                    roots.len() - 1
+                }
+            };
+            roots[idx].1.insert(&name.split('.').collect::<Vec<_>>());
+        }
+
+        let mut ranks = HashMap::new();
+        for (origin, root) in &roots {
+            root.rank(*origin, &mut ranks, &mut 0);
+        }
+
+        Self { ranks }
+    }
+
+    /// Creates the layer order of the layers declared in the stylesheets
+    pub fn from_stylesheets(sheets: &[CssStylesheet]) -> Self {
+        let names = sheets
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, sheet)| {
+                sheet
+                    .layers
+                    .iter()
+                    .map(move |name| (sheet.origin, sheet_layer_name(idx, name)))
+            })
+            .collect::<Vec<_>>();
+
+        Self::new(names.iter().map(|(origin, name)| (*origin, name.as_str())))
+    }
+
+    /// Returns the position of the layer in the order of its origin, or `None` when the layer is unknown
+    pub fn rank(&self, origin: CssOrigin, name: &str) -> Option<usize> {
+        self.ranks.get(&(origin, name.to_string())).copied()
+    }
+
+    /// Returns the position of a layer declared in the stylesheet at the given index of the stylesheets this order
+    /// was created from
+    pub fn sheet_rank(&self, sheet: usize, origin: CssOrigin, name: &str) -> Option<usize> {
+        self.rank(origin, &sheet_layer_name(sheet, name))
+    }
+}
+
+/// Named layers with the same name are the same layer in all stylesheets of an origin, but anonymous layers are only
+/// numbered per stylesheet. These get the index of their stylesheet, so they stay distinct.
+fn sheet_layer_name(sheet: usize, name: &str) -> String {
+    if !name.contains('#') {
+        return name.to_string();
+    }
+
+    name.split('.')
+        .map(|part| match part.starts_with('#') {
+            true => format!("{}@{}", part, sheet),
+            false => part.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Css3;
+    use gosub_shared::config::ParserConfig;
+
+    #[test]
+    fn layer_order() {
+        let order = LayerOrder::new(
+            ["reset", "framework", "framework.base", "framework.theme", "reset.x"]
+                .into_iter()
+                .map(|name| (CssOrigin::Author, name)),
+        );
+
+        let ranks: Vec<_> = ["reset.x", "reset", "framework.base", "framework.theme", "framework"]
+            .iter()
+            .map(|name| order.rank(CssOrigin::Author, name))
+            .collect();
+
+        assert_eq!(ranks, vec![Some(0), Some(1), Some(2), Some(3), Some(4)]);
+        assert_eq!(order.rank(CssOrigin::Author, "unknown"), None);
+        assert_eq!(order.rank(CssOrigin::UserAgent, "reset"), None);
+    }
+
+    #[test]
+    fn layer_order_of_stylesheets() {
+        let parse = |css: &str, origin: CssOrigin| Css3::parse_str(css, ParserConfig::default(), origin, "").unwrap();
+
+        let sheets = [
+            parse("@layer base, theme; @layer { a { color: red; } }", CssOrigin::UserAgent),
+            parse("@layer theme, base; @layer { a { color: red; } }", CssOrigin::Author),
+            parse("@layer { a { color: red; } } @layer extra;", CssOrigin::Author),
+        ];
+        let order = LayerOrder::from_stylesheets(&sheets);
+
+        // Each origin has its own order
+        assert_eq!(order.rank(CssOrigin::UserAgent, "base"), Some(0));
+        assert_eq!(order.rank(CssOrigin::UserAgent, "theme"), Some(1));
+        assert_eq!(order.rank(CssOrigin::Author, "theme"), Some(0));
+        assert_eq!(order.rank(CssOrigin::Author, "base"), Some(1));
+
+        // Anonymous layers have the same name in every stylesheet, but are different layers
+        assert_eq!(sheets[1].layers[2], sheets[2].layers[0]);
+        assert_eq!(order.sheet_rank(0, CssOrigin::UserAgent, &sheets[0].layers[2]), Some(2));
+        assert_eq!(order.sheet_rank(1, CssOrigin::Author, &sheets[1].layers[2]), Some(2));
+        assert_eq!(order.sheet_rank(2, CssOrigin::Author, &sheets[2].layers[0]), Some(3));
+        assert_eq!(order.sheet_rank(2, CssOrigin::Author, "extra"), Some(4));
+    }
+}
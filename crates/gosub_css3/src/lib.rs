@@ -1,8 +1,9 @@
 extern crate core;
 
-use crate::ast::convert_ast_to_stylesheet;
-use crate::stylesheet::CssStylesheet;
-use crate::tokenizer::Tokenizer;
+use crate::ast::{convert_ast_to_stylesheet, convert_selector_list};
+use crate::matcher::styling::selector_is_supported;
+use crate::stylesheet::{CssSelector, CssSelectorPart, CssStylesheet};
+use crate::tokenizer::{TokenType, Tokenizer};
 
 use gosub_interface::css3::CssOrigin;
 use gosub_shared::byte_stream::{ByteStream, Encoding, Location};
@@ -18,8 +19,10 @@ pub mod ast;
 pub mod colors;
 pub mod errors;
 mod functions;
+pub mod layer;
 #[allow(dead_code)]
 pub mod matcher;
+pub mod media;
 pub mod node;
 pub mod parser;
 pub mod stylesheet;
@@ -77,6 +80,44 @@ impl<'stream> Css3<'stream> {
         Css3::new(stream, config, origin, source_url).parse()
     }
 
+    /// Parses a selector list, like the argument of `querySelector()`. Returns an error when the string is not a
+    /// valid selector, or when it uses pseudo classes or pseudo elements that are not supported.
+    pub fn parse_selector_str(data: &str) -> CssResult<CssSelector> {
+        let mut stream = ByteStream::new(Encoding::UTF8, None);
+        stream.read_from_str(data, Some(Encoding::UTF8));
+        stream.close();
+
+        let mut parser = Css3::new(&mut stream, ParserConfig::default(), CssOrigin::Author, "");
+        parser.consume_whitespace_comments();
+        let node = parser.parse_selector_list()?;
+        parser.consume_whitespace_comments();
+        let next = parser.tokenizer.lookahead(0);
+        if next.token_type != TokenType::Eof {
+            return Err(CssError::with_location(
+                "Unexpected input after selector",
+                next.location,
+            ));
+        }
+
+        let selector = convert_selector_list(&node)?;
+        if selector.parts.iter().any(Vec::is_empty) {
+            return Err(CssError::new(&format!("Empty selector in '{data}'")));
+        }
+        let is_combinator = |part: Option<&CssSelectorPart>| matches!(part, Some(CssSelectorPart::Combinator(_)));
+        if selector
+            .parts
+            .iter()
+            .any(|parts| is_combinator(parts.first()) || is_combinator(parts.last()))
+        {
+            return Err(CssError::new(&format!("Dangling combinator in '{data}'")));
+        }
+        if !selector_is_supported(&selector) {
+            return Err(CssError::new(&format!("Unsupported selector '{data}'")));
+        }
+
+        Ok(selector)
+    }
+
     fn parse(&mut self) -> CssResult<CssStylesheet> {
         if self.config.context != Context::Stylesheet {
             return Err(CssError::new("Expected a stylesheet context"));
@@ -149,4 +190,19 @@ mod tests {
         // let w = Walker::new(&binding);
         // w.walk_stdout();
     }
+
+    #[test]
+    fn parse_selector_str() {
+        let selector = Css3::parse_selector_str(" div > p.intro, #main a[href] ").unwrap();
+        assert_eq!(selector.parts.len(), 2);
+        assert_eq!(selector.parts[0].len(), 4);
+
+        assert!(Css3::parse_selector_str("ul li:nth-child(2n+1):not(.skip)").is_ok());
+
+        assert!(Css3::parse_selector_str("").is_err());
+        assert!(Css3::parse_selector_str("div {").is_err());
+        assert!(Css3::parse_selector_str("div >").is_err());
+        assert!(Css3::parse_selector_str("+ p").is_err());
+        assert!(Css3::parse_selector_str("p:unknown-pseudo").is_err());
+    }
 }
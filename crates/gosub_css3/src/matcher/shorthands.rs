@@ -118,6 +118,25 @@ pub struct FixListInfo {
     important: bool,
     location: String,
     specificity: Specificity,
+    layer: Option<usize>,
+}
+
+impl FixListInfo {
+    pub fn new(
+        origin: CssOrigin,
+        important: bool,
+        location: String,
+        specificity: Specificity,
+        layer: Option<usize>,
+    ) -> Self {
+        Self {
+            origin,
+            important,
+            location,
+            specificity,
+            layer,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -345,6 +364,7 @@ impl FixList {
                 important: info.important,
                 specificity: info.specificity,
                 location: info.location.clone(),
+                layer: info.layer,
             }
         } else {
             DeclarationProperty {
@@ -353,6 +373,7 @@ impl FixList {
                 important: false,
                 specificity: Specificity::new(0, 0, 0),
                 location: String::new(),
+                layer: None,
             }
         }
     }
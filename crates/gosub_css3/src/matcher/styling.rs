@@ -13,20 +13,66 @@ use gosub_interface::document::Document;
 use gosub_interface::node::ClassList;
 use gosub_interface::node::ElementDataType;
 use gosub_interface::node::Node;
+use gosub_interface::node::NodeType;
+use gosub_interface::node::TextDataType;
 use gosub_shared::node::NodeId;
 
+use crate::functions::calc::{is_math_function, resolve_calc, CalcContext};
 use crate::matcher::property_definitions::get_css_definitions;
-use crate::stylesheet::{Combinator, CssSelector, CssSelectorPart, CssValue, MatcherType, Specificity};
+use crate::stylesheet::{
+    Combinator, CssSelector, CssSelectorPart, CssValue, MatcherType, PseudoClassArgument, PseudoClassFunction,
+    Specificity,
+};
 use crate::system::Css3System;
 
 // Matches a complete selector (all parts) against the given node(id)
-pub(crate) fn match_selector<C: HasDocument>(
+pub fn match_selector<C: HasDocument>(
     document: &C::Document,
     node_id: NodeId,
     selector: &CssSelector,
+) -> (bool, Specificity) {
+    match_selector_for::<C>(document, node_id, selector, None, None)
+}
+
+/// Matches the selector like `match_selector`, with `:scope` matching the given scope element instead of the root
+/// element (like `querySelector()` on an element does)
+pub fn match_selector_in_scope<C: HasDocument>(
+    document: &C::Document,
+    node_id: NodeId,
+    selector: &CssSelector,
+    scope: NodeId,
+) -> (bool, Specificity) {
+    match_selector_for::<C>(document, node_id, selector, None, Some(scope))
+}
+
+/// Matches the selector against the given pseudo element (like "before" or "marker") of the node. Only selectors
+/// that end with that pseudo element will match.
+pub(crate) fn match_pseudo_element_selector<C: HasDocument>(
+    document: &C::Document,
+    node_id: NodeId,
+    selector: &CssSelector,
+    pseudo_element: &str,
+) -> (bool, Specificity) {
+    match_selector_for::<C>(document, node_id, selector, Some(pseudo_element), None)
+}
+
+fn match_selector_for<C: HasDocument>(
+    document: &C::Document,
+    node_id: NodeId,
+    selector: &CssSelector,
+    pseudo_element: Option<&str>,
+    scope: Option<NodeId>,
 ) -> (bool, Specificity) {
     for part in &selector.parts {
-        if match_selector_parts::<C>(document, node_id, part) {
+        let parts = match (part.last(), pseudo_element) {
+            (Some(CssSelectorPart::PseudoElement(name)), Some(pseudo)) if name.eq_ignore_ascii_case(pseudo) => {
+                &part[..part.len() - 1]
+            }
+            (_, Some(_)) => continue,
+            (_, None) => part.as_slice(),
+        };
+
+        if match_selector_parts::<C>(document, node_id, parts, scope) {
             return (true, Specificity::from(part.as_slice()));
         }
     }
@@ -45,7 +91,12 @@ fn consume<'a, T>(this: &mut &'a [T]) -> Option<&'a T> {
 }
 
 /// Returns true when the given node matches the part(s)
-fn match_selector_parts<C: HasDocument>(doc: &C::Document, node_id: NodeId, mut parts: &[CssSelectorPart]) -> bool {
+fn match_selector_parts<C: HasDocument>(
+    doc: &C::Document,
+    node_id: NodeId,
+    mut parts: &[CssSelectorPart],
+    scope: Option<NodeId>,
+) -> bool {
     let mut next_current_node = doc.node_by_id(node_id);
     if next_current_node.is_none() {
         return false;
@@ -60,7 +111,7 @@ fn match_selector_parts<C: HasDocument>(doc: &C::Document, node_id: NodeId, mut
             return false;
         }
 
-        if !match_selector_part::<C>(part, current_node, doc, &mut next_current_node, &mut parts) {
+        if !match_selector_part::<C>(part, current_node, doc, &mut next_current_node, &mut parts, scope) {
             return false;
         }
 
@@ -79,6 +130,7 @@ fn match_selector_part<'a, C: HasDocument>(
     doc: &'a C::Document,
     next_node: &mut Option<&'a C::Node>,
     parts: &mut &[CssSelectorPart],
+    scope: Option<NodeId>,
 ) -> bool {
     match part {
         CssSelectorPart::Universal => {
@@ -167,12 +219,10 @@ fn match_selector_part<'a, C: HasDocument>(
                 }
             }
         }
-        CssSelectorPart::PseudoClass(_name) => {
-            // @Todo: implement pseudo classes
-            false
-        }
+        CssSelectorPart::PseudoClass(name) => match_pseudo_class::<C>(name, current_node, doc, scope),
+        CssSelectorPart::PseudoClassFunction(func) => match_pseudo_class_function::<C>(func, current_node, doc, scope),
         CssSelectorPart::PseudoElement(_name) => {
-            // @Todo: implement pseudo elements
+            // Pseudo elements are matched with `match_pseudo_element_selector`, and never match the element itself
             false
         }
         CssSelectorPart::Combinator(combinator) => {
@@ -197,7 +247,7 @@ fn match_selector_part<'a, C: HasDocument>(
 
                         *next_node = Some(parent);
 
-                        if match_selector_part::<C>(last, parent, doc, next_node, parts) {
+                        if match_selector_part::<C>(last, parent, doc, next_node, parts, scope) {
                             return true;
                         }
 
@@ -226,7 +276,7 @@ fn match_selector_part<'a, C: HasDocument>(
 
                     *next_node = Some(parent);
 
-                    match_selector_part::<C>(last, parent, doc, next_node, parts)
+                    match_selector_part::<C>(last, parent, doc, next_node, parts, scope)
                 }
                 Combinator::NextSibling => {
                     let parent_node = doc.node_by_id(current_node.parent_id().unwrap());
@@ -260,7 +310,7 @@ fn match_selector_part<'a, C: HasDocument>(
 
                     *next_node = Some(prev);
 
-                    match_selector_part::<C>(last, prev, doc, next_node, parts)
+                    match_selector_part::<C>(last, prev, doc, next_node, parts, scope)
                 }
                 Combinator::SubsequentSibling => {
                     let parent_node = doc.node_by_id(current_node.parent_id().unwrap());
@@ -281,7 +331,7 @@ fn match_selector_part<'a, C: HasDocument>(
                             continue;
                         };
 
-                        if match_selector_part::<C>(last, child, doc, next_node, parts) {
+                        if match_selector_part::<C>(last, child, doc, next_node, parts, scope) {
                             return true;
                         }
                     }
@@ -315,6 +365,421 @@ fn match_selector_part<'a, C: HasDocument>(
     }
 }
 
+/// Returns true when the given node matches the (non-functional) pseudo class. Without a scope element, `:scope` is
+/// the root element.
+fn match_pseudo_class<C: HasDocument>(
+    name: &str,
+    current_node: &C::Node,
+    doc: &C::Document,
+    scope: Option<NodeId>,
+) -> bool {
+    let Some(element) = current_node.get_element_data() else {
+        return false;
+    };
+
+    match name {
+        "scope" if scope.is_some() => scope == Some(current_node.id()),
+        "root" | "scope" => current_node
+            .parent_id()
+            .and_then(|id| doc.node_by_id(id))
+            .is_some_and(|parent| parent.type_of() == NodeType::DocumentNode),
+        "empty" => current_node.children().iter().all(|child| {
+            let Some(child) = doc.node_by_id(*child) else {
+                return true;
+            };
+
+            match child.type_of() {
+                NodeType::ElementNode => false,
+                NodeType::TextNode => child.get_text_data().is_some_and(|text| text.value().is_empty()),
+                _ => true,
+            }
+        }),
+        "first-child" => nth_position::<C>(current_node, doc, false, |_| true) == Some(1),
+        "last-child" => nth_position::<C>(current_node, doc, true, |_| true) == Some(1),
+        "only-child" => {
+            nth_position::<C>(current_node, doc, false, |_| true) == Some(1)
+                && nth_position::<C>(current_node, doc, true, |_| true) == Some(1)
+        }
+        "first-of-type" => nth_position::<C>(current_node, doc, false, |n| same_type::<C>(n, current_node)) == Some(1),
+        "last-of-type" => nth_position::<C>(current_node, doc, true, |n| same_type::<C>(n, current_node)) == Some(1),
+        "only-of-type" => {
+            nth_position::<C>(current_node, doc, false, |n| same_type::<C>(n, current_node)) == Some(1)
+                && nth_position::<C>(current_node, doc, true, |n| same_type::<C>(n, current_node)) == Some(1)
+        }
+        // We don't keep track of visited links, so every link is unvisited
+        "link" | "any-link" => matches!(element.name(), "a" | "area") && element.attribute("href").is_some(),
+        "checked" => match element.name() {
+            "input" => {
+                element.attribute("checked").is_some()
+                    && element
+                        .attribute("type")
+                        .is_some_and(|t| t.eq_ignore_ascii_case("checkbox") || t.eq_ignore_ascii_case("radio"))
+            }
+            "option" => element.attribute("selected").is_some(),
+            _ => false,
+        },
+        "disabled" => is_form_element(element.name()) && element.attribute("disabled").is_some(),
+        "enabled" => is_form_element(element.name()) && element.attribute("disabled").is_none(),
+        "required" => {
+            matches!(element.name(), "input" | "select" | "textarea") && element.attribute("required").is_some()
+        }
+        "optional" => {
+            matches!(element.name(), "input" | "select" | "textarea") && element.attribute("required").is_none()
+        }
+        "defined" => true,
+        // User action pseudo classes (:hover, :focus etc.) and all other pseudo classes never match, as we don't
+        // have any state to match them against.
+        _ => false,
+    }
+}
+
+/// Returns true when all pseudo classes and pseudo elements in the selector are known. Unknown ones make a
+/// selector invalid, which is what `@supports selector()` tests for.
+pub(crate) fn selector_is_supported(selector: &CssSelector) -> bool {
+    selector.parts.iter().flatten().all(|part| match part {
+        CssSelectorPart::PseudoClass(name) => matches!(
+            name.as_str(),
+            "root"
+                | "scope"
+                | "empty"
+                | "first-child"
+                | "last-child"
+                | "only-child"
+                | "first-of-type"
+                | "last-of-type"
+                | "only-of-type"
+                | "link"
+                | "any-link"
+                | "visited"
+                | "checked"
+                | "disabled"
+                | "enabled"
+                | "required"
+                | "optional"
+                | "defined"
+                | "hover"
+                | "active"
+                | "focus"
+                | "focus-within"
+                | "focus-visible"
+                | "target"
+        ),
+        CssSelectorPart::PseudoClassFunction(func) => match (func.name.as_str(), &func.argument) {
+            (
+                "is" | "where" | "matches" | "-webkit-any" | "-moz-any" | "not" | "has",
+                PseudoClassArgument::Selector(selector),
+            ) => selector_is_supported(selector),
+            ("nth-child" | "nth-last-child", PseudoClassArgument::Nth { of, .. }) => match of {
+                Some(of) => selector_is_supported(of),
+                None => true,
+            },
+            ("nth-of-type" | "nth-last-of-type", PseudoClassArgument::Nth { .. }) => true,
+            ("lang" | "dir", PseudoClassArgument::Ident(_)) => true,
+            _ => false,
+        },
+        CssSelectorPart::PseudoElement(name) => {
+            matches!(
+                name.as_str(),
+                "before" | "after" | "marker" | "first-line" | "first-letter"
+            )
+        }
+        _ => true,
+    })
+}
+
+/// Returns true when the given node matches the functional pseudo class
+fn match_pseudo_class_function<C: HasDocument>(
+    func: &PseudoClassFunction,
+    current_node: &C::Node,
+    doc: &C::Document,
+    scope: Option<NodeId>,
+) -> bool {
+    if !current_node.is_element_node() {
+        return false;
+    }
+
+    match (func.name.as_str(), &func.argument) {
+        ("is" | "where" | "matches" | "-webkit-any" | "-moz-any", PseudoClassArgument::Selector(selector)) => {
+            match_selector_for::<C>(doc, current_node.id(), selector, None, scope).0
+        }
+        ("not", PseudoClassArgument::Selector(selector)) => {
+            !match_selector_for::<C>(doc, current_node.id(), selector, None, scope).0
+        }
+        ("has", PseudoClassArgument::Selector(selector)) => selector
+            .parts
+            .iter()
+            .any(|parts| match_relative_selector::<C>(current_node, doc, parts, scope)),
+        ("nth-child" | "nth-last-child", PseudoClassArgument::Nth { a, b, of }) => {
+            // With "of S", only siblings matching S are counted, and the element itself must match S as well
+            if let Some(of) = of {
+                if !match_selector_for::<C>(doc, current_node.id(), of, None, scope).0 {
+                    return false;
+                }
+            }
+
+            let from_end = func.name == "nth-last-child";
+            let position = nth_position::<C>(current_node, doc, from_end, |n| match of {
+                Some(of) => match_selector_for::<C>(doc, n.id(), of, None, scope).0,
+                None => true,
+            });
+
+            position.is_some_and(|position| nth_matches(*a, *b, position))
+        }
+        ("nth-of-type" | "nth-last-of-type", PseudoClassArgument::Nth { a, b, .. }) => {
+            let from_end = func.name == "nth-last-of-type";
+            let position = nth_position::<C>(current_node, doc, from_end, |n| same_type::<C>(n, current_node));
+
+            position.is_some_and(|position| nth_matches(*a, *b, position))
+        }
+        ("lang", PseudoClassArgument::Ident(lang)) => {
+            let Some(node_lang) = language::<C>(current_node, doc) else {
+                return false;
+            };
+
+            let lang = lang.cow_to_ascii_lowercase();
+            let node_lang = node_lang.cow_to_ascii_lowercase();
+
+            node_lang == lang || node_lang.starts_with(&format!("{}-", lang))
+        }
+        ("dir", PseudoClassArgument::Ident(dir)) => {
+            let mut node = Some(current_node);
+            while let Some(n) = node {
+                if let Some(value) = n.get_element_data().and_then(|e| e.attribute("dir")) {
+                    if value.eq_ignore_ascii_case("ltr") || value.eq_ignore_ascii_case("rtl") {
+                        return value.eq_ignore_ascii_case(dir);
+                    }
+                }
+
+                node = n.parent_id().and_then(|id| doc.node_by_id(id));
+            }
+
+            // Documents are left-to-right by default
+            dir.eq_ignore_ascii_case("ltr")
+        }
+        _ => false,
+    }
+}
+
+/// Returns true when the node matches the relative selector (the argument of :has()). The selector is anchored at
+/// the given node, and starts with an optional combinator (descendant when omitted).
+fn match_relative_selector<C: HasDocument>(
+    anchor: &C::Node,
+    doc: &C::Document,
+    parts: &[CssSelectorPart],
+    scope: Option<NodeId>,
+) -> bool {
+    let (combinator, parts) = match parts.first() {
+        Some(CssSelectorPart::Combinator(combinator)) => (combinator.clone(), &parts[1..]),
+        _ => (Combinator::Descendant, parts),
+    };
+
+    if parts.is_empty() {
+        return false;
+    }
+
+    // The subject of the selector is in the subtree of the anchor, or in the subtrees of its following siblings
+    let mut subjects = Vec::new();
+    match combinator {
+        Combinator::Descendant | Combinator::Child => collect_descendants::<C>(anchor, doc, &mut subjects),
+        Combinator::NextSibling | Combinator::SubsequentSibling => {
+            for sibling_id in following_siblings::<C>(anchor, doc) {
+                subjects.push(sibling_id);
+                if let Some(sibling) = doc.node_by_id(sibling_id) {
+                    collect_descendants::<C>(sibling, doc, &mut subjects);
+                }
+            }
+        }
+        _ => return false,
+    }
+
+    subjects
+        .iter()
+        .any(|id| match_anchored_parts::<C>(doc, *id, parts, anchor.id(), &combinator, scope))
+}
+
+/// Matches the parts of a relative selector right-to-left, starting at the node. The leftmost compound selector must
+/// be related to the anchor by the combinator the relative selector starts with.
+fn match_anchored_parts<C: HasDocument>(
+    doc: &C::Document,
+    node_id: NodeId,
+    parts: &[CssSelectorPart],
+    anchor_id: NodeId,
+    anchor_combinator: &Combinator,
+    scope: Option<NodeId>,
+) -> bool {
+    let split = parts.iter().rposition(|part| {
+        matches!(
+            part,
+            CssSelectorPart::Combinator(
+                Combinator::Descendant | Combinator::Child | Combinator::NextSibling | Combinator::SubsequentSibling
+            )
+        )
+    });
+    let (rest, combinator, compound) = match split {
+        Some(pos) => match &parts[pos] {
+            CssSelectorPart::Combinator(combinator) => (&parts[..pos], combinator, &parts[pos + 1..]),
+            _ => return false,
+        },
+        None => (&parts[..0], anchor_combinator, parts),
+    };
+
+    if compound.is_empty() || !match_selector_parts::<C>(doc, node_id, compound, scope) {
+        return false;
+    }
+
+    let Some(node) = doc.node_by_id(node_id) else {
+        return false;
+    };
+
+    let related = match combinator {
+        Combinator::Descendant => {
+            let mut ancestors = Vec::new();
+            let mut parent_id = node.parent_id();
+            while let Some(id) = parent_id {
+                ancestors.push(id);
+                parent_id = doc.node_by_id(id).and_then(|parent| parent.parent_id());
+            }
+            ancestors
+        }
+        Combinator::Child => node.parent_id().into_iter().collect(),
+        Combinator::NextSibling => preceding_siblings::<C>(node, doc)
+            .into_iter()
+            .last()
+            .into_iter()
+            .collect(),
+        Combinator::SubsequentSibling => preceding_siblings::<C>(node, doc),
+        _ => return false,
+    };
+
+    if split.is_none() {
+        return related.contains(&anchor_id);
+    }
+
+    related
+        .iter()
+        .any(|id| match_anchored_parts::<C>(doc, *id, rest, anchor_id, anchor_combinator, scope))
+}
+
+/// Returns the element children of the given node
+fn element_children<C: HasDocument>(node: &C::Node, doc: &C::Document) -> Vec<NodeId> {
+    node.children()
+        .iter()
+        .filter(|id| doc.node_by_id(**id).is_some_and(|n| n.is_element_node()))
+        .copied()
+        .collect()
+}
+
+/// Collects all element descendants of the given node in tree order
+fn collect_descendants<C: HasDocument>(node: &C::Node, doc: &C::Document, descendants: &mut Vec<NodeId>) {
+    for child_id in node.children() {
+        let Some(child) = doc.node_by_id(*child_id) else {
+            continue;
+        };
+
+        if child.is_element_node() {
+            descendants.push(*child_id);
+            collect_descendants::<C>(child, doc, descendants);
+        }
+    }
+}
+
+/// Returns the element siblings that come after the given node
+fn following_siblings<C: HasDocument>(node: &C::Node, doc: &C::Document) -> Vec<NodeId> {
+    let Some(parent) = node.parent_id().and_then(|id| doc.node_by_id(id)) else {
+        return Vec::new();
+    };
+
+    let siblings = element_children::<C>(parent, doc);
+    match siblings.iter().position(|id| *id == node.id()) {
+        Some(pos) => siblings[pos + 1..].to_vec(),
+        None => Vec::new(),
+    }
+}
+
+/// Returns the element siblings that come before the given node
+fn preceding_siblings<C: HasDocument>(node: &C::Node, doc: &C::Document) -> Vec<NodeId> {
+    let Some(parent) = node.parent_id().and_then(|id| doc.node_by_id(id)) else {
+        return Vec::new();
+    };
+
+    let siblings = element_children::<C>(parent, doc);
+    match siblings.iter().position(|id| *id == node.id()) {
+        Some(pos) => siblings[..pos].to_vec(),
+        None => Vec::new(),
+    }
+}
+
+/// Returns the 1-based position of the node between its element siblings that pass the filter. The node itself is
+/// always counted. When `from_end` is set, the position is counted from the last sibling.
+fn nth_position<C: HasDocument>(
+    node: &C::Node,
+    doc: &C::Document,
+    from_end: bool,
+    filter: impl Fn(&C::Node) -> bool,
+) -> Option<i32> {
+    let Some(parent) = node.parent_id().and_then(|id| doc.node_by_id(id)) else {
+        return Some(1);
+    };
+
+    let mut siblings = parent
+        .children()
+        .iter()
+        .filter_map(|id| doc.node_by_id(*id))
+        .filter(|n| n.is_element_node() && (n.id() == node.id() || filter(n)))
+        .map(|n| n.id())
+        .collect::<Vec<_>>();
+
+    if from_end {
+        siblings.reverse();
+    }
+
+    let pos = siblings.iter().position(|id| *id == node.id())?;
+
+    Some(pos as i32 + 1)
+}
+
+/// Returns true when the position matches An+B for some non-negative integer n
+fn nth_matches(a: i32, b: i32, position: i32) -> bool {
+    if a == 0 {
+        return position == b;
+    }
+
+    let diff = position - b;
+    diff % a == 0 && diff / a >= 0
+}
+
+/// Returns true when both nodes are elements with the same name and namespace
+fn same_type<C: HasDocument>(node: &C::Node, other: &C::Node) -> bool {
+    match (node.get_element_data(), other.get_element_data()) {
+        (Some(a), Some(b)) => a.name() == b.name() && a.namespace() == b.namespace(),
+        _ => false,
+    }
+}
+
+/// Returns true for elements that can be enabled or disabled
+fn is_form_element(name: &str) -> bool {
+    matches!(
+        name,
+        "button" | "input" | "select" | "textarea" | "optgroup" | "option" | "fieldset"
+    )
+}
+
+/// Returns the language of the node, which is inherited from the closest ancestor with a lang attribute
+fn language<'a, C: HasDocument>(node: &'a C::Node, doc: &'a C::Document) -> Option<&'a str> {
+    let mut node = Some(node);
+    while let Some(n) = node {
+        if let Some(element) = n.get_element_data() {
+            if let Some(lang) = element.attribute("lang").or_else(|| element.attribute("xml:lang")) {
+                return Some(lang.as_str());
+            }
+        }
+
+        node = n.parent_id().and_then(|id| doc.node_by_id(id));
+    }
+
+    None
+}
+
 /// A declarationProperty defines a single value for a property (color: red;). It consists of the value,
 /// origin, importance, location and specificity of the declaration.
 #[derive(Debug, Clone)]
@@ -330,6 +795,8 @@ pub struct DeclarationProperty {
     pub location: String,
     /// The specificity of the selector that declared this property
     pub specificity: Specificity,
+    /// Position of the cascade layer of the declaration in the layer order, or `None` when it is unlayered
+    pub layer: Option<usize>,
 }
 
 impl DeclarationProperty {
@@ -359,6 +826,23 @@ impl DeclarationProperty {
             }
         }
     }
+
+    /// Compares the cascade layers of two declarations with the same priority. Unlayered declarations win over
+    /// layered ones and later layers win over earlier ones. For important declarations this order is reversed.
+    fn cmp_layer(&self, other: &Self) -> Ordering {
+        let ordering = match (self.layer, other.layer) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+            (Some(layer), Some(other)) => layer.cmp(&other),
+        };
+
+        if self.important {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
 }
 
 impl PartialEq<Self> for DeclarationProperty {
@@ -379,6 +863,7 @@ impl Ord for DeclarationProperty {
     fn cmp(&self, other: &Self) -> Ordering {
         self.priority()
             .cmp(&other.priority())
+            .then_with(|| self.cmp_layer(other))
             .then_with(|| self.specificity.cmp(&other.specificity))
     }
 }
@@ -518,6 +1003,7 @@ impl From<CssValue> for CssProperty {
             value,
             origin: CssOrigin::Author,
             specificity: Specificity::new(0, 0, 0),
+            layer: None,
         }];
 
         this.calculate_value();
@@ -534,6 +1020,7 @@ impl From<CssValue> for DeclarationProperty {
             value,
             origin: CssOrigin::Author,
             specificity: Specificity::new(0, 0, 0),
+            layer: None,
         }
     }
 }
@@ -612,6 +1099,28 @@ impl css3::CssProperty<Css3System> for CssProperty {
         }
     }
 
+    fn resolve_calc(&self, percentage_basis: Option<f32>, font_size: f32) -> Option<f32> {
+        if !is_math_function(&self.actual) {
+            return None;
+        }
+
+        // The root font size is not known here, so `rem` is resolved against the default font size like
+        // `unit_to_px` does
+        let ctx = CalcContext {
+            percentage_basis,
+            font_size: Some(font_size),
+            root_font_size: Some(16.0),
+            ..Default::default()
+        };
+
+        match resolve_calc(&self.actual, &ctx)? {
+            CssValue::Unit(value, unit) if unit == "px" => Some(value),
+            CssValue::Number(value) => Some(value),
+            CssValue::Zero => Some(0.0),
+            _ => None,
+        }
+    }
+
     fn is_none(&self) -> bool {
         matches!(self.actual, CssValue::None)
     }
@@ -716,6 +1225,7 @@ mod tests {
             important: false,
             location: "".into(),
             specificity: Specificity::new(1, 0, 0),
+            layer: None,
         });
 
         assert_eq!(
@@ -742,6 +1252,7 @@ mod tests {
             important: false,
             location: "".into(),
             specificity: Specificity::new(1, 0, 0),
+            layer: None,
         });
 
         assert_eq!(prop.compute_value(), &CssValue::String("red".into()));
@@ -759,6 +1270,7 @@ mod tests {
             important: false,
             location: "".into(),
             specificity: Specificity::new(1, 0, 0),
+            layer: None,
         };
         let b = DeclarationProperty {
             value: CssValue::String("blue".into()),
@@ -766,6 +1278,7 @@ mod tests {
             important: false,
             location: "".into(),
             specificity: Specificity::new(1, 0, 0),
+            layer: None,
         };
         let c = DeclarationProperty {
             value: CssValue::String("green".into()),
@@ -773,6 +1286,7 @@ mod tests {
             important: false,
             location: "".into(),
             specificity: Specificity::new(1, 0, 0),
+            layer: None,
         };
         let d = DeclarationProperty {
             value: CssValue::String("yellow".into()),
@@ -780,6 +1294,7 @@ mod tests {
             important: true,
             location: "".into(),
             specificity: Specificity::new(1, 0, 0),
+            layer: None,
         };
         let e = DeclarationProperty {
             value: CssValue::String("orange".into()),
@@ -787,6 +1302,7 @@ mod tests {
             important: true,
             location: "".into(),
             specificity: Specificity::new(1, 0, 0),
+            layer: None,
         };
         let f = DeclarationProperty {
             value: CssValue::String("purple".into()),
@@ -794,6 +1310,7 @@ mod tests {
             important: true,
             location: "".into(),
             specificity: Specificity::new(1, 0, 0),
+            layer: None,
         };
 
         assert_eq!(3, a.priority());
@@ -0,0 +1,269 @@
+use cow_utils::CowUtils;
+use gosub_interface::css3::{ColorScheme, MediaEnvironment};
+
+/// List of media queries as found in the prelude of a `@media` rule. The list matches when any of its queries
+/// matches. An empty list always matches.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct MediaQueryList {
+    pub queries: Vec<MediaQuery>,
+}
+
+impl MediaQueryList {
+    pub fn matches(&self, env: &MediaEnvironment) -> bool {
+        self.queries.is_empty() || self.queries.iter().any(|query| query.matches(env))
+    }
+}
+
+/// A single media query, like `not screen and (min-width: 600px)`
+#[derive(Debug, PartialEq, Clone)]
+pub struct MediaQuery {
+    /// The query has the `not` modifier
+    pub negated: bool,
+    /// Media type (screen, print, etc.). `None` means all media types
+    pub media_type: Option<String>,
+    pub condition: Option<MediaCondition>,
+}
+
+impl MediaQuery {
+    pub fn matches(&self, env: &MediaEnvironment) -> bool {
+        let type_matches = match &self.media_type {
+            None => true,
+            Some(media_type) => media_type == "all" || media_type.eq_ignore_ascii_case(&env.media_type),
+        };
+
+        let condition_matches = match &self.condition {
+            Some(condition) => condition.matches(env),
+            None => true,
+        };
+        let matches = type_matches && condition_matches;
+
+        matches != self.negated
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum MediaCondition {
+    Feature(MediaFeature),
+    Not(Box<MediaCondition>),
+    And(Vec<MediaCondition>),
+    Or(Vec<MediaCondition>),
+    /// Condition we don't understand. This never matches
+    Unknown,
+}
+
+impl MediaCondition {
+    pub fn matches(&self, env: &MediaEnvironment) -> bool {
+        match self {
+            MediaCondition::Feature(feature) => feature.matches(env),
+            MediaCondition::Not(condition) => !condition.matches(env),
+            MediaCondition::And(conditions) => conditions.iter().all(|c| c.matches(env)),
+            MediaCondition::Or(conditions) => conditions.iter().any(|c| c.matches(env)),
+            MediaCondition::Unknown => false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Comparison {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    /// Returns the comparison with the operands swapped (`a < b` is the same as `b > a`)
+    pub fn flip(self) -> Self {
+        match self {
+            Comparison::Eq => Comparison::Eq,
+            Comparison::Lt => Comparison::Gt,
+            Comparison::Le => Comparison::Ge,
+            Comparison::Gt => Comparison::Lt,
+            Comparison::Ge => Comparison::Le,
+        }
+    }
+}
+
+/// Value of a media feature. Lengths are converted to pixels, resolutions to dppx and ratios to numbers.
+#[derive(Debug, PartialEq, Clone)]
+pub enum MediaValue {
+    Number(f32),
+    Ident(String),
+}
+
+/// A media feature test, like `(min-width: 600px)` or `(color)`. Features with a `min-` or `max-` prefix are
+/// converted into a comparison.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MediaFeature {
+    pub name: String,
+    /// When there is no comparison, the feature is evaluated in a boolean context
+    pub comparison: Option<(Comparison, MediaValue)>,
+}
+
+impl MediaFeature {
+    pub fn matches(&self, env: &MediaEnvironment) -> bool {
+        let Some(actual) = feature_value(&self.name, env) else {
+            return false;
+        };
+
+        let Some((comparison, expected)) = &self.comparison else {
+            return match actual {
+                MediaValue::Number(num) => num != 0.0,
+                MediaValue::Ident(ident) => ident != "none" && ident != "no-preference",
+            };
+        };
+
+        match (actual, expected) {
+            (MediaValue::Number(actual), MediaValue::Number(expected)) => match comparison {
+                Comparison::Eq => (actual - expected).abs() < 0.001,
+                Comparison::Lt => actual < *expected,
+                Comparison::Le => actual <= *expected,
+                Comparison::Gt => actual > *expected,
+                Comparison::Ge => actual >= *expected,
+            },
+            (MediaValue::Ident(actual), MediaValue::Ident(expected)) => {
+                *comparison == Comparison::Eq && actual.eq_ignore_ascii_case(expected)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Returns the value of the media feature in the given environment, or `None` when the feature is unknown
+fn feature_value(name: &str, env: &MediaEnvironment) -> Option<MediaValue> {
+    let name = name.cow_to_ascii_lowercase();
+
+    let value = match name.as_ref() {
+        "width" => MediaValue::Number(env.width),
+        "height" => MediaValue::Number(env.height),
+        "aspect-ratio" if env.height > 0.0 => MediaValue::Number(env.width / env.height),
+        "orientation" if env.height >= env.width => MediaValue::Ident("portrait".into()),
+        "orientation" => MediaValue::Ident("landscape".into()),
+        "resolution" => MediaValue::Number(env.resolution),
+        "prefers-color-scheme" => MediaValue::Ident(
+            match env.color_scheme {
+                ColorScheme::Light => "light",
+                ColorScheme::Dark => "dark",
+            }
+            .into(),
+        ),
+        "color" => MediaValue::Number(8.0),
+        "color-index" | "monochrome" | "grid" => MediaValue::Number(0.0),
+        "hover" | "any-hover" => MediaValue::Ident("hover".into()),
+        "pointer" | "any-pointer" => MediaValue::Ident("fine".into()),
+        "prefers-reduced-motion" | "prefers-reduced-transparency" => MediaValue::Ident("no-preference".into()),
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+/// Converts a dimension into a media value. Returns `None` for unknown units.
+pub fn dimension_to_media_value(value: f32, unit: &str) -> Option<MediaValue> {
+    let factor = match unit.cow_to_ascii_lowercase().as_ref() {
+        "px" => 1.0,
+        "em" | "rem" => 16.0,
+        "in" => 96.0,
+        "cm" => 96.0 / 2.54,
+        "mm" => 96.0 / 25.4,
+        "q" => 96.0 / 101.6,
+        "pt" => 96.0 / 72.0,
+        "pc" => 16.0,
+        // resolutions are converted to dppx
+        "dppx" | "x" => 1.0,
+        "dpi" => 1.0 / 96.0,
+        "dpcm" => 2.54 / 96.0,
+        _ => return None,
+    };
+
+    Some(MediaValue::Number(value * factor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feature(name: &str, comparison: Comparison, value: MediaValue) -> MediaCondition {
+        MediaCondition::Feature(MediaFeature {
+            name: name.to_string(),
+            comparison: Some((comparison, value)),
+        })
+    }
+
+    #[test]
+    fn evaluate_queries() {
+        let env = MediaEnvironment::default().with_size(1024.0, 768.0);
+
+        let query = MediaQuery {
+            negated: false,
+            media_type: Some("screen".into()),
+            condition: Some(MediaCondition::And(vec![
+                feature("width", Comparison::Ge, MediaValue::Number(600.0)),
+                feature("orientation", Comparison::Eq, MediaValue::Ident("landscape".into())),
+            ])),
+        };
+        assert!(query.matches(&env));
+        assert!(!query.matches(&env.with_size(500.0, 768.0)));
+        assert!(!query.matches(&env.with_size(700.0, 768.0)));
+
+        let print = MediaQuery {
+            negated: false,
+            media_type: Some("print".into()),
+            condition: None,
+        };
+        assert!(!print.matches(&env));
+        assert!(MediaQuery { negated: true, ..print }.matches(&env));
+
+        let list = MediaQueryList {
+            queries: vec![
+                MediaQuery {
+                    negated: false,
+                    media_type: Some("print".into()),
+                    condition: None,
+                },
+                MediaQuery {
+                    negated: false,
+                    media_type: None,
+                    condition: Some(feature(
+                        "prefers-color-scheme",
+                        Comparison::Eq,
+                        MediaValue::Ident("dark".into()),
+                    )),
+                },
+            ],
+        };
+        assert!(!list.matches(&env));
+        assert!(list.matches(&MediaEnvironment {
+            color_scheme: ColorScheme::Dark,
+            ..env.clone()
+        }));
+        assert!(MediaQueryList::default().matches(&env));
+    }
+
+    #[test]
+    fn evaluate_features() {
+        let env = MediaEnvironment {
+            resolution: 2.0,
+            ..MediaEnvironment::default().with_size(800.0, 600.0)
+        };
+
+        assert!(feature("resolution", Comparison::Ge, MediaValue::Number(2.0)).matches(&env));
+        assert!(feature("aspect-ratio", Comparison::Gt, MediaValue::Number(1.0)).matches(&env));
+        assert!(!feature("unknown", Comparison::Eq, MediaValue::Number(1.0)).matches(&env));
+        assert!(MediaCondition::Feature(MediaFeature {
+            name: "color".into(),
+            comparison: None
+        })
+        .matches(&env));
+        assert!(!MediaCondition::Feature(MediaFeature {
+            name: "grid".into(),
+            comparison: None
+        })
+        .matches(&env));
+
+        assert_eq!(dimension_to_media_value(2.0, "em"), Some(MediaValue::Number(32.0)));
+        assert_eq!(dimension_to_media_value(192.0, "dpi"), Some(MediaValue::Number(2.0)));
+        assert_eq!(dimension_to_media_value(1.0, "foo"), None);
+    }
+}
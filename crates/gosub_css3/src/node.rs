@@ -439,6 +439,11 @@ impl Display for Node {
             }
             NodeType::AnPlusB { a, b } => format!("{}n+{}", a, b),
             NodeType::Calc { expr } => format!("calc({})", expr),
+            NodeType::Value { children } => children
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<String>>()
+                .join(" "),
             NodeType::Raw { value } => value.clone(),
 
             _ => {
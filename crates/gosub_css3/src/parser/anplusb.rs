@@ -135,9 +135,11 @@ impl Css3<'_> {
                 b.push_str(s.as_str());
             }
             _ => {
+                // -n-<digits>
                 self.expect_char(value, "-", 2)?;
                 self.check_integer(value, 3, false)?;
-                b.push_str("foobar");
+                self.consume_any()?;
+                b.push_str(&value[2..]);
             }
         }
 
@@ -169,9 +171,11 @@ impl Css3<'_> {
                 b.push_str(s.as_str());
             }
             _ => {
+                // n-<digits>
                 self.expect_char(value, "-", 1)?;
                 self.check_integer(value, 2, false)?;
-                b.push_str("foobar");
+                self.consume_any()?;
+                b.push_str(&value[1..]);
             }
         }
 
@@ -395,6 +399,22 @@ mod test {
                 b: "6".to_string()
             })
         );
+        test!(
+            parse_anplusb,
+            "n-3",
+            Box::new(NodeType::AnPlusB {
+                a: "1".to_string(),
+                b: "-3".to_string()
+            })
+        );
+        test!(
+            parse_anplusb,
+            "-n-3",
+            Box::new(NodeType::AnPlusB {
+                a: "-1".to_string(),
+                b: "-3".to_string()
+            })
+        );
         test!(
             parse_anplusb,
             "-n +6",
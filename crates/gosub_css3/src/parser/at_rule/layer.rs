@@ -1,4 +1,5 @@
 use crate::node::{Node, NodeType};
+use crate::tokenizer::TokenType;
 use crate::Css3;
 use gosub_shared::errors::CssResult;
 
@@ -10,9 +11,20 @@ impl Css3<'_> {
         todo!();
     }
 
+    /// Parses a (dotted) layer name like `framework.base` into a single ident
     fn parse_layer_query(&mut self) -> CssResult<Node> {
-        let _children: Vec<Node> = Vec::new();
-        todo!();
+        log::trace!("parse_layer_query");
+
+        let loc = self.tokenizer.current_location();
+
+        let mut name = self.consume_any_ident()?;
+        while self.tokenizer.lookahead(0).is_delim('.') {
+            self.consume_any()?;
+            name.push('.');
+            name.push_str(&self.consume_any_ident()?);
+        }
+
+        Ok(Node::new(NodeType::Ident { value: name }, loc))
     }
 
     pub fn parse_at_rule_layer_prelude(&mut self) -> CssResult<Node> {
@@ -25,6 +37,14 @@ impl Css3<'_> {
         let mut layers = vec![];
 
         while !self.tokenizer.eof() {
+            self.consume_whitespace_comments();
+
+            // Anonymous layers don't have a name
+            let t = self.tokenizer.lookahead(0);
+            if t.token_type == TokenType::LCurly || t.token_type == TokenType::Semicolon {
+                break;
+            }
+
             let layer = self.parse_layer_query()?;
             layers.push(layer);
 
@@ -40,3 +60,26 @@ impl Css3<'_> {
         Ok(Node::new(NodeType::LayerList { layers }, loc))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::walker::Walker;
+    use crate::{CssOrigin, ParserConfig};
+    use gosub_shared::byte_stream::{ByteStream, Encoding};
+
+    #[test]
+    fn test_parse_at_rule_layer_prelude() {
+        let mut stream = ByteStream::new(Encoding::UTF8, None);
+        stream.read_from_str("reset, framework.base ;", Some(Encoding::UTF8));
+        stream.close();
+
+        let mut parser = crate::Css3::new(&mut stream, ParserConfig::default(), CssOrigin::User, "");
+        let node = parser.parse_at_rule_layer_prelude().unwrap();
+
+        let w = Walker::new(&node);
+        assert_eq!(
+            w.walk_to_string(),
+            "[LayerList]\n  [Ident] reset\n  [Ident] framework.base\n"
+        );
+    }
+}
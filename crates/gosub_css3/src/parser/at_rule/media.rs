@@ -1,7 +1,8 @@
-use crate::node::{FeatureKind, Node, NodeType};
+use crate::node::{FeatureKind, Node, NodeType, Number};
 use crate::tokenizer::TokenType;
 use crate::Css3;
 use cow_utils::CowUtils;
+use gosub_shared::byte_stream::Location;
 use gosub_shared::errors::{CssError, CssResult};
 
 impl Css3<'_> {
@@ -13,7 +14,7 @@ impl Css3<'_> {
         let t = self.consume_any()?;
         match t.token_type {
             TokenType::Ident(ident) => Ok(Node::new(NodeType::Ident { value: ident }, loc)),
-            TokenType::Number(value) => Ok(Node::new(NodeType::Number { value }, loc)),
+            TokenType::Number(value) => self.parse_media_ratio(value, loc),
             TokenType::Dimension { value, unit } => Ok(Node::new(NodeType::Dimension { value, unit }, loc)),
             TokenType::Function(name) => {
                 let name = name.cow_to_lowercase();
@@ -35,6 +36,28 @@ impl Css3<'_> {
         }
     }
 
+    /// Parses an optional ratio (like `16/9`) after the given number. Ratios are stored as a single number.
+    fn parse_media_ratio(&mut self, value: Number, loc: Location) -> CssResult<Node> {
+        if !self.tokenizer.lookahead_sc(0).is_delim('/') {
+            return Ok(Node::new(NodeType::Number { value }, loc));
+        }
+
+        self.consume_whitespace_comments();
+        self.consume_delim('/')?;
+        self.consume_whitespace_comments();
+
+        let t = self.consume_any()?;
+        match t.token_type {
+            TokenType::Number(denominator) if denominator != 0.0 => Ok(Node::new(
+                NodeType::Number {
+                    value: value / denominator,
+                },
+                loc,
+            )),
+            _ => Err(CssError::with_location("Expected ratio denominator", t.location)),
+        }
+    }
+
     fn parse_media_read_comparison(&mut self) -> CssResult<Node> {
         self.consume_whitespace_comments();
 
@@ -46,12 +69,11 @@ impl Css3<'_> {
         }
 
         if delim == '>' || delim == '<' {
-            let eq_sign = self.consume_any_delim()?;
-            if eq_sign == '=' {
+            if self.tokenizer.lookahead(0).is_delim('=') {
+                self.consume_delim('=')?;
                 return Ok(Node::new(NodeType::Operator(format!("{}=", delim)), loc));
             }
 
-            self.tokenizer.reconsume();
             return Ok(Node::new(NodeType::Operator(format!("{}", delim)), loc));
         }
 
@@ -106,7 +128,7 @@ impl Css3<'_> {
 
             let t = self.consume_any()?;
             value = match t.token_type {
-                TokenType::Number(value) => Some(Node::new(NodeType::Number { value }, t.location)),
+                TokenType::Number(value) => Some(self.parse_media_ratio(value, t.location)?),
                 TokenType::Dimension { value, unit } => {
                     Some(Node::new(NodeType::Dimension { value, unit }, t.location))
                 }
@@ -156,13 +178,14 @@ impl Css3<'_> {
         let mut right_comparison = None;
         let mut right = None;
 
-        if self.tokenizer.lookahead_sc(0).is_delim('(') {
+        let t = self.tokenizer.lookahead_sc(0);
+        if t.is_delim('<') || t.is_delim('>') || t.is_delim('=') {
             right_comparison = Some(self.parse_media_read_comparison()?);
             right = Some(self.parse_media_read_term()?);
         }
 
         self.consume_whitespace_comments();
-        self.consume_delim(')')?;
+        self.consume(TokenType::RParen)?;
 
         Ok(Node::new(
             NodeType::Range {
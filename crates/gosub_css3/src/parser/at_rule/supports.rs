@@ -1,17 +1,147 @@
 use crate::node::{Node, NodeType};
+use crate::tokenizer::TokenType;
 use crate::Css3;
-use gosub_shared::errors::CssResult;
+use gosub_shared::byte_stream::Location;
+use gosub_shared::errors::{CssError, CssResult};
 
 impl Css3<'_> {
     pub fn parse_at_rule_supports_prelude(&mut self) -> CssResult<Node> {
         log::trace!("parse_at_rule_supports_prelude");
 
+        self.parse_supports_condition()
+    }
+
+    /// Parses a supports condition. This is either `not <term>`, or one or more terms joined by `and` or `or`. The
+    /// keywords are added as idents to the condition list.
+    fn parse_supports_condition(&mut self) -> CssResult<Node> {
+        log::trace!("parse_supports_condition");
+
         let loc = self.tokenizer.current_location();
 
-        // @todo: parse supports condition
-        let value = self.consume_raw_condition()?;
+        let mut list = Vec::new();
+
+        self.consume_whitespace_comments();
+
+        let t = self.tokenizer.lookahead(0);
+        if let TokenType::Ident(ident) = &t.token_type {
+            if ident.eq_ignore_ascii_case("not") {
+                self.consume_any()?;
+                self.consume_whitespace_comments();
+
+                list.push(Node::new(NodeType::Ident { value: "not".into() }, t.location));
+                list.push(self.parse_supports_term()?);
+
+                return Ok(Node::new(NodeType::Condition { list }, loc));
+            }
+        }
+
+        list.push(self.parse_supports_term()?);
+
+        loop {
+            self.consume_whitespace_comments();
+
+            let t = self.tokenizer.lookahead(0);
+            let TokenType::Ident(ident) = &t.token_type else {
+                break;
+            };
+
+            let keyword = if ident.eq_ignore_ascii_case("and") {
+                "and"
+            } else if ident.eq_ignore_ascii_case("or") {
+                "or"
+            } else {
+                break;
+            };
+
+            self.consume_any()?;
+            self.consume_whitespace_comments();
+
+            list.push(Node::new(NodeType::Ident { value: keyword.into() }, t.location));
+            list.push(self.parse_supports_term()?);
+        }
+
+        Ok(Node::new(NodeType::Condition { list }, loc))
+    }
+
+    /// Parses a single term of a supports condition: a declaration between parenthesis, a nested condition, or
+    /// a `selector()` function. Anything else between parenthesis is returned as a raw node, which never matches.
+    fn parse_supports_term(&mut self) -> CssResult<Node> {
+        log::trace!("parse_supports_term");
+
+        let t = self.consume_any()?;
+        match t.token_type {
+            TokenType::LParen => {
+                self.consume_whitespace_comments();
+
+                let next = self.tokenizer.lookahead(0);
+                let is_declaration = next.is_ident() && self.tokenizer.lookahead_sc(1).token_type == TokenType::Colon;
+                let is_condition = next.token_type == TokenType::LParen
+                    || matches!(&next.token_type, TokenType::Ident(ident) if ident.eq_ignore_ascii_case("not"))
+                    || matches!(&next.token_type, TokenType::Function(name) if name.eq_ignore_ascii_case("selector"));
 
-        Ok(Node::new(NodeType::Raw { value }, loc))
+                let term = if is_declaration {
+                    self.parse_declaration_internal()
+                        .map(|declaration| Node::new(NodeType::SupportsDeclaration { term: declaration }, t.location))
+                } else if is_condition {
+                    self.parse_supports_condition()
+                } else {
+                    Err(CssError::with_location("Unknown supports term", next.location))
+                };
+
+                self.consume_whitespace_comments();
+                match term {
+                    Ok(term) if self.tokenizer.lookahead(0).token_type == TokenType::RParen => {
+                        self.consume_any()?;
+                        Ok(term)
+                    }
+                    _ => self.parse_supports_general_enclosed(t.location),
+                }
+            }
+            TokenType::Function(name) if name.eq_ignore_ascii_case("selector") => {
+                self.consume_whitespace_comments();
+
+                let selector = self.parse_selector_list();
+
+                self.consume_whitespace_comments();
+                match selector {
+                    Ok(selector) if self.tokenizer.lookahead(0).token_type == TokenType::RParen => {
+                        self.consume_any()?;
+                        Ok(Node::new(
+                            NodeType::Function {
+                                name: "selector".into(),
+                                arguments: vec![selector],
+                            },
+                            t.location,
+                        ))
+                    }
+                    _ => self.parse_supports_general_enclosed(t.location),
+                }
+            }
+            TokenType::Function(_) => self.parse_supports_general_enclosed(t.location),
+            _ => Err(CssError::with_location(
+                format!("Expected supports condition, got {:?}", t).as_str(),
+                t.location,
+            )),
+        }
+    }
+
+    /// Skips everything up to and including the closing parenthesis of the current term, and returns it as a raw
+    /// node. These are valid syntax, but unknown conditions (like `font-tech()`) that evaluate to false.
+    fn parse_supports_general_enclosed(&mut self, loc: Location) -> CssResult<Node> {
+        log::trace!("parse_supports_general_enclosed");
+
+        let mut depth = 1;
+        while depth > 0 {
+            let t = self.consume_any()?;
+            match t.token_type {
+                TokenType::LParen | TokenType::Function(_) => depth += 1,
+                TokenType::RParen => depth -= 1,
+                TokenType::Eof => return Err(CssError::with_location("Unexpected end of supports condition", loc)),
+                _ => {}
+            }
+        }
+
+        Ok(Node::new(NodeType::Raw { value: String::new() }, loc))
     }
 }
 
@@ -21,16 +151,38 @@ mod tests {
     use crate::{CssOrigin, ParserConfig};
     use gosub_shared::byte_stream::{ByteStream, Encoding};
 
-    #[test]
-    fn test_parse_at_rule_supports_prelude() {
+    fn parse(input: &str) -> String {
         let mut stream = ByteStream::new(Encoding::UTF8, None);
-        stream.read_from_str("(display: flex)", Some(Encoding::UTF8));
+        stream.read_from_str(input, Some(Encoding::UTF8));
         stream.close();
 
         let mut parser = crate::Css3::new(&mut stream, ParserConfig::default(), CssOrigin::User, "");
         let node = parser.parse_at_rule_supports_prelude().unwrap();
 
-        let w = Walker::new(&node);
-        assert_eq!(w.walk_to_string(), "[Raw] (display: flex)\n")
+        Walker::new(&node).walk_to_string()
+    }
+
+    #[test]
+    fn test_parse_at_rule_supports_prelude() {
+        assert_eq!(
+            parse("(display: flex)"),
+            "[Condition (1)]\n  [SupportsDeclaration]\n    [Declaration] property: display important: false\n      [Ident] flex\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_supports_conditions() {
+        assert_eq!(
+            parse("not (display: grid)"),
+            "[Condition (2)]\n  [Ident] not\n  [SupportsDeclaration]\n    [Declaration] property: display important: false\n      [Ident] grid\n"
+        );
+
+        let output = parse("((display: flex) or (foo bar)) and selector(a > b)");
+        assert!(output.starts_with("[Condition (3)]\n  [Condition (3)]\n"));
+        assert!(output.contains("[Raw] \n"));
+        assert!(output.contains("[Ident] and\n"));
+        assert!(output.contains("[Function] selector\n"));
+
+        assert!(parse("font-tech(color-COLRv1)").contains("[Raw]"));
     }
 }
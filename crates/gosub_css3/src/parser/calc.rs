@@ -1,9 +1,11 @@
 use crate::node::{Node, NodeType};
 use crate::tokenizer::TokenType;
 use crate::Css3;
-use gosub_shared::errors::CssResult;
+use cow_utils::CowUtils;
+use gosub_shared::errors::{CssError, CssResult};
 
 impl Css3<'_> {
+    /// Parses the arguments of a `calc()` function. The function token itself has already been consumed.
     pub fn parse_calc(&mut self) -> CssResult<Node> {
         log::trace!("parse_calc");
 
@@ -14,51 +16,149 @@ impl Css3<'_> {
         Ok(Node::new(NodeType::Calc { expr }, loc))
     }
 
+    /// Parses the arguments of a `min()`, `max()` or `clamp()` function. These are comma separated calc sums. The
+    /// function token itself has already been consumed.
+    pub fn parse_math_function(&mut self, name: String) -> CssResult<Node> {
+        log::trace!("parse_math_function");
+
+        let loc = self.tokenizer.current_location();
+
+        let expr = self.parse_calc_expr()?;
+        let NodeType::Value { children: arguments } = *expr.node_type else {
+            unreachable!()
+        };
+
+        Ok(Node::new(NodeType::Function { name, arguments }, loc))
+    }
+
+    /// Parses a calc expression up to and including the closing parenthesis. Parenthesized sub expressions are
+    /// returned as nested `Calc` nodes, as `(a + b)` is the same as `calc(a + b)`.
     fn parse_calc_expr(&mut self) -> CssResult<Node> {
         log::trace!("parse_calc_expr");
 
         let loc = self.tokenizer.current_location();
 
-        let start = self.tokenizer.tell();
+        let mut children = Vec::new();
 
         loop {
             let t = self.consume_any()?;
-            match t.token_type {
-                TokenType::Eof => break,
-                TokenType::Function(_) => {
-                    self.parse_calc_expr()?;
-                }
-                TokenType::LParen => {
-                    self.parse_calc_expr()?;
+            let node = match t.token_type {
+                TokenType::Eof | TokenType::RParen => break,
+                TokenType::Whitespace(_) | TokenType::Comment(_) => continue,
+                TokenType::Number(value) => Node::new(NodeType::Number { value }, t.location),
+                TokenType::Percentage(value) => Node::new(NodeType::Percentage { value }, t.location),
+                TokenType::Dimension { value, unit } => Node::new(NodeType::Dimension { value, unit }, t.location),
+                TokenType::Ident(value) => Node::new(NodeType::Ident { value }, t.location),
+                TokenType::Comma => Node::new(NodeType::Comma, t.location),
+                TokenType::Delim(c @ ('+' | '-' | '*' | '/')) => {
+                    Node::new(NodeType::Operator(c.to_string()), t.location)
                 }
-                TokenType::RParen => break,
+                TokenType::LParen => self.parse_calc()?,
+                TokenType::Function(name) => match name.cow_to_ascii_lowercase().as_ref() {
+                    "calc" => self.parse_calc()?,
+                    func @ ("min" | "max" | "clamp") => self.parse_math_function(func.to_string())?,
+                    _ => {
+                        self.tokenizer.reconsume();
+                        self.parse_function()?
+                    }
+                },
                 _ => {
-                    // ignore
+                    return Err(CssError::with_location(
+                        format!("Unexpected token in calc expression: {:?}", t).as_str(),
+                        t.location,
+                    ))
                 }
-            }
-        }
-
-        let end = self.tokenizer.tell();
+            };
 
-        let expr = self.tokenizer.slice(start, end);
+            children.push(node);
+        }
 
-        Ok(Node::new(NodeType::Raw { value: expr }, loc))
+        Ok(Node::new(NodeType::Value { children }, loc))
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::stylesheet::CssValue;
+    use crate::Css3;
+
+    fn parse(value: &str) -> CssValue {
+        let sheet = Css3::parse_str(
+            &format!("a {{ width: {value}; }}"),
+            Default::default(),
+            gosub_interface::css3::CssOrigin::Author,
+            "test.css",
+        )
+        .unwrap();
+
+        sheet.rules[0].declarations[0].value.clone()
+    }
+
+    fn op(op: &str) -> CssValue {
+        CssValue::String(op.into())
+    }
+
     #[test]
     fn test_parse_calc() {
-        // test!(parse_calc, "calc(1px + 2px)", Box::new(NodeType::AnPlusB { a: "1".to_string(), b: "2".to_string() } ));
-        // test!(parse_calc, "calc(100px + (200px - 100px) * ((100vh - 500px) / (800 - 500)))", Box::new(NodeType::AnPlusB { a: "1".to_string(), b: "2".to_string() } ));
-        // test!(parse_calc, "calc(12px + (20 - 12) * ((100vw - 300px) / (700 - 300))", Box::new(NodeType::AnPlusB { a: "1".to_string(), b: "2".to_string() } ));
-        // test!(parse_calc, "calc(50px + 5 * (100vw - 300px) / (1100 - 300)), 1fr)", Box::new(NodeType::AnPlusB { a: "1".to_string(), b: "2".to_string() } ));
-        // test!(parse_calc, "calc(10px + 20 * (100vw - 300px) / (1100 - 300))", Box::new(NodeType::AnPlusB { a: "1".to_string(), b: "2".to_string() } ));
-        // test!(parse_calc, "calc(20px + (50px - 20px) * ((100vw - 600px) / (1000 - 600)))", Box::new(NodeType::AnPlusB { a: "1".to_string(), b: "2".to_string() } ));
-        // test!(parse_calc, "calc(100px + (200px - 100px) * ((100vh - 500px) / (800 - 500))", Box::new(NodeType::AnPlusB { a: "1".to_string(), b: "2".to_string() } ));
-        // test!(parse_calc, "calc(40% + calc(82vw / 53em))", Box::new(NodeType::AnPlusB { a: "1".to_string(), b: "2".to_string() } ));
-        // test!(parse_calc, "calc(52% / 48px)", Box::new(NodeType::AnPlusB { a: "1".to_string(), b: "2".to_string() } ));
-        // test!(parse_calc, "calc((100% - var(--maxContainerWidth)) / 2)", Box::new(NodeType::AnPlusB { a: "1".to_string(), b: "2".to_string() } ));
+        assert_eq!(
+            parse("calc(1px + 2px)"),
+            CssValue::Function(
+                "calc".into(),
+                vec![
+                    CssValue::Unit(1.0, "px".into()),
+                    op("+"),
+                    CssValue::Unit(2.0, "px".into())
+                ]
+            )
+        );
+
+        assert_eq!(
+            parse("calc((100% - 2em) / 2)"),
+            CssValue::Function(
+                "calc".into(),
+                vec![
+                    CssValue::Function(
+                        "calc".into(),
+                        vec![CssValue::Percentage(100.0), op("-"), CssValue::Unit(2.0, "em".into())]
+                    ),
+                    op("/"),
+                    CssValue::Number(2.0),
+                ]
+            )
+        );
+
+        assert_eq!(
+            parse("min(100%, calc(10px * 3))"),
+            CssValue::Function(
+                "min".into(),
+                vec![
+                    CssValue::Percentage(100.0),
+                    CssValue::Comma,
+                    CssValue::Function(
+                        "calc".into(),
+                        vec![CssValue::Unit(10.0, "px".into()), op("*"), CssValue::Number(3.0)]
+                    ),
+                ]
+            )
+        );
+
+        assert_eq!(
+            parse("calc(var(--gap, 4px) * 2)"),
+            CssValue::Function(
+                "calc".into(),
+                vec![
+                    CssValue::Function(
+                        "var".into(),
+                        vec![
+                            CssValue::String("--gap".into()),
+                            CssValue::Comma,
+                            CssValue::Unit(4.0, "px".into())
+                        ]
+                    ),
+                    op("*"),
+                    CssValue::Number(2.0),
+                ]
+            )
+        );
     }
 }
@@ -52,7 +52,7 @@ impl Css3<'_> {
         Ok(None)
     }
 
-    fn parse_declaration_internal(&mut self) -> CssResult<Node> {
+    pub(crate) fn parse_declaration_internal(&mut self) -> CssResult<Node> {
         let loc = self.tokenizer.current_location();
 
         let mut important = false;
@@ -77,6 +77,7 @@ impl Css3<'_> {
             TokenType::Function(name) => {
                 let node = match name.cow_to_ascii_lowercase().as_ref() {
                     "calc" => self.parse_calc()?,
+                    func @ ("min" | "max" | "clamp") => self.parse_math_function(func.to_string())?,
                     "url" => {
                         self.tokenizer.reconsume();
                         self.parse_url()?
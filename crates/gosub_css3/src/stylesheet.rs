@@ -1,13 +1,15 @@
 use core::fmt::Debug;
 use core::slice;
-use gosub_interface::css3::CssOrigin;
+use gosub_interface::css3::{CssOrigin, MediaEnvironment};
 use gosub_shared::byte_stream::Location;
 use gosub_shared::errors::CssError;
 use gosub_shared::errors::CssResult;
 use std::cmp::Ordering;
 use std::fmt::Display;
+use std::ops::Add;
 
 use crate::colors::RgbColor;
+use crate::media::MediaQueryList;
 
 /// Severity of a CSS error
 #[derive(Debug, PartialEq)]
@@ -106,6 +108,8 @@ pub struct CssStylesheet {
     pub url: String,
     /// Any issues during parsing of the stylesheet
     pub parse_log: Vec<CssLog>,
+    /// Names of the cascade layers declared in this stylesheet, in order of their first declaration
+    pub layers: Vec<String>,
 }
 
 impl gosub_interface::css3::CssStylesheet for CssStylesheet {
@@ -116,6 +120,22 @@ impl gosub_interface::css3::CssStylesheet for CssStylesheet {
     fn url(&self) -> &str {
         &self.url
     }
+
+    fn media_changed(&self, from: &MediaEnvironment, to: &MediaEnvironment) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| !rule.media.is_empty() && rule.matches_media(from) != rule.matches_media(to))
+    }
+
+    fn has_nonlocal_dependencies(&self) -> bool {
+        self.rules.iter().any(|rule| {
+            rule.selectors.iter().any(|selector| selector.has_relative())
+                || rule
+                    .declarations
+                    .iter()
+                    .any(|declaration| declaration.changes_counters())
+        })
+    }
 }
 
 /// A CSS rule, which contains a list of selectors and a list of declarations
@@ -125,9 +145,19 @@ pub struct CssRule {
     pub selectors: Vec<CssSelector>,
     /// Actual declarations that will be applied if the selectors match
     pub declarations: Vec<CssDeclaration>,
+    /// Media query lists of the (nested) `@media` rules this rule is found in. All lists must match before the rule
+    /// applies.
+    pub media: Vec<MediaQueryList>,
+    /// Full (dotted) name of the cascade layer this rule is found in, or `None` when the rule is unlayered
+    pub layer: Option<String>,
 }
 
 impl CssRule {
+    /// Returns true when the rule applies in the given media environment
+    pub fn matches_media(&self, env: &MediaEnvironment) -> bool {
+        self.media.iter().all(|list| list.matches(env))
+    }
+
     pub fn selectors(&self) -> &Vec<CssSelector> {
         &self.selectors
     }
@@ -149,6 +179,29 @@ pub struct CssDeclaration {
     pub important: bool,
 }
 
+impl CssDeclaration {
+    /// Returns true when the declaration resets, increments or sets a counter. The `list-item` counter is left out,
+    /// as every list resets it for its own items.
+    pub fn changes_counters(&self) -> bool {
+        if !matches!(
+            self.property.as_str(),
+            "counter-reset" | "counter-increment" | "counter-set"
+        ) {
+            return false;
+        }
+
+        let is_counter = |value: &CssValue| match value {
+            CssValue::String(name) => name != "none" && name != "list-item",
+            CssValue::Function(..) => true,
+            _ => false,
+        };
+        match &self.value {
+            CssValue::List(values) => values.iter().any(is_counter),
+            value => is_counter(value),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct CssSelector {
     // List of parts that make up this selector
@@ -163,6 +216,31 @@ impl CssSelector {
             .map(|part| Specificity::from(part.as_slice()))
             .collect()
     }
+
+    /// Returns the specificity of the most specific selector in this selector list
+    pub fn max_specificity(&self) -> Specificity {
+        self.specificity()
+            .into_iter()
+            .max()
+            .unwrap_or(Specificity::new(0, 0, 0))
+    }
+
+    /// Returns true when the selector uses `:has()`, which matches elements by their descendants or following
+    /// siblings
+    pub fn has_relative(&self) -> bool {
+        self.parts.iter().flatten().any(|part| {
+            let CssSelectorPart::PseudoClassFunction(func) = part else {
+                return false;
+            };
+
+            match &func.argument {
+                _ if func.name == "has" => true,
+                PseudoClassArgument::Selector(selector) => selector.has_relative(),
+                PseudoClassArgument::Nth { of: Some(selector), .. } => selector.has_relative(),
+                _ => false,
+            }
+        })
+    }
 }
 
 /// Represents a CSS selector part, which has a type and value (e.g. type=Class, class="my-class")
@@ -174,11 +252,47 @@ pub enum CssSelectorPart {
     Class(String),
     Id(String),
     PseudoClass(String),
+    PseudoClassFunction(Box<PseudoClassFunction>),
     PseudoElement(String),
     Combinator(Combinator),
     Type(String),
 }
 
+/// Functional pseudo class with its argument (e.g. :not(.foo), :nth-child(2n+1))
+#[derive(PartialEq, Clone, Debug)]
+pub struct PseudoClassFunction {
+    pub name: String,
+    pub argument: PseudoClassArgument,
+}
+
+/// Argument of a functional pseudo class
+#[derive(PartialEq, Clone, Debug)]
+pub enum PseudoClassArgument {
+    /// Selector list (:is(), :where(), :not(), :has())
+    Selector(CssSelector),
+    /// An+B with an optional "of S" selector list (:nth-child(2n+1 of .foo))
+    Nth { a: i32, b: i32, of: Option<CssSelector> },
+    /// Single identifier (:lang(en), :dir(rtl))
+    Ident(String),
+}
+
+impl PseudoClassFunction {
+    /// Returns the specificity this pseudo class adds to the selector it is part of
+    fn specificity(&self) -> Specificity {
+        match (self.name.as_str(), &self.argument) {
+            // :where() never adds any specificity
+            ("where", _) => Specificity::new(0, 0, 0),
+            // :is(), :not() and :has() take the specificity of the most specific selector in their argument
+            (_, PseudoClassArgument::Selector(selector)) => selector.max_specificity(),
+            // :nth-child(An+B of S) counts as a pseudo class plus the most specific selector in S
+            (_, PseudoClassArgument::Nth { of: Some(selector), .. }) => {
+                Specificity::new(0, 1, 0) + selector.max_specificity()
+            }
+            _ => Specificity::new(0, 1, 0),
+        }
+    }
+}
+
 #[derive(PartialEq, Clone, Default, Debug)]
 pub struct AttributeSelector {
     pub name: String,
@@ -232,6 +346,14 @@ impl Debug for CssSelectorPart {
             CssSelectorPart::PseudoClass(name) => {
                 write!(f, ":{}", name)
             }
+            CssSelectorPart::PseudoClassFunction(func) => match &func.argument {
+                PseudoClassArgument::Selector(selector) => write!(f, ":{}({:?})", func.name, selector.parts),
+                PseudoClassArgument::Nth { a, b, of: None } => write!(f, ":{}({}n+{})", func.name, a, b),
+                PseudoClassArgument::Nth { a, b, of: Some(of) } => {
+                    write!(f, ":{}({}n+{} of {:?})", func.name, a, b, of.parts)
+                }
+                PseudoClassArgument::Ident(ident) => write!(f, ":{}({})", func.name, ident),
+            },
             CssSelectorPart::PseudoElement(name) => {
                 write!(f, "::{}", name)
             }
@@ -298,24 +420,33 @@ impl Specificity {
 
 impl From<&[CssSelectorPart]> for Specificity {
     fn from(parts: &[CssSelectorPart]) -> Self {
-        let mut id_count = 0;
-        let mut class_count = 0;
-        let mut element_count = 0;
+        let mut specificity = Specificity::new(0, 0, 0);
         for part in parts {
             match part {
                 CssSelectorPart::Id(_) => {
-                    id_count += 1;
+                    specificity.0 += 1;
                 }
-                CssSelectorPart::Class(_) => {
-                    class_count += 1;
+                CssSelectorPart::Class(_) | CssSelectorPart::Attribute(_) | CssSelectorPart::PseudoClass(_) => {
+                    specificity.1 += 1;
                 }
-                CssSelectorPart::Type(_) => {
-                    element_count += 1;
+                CssSelectorPart::PseudoClassFunction(func) => {
+                    specificity = specificity + func.specificity();
+                }
+                CssSelectorPart::Type(_) | CssSelectorPart::PseudoElement(_) => {
+                    specificity.2 += 1;
                 }
                 _ => {}
             }
         }
-        Specificity::new(id_count, class_count, element_count)
+        specificity
+    }
+}
+
+impl Add for Specificity {
+    type Output = Specificity;
+
+    fn add(self, other: Self) -> Self::Output {
+        Specificity::new(self.0 + other.0, self.1 + other.1, self.2 + other.2)
     }
 }
 
@@ -377,6 +508,16 @@ impl Display for CssValue {
             CssValue::Percentage(p) => write!(f, "{}%", p),
             CssValue::String(s) => write!(f, "{}", s),
             CssValue::Unit(val, unit) => write!(f, "{}{}", val, unit),
+            CssValue::Function(name, args) if name == "calc" => {
+                write!(f, "calc(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
             CssValue::Function(name, args) => {
                 write!(f, "{}(", name)?;
                 for (i, arg) in args.iter().enumerate() {
@@ -483,10 +624,19 @@ impl CssValue {
                 Ok(CssValue::String(value))
             }
             crate::node::NodeType::Operator(_) => Ok(CssValue::None),
-            crate::node::NodeType::Calc { .. } => Ok(CssValue::Function("calc".to_string(), vec![])),
+            crate::node::NodeType::Calc { expr } => match *expr.node_type {
+                crate::node::NodeType::Value { children } => Ok(CssValue::Function(
+                    "calc".to_string(),
+                    Self::parse_calc_nodes(&children)?,
+                )),
+                _ => Ok(CssValue::Function("calc".to_string(), vec![])),
+            },
             crate::node::NodeType::Url { url } => {
                 Ok(CssValue::Function("url".to_string(), vec![CssValue::String(url)]))
             }
+            crate::node::NodeType::Function { name, arguments } if matches!(name.as_str(), "min" | "max" | "clamp") => {
+                Ok(CssValue::Function(name, Self::parse_calc_nodes(&arguments)?))
+            }
             crate::node::NodeType::Function { name, arguments } => {
                 let mut list = vec![];
                 for node in arguments.iter() {
@@ -506,6 +656,18 @@ impl CssValue {
         }
     }
 
+    /// Converts the nodes of a math function to CSS values. Operators are kept as strings, so the expression can
+    /// be evaluated later on.
+    fn parse_calc_nodes(nodes: &[crate::node::Node]) -> CssResult<Vec<CssValue>> {
+        nodes
+            .iter()
+            .map(|node| match &*node.node_type {
+                crate::node::NodeType::Operator(op) => Ok(CssValue::String(op.clone())),
+                _ => CssValue::parse_ast_node(node),
+            })
+            .collect()
+    }
+
     /// Parses a string into a CSS value or list of css values
     pub fn parse_str(value: &str) -> CssResult<CssValue> {
         match value {
@@ -678,6 +840,8 @@ mod test {
                 value: CssValue::String("red".to_string()),
                 important: false,
             }],
+            media: vec![],
+            layer: None,
         };
 
         assert_eq!(rule.selectors().len(), 1);
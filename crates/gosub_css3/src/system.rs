@@ -1,13 +1,17 @@
 use crate::functions::attr::resolve_attr;
-use crate::functions::calc::resolve_calc;
-use crate::functions::var::resolve_var;
+use crate::functions::calc::{is_math_function, resolve_calc, CalcContext};
+use crate::functions::var::{contains_var, substitute_vars, VariableEnvironment};
+use crate::layer::LayerOrder;
 use crate::matcher::property_definitions::get_css_definitions;
-use crate::matcher::shorthands::FixList;
-use crate::matcher::styling::{match_selector, CssProperties, CssProperty, DeclarationProperty};
+use crate::matcher::shorthands::{FixList, FixListInfo};
+use crate::matcher::styling::{
+    match_pseudo_element_selector, match_selector, CssProperties, CssProperty, DeclarationProperty,
+};
 use crate::stylesheet::{CssDeclaration, CssValue, Specificity};
 use crate::{load_default_useragent_stylesheet, Css3};
 use gosub_interface::config::{HasDocument, HasRenderTree};
-use gosub_interface::css3::{CssOrigin, CssPropertyMap, CssSystem};
+use gosub_interface::css3::{CssOrigin, CssPropertyMap, CssSystem, MediaEnvironment};
+use gosub_interface::document::Document;
 
 use gosub_interface::node::{ElementDataType, Node, TextDataType};
 use gosub_interface::render_tree::{RenderTree, RenderTreeNode};
@@ -15,6 +19,9 @@ use gosub_shared::config::ParserConfig;
 use gosub_shared::errors::CssResult;
 use gosub_shared::node::NodeId;
 use log::warn;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
 use std::slice;
 
 #[derive(Debug, Clone)]
@@ -38,20 +45,122 @@ impl CssSystem for Css3System {
         doc: &C::Document,
         id: NodeId,
     ) -> Option<Self::PropertyMap> {
-        let mut css_map_entry = CssProperties::new();
-
         if node_is_unrenderable::<C>(node) {
             return None;
         }
 
+        Some(Self::cascade::<C>(node, sheets, doc, id, None))
+    }
+
+    fn pseudo_element_properties_from_node<C: HasDocument<CssSystem = Self>>(
+        node: &C::Node,
+        sheets: &[Self::Stylesheet],
+        doc: &C::Document,
+        id: NodeId,
+        pseudo_element: &str,
+    ) -> Option<Self::PropertyMap> {
+        if !node.is_element_node() || node_is_unrenderable::<C>(node) {
+            return None;
+        }
+
+        Some(Self::cascade::<C>(node, sheets, doc, id, Some(pseudo_element)))
+    }
+
+    fn inheritance<C: HasRenderTree<CssSystem = Self>>(tree: &mut C::RenderTree, media: &MediaEnvironment) {
+        Self::resolve_inheritance::<C>(
+            tree,
+            tree.root(),
+            &Vec::new(),
+            &Rc::new(VariableEnvironment::default()),
+            &CalcContext::for_media(media),
+        );
+    }
+
+    fn inheritance_from<C: HasRenderTree<CssSystem = Self>>(
+        tree: &mut C::RenderTree,
+        node_id: <C::RenderTree as RenderTree<C>>::NodeId,
+        media: &MediaEnvironment,
+    ) {
+        let mut ancestors = Vec::new();
+        let mut parent = tree.get_parent(node_id);
+        while let Some(id) = parent {
+            ancestors.push(id);
+            parent = tree.get_parent(id);
+        }
+
+        // The variable environments are not kept, so they are created again from the custom properties of the
+        // ancestors. The parent has all inherited properties of its own ancestors already.
+        let mut variables = Rc::new(VariableEnvironment::default());
+        for id in ancestors.iter().rev() {
+            if let Some(node) = tree.get_node(*id) {
+                variables = Self::variable_environment(node.props(), &variables);
+            }
+        }
+
+        let inherit_props = ancestors
+            .first()
+            .and_then(|parent| tree.get_node(*parent))
+            .map(|parent| {
+                parent
+                    .props()
+                    .properties
+                    .iter()
+                    .filter(|(name, _)| prop_is_inherit(name))
+                    .map(|(name, prop)| (name.clone(), prop.actual.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self::resolve_inheritance::<C>(
+            tree,
+            node_id,
+            &inherit_props,
+            &variables,
+            &CalcContext::for_media(media),
+        );
+    }
+
+    fn load_default_useragent_stylesheet() -> Self::Stylesheet {
+        load_default_useragent_stylesheet()
+    }
+}
+
+impl Css3System {
+    /// Collects all declared properties of the node, or of the given pseudo element of the node
+    fn cascade<C: HasDocument<CssSystem = Self>>(
+        node: &C::Node,
+        sheets: &[crate::stylesheet::CssStylesheet],
+        doc: &C::Document,
+        id: NodeId,
+        pseudo_element: Option<&str>,
+    ) -> CssProperties {
+        let mut css_map_entry = CssProperties::new();
+
         let definitions = get_css_definitions();
 
         let mut fix_list = FixList::new();
 
-        for sheet in sheets {
+        let media = doc.media_environment();
+        let calc = CalcContext::for_media(media);
+
+        let layers = layer_order(sheets);
+
+        for (sheet_idx, sheet) in sheets.iter().enumerate() {
             for rule in &sheet.rules {
+                if !rule.matches_media(media) {
+                    continue;
+                }
+
+                let layer = rule
+                    .layer
+                    .as_deref()
+                    .and_then(|name| layers.sheet_rank(sheet_idx, sheet.origin, name));
+
                 for selector in rule.selectors().iter() {
-                    let (matched, specificity) = match_selector::<C>(doc, id, selector);
+                    let (matched, specificity) = match pseudo_element {
+                        Some(pseudo) => match_pseudo_element_selector::<C>(doc, id, selector, pseudo),
+                        None => match_selector::<C>(doc, id, selector),
+                    };
 
                     if !matched {
                         continue;
@@ -59,6 +168,13 @@ impl CssSystem for Css3System {
 
                     // Selector matched, so we add all declared values to the map
                     for declaration in rule.declarations().iter() {
+                        // Custom properties and declarations that use them can only be checked once the custom
+                        // properties of the node are known, which is during inheritance.
+                        if declaration.property.starts_with("--") || contains_var(&declaration.value) {
+                            add_property_to_map(&mut css_map_entry, sheet, specificity, layer, declaration);
+                            continue;
+                        }
+
                         // Step 1: find the property in our CSS definition list
                         let Some(definition) = definitions.find_property(&declaration.property) else {
                             // If not found, we skip this declaration
@@ -66,7 +182,10 @@ impl CssSystem for Css3System {
                             continue;
                         };
 
-                        let value = resolve_functions::<C>(&declaration.value, node, doc);
+                        let Some(value) = resolve_functions::<C>(&declaration.value, node, &calc) else {
+                            warn!("Declaration has an invalid math function: {:?}", declaration);
+                            continue;
+                        };
 
                         let match_value = if let CssValue::List(value) = &value {
                             &**value
@@ -75,6 +194,13 @@ impl CssSystem for Css3System {
                         };
 
                         // Check if the declaration matches the definition and return the "expanded" order
+                        fix_list.set_info(FixListInfo::new(
+                            sheet.origin,
+                            declaration.important,
+                            sheet.url.clone(),
+                            specificity,
+                            layer,
+                        ));
                         let res = definition.matches_and_shorthands(match_value, &mut fix_list);
                         if !res {
                             warn!("Declaration does not match definition: {:?}", declaration);
@@ -99,7 +225,7 @@ impl CssSystem for Css3System {
                             important: declaration.important,
                         };
 
-                        add_property_to_map(&mut css_map_entry, sheet, specificity, &decl);
+                        add_property_to_map(&mut css_map_entry, sheet, specificity, layer, &decl);
                     }
                 }
             }
@@ -109,23 +235,15 @@ impl CssSystem for Css3System {
 
         fix_list.apply(&mut css_map_entry);
 
-        Some(css_map_entry)
-    }
-
-    fn inheritance<C: HasRenderTree<CssSystem = Self>>(tree: &mut C::RenderTree) {
-        Self::resolve_inheritance::<C>(tree, tree.root(), &Vec::new());
+        css_map_entry
     }
 
-    fn load_default_useragent_stylesheet() -> Self::Stylesheet {
-        load_default_useragent_stylesheet()
-    }
-}
-
-impl Css3System {
     fn resolve_inheritance<C: HasRenderTree<CssSystem = Self>>(
         tree: &mut C::RenderTree,
         node_id: <C::RenderTree as RenderTree<C>>::NodeId,
         inherit_props: &Vec<(String, CssValue)>,
+        variables: &Rc<VariableEnvironment>,
+        calc: &CalcContext,
     ) {
         let Some(current_node) = tree.get_node_mut(node_id) else {
             return;
@@ -139,6 +257,8 @@ impl Css3System {
             current_node.props_mut().insert_inherited(prop.0.as_str(), p);
         }
 
+        let variables = Self::resolve_variables(current_node.props_mut(), inherit_props, variables, calc);
+
         let mut inherit_props = inherit_props.clone();
 
         'props: for (name, prop) in &mut current_node.props_mut().iter_mut() {
@@ -163,11 +283,140 @@ impl Css3System {
         };
 
         for child in children {
-            Self::resolve_inheritance::<C>(tree, child, &inherit_props);
+            Self::resolve_inheritance::<C>(tree, child, &inherit_props, &variables, calc);
+        }
+    }
+}
+
+impl Css3System {
+    /// Creates the variable environment of a node from the inherited environment and its own custom properties
+    fn variable_environment(props: &CssProperties, inherited: &Rc<VariableEnvironment>) -> Rc<VariableEnvironment> {
+        let declared: HashMap<String, CssValue> = props
+            .properties
+            .iter()
+            .filter(|(name, _)| name.starts_with("--"))
+            .filter_map(|(name, prop)| Some((name.clone(), prop.declared.iter().max()?.value.clone())))
+            .collect();
+
+        if declared.is_empty() {
+            inherited.clone()
+        } else {
+            Rc::new(VariableEnvironment::with_declared(inherited, declared))
+        }
+    }
+
+    /// Creates the variable environment of a node from the inherited environment and its own custom properties,
+    /// and substitutes the variables in all its declarations that use them. Declarations that are invalid after
+    /// substitution are "invalid at computed-value time": the property behaves as if it was set to `unset`.
+    fn resolve_variables(
+        props: &mut CssProperties,
+        inherit_props: &[(String, CssValue)],
+        inherited: &Rc<VariableEnvironment>,
+        calc: &CalcContext,
+    ) -> Rc<VariableEnvironment> {
+        let variables = Self::variable_environment(props, inherited);
+
+        let definitions = get_css_definitions();
+        let mut fix_list = FixList::new();
+        let mut changed = false;
+
+        for (name, prop) in props.properties.iter_mut() {
+            if name.starts_with("--") {
+                continue;
+            }
+
+            let Some(declaration) = prop.declared.iter_mut().max() else {
+                continue;
+            };
+
+            if !contains_var(&declaration.value) {
+                continue;
+            }
+
+            changed = true;
+
+            let value =
+                substitute_vars(&declaration.value, &variables).and_then(|value| resolve_math_functions(value, calc));
+            let definition = definitions.find_property(name);
+
+            let valid = match (&value, definition) {
+                (Some(value), Some(definition)) => {
+                    fix_list.set_info(FixListInfo::new(
+                        declaration.origin,
+                        declaration.important,
+                        declaration.location.clone(),
+                        declaration.specificity,
+                        declaration.layer,
+                    ));
+                    definition.matches_and_shorthands(value.to_slice(), &mut fix_list)
+                }
+                _ => false,
+            };
+
+            match value {
+                Some(value) if valid => declaration.value = value,
+                _ => {
+                    warn!("Declaration is invalid after variable substitution: {:?}", name);
+
+                    // Unset: inherited properties get their inherited value, all others their initial value
+                    prop.declared.clear();
+                    prop.inherited = inherit_props
+                        .iter()
+                        .find(|(n, _)| n == name)
+                        .map(|(_, value)| value.clone())
+                        .unwrap_or(CssValue::None);
+                }
+            }
         }
+
+        if changed {
+            fix_list.resolve_nested(definitions);
+            fix_list.apply(props);
+
+            for prop in props.properties.values_mut() {
+                prop.mark_dirty();
+            }
+        }
+
+        variables
     }
 }
 
+/// Layers declared by each stylesheet of a set, with the layer order created from them
+type LayerOrderCache = (Vec<(CssOrigin, Vec<String>)>, Rc<LayerOrder>);
+
+thread_local! {
+    static LAYER_ORDER: RefCell<Option<LayerOrderCache>> = const { RefCell::new(None) };
+}
+
+/// Returns the layer order of the stylesheets. All nodes of a document are styled with the same stylesheets, so the
+/// order is only created again when the layers of the stylesheets differ from the previous call.
+fn layer_order(sheets: &[crate::stylesheet::CssStylesheet]) -> Rc<LayerOrder> {
+    LAYER_ORDER.with(|cache| {
+        let mut cache = cache.borrow_mut();
+
+        if let Some((declared, order)) = cache.as_ref() {
+            if declared.len() == sheets.len()
+                && declared
+                    .iter()
+                    .zip(sheets)
+                    .all(|((origin, layers), sheet)| *origin == sheet.origin && *layers == sheet.layers)
+            {
+                return order.clone();
+            }
+        }
+
+        let order = Rc::new(LayerOrder::from_stylesheets(sheets));
+        let declared = sheets
+            .iter()
+            .map(|sheet| (sheet.origin, sheet.layers.clone()))
+            .collect();
+        *cache = Some((declared, order.clone()));
+
+        order
+    })
+}
+
 pub fn prop_is_inherit(name: &str) -> bool {
     get_css_definitions()
         .find_property(name)
@@ -179,6 +428,7 @@ pub fn add_property_to_map(
     css_map_entry: &mut CssProperties,
     sheet: &crate::stylesheet::CssStylesheet,
     specificity: Specificity,
+    layer: Option<usize>,
     declaration: &CssDeclaration,
 ) {
     let property_name = declaration.property.clone();
@@ -205,6 +455,7 @@ pub fn add_property_to_map(
         important: declaration.important,
         location: sheet.url.clone(),
         specificity,
+        layer,
     };
 
     if let std::collections::hash_map::Entry::Vacant(e) = css_map_entry.properties.entry(property_name.clone()) {
@@ -239,27 +490,91 @@ pub fn node_is_unrenderable<C: HasDocument>(node: &C::Node) -> bool {
     false
 }
 
-pub fn resolve_functions<C: HasDocument>(value: &CssValue, node: &C::Node, doc: &C::Document) -> CssValue {
-    fn resolve<C: HasDocument>(val: &CssValue, node: &C::Node, doc: &C::Document) -> CssValue {
+pub fn resolve_functions<C: HasDocument>(value: &CssValue, node: &C::Node, calc: &CalcContext) -> Option<CssValue> {
+    fn resolve<C: HasDocument>(val: &CssValue, node: &C::Node, calc: &CalcContext) -> Option<CssValue> {
         match val {
+            val if is_math_function(val) => resolve_calc(val, calc),
             CssValue::Function(func, values) => {
                 let resolved = match func.as_str() {
-                    "calc" => resolve_calc(values),
                     "attr" => resolve_attr::<C>(values, node),
-                    "var" => resolve_var::<C>(values, doc, node),
                     _ => vec![val.clone()],
                 };
 
-                CssValue::List(resolved)
+                Some(CssValue::List(resolved))
             }
-            _ => val.clone(),
+            _ => Some(val.clone()),
         }
     }
 
     if let CssValue::List(list) = value {
-        let resolved = list.iter().map(|val| resolve::<C>(val, node, doc)).collect();
-        CssValue::List(resolved)
+        let resolved = list
+            .iter()
+            .map(|val| resolve::<C>(val, node, calc))
+            .collect::<Option<_>>()?;
+        Some(CssValue::List(resolved))
     } else {
-        resolve::<C>(value, node, doc)
+        resolve::<C>(value, node, calc)
+    }
+}
+
+/// Evaluates the math functions in the value as far as possible. Returns `None` when any of them is invalid.
+fn resolve_math_functions(value: CssValue, calc: &CalcContext) -> Option<CssValue> {
+    match value {
+        value if is_math_function(&value) => resolve_calc(&value, calc),
+        CssValue::List(list) => Some(CssValue::List(
+            list.into_iter()
+                .map(|value| resolve_math_functions(value, calc))
+                .collect::<Option<_>>()?,
+        )),
+        value => Some(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(name: &str) -> CssValue {
+        CssValue::Function("var".into(), vec![CssValue::String(name.into())])
+    }
+
+    fn declare(props: &mut CssProperties, name: &str, value: CssValue) {
+        let mut prop = CssProperty::new(name);
+        prop.declared.push(DeclarationProperty::from(value));
+        props.properties.insert(name.to_string(), prop);
+    }
+
+    #[test]
+    fn substitute_variables() {
+        let mut inherited = VariableEnvironment::default();
+        inherited
+            .values
+            .insert("--color".into(), CssValue::String("red".into()));
+
+        let mut props = CssProperties::new();
+        declare(&mut props, "--gap", CssValue::Unit(4.0, "px".into()));
+        declare(&mut props, "color", var("--color"));
+        declare(&mut props, "margin", var("--gap"));
+        declare(&mut props, "width", var("--missing"));
+        declare(&mut props, "display", var("--gap"));
+
+        let inherit_props = vec![("color".to_string(), CssValue::String("blue".into()))];
+        let env =
+            Css3System::resolve_variables(&mut props, &inherit_props, &Rc::new(inherited), &CalcContext::default());
+
+        assert_eq!(env.get("--gap"), Some(&CssValue::Unit(4.0, "px".into())));
+        assert_eq!(env.get("--color"), Some(&CssValue::String("red".into())));
+
+        let mut value = |name: &str| props.properties.get_mut(name).map(|prop| prop.compute_value().clone());
+
+        assert_eq!(value("color"), Some(CssValue::String("red".into())));
+        assert_eq!(value("margin-top"), Some(CssValue::Unit(4.0, "px".into())));
+        assert_eq!(value("margin-left"), Some(CssValue::Unit(4.0, "px".into())));
+
+        // Invalid at computed-value time: the initial value is used
+        let initial = |name: &str| Some(CssProperty::new(name).compute_value().clone());
+        assert_eq!(value("width"), initial("width"));
+        assert_eq!(value("display"), initial("display"));
+        assert!(props.properties["display"].declared.is_empty());
     }
 }
@@ -0,0 +1,306 @@
+//! Cascade tests
+//!
+//! Parses HTML documents with style elements, and checks the properties the cascade finds for their elements.
+use gosub_css3::matcher::styling::CssProperties;
+use gosub_css3::stylesheet::CssValue;
+use gosub_css3::system::Css3System;
+use gosub_html5::document::builder::DocumentBuilderImpl;
+use gosub_html5::document::document_impl::DocumentImpl;
+use gosub_html5::document::fragment::DocumentFragmentImpl;
+use gosub_interface::config::{HasCssSystem, HasDocument};
+use gosub_interface::css3::{CssProperty, CssSystem, MediaEnvironment};
+use gosub_interface::document::Document;
+use gosub_interface::node::{ElementDataType, Node};
+use gosub_shared::node::NodeId;
+
+#[derive(Clone, Debug, PartialEq)]
+struct Config;
+
+impl HasCssSystem for Config {
+    type CssSystem = Css3System;
+}
+impl HasDocument for Config {
+    type Document = DocumentImpl<Self>;
+    type DocumentFragment = DocumentFragmentImpl<Self>;
+    type DocumentBuilder = DocumentBuilderImpl;
+}
+
+type Doc = DocumentImpl<Config>;
+
+/// Parses the HTML into a document, with the stylesheets of its style elements
+fn parse(html: &str) -> Doc {
+    gosub_html5::html_compile::<Config>(html)
+}
+
+/// Returns the cascaded properties of the element with the given id attribute
+fn properties(doc: &Doc, id: &str) -> CssProperties {
+    let node = doc.node_by_named_id(id).unwrap();
+    Css3System::properties_from_node::<Config>(node, doc.stylesheets(), doc, node.id()).unwrap()
+}
+
+/// Returns the cascaded properties of the pseudo element of the element with the given id attribute
+fn pseudo_properties(doc: &Doc, id: &str, pseudo_element: &str) -> CssProperties {
+    let node = doc.node_by_named_id(id).unwrap();
+    Css3System::pseudo_element_properties_from_node::<Config>(node, doc.stylesheets(), doc, node.id(), pseudo_element)
+        .unwrap()
+}
+
+/// Returns the actual value of the property of the element with the given id attribute, or None when no value is
+/// found for the property
+fn actual(doc: &Doc, id: &str, prop: &str) -> Option<String> {
+    let mut props = properties(doc, id);
+    let prop = props.properties.get_mut(prop)?;
+    prop.compute_value();
+
+    Some(prop.actual.to_string())
+}
+
+/// Returns the sorted id attributes of all elements that have a declared value for the property
+fn declared_elements(doc: &Doc, prop: &str) -> Vec<String> {
+    let mut ids = Vec::new();
+    for id in 0..doc.node_count() {
+        let id = NodeId::from(id);
+        let Some(node) = doc.node_by_id(id) else {
+            continue;
+        };
+        let Some(name) = node.get_element_data().and_then(|e| e.attribute("id")) else {
+            continue;
+        };
+
+        let props = Css3System::properties_from_node::<Config>(node, doc.stylesheets(), doc, id);
+        if props.is_some_and(|p| p.properties.get(prop).is_some_and(|p| !p.declared.is_empty())) {
+            ids.push(name.clone());
+        }
+    }
+
+    ids.sort();
+    ids
+}
+
+#[test]
+fn pseudo_class_structural() {
+    let doc = parse(
+        r#"<html><head><style>
+            li:first-child, li:nth-child(2n+4), li:last-of-type { color: red; }
+            div:empty { color: red; }
+            p:only-child { color: red; }
+        </style></head><body>
+            <ul><li id="l1"></li><li id="l2"></li><li id="l3"></li><li id="l4"></li><li id="l5"></li></ul>
+            <div id="d1"></div><div id="d2"><p id="p1">text</p></div>
+        </body></html>"#,
+    );
+
+    assert_eq!(declared_elements(&doc, "color"), vec!["d1", "l1", "l4", "l5", "p1"]);
+}
+
+#[test]
+fn pseudo_class_nth_of() {
+    let doc = parse(
+        r#"<html><head><style>
+            li:nth-child(odd of .x) { color: red; }
+            li:nth-last-child(-n+1) { color: red; }
+        </style></head><body>
+            <ul><li id="l1" class="x"></li><li id="l2"></li><li id="l3" class="x"></li><li id="l4" class="x"></li><li id="l5"></li></ul>
+        </body></html>"#,
+    );
+
+    assert_eq!(declared_elements(&doc, "color"), vec!["l1", "l4", "l5"]);
+}
+
+#[test]
+fn pseudo_class_logical() {
+    let doc = parse(
+        r#"<html><head><style>
+            span:not(.a, .b) { color: red; }
+            :is(section, article) > em { color: red; }
+            div:has(> img) { color: red; }
+            a:link { color: red; }
+        </style></head><body>
+            <span id="s1" class="a"></span><span id="s2" class="c"></span>
+            <section><em id="e1"></em></section><aside><em id="e2"></em></aside>
+            <div id="d1"><img></div><div id="d2"><p><img></p></div>
+            <a id="a1" href="/"></a><a id="a2"></a>
+        </body></html>"#,
+    );
+
+    assert_eq!(declared_elements(&doc, "color"), vec!["a1", "d1", "e1", "s2"]);
+}
+
+#[test]
+fn pseudo_class_has_anchored() {
+    let doc = parse(
+        r#"<html><head><style>
+            div:has(> span > b) { color: red; }
+            section:has(> p b) { color: red; }
+            h1:has(+ p > em) { color: red; }
+            h2:has(~ p .x) { color: red; }
+        </style></head><body>
+            <div id="d1"><span><b></b></span></div><div id="d2"><span><span><b></b></span></span></div>
+            <section id="s1"><p><i><b></b></i></p></section><section id="s2"><div><p><b></b></p></div></section>
+            <h1 id="h1"></h1><p><em></em></p><h1 id="h2"></h1><div></div><p><em></em></p>
+            <h2 id="h3"></h2><div></div><p><span class="x"></span></p><h2 id="h4"></h2><p></p>
+        </body></html>"#,
+    );
+
+    // The leftmost compound of the argument is anchored to the element, so a match deeper in the tree does not count
+    assert_eq!(declared_elements(&doc, "color"), vec!["d1", "h1", "h3", "s1"]);
+}
+
+#[test]
+fn pseudo_class_root_and_lang() {
+    let doc = parse(
+        r#"<html id="h" lang="en-US"><head><style>
+            :root { color: red; }
+            p:lang(en) { color: red; }
+        </style></head><body>
+            <p id="p1"></p><p id="p2" lang="nl"></p>
+        </body></html>"#,
+    );
+
+    assert_eq!(declared_elements(&doc, "color"), vec!["h", "p1"]);
+}
+
+#[test]
+fn pseudo_class_specificity() {
+    let doc = parse(
+        r#"<html><head><style>
+            p:where(.x) { color: red; }
+            p { color: blue; }
+            .y:is(#z) { color: green; }
+            .y#z { color: yellow; }
+        </style></head><body>
+            <p id="p1" class="x"></p><p id="z" class="y"></p>
+        </body></html>"#,
+    );
+
+    // :where() has no specificity, so the later "p" rule wins
+    assert_eq!(actual(&doc, "p1", "color").as_deref(), Some("blue"));
+    // :is(#z) has the same specificity as #z, so the later rule wins
+    assert_eq!(actual(&doc, "z", "color").as_deref(), Some("yellow"));
+}
+
+#[test]
+fn pseudo_element_selectors() {
+    let doc = parse(
+        r#"<html><head><style>
+            p::before { content: "a" attr(title); }
+            p:after { content: "b"; }
+            p::after { color: red; }
+            li::marker { content: "-"; }
+        </style></head><body><p id="p" title="Hello world">x</p><ul><li id="li">item</li></ul></body></html>"#,
+    );
+
+    let declared = |props: CssProperties, prop: &str| {
+        props
+            .properties
+            .get(prop)
+            .map(|p| p.declared.iter().map(|d| d.value.clone()).collect::<Vec<_>>())
+            .unwrap_or_default()
+    };
+
+    // Pseudo element rules never apply to the element itself
+    assert!(declared(properties(&doc, "p"), "content").is_empty());
+    assert!(declared(properties(&doc, "p"), "color").is_empty());
+
+    assert_eq!(
+        declared(pseudo_properties(&doc, "p", "before"), "content"),
+        vec![CssValue::List(vec![
+            CssValue::String("a".into()),
+            CssValue::List(vec![CssValue::String("Hello world".into())]),
+        ])]
+    );
+    assert_eq!(
+        declared(pseudo_properties(&doc, "p", "after"), "content"),
+        vec![CssValue::String("b".into())]
+    );
+    assert_eq!(declared(pseudo_properties(&doc, "p", "after"), "color").len(), 1);
+    assert!(declared(pseudo_properties(&doc, "p", "marker"), "content").is_empty());
+    assert_eq!(
+        declared(pseudo_properties(&doc, "li", "marker"), "content"),
+        vec![CssValue::String("-".into())]
+    );
+    assert!(declared(pseudo_properties(&doc, "li", "before"), "content").is_empty());
+}
+
+#[test]
+fn media_queries() {
+    let mut doc = parse(
+        r#"<html><head><style>
+            @media (max-width: 600px) { #narrow { color: red; } }
+            @media (min-width: 601px) { #wide { color: red; } }
+            @media print { #print { color: red; } }
+            #always { color: red; }
+        </style></head><body><p id="narrow"></p><p id="wide"></p><p id="print"></p><p id="always"></p></body></html>"#,
+    );
+
+    assert_eq!(declared_elements(&doc, "color"), vec!["always", "wide"]);
+
+    // Nothing changes within the same media query ranges
+    assert!(!doc.set_media_environment(MediaEnvironment::default().with_size(1280.0, 768.0)));
+    assert_eq!(declared_elements(&doc, "color"), vec!["always", "wide"]);
+
+    assert!(doc.set_media_environment(MediaEnvironment::default().with_size(400.0, 768.0)));
+    assert_eq!(declared_elements(&doc, "color"), vec!["always", "narrow"]);
+
+    let print = MediaEnvironment {
+        media_type: "print".into(),
+        ..MediaEnvironment::default().with_size(400.0, 768.0)
+    };
+    assert!(doc.set_media_environment(print));
+    assert_eq!(declared_elements(&doc, "color"), vec!["always", "narrow", "print"]);
+}
+
+#[test]
+fn cascade_layers_and_supports() {
+    let doc = parse(
+        r#"<html><head><style>
+            @layer base, theme;
+            @layer theme {
+                #x { color: red; }
+                #y.layered { color: red; }
+                #z { color: red !important; }
+            }
+            @layer base {
+                #x { color: blue; }
+                #z { color: blue !important; }
+            }
+            p#y { color: green; }
+            p#z { color: green !important; }
+            @supports (display: flex) { #s { color: yellow; } }
+            @supports (display: banana) { #s { color: purple; } }
+        </style></head><body><p id="x"></p><p id="y" class="layered"></p><p id="z"></p><p id="s"></p></body></html>"#,
+    );
+
+    // Later layers win, unlayered styles win over layers, and important declarations reverse the layer order
+    assert_eq!(actual(&doc, "x", "color").as_deref(), Some("red"));
+    assert_eq!(actual(&doc, "y", "color").as_deref(), Some("green"));
+    assert_eq!(actual(&doc, "z", "color").as_deref(), Some("blue"));
+    assert_eq!(actual(&doc, "s", "color").as_deref(), Some("yellow"));
+}
+
+#[test]
+fn cascade_math_functions() {
+    let doc = parse(
+        r#"<html><head><style>
+            #a { width: calc(10px * 3 + 1in); }
+            #b { width: calc((100% - 2 * 10px) / 2); }
+            #c { width: min(50vw, 300px); }
+            #d { width: calc(10px + 1); }
+            #e { width: calc(50% + 2em); }
+        </style></head><body><p id="a"></p><p id="b"></p><p id="c"></p><p id="d"></p><p id="e"></p></body></html>"#,
+    );
+
+    assert_eq!(actual(&doc, "a", "width").as_deref(), Some("126px"));
+    // Percentages are kept until layout
+    assert_eq!(actual(&doc, "b", "width").as_deref(), Some("calc(50% - 10px)"));
+    assert_eq!(actual(&doc, "c", "width").as_deref(), Some("300px"));
+    // Invalid expressions drop the declaration
+    assert_eq!(actual(&doc, "d", "width"), None);
+
+    // During layout, percentages resolve against the containing block and `em` against the font size of the element
+    let mut props = properties(&doc, "e");
+    let width = props.properties.get_mut("width").unwrap();
+    width.compute_value();
+    assert_eq!(width.resolve_calc(Some(200.0), 20.0), Some(140.0));
+    assert_eq!(width.resolve_calc(None, 20.0), None);
+}
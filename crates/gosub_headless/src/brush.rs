@@ -0,0 +1,51 @@
+use crate::{Color, Gradient, HeadlessBackend, Image};
+use gosub_interface::render_backend::Brush as TBrush;
+use tiny_skia::{FilterQuality, Paint, Pattern, Shader, SpreadMode};
+
+#[derive(Clone, Debug)]
+pub enum Brush {
+    Solid(Color),
+    Gradient(Gradient),
+    Image(Image),
+}
+
+impl Brush {
+    /// Creates a tiny-skia paint for this brush. The brush transform is applied to the shader only, the shape
+    /// transform is given separately when filling or stroking. Returns None when there is nothing to paint, for
+    /// instance with an image that has not been loaded yet.
+    pub(crate) fn paint(&self, brush_transform: Option<tiny_skia::Transform>) -> Option<Paint<'_>> {
+        let transform = brush_transform.unwrap_or_default();
+
+        let shader = match self {
+            Brush::Solid(color) => Shader::SolidColor((*color).into()),
+            Brush::Gradient(gradient) => gradient.shader(transform)?,
+            Brush::Image(image) => Pattern::new(
+                image.pixmap()?.as_ref(),
+                SpreadMode::Pad,
+                FilterQuality::Bilinear,
+                1.0,
+                transform,
+            ),
+        };
+
+        Some(Paint {
+            shader,
+            anti_alias: true,
+            ..Default::default()
+        })
+    }
+}
+
+impl TBrush<HeadlessBackend> for Brush {
+    fn gradient(gradient: Gradient) -> Self {
+        Brush::Gradient(gradient)
+    }
+
+    fn color(color: Color) -> Self {
+        Brush::Solid(color)
+    }
+
+    fn image(image: Image) -> Self {
+        Brush::Image(image)
+    }
+}
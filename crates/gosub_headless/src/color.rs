@@ -0,0 +1,55 @@
+use gosub_interface::render_backend::Color as TColor;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Color(pub(crate) tiny_skia::ColorU8);
+
+impl From<tiny_skia::ColorU8> for Color {
+    fn from(color: tiny_skia::ColorU8) -> Self {
+        Color(color)
+    }
+}
+
+impl From<Color> for tiny_skia::Color {
+    fn from(color: Color) -> Self {
+        let c = color.0;
+        tiny_skia::Color::from_rgba8(c.red(), c.green(), c.blue(), c.alpha())
+    }
+}
+
+impl Color {
+    pub const fn rgba8(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Color(tiny_skia::ColorU8::from_rgba(r, g, b, a))
+    }
+}
+
+impl TColor for Color {
+    fn with_alpha(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Color::rgba8(r, g, b, a)
+    }
+
+    fn r(&self) -> u8 {
+        self.0.red()
+    }
+
+    fn g(&self) -> u8 {
+        self.0.green()
+    }
+
+    fn b(&self) -> u8 {
+        self.0.blue()
+    }
+
+    fn a(&self) -> u8 {
+        self.0.alpha()
+    }
+
+    const WHITE: Self = Color::rgba8(255, 255, 255, 255);
+    const BLACK: Self = Color::rgba8(0, 0, 0, 255);
+    const RED: Self = Color::rgba8(255, 0, 0, 255);
+    const GREEN: Self = Color::rgba8(0, 255, 0, 255);
+    const BLUE: Self = Color::rgba8(0, 0, 255, 255);
+    const YELLOW: Self = Color::rgba8(255, 255, 0, 255);
+    const CYAN: Self = Color::rgba8(0, 255, 255, 255);
+    const MAGENTA: Self = Color::rgba8(255, 0, 255, 255);
+    const TRANSPARENT: Self = Color::rgba8(0, 0, 0, 0);
+}
@@ -0,0 +1,56 @@
+use crate::text::GlyphPen;
+use crate::{Brush, Color, Transform};
+use gosub_interface::render_backend::Color as _;
+use gosub_shared::types::Point;
+use gosub_shared::ROBOTO_FONT;
+use skrifa::instance::{LocationRef, Size};
+use skrifa::outline::DrawSettings;
+use skrifa::{FontRef, MetadataProvider};
+use tiny_skia::{FillRule, PixmapMut};
+
+/// Renders a simple single line(s) text in the default font. This is only used for debugging purposes.
+pub fn render_text_simple(pixmap: &mut PixmapMut, text: &str, point: Point<f32>, font_size: f32, transform: Transform) {
+    let Ok(font) = FontRef::new(ROBOTO_FONT) else {
+        return;
+    };
+
+    let fs = Size::new(font_size);
+    let location = LocationRef::default();
+
+    let metrics = font.metrics(fs, location);
+    let line_height = metrics.ascent - metrics.descent + metrics.leading;
+    let glyph_metrics = font.glyph_metrics(fs, location);
+    let charmap = font.charmap();
+    let outlines = font.outline_glyphs();
+
+    let mut pen = GlyphPen::default();
+    let mut pen_x = 0f32;
+    let mut pen_y = 0f32;
+
+    for ch in text.chars() {
+        if ch == '\n' {
+            pen_y += line_height;
+            pen_x = 0.0;
+            continue;
+        }
+
+        let gid = charmap.map(ch).unwrap_or_default();
+
+        if let Some(outline) = outlines.get(gid) {
+            pen.origin = (point.x + pen_x, point.y + pen_y);
+            _ = outline.draw(DrawSettings::unhinted(fs, location), &mut pen);
+        }
+
+        pen_x += glyph_metrics.advance_width(gid).unwrap_or_default();
+    }
+
+    let Some(path) = pen.builder.finish() else {
+        return;
+    };
+
+    let brush = Brush::Solid(Color::BLACK);
+
+    if let Some(paint) = brush.paint(None) {
+        pixmap.fill_path(&path, &paint, FillRule::Winding, transform.0, None);
+    }
+}
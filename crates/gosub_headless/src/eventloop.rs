@@ -0,0 +1,163 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use gosub_interface::config::{HasDrawComponents, HasTreeDrawer};
+use gosub_interface::draw::TreeDrawer;
+use gosub_interface::eventloop::EventLoopHandle;
+use gosub_interface::render_backend::{ImageBuffer, ImgCache};
+use gosub_shared::geo::SizeU32;
+use gosub_shared::types::Result;
+use url::Url;
+
+use crate::{Framebuffer, HeadlessBackend};
+
+/// Maximum number of times we redraw a tree drawer while waiting for its images to arrive
+const MAX_IMAGE_PASSES: usize = 8;
+
+/// Image that has been loaded in the background and needs to be added to the image cache of the drawer
+pub struct LoadedImage {
+    pub url: Url,
+    pub img: ImageBuffer<HeadlessBackend>,
+    pub size: Option<SizeU32>,
+}
+
+struct Inner<C: HasDrawComponents> {
+    images: Mutex<Vec<LoadedImage>>,
+    tree: Mutex<Option<C::RenderTree>>,
+    cond: Condvar,
+    redraw: AtomicBool,
+}
+
+/// Event loop handle that can be used to draw a tree drawer without any window or chrome. All events are collected
+/// so the caller can pick them up between draws.
+pub struct HeadlessEventLoop<C: HasDrawComponents> {
+    inner: Arc<Inner<C>>,
+}
+
+impl<C: HasDrawComponents> Clone for HeadlessEventLoop<C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<C: HasDrawComponents> Default for HeadlessEventLoop<C> {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                images: Mutex::new(Vec::new()),
+                tree: Mutex::new(None),
+                cond: Condvar::new(),
+                redraw: AtomicBool::new(false),
+            }),
+        }
+    }
+}
+
+impl<C: HasDrawComponents> HeadlessEventLoop<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the render tree the drawer should be reloaded from, when one has been sent since the last call.
+    /// Only the latest tree is kept.
+    pub fn take_tree(&self) -> Option<C::RenderTree> {
+        self.inner.tree.lock().ok()?.take()
+    }
+
+    /// Returns true when a redraw has been requested since the last call
+    pub fn take_redraw(&self) -> bool {
+        self.inner.redraw.swap(false, Ordering::SeqCst)
+    }
+
+    fn has_tree(&self) -> bool {
+        self.inner.tree.lock().is_ok_and(|tree| tree.is_some())
+    }
+
+    /// Waits at most `timeout` until at least one image has been loaded or a render tree has been sent, and returns
+    /// all loaded images
+    pub fn wait_for_images(&self, timeout: Duration) -> Vec<LoadedImage> {
+        let deadline = Instant::now() + timeout;
+
+        let Ok(mut images) = self.inner.images.lock() else {
+            return Vec::new();
+        };
+
+        while images.is_empty() && !self.has_tree() {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+
+            images = match self.inner.cond.wait_timeout(images, deadline - now) {
+                Ok((images, _)) => images,
+                Err(_) => return Vec::new(),
+            };
+        }
+
+        std::mem::take(&mut *images)
+    }
+}
+
+impl<C: HasDrawComponents<RenderBackend = HeadlessBackend>> EventLoopHandle<C> for HeadlessEventLoop<C> {
+    fn redraw(&self) {
+        self.inner.redraw.store(true, Ordering::SeqCst);
+    }
+
+    fn add_img_cache(&self, url: Url, buf: ImageBuffer<HeadlessBackend>, size: Option<SizeU32>) {
+        if let Ok(mut images) = self.inner.images.lock() {
+            images.push(LoadedImage { url, img: buf, size });
+            self.inner.cond.notify_all();
+        }
+    }
+
+    fn reload_from(&self, rt: C::RenderTree) {
+        if let Ok(mut tree) = self.inner.tree.lock() {
+            *tree = Some(rt);
+            self.inner.cond.notify_all();
+        }
+        self.inner.redraw.store(true, Ordering::SeqCst);
+    }
+
+    fn show_partial(&self, _rt: C::RenderTree) {
+        // Pages are only drawn once they have loaded
+    }
+}
+
+/// Draws the tree drawer onto a framebuffer of the given size. Images that are referenced by the page are loaded in
+/// the background. We wait at most `image_timeout` for each batch of images to arrive and redraw the page with them.
+/// Render trees that are sent to the event loop while drawing replace the tree of the drawer before the next draw.
+pub fn render_drawer<C: HasTreeDrawer<RenderBackend = HeadlessBackend>>(
+    drawer: &mut C::TreeDrawer,
+    size: SizeU32,
+    image_timeout: Duration,
+) -> Result<Framebuffer> {
+    let el = HeadlessEventLoop::new();
+    let mut backend = HeadlessBackend::new();
+
+    let mut scene = drawer.draw(size, &el);
+
+    for _ in 0..MAX_IMAGE_PASSES {
+        let images = el.wait_for_images(image_timeout);
+        let tree = el.take_tree();
+        if images.is_empty() && tree.is_none() {
+            break;
+        }
+
+        if let Some(tree) = tree {
+            drawer.reload_from(tree);
+        }
+
+        let cache = drawer.get_img_cache();
+        for image in images {
+            cache.add(image.url.to_string(), image.img, image.size);
+        }
+
+        drawer.clear_buffers();
+        scene = drawer.draw(size, &el);
+    }
+
+    backend.render_scene(&scene, size)
+}
@@ -0,0 +1,101 @@
+use crate::{Color, Convert, HeadlessBackend};
+use gosub_interface::render_backend::{ColorStops, Gradient as TGradient};
+use gosub_shared::geo::{Point, FP};
+use log::warn;
+use tiny_skia::{GradientStop, LinearGradient, RadialGradient, Shader, SpreadMode};
+
+#[derive(Clone, Debug)]
+enum Kind {
+    Linear {
+        start: Point,
+        end: Point,
+    },
+    Radial {
+        start_center: Point,
+        end_center: Point,
+        end_radius: FP,
+    },
+    Sweep {
+        center: Point,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct Gradient {
+    kind: Kind,
+    stops: Vec<(FP, Color)>,
+}
+
+impl Gradient {
+    fn new(kind: Kind, stops: ColorStops<HeadlessBackend>) -> Self {
+        let stops = stops.into_iter().map(|stop| (stop.offset, stop.color)).collect();
+
+        Self { kind, stops }
+    }
+
+    fn tiny_skia_stops(&self) -> Vec<GradientStop> {
+        self.stops
+            .iter()
+            .map(|(offset, color)| GradientStop::new(*offset, (*color).into()))
+            .collect()
+    }
+
+    pub(crate) fn shader(&self, transform: tiny_skia::Transform) -> Option<Shader<'static>> {
+        match &self.kind {
+            Kind::Linear { start, end } => LinearGradient::new(
+                start.convert(),
+                end.convert(),
+                self.tiny_skia_stops(),
+                SpreadMode::Pad,
+                transform,
+            ),
+            Kind::Radial {
+                start_center,
+                end_center,
+                end_radius,
+            } => RadialGradient::new(
+                start_center.convert(),
+                end_center.convert(),
+                *end_radius,
+                self.tiny_skia_stops(),
+                SpreadMode::Pad,
+                transform,
+            ),
+            Kind::Sweep { center } => {
+                // tiny-skia has no sweep gradients, so we fall back to the color of the first stop
+                warn!("Sweep gradients are not supported by the headless backend (center {center:?})");
+
+                let (_, color) = self.stops.first()?;
+                Some(Shader::SolidColor((*color).into()))
+            }
+        }
+    }
+}
+
+impl TGradient<HeadlessBackend> for Gradient {
+    fn new_linear(start: Point, end: Point, stops: ColorStops<HeadlessBackend>) -> Self {
+        Gradient::new(Kind::Linear { start, end }, stops)
+    }
+
+    fn new_radial_two_point(
+        start_center: Point,
+        _start_radius: FP,
+        end_center: Point,
+        end_radius: FP,
+        stops: ColorStops<HeadlessBackend>,
+    ) -> Self {
+        // tiny-skia only supports two point conical gradients that start with a zero radius
+        Gradient::new(
+            Kind::Radial {
+                start_center,
+                end_center,
+                end_radius,
+            },
+            stops,
+        )
+    }
+
+    fn new_sweep(center: Point, _start_angle: FP, _end_angle: FP, stops: ColorStops<HeadlessBackend>) -> Self {
+        Gradient::new(Kind::Sweep { center }, stops)
+    }
+}
@@ -0,0 +1,55 @@
+use std::sync::Arc;
+
+use gosub_interface::render_backend::Image as TImage;
+use gosub_shared::geo::FP;
+use image::{DynamicImage, GenericImageView};
+use tiny_skia::{IntSize, Pixmap};
+
+/// RGBA image that can be used as a brush. Images with a zero width or height (like images that are still
+/// loading) do not have a pixmap and are never drawn.
+#[derive(Clone, Debug)]
+pub struct Image {
+    pixmap: Option<Arc<Pixmap>>,
+    width: u32,
+    height: u32,
+}
+
+impl Image {
+    /// Creates an image from straight (non-premultiplied) RGBA8 data
+    fn from_rgba8(width: u32, height: u32, mut data: Vec<u8>) -> Self {
+        for px in data.chunks_exact_mut(4) {
+            let c = tiny_skia::ColorU8::from_rgba(px[0], px[1], px[2], px[3]).premultiply();
+            px.copy_from_slice(&[c.red(), c.green(), c.blue(), c.alpha()]);
+        }
+
+        let pixmap = IntSize::from_wh(width, height)
+            .and_then(|size| Pixmap::from_vec(data, size))
+            .map(Arc::new);
+
+        Self { pixmap, width, height }
+    }
+
+    pub(crate) fn pixmap(&self) -> Option<&Pixmap> {
+        self.pixmap.as_deref()
+    }
+}
+
+impl TImage for Image {
+    fn new(size: (FP, FP), data: Vec<u8>) -> Self {
+        Image::from_rgba8(size.0 as u32, size.1 as u32, data)
+    }
+
+    fn from_img(img: DynamicImage) -> Self {
+        let (width, height) = img.dimensions();
+
+        Image::from_rgba8(width, height, img.into_rgba8().into_raw())
+    }
+
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+}
@@ -0,0 +1,206 @@
+//! Headless render backend
+//!
+//! This backend rasterizes scenes on the CPU with tiny-skia into an in-memory RGBA framebuffer. It does not need a
+//! GPU, a display server or a window, which makes it usable for screenshot tests on build machines.
+use std::fmt::Debug;
+
+use gosub_fontmanager::FontManager;
+use gosub_interface::font::HasFontManager;
+use gosub_interface::render_backend::{
+    Color as TColor, RenderBackend, RenderRect, RenderText, Scene as TScene, WindowHandle,
+};
+use gosub_shared::geo::{Point, SizeU32};
+use gosub_shared::types::Result;
+use log::info;
+
+pub use border::*;
+pub use brush::*;
+pub use color::*;
+pub use eventloop::*;
+pub use gradient::*;
+pub use image::*;
+pub use rect::*;
+pub use render::*;
+pub use scene::*;
+pub use text::*;
+pub use transform::*;
+
+use crate::render::window::{ActiveWindowData, WindowData};
+
+mod border;
+mod brush;
+mod color;
+mod eventloop;
+mod gradient;
+mod image;
+mod rect;
+mod render;
+mod scene;
+mod text;
+mod transform;
+
+pub mod reftest;
+
+mod debug;
+
+#[derive(Clone)]
+pub struct HeadlessBackend;
+
+impl Debug for HeadlessBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HeadlessRenderer").finish()
+    }
+}
+
+impl HasFontManager for HeadlessBackend {
+    type FontManager = FontManager;
+}
+
+impl RenderBackend for HeadlessBackend {
+    type Rect = Rect;
+    type Border = Border;
+    type BorderSide = BorderSide;
+    type BorderRadius = BorderRadius;
+    type Transform = Transform;
+    type Gradient = Gradient;
+    type Color = Color;
+    type Image = Image;
+    type Brush = Brush;
+    type Scene = Scene;
+    type Text = Text;
+    type SVGRenderer = gosub_svg::resvg::Resvg;
+    type FontManager = FontManager;
+
+    type ActiveWindowData<'a> = ActiveWindowData;
+    type WindowData<'a> = WindowData;
+
+    fn draw_rect(&mut self, data: &mut Self::WindowData<'_>, rect: &RenderRect<Self>) {
+        data.scene.draw_rect(rect);
+    }
+
+    fn draw_text(&mut self, data: &mut Self::WindowData<'_>, text: &RenderText<Self>) {
+        data.scene.draw_text(text);
+    }
+
+    fn apply_scene(
+        &mut self,
+        data: &mut Self::WindowData<'_>,
+        scene: &Self::Scene,
+        transform: Option<Self::Transform>,
+    ) {
+        data.scene.apply_scene(scene, transform);
+    }
+
+    fn reset(&mut self, data: &mut Self::WindowData<'_>) {
+        data.scene.reset();
+    }
+
+    fn activate_window<'a>(
+        &mut self,
+        _handle: impl WindowHandle + 'a,
+        _data: &mut Self::WindowData<'_>,
+        size: SizeU32,
+    ) -> Result<Self::ActiveWindowData<'a>> {
+        // The window handle is never touched. Whatever window we are attached to, we only render into our own
+        // framebuffer which can be read back by the caller.
+        Ok(ActiveWindowData {
+            framebuffer: Framebuffer::new(size)?,
+        })
+    }
+
+    fn suspend_window(
+        &mut self,
+        _handle: impl WindowHandle,
+        _data: &mut Self::ActiveWindowData<'_>,
+        _window_data: &mut Self::WindowData<'_>,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn create_window_data<'a>(&mut self, _handle: impl WindowHandle) -> Result<Self::WindowData<'a>> {
+        Ok(WindowData::default())
+    }
+
+    fn resize_window(
+        &mut self,
+        _window_data: &mut Self::WindowData<'_>,
+        active_window_data: &mut Self::ActiveWindowData<'_>,
+        size: SizeU32,
+    ) -> Result<()> {
+        info!("HeadlessBackend::resize_window({}x{})", size.width, size.height);
+
+        active_window_data.framebuffer = Framebuffer::new(size)?;
+
+        Ok(())
+    }
+
+    fn render(
+        &mut self,
+        window_data: &mut Self::WindowData<'_>,
+        active_data: &mut Self::ActiveWindowData<'_>,
+    ) -> Result<()> {
+        active_data.framebuffer.clear(Color::WHITE);
+        active_data.framebuffer.draw_scene(&window_data.scene);
+
+        Ok(())
+    }
+}
+
+impl HeadlessBackend {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// Rasterizes the given scene into a new framebuffer of the given size. The framebuffer is cleared to white
+    /// before the scene is drawn, just like the window based backends do.
+    pub fn render_scene(&mut self, scene: &Scene, size: SizeU32) -> Result<Framebuffer> {
+        let mut window_data = WindowData { scene: scene.clone() };
+        let mut active_data = ActiveWindowData {
+            framebuffer: Framebuffer::new(size)?,
+        };
+
+        self.render(&mut window_data, &mut active_data)?;
+
+        Ok(active_data.framebuffer)
+    }
+}
+
+impl Default for HeadlessBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+trait Convert<T> {
+    fn convert(self) -> T;
+}
+
+impl Convert<tiny_skia::Point> for Point {
+    fn convert(self) -> tiny_skia::Point {
+        tiny_skia::Point::from_xy(self.x, self.y)
+    }
+}
+
+/// Converts any kurbo shape into a tiny-skia path. Returns None when the shape is empty.
+pub(crate) fn to_path(shape: &impl kurbo::Shape) -> Option<tiny_skia::Path> {
+    let mut pb = tiny_skia::PathBuilder::new();
+
+    for el in shape.path_elements(0.1) {
+        match el {
+            kurbo::PathEl::MoveTo(p) => pb.move_to(p.x as f32, p.y as f32),
+            kurbo::PathEl::LineTo(p) => pb.line_to(p.x as f32, p.y as f32),
+            kurbo::PathEl::QuadTo(p1, p2) => pb.quad_to(p1.x as f32, p1.y as f32, p2.x as f32, p2.y as f32),
+            kurbo::PathEl::CurveTo(p1, p2, p3) => pb.cubic_to(
+                p1.x as f32,
+                p1.y as f32,
+                p2.x as f32,
+                p2.y as f32,
+                p3.x as f32,
+                p3.y as f32,
+            ),
+            kurbo::PathEl::ClosePath => pb.close(),
+        }
+    }
+
+    pb.finish()
+}
@@ -0,0 +1,22 @@
+use gosub_interface::render_backend::Rect as TRect;
+use gosub_shared::geo::{Point, Size, FP};
+use kurbo::Rect as KurboRect;
+
+#[derive(Clone, Debug)]
+pub struct Rect(pub(crate) KurboRect);
+
+impl From<KurboRect> for Rect {
+    fn from(rect: KurboRect) -> Self {
+        Rect(rect)
+    }
+}
+
+impl TRect for Rect {
+    fn new(x: FP, y: FP, width: FP, height: FP) -> Self {
+        KurboRect::new(x as f64, y as f64, x as f64 + width as f64, y as f64 + height as f64).into()
+    }
+
+    fn from_point(point: Point, size: Size) -> Self {
+        TRect::new(point.x, point.y, size.width, size.height)
+    }
+}
@@ -0,0 +1,370 @@
+//! Reference image tests
+//!
+//! A reftest consists of a test file and a reference file. Both are rendered with the headless backend and the
+//! resulting images are compared pixel by pixel. A "match" test passes when both images are (almost) the same, a
+//! "mismatch" test passes when they differ.
+//!
+//! Tests are found by naming convention: for every `name.html` there must be either a `name-ref.html` (match) or a
+//! `name-notref.html` (mismatch) next to it.
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use gosub_interface::config::{HasDocument, HasHtmlParser, HasScriptExecutor, HasTreeDrawer};
+use gosub_interface::document::Document;
+use gosub_interface::draw::TreeDrawer;
+use gosub_interface::render_tree::RenderTree;
+use gosub_shared::geo::SizeU32;
+use gosub_shared::types::Result;
+use image::{Rgba, RgbaImage};
+use url::Url;
+use walkdir::WalkDir;
+
+use crate::{render_drawer, Framebuffer, HeadlessBackend};
+
+const REF_SUFFIX: &str = "-ref";
+const NOTREF_SUFFIX: &str = "-notref";
+
+/// Whether the test and reference should render the same or not
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RefKind {
+    Match,
+    Mismatch,
+}
+
+/// A single test file together with its reference file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RefTest {
+    pub test: PathBuf,
+    pub reference: PathBuf,
+    pub kind: RefKind,
+}
+
+impl Display for RefTest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let op = match self.kind {
+            RefKind::Match => "==",
+            RefKind::Mismatch => "!=",
+        };
+
+        write!(f, "{} {} {}", self.test.display(), op, self.reference.display())
+    }
+}
+
+/// Allowed differences between two images before they are considered different
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tolerance {
+    /// Maximum difference of a single color channel before a pixel is counted as different
+    pub max_channel_diff: u8,
+    /// Maximum number of different pixels
+    pub max_differing_pixels: u64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            max_channel_diff: 2,
+            max_differing_pixels: 0,
+        }
+    }
+}
+
+/// Result of comparing two images
+#[derive(Clone, Debug)]
+pub struct ImageDiff {
+    /// Number of pixels that differ more than the tolerance allows
+    pub differing_pixels: u64,
+    /// Largest channel difference found in the whole image
+    pub max_channel_diff: u8,
+    /// Image where differing pixels are red and all other pixels are a faded version of the test image
+    pub image: RgbaImage,
+}
+
+impl ImageDiff {
+    /// Returns true when the images are considered the same within the given tolerance
+    pub fn is_match(&self, tolerance: Tolerance) -> bool {
+        self.differing_pixels <= tolerance.max_differing_pixels
+    }
+}
+
+/// Compares two images pixel by pixel. When the images do not have the same size, every pixel that is only present
+/// in one of the images counts as a different pixel.
+pub fn compare_images(test: &RgbaImage, reference: &RgbaImage, tolerance: Tolerance) -> ImageDiff {
+    let width = test.width().max(reference.width());
+    let height = test.height().max(reference.height());
+
+    let mut image = RgbaImage::new(width, height);
+    let mut differing_pixels = 0;
+    let mut max_channel_diff = 0;
+
+    for y in 0..height {
+        for x in 0..width {
+            let (Some(a), Some(b)) = (test.get_pixel_checked(x, y), reference.get_pixel_checked(x, y)) else {
+                differing_pixels += 1;
+                max_channel_diff = u8::MAX;
+                image.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+                continue;
+            };
+
+            let diff =
+                a.0.iter()
+                    .zip(b.0.iter())
+                    .map(|(a, b)| a.abs_diff(*b))
+                    .max()
+                    .unwrap_or(0);
+
+            max_channel_diff = max_channel_diff.max(diff);
+
+            if diff > tolerance.max_channel_diff {
+                differing_pixels += 1;
+                image.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+            } else {
+                let luma = (a.0[0] as u32 * 299 + a.0[1] as u32 * 587 + a.0[2] as u32 * 114) / 1000;
+                let faded = (192 + luma / 4) as u8;
+                image.put_pixel(x, y, Rgba([faded, faded, faded, 255]));
+            }
+        }
+    }
+
+    ImageDiff {
+        differing_pixels,
+        max_channel_diff,
+        image,
+    }
+}
+
+/// Finds all reftests in the given path. The path can be a single test file or a directory, which is searched
+/// recursively. The tests are returned in a stable (sorted) order.
+pub fn discover(path: impl AsRef<Path>) -> Result<Vec<RefTest>> {
+    let path = path.as_ref();
+
+    if path.is_file() {
+        let test = find_reference(path).ok_or_else(|| anyhow!("No reference file found for {}", path.display()))?;
+        return Ok(vec![test]);
+    }
+
+    let mut tests = Vec::new();
+    for entry in WalkDir::new(path).sort_by_file_name() {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        if let Some(test) = find_reference(entry.path()) {
+            tests.push(test);
+        }
+    }
+
+    Ok(tests)
+}
+
+/// Returns the reftest for the given test file when it has a reference file next to it
+fn find_reference(path: &Path) -> Option<RefTest> {
+    let ext = path.extension()?.to_str()?;
+    if ext != "html" && ext != "htm" {
+        return None;
+    }
+
+    let stem = path.file_stem()?.to_str()?;
+    if stem.ends_with(REF_SUFFIX) || stem.ends_with(NOTREF_SUFFIX) {
+        return None;
+    }
+
+    for (suffix, kind) in [(REF_SUFFIX, RefKind::Match), (NOTREF_SUFFIX, RefKind::Mismatch)] {
+        let reference = path.with_file_name(format!("{stem}{suffix}.{ext}"));
+        if reference.is_file() {
+            return Some(RefTest {
+                test: path.to_path_buf(),
+                reference,
+                kind,
+            });
+        }
+    }
+
+    None
+}
+
+/// Options used when running reftests
+#[derive(Clone, Debug)]
+pub struct RefTestOptions {
+    /// Size of the viewport the files are rendered in
+    pub size: SizeU32,
+    pub tolerance: Tolerance,
+    /// Maximum time to wait for each batch of images referenced by the files
+    pub image_timeout: Duration,
+    /// Directory where the rendered images and diff image are stored for failing tests
+    pub output: Option<PathBuf>,
+}
+
+impl Default for RefTestOptions {
+    fn default() -> Self {
+        Self {
+            size: SizeU32::new(800, 600),
+            tolerance: Tolerance::default(),
+            image_timeout: Duration::from_millis(500),
+            output: None,
+        }
+    }
+}
+
+/// Result of a single reftest
+#[derive(Debug)]
+pub struct RefTestResult {
+    pub test: RefTest,
+    pub passed: bool,
+    pub diff: ImageDiff,
+    /// Path of the diff image, when it has been written
+    pub diff_path: Option<PathBuf>,
+}
+
+/// Renders a single html file with the headless backend
+pub fn render_file<C>(path: impl AsRef<Path>, layouter: C::Layouter, options: &RefTestOptions) -> Result<Framebuffer>
+where
+    C: HasTreeDrawer<RenderBackend = HeadlessBackend> + HasDocument + HasHtmlParser + HasScriptExecutor,
+{
+    let path = std::fs::canonicalize(path.as_ref())?;
+    let url = Url::from_file_path(&path).map_err(|_| anyhow!("Invalid file path {}", path.display()))?;
+    let html = std::fs::read_to_string(&path)?;
+
+    let (mut drawer, mut doc) = C::TreeDrawer::from_source(url, &html, layouter, false)?;
+
+    // The render tree is generated for the default media environment, so media queries must be evaluated again
+    // against the size of the framebuffer
+    let env = doc
+        .media_environment()
+        .with_size(options.size.width as f32, options.size.height as f32);
+    if doc.set_media_environment(env) {
+        drawer.reload_from(<C::RenderTree as RenderTree<C>>::from_document(&doc));
+    }
+
+    render_drawer::<C>(&mut drawer, options.size, options.image_timeout)
+}
+
+/// Renders the test and reference file of the reftest and compares the results
+pub fn run_reftest<C>(test: &RefTest, layouter: C::Layouter, options: &RefTestOptions) -> Result<RefTestResult>
+where
+    C: HasTreeDrawer<RenderBackend = HeadlessBackend> + HasDocument + HasHtmlParser + HasScriptExecutor,
+{
+    let actual = render_file::<C>(&test.test, layouter.clone(), options)?.to_image();
+    let expected = render_file::<C>(&test.reference, layouter, options)?.to_image();
+
+    let diff = compare_images(&actual, &expected, options.tolerance);
+
+    let passed = match test.kind {
+        RefKind::Match => diff.is_match(options.tolerance),
+        RefKind::Mismatch => !diff.is_match(options.tolerance),
+    };
+
+    let mut diff_path = None;
+    if !passed {
+        if let Some(output) = &options.output {
+            std::fs::create_dir_all(output)?;
+
+            let name = test
+                .test
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("reftest")
+                .to_string();
+
+            actual.save(output.join(format!("{name}-actual.png")))?;
+            expected.save(output.join(format!("{name}-expected.png")))?;
+
+            let path = output.join(format!("{name}-diff.png"));
+            diff.image.save(&path)?;
+            diff_path = Some(path);
+        }
+    }
+
+    Ok(RefTestResult {
+        test: test.clone(),
+        passed,
+        diff,
+        diff_path,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, height: u32, color: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba(color))
+    }
+
+    #[test]
+    fn compare_identical() {
+        let a = image(4, 4, [10, 20, 30, 255]);
+        let diff = compare_images(&a, &a.clone(), Tolerance::default());
+
+        assert_eq!(diff.differing_pixels, 0);
+        assert_eq!(diff.max_channel_diff, 0);
+        assert!(diff.is_match(Tolerance::default()));
+    }
+
+    #[test]
+    fn compare_with_tolerance() {
+        let a = image(4, 4, [10, 20, 30, 255]);
+        let mut b = a.clone();
+        b.put_pixel(1, 1, Rgba([12, 20, 30, 255]));
+        b.put_pixel(2, 2, Rgba([200, 20, 30, 255]));
+
+        let tolerance = Tolerance {
+            max_channel_diff: 2,
+            max_differing_pixels: 0,
+        };
+        let diff = compare_images(&a, &b, tolerance);
+
+        assert_eq!(diff.differing_pixels, 1);
+        assert_eq!(diff.max_channel_diff, 190);
+        assert!(!diff.is_match(tolerance));
+        assert_eq!(diff.image.get_pixel(2, 2), &Rgba([255, 0, 0, 255]));
+        assert_ne!(diff.image.get_pixel(1, 1), &Rgba([255, 0, 0, 255]));
+
+        let tolerance = Tolerance {
+            max_channel_diff: 2,
+            max_differing_pixels: 1,
+        };
+        assert!(compare_images(&a, &b, tolerance).is_match(tolerance));
+    }
+
+    #[test]
+    fn compare_different_sizes() {
+        let a = image(4, 4, [0, 0, 0, 255]);
+        let b = image(4, 2, [0, 0, 0, 255]);
+
+        let diff = compare_images(&a, &b, Tolerance::default());
+        assert_eq!(diff.differing_pixels, 8);
+        assert_eq!(diff.image.dimensions(), (4, 4));
+    }
+
+    #[test]
+    fn discover_tests() {
+        let dir = std::env::temp_dir().join(format!("gosub-reftest-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+
+        for file in [
+            "a.html",
+            "a-ref.html",
+            "b.html",
+            "b-notref.html",
+            "c.html",
+            "sub/d.htm",
+            "sub/d-ref.htm",
+        ] {
+            std::fs::write(dir.join(file), "<p>test</p>").unwrap();
+        }
+
+        let tests = discover(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(tests.len(), 3);
+        assert_eq!(tests[0].test, dir.join("a.html"));
+        assert_eq!(tests[0].reference, dir.join("a-ref.html"));
+        assert_eq!(tests[0].kind, RefKind::Match);
+        assert_eq!(tests[1].test, dir.join("b.html"));
+        assert_eq!(tests[1].kind, RefKind::Mismatch);
+        assert_eq!(tests[2].test, dir.join("sub/d.htm"));
+    }
+}
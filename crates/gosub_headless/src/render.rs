@@ -0,0 +1,112 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+use gosub_shared::geo::SizeU32;
+use gosub_shared::types::Result;
+use image::RgbaImage;
+use tiny_skia::Pixmap;
+
+use crate::{Color, Scene, Transform};
+use gosub_interface::render_backend::Transform as _;
+
+pub mod window;
+
+/// In-memory RGBA framebuffer that scenes are rasterized into
+#[derive(Clone, Debug)]
+pub struct Framebuffer {
+    pixmap: Pixmap,
+}
+
+impl Framebuffer {
+    pub fn new(size: SizeU32) -> Result<Self> {
+        let pixmap = Pixmap::new(size.width, size.height)
+            .ok_or_else(|| anyhow!("Invalid framebuffer size {}x{}", size.width, size.height))?;
+
+        Ok(Self { pixmap })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.pixmap.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.pixmap.height()
+    }
+
+    pub fn size(&self) -> SizeU32 {
+        SizeU32::new(self.width(), self.height())
+    }
+
+    /// Fills the whole framebuffer with the given color
+    pub fn clear(&mut self, color: Color) {
+        self.pixmap.fill(color.into());
+    }
+
+    /// Rasterizes the scene on top of the current content of the framebuffer
+    pub fn draw_scene(&mut self, scene: &Scene) {
+        scene.draw(&mut self.pixmap.as_mut(), Transform::IDENTITY);
+    }
+
+    /// Returns the (non-premultiplied) RGBA value of the pixel at the given position
+    pub fn pixel(&self, x: u32, y: u32) -> Option<[u8; 4]> {
+        let c = self.pixmap.pixel(x, y)?.demultiply();
+
+        Some([c.red(), c.green(), c.blue(), c.alpha()])
+    }
+
+    /// Converts the framebuffer into an RGBA image
+    pub fn to_image(&self) -> RgbaImage {
+        let data = self
+            .pixmap
+            .pixels()
+            .iter()
+            .flat_map(|px| {
+                let c = px.demultiply();
+                [c.red(), c.green(), c.blue(), c.alpha()]
+            })
+            .collect();
+
+        // The buffer always has exactly width * height * 4 bytes
+        RgbaImage::from_raw(self.width(), self.height(), data).unwrap_or_default()
+    }
+
+    /// Encodes the framebuffer as PNG
+    pub fn encode_png(&self) -> Result<Vec<u8>> {
+        Ok(self.pixmap.encode_png()?)
+    }
+
+    /// Writes the framebuffer as PNG file to the given path
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<()> {
+        Ok(self.pixmap.save_png(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Brush, HeadlessBackend, Rect};
+    use gosub_interface::render_backend::{Brush as _, Rect as _, RenderRect, Scene as _};
+
+    #[test]
+    fn render_solid_rect() {
+        let mut scene = Scene::new();
+        let rect = RenderRect::<HeadlessBackend>::new(
+            Rect::new(10.0, 10.0, 20.0, 20.0),
+            Brush::color(Color::rgba8(255, 0, 0, 255)),
+        );
+        scene.draw_rect(&rect);
+
+        let fb = HeadlessBackend::new()
+            .render_scene(&scene, SizeU32::new(40, 40))
+            .unwrap();
+
+        assert_eq!(fb.size(), SizeU32::new(40, 40));
+        assert_eq!(fb.pixel(5, 5), Some([255, 255, 255, 255]));
+        assert_eq!(fb.pixel(20, 20), Some([255, 0, 0, 255]));
+        assert_eq!(fb.pixel(35, 35), Some([255, 255, 255, 255]));
+        assert_eq!(fb.pixel(40, 40), None);
+
+        let png = fb.encode_png().unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+    }
+}
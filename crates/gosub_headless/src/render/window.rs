@@ -0,0 +1,17 @@
+use crate::{Framebuffer, Scene};
+
+#[derive(Default)]
+pub struct WindowData {
+    pub(crate) scene: Scene,
+}
+
+pub struct ActiveWindowData {
+    pub(crate) framebuffer: Framebuffer,
+}
+
+impl ActiveWindowData {
+    /// Returns the framebuffer that the last call to `render()` has drawn into
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+}
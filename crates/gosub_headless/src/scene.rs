@@ -0,0 +1,114 @@
+use gosub_interface::render_backend::{
+    Point, RenderBackend, RenderRect, RenderText, Scene as TScene, Transform as TTransform, FP,
+};
+use kurbo::RoundedRect;
+use tiny_skia::{FillRule, PixmapMut};
+
+use crate::debug::text::render_text_simple;
+use crate::{to_path, Border, BorderRenderOptions, HeadlessBackend, Text, Transform};
+
+/// A scene command that is replayed onto a pixmap when the scene gets rasterized
+#[derive(Clone, Debug)]
+enum SceneCommand {
+    // Draw a rectangle, including rounded corners and border
+    Rectangle(Box<RenderRect<HeadlessBackend>>),
+    // Draw a text
+    Text(Box<RenderText<HeadlessBackend>>),
+    // Draw a simple text without too much decoration and in a single font / color
+    SimpleText { text: String, pos: Point, size: FP },
+    // Draw another scene on a certain transform (translation, rotation, scale)
+    Scene { scene: Scene, transform: Transform },
+}
+
+/// A scene holds all the commands that must be drawn. Nothing is rasterized until the scene is drawn onto a
+/// framebuffer.
+#[derive(Clone, Debug, Default)]
+pub struct Scene {
+    commands: Vec<SceneCommand>,
+}
+
+impl Scene {
+    pub fn create() -> Self {
+        Self::default()
+    }
+
+    /// Returns true when nothing has been drawn onto this scene
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Rasterizes all commands of this scene onto the given pixmap
+    pub(crate) fn draw(&self, pixmap: &mut PixmapMut, transform: Transform) {
+        for command in &self.commands {
+            match command {
+                SceneCommand::Rectangle(rect) => Self::draw_rect_command(pixmap, rect, transform),
+                SceneCommand::Text(text) => Text::show(pixmap, text, transform),
+                SceneCommand::SimpleText { text, pos, size } => {
+                    render_text_simple(pixmap, text, *pos, *size, transform)
+                }
+                SceneCommand::Scene {
+                    scene,
+                    transform: scene_transform,
+                } => scene.draw(pixmap, transform * *scene_transform),
+            }
+        }
+    }
+
+    fn draw_rect_command(pixmap: &mut PixmapMut, rect: &RenderRect<HeadlessBackend>, parent: Transform) {
+        let transform = parent * rect.transform.unwrap_or(Transform::IDENTITY);
+        let brush_transform = rect.brush_transform.map(|t| t.0);
+
+        let path = match &rect.radius {
+            Some(radius) => to_path(&RoundedRect::from_rect(rect.rect.0, radius.clone())),
+            None => to_path(&rect.rect.0),
+        };
+
+        if let (Some(path), Some(paint)) = (path, rect.brush.paint(brush_transform)) {
+            pixmap.fill_path(&path, &paint, FillRule::Winding, transform.0, None);
+        }
+
+        if let Some(border) = &rect.border {
+            let opts = BorderRenderOptions {
+                border,
+                rect: &rect.rect,
+                transform: Some(&transform),
+                radius: rect.radius.as_ref(),
+            };
+
+            Border::draw(pixmap, opts);
+        }
+    }
+}
+
+impl TScene<HeadlessBackend> for Scene {
+    fn draw_rect(&mut self, rect: &RenderRect<HeadlessBackend>) {
+        self.commands.push(SceneCommand::Rectangle(Box::new(rect.clone())));
+    }
+
+    fn draw_text(&mut self, text: &RenderText<HeadlessBackend>) {
+        self.commands.push(SceneCommand::Text(Box::new(text.clone())));
+    }
+
+    fn debug_draw_simple_text(&mut self, text: &str, pos: Point, size: FP) {
+        self.commands.push(SceneCommand::SimpleText {
+            text: text.to_string(),
+            pos,
+            size,
+        });
+    }
+
+    fn apply_scene(&mut self, scene: &<HeadlessBackend as RenderBackend>::Scene, transform: Option<Transform>) {
+        self.commands.push(SceneCommand::Scene {
+            scene: scene.clone(),
+            transform: transform.unwrap_or(Transform::IDENTITY),
+        });
+    }
+
+    fn reset(&mut self) {
+        self.commands.clear();
+    }
+
+    fn new() -> Self {
+        Self::default()
+    }
+}
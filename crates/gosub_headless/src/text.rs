@@ -0,0 +1,207 @@
+use crate::{Brush, Color, HeadlessBackend, Transform};
+use gosub_interface::font::FontBlob;
+use gosub_interface::layout::{Decoration, TextLayout};
+use gosub_interface::render_backend::{RenderText, Text as TText, Transform as TTransform};
+use gosub_shared::font::Glyph;
+use gosub_shared::geo::{NormalizedCoord, Point, FP};
+use skrifa::instance::{LocationRef, NormalizedCoord as SkrifaCoord, Size};
+use skrifa::outline::{DrawSettings, OutlinePen};
+use skrifa::raw::FileRef;
+use skrifa::{FontRef, GlyphId, MetadataProvider};
+use tiny_skia::{FillRule, PathBuilder, PixmapMut, Stroke};
+
+#[derive(Clone, Debug)]
+pub struct Text {
+    glyphs: Vec<Glyph>,
+    fs: FP,
+    font_data: FontBlob,
+    coords: Vec<NormalizedCoord>,
+    decoration: Decoration,
+    offset: Point,
+}
+
+impl Text {
+    pub(crate) fn show(pixmap: &mut PixmapMut, render: &RenderText<HeadlessBackend>, parent: Transform) {
+        let transform = parent * render.transform.unwrap_or(Transform::IDENTITY);
+        let brush_transform = render.brush_transform.map(|t| t.0);
+
+        let x = render.rect.0.x0 as FP;
+        let y = render.rect.0.y0 as FP;
+        let width = render.rect.0.width() as FP;
+        let height = render.rect.0.height() as FP;
+
+        for text in &render.text {
+            let glyph_transform = transform.pre_translate(x + text.offset.x, y + text.offset.y);
+
+            if let Some(path) = text.outline() {
+                if let Some(paint) = render.brush.paint(brush_transform) {
+                    pixmap.fill_path(&path, &paint, FillRule::Winding, glyph_transform.0, None);
+                }
+            }
+
+            {
+                let decoration = &text.decoration;
+
+                let c = decoration.color;
+
+                let brush = Brush::Solid(Color::rgba8(
+                    (c.0 * 255.0) as u8,
+                    (c.1 * 255.0) as u8,
+                    (c.2 * 255.0) as u8,
+                    (c.3 * 255.0) as u8,
+                ));
+
+                let offset = decoration.x_offset;
+
+                if decoration.underline {
+                    let y = y + decoration.underline_offset + height;
+
+                    draw_line(
+                        pixmap,
+                        &brush,
+                        decoration.width,
+                        (x + offset, y),
+                        (x + width, y),
+                        transform,
+                    );
+                }
+
+                if decoration.overline {
+                    draw_line(
+                        pixmap,
+                        &brush,
+                        decoration.width,
+                        (x + offset, y),
+                        (x + width, y),
+                        transform,
+                    );
+                }
+
+                if decoration.line_through {
+                    let y = y + height / 2.0;
+
+                    draw_line(
+                        pixmap,
+                        &brush,
+                        decoration.width,
+                        (x + offset, y),
+                        (x + width, y),
+                        transform,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Converts all glyphs of this text into a single path, relative to the text origin
+    fn outline(&self) -> Option<tiny_skia::Path> {
+        let font = to_font_ref(&self.font_data)?;
+        let outlines = font.outline_glyphs();
+
+        let coords = self
+            .coords
+            .iter()
+            .map(|c| SkrifaCoord::from_bits(*c))
+            .collect::<Vec<_>>();
+
+        let location = LocationRef::new(&coords);
+
+        let mut pen = GlyphPen::default();
+
+        for glyph in &self.glyphs {
+            let Some(outline) = outlines.get(GlyphId::new(glyph.id as u32)) else {
+                continue;
+            };
+
+            pen.origin = (glyph.x, glyph.y);
+            _ = outline.draw(DrawSettings::unhinted(Size::new(self.fs), location), &mut pen);
+        }
+
+        pen.builder.finish()
+    }
+}
+
+impl TText for Text {
+    fn new(layout: &impl TextLayout) -> Self {
+        Self {
+            glyphs: layout.glyphs().to_vec(),
+            font_data: layout.font_data().clone(),
+            fs: layout.font_size(),
+            coords: layout.coords().to_vec(),
+            decoration: layout.decorations().clone(),
+            offset: layout.offset(),
+        }
+    }
+}
+
+fn draw_line(pixmap: &mut PixmapMut, brush: &Brush, width: f32, from: (FP, FP), to: (FP, FP), transform: Transform) {
+    let mut pb = PathBuilder::new();
+    pb.move_to(from.0, from.1);
+    pb.line_to(to.0, to.1);
+
+    let Some(path) = pb.finish() else {
+        return;
+    };
+
+    let Some(paint) = brush.paint(None) else {
+        return;
+    };
+
+    let stroke = Stroke {
+        width,
+        ..Default::default()
+    };
+
+    pixmap.stroke_path(&path, &paint, &stroke, transform.0, None);
+}
+
+pub(crate) fn to_font_ref(font: &FontBlob) -> Option<FontRef<'_>> {
+    let file_ref = FileRef::new(font.as_u8()).ok()?;
+    match file_ref {
+        FileRef::Font(font) => Some(font),
+        FileRef::Collection(collection) => collection.get(font.index).ok(),
+    }
+}
+
+/// Pen that collects glyph outlines into a tiny-skia path. Font outlines are y-up, so they are flipped and moved
+/// to the glyph origin.
+#[derive(Default)]
+pub(crate) struct GlyphPen {
+    pub(crate) builder: PathBuilder,
+    pub(crate) origin: (f32, f32),
+}
+
+impl GlyphPen {
+    fn map(&self, x: f32, y: f32) -> (f32, f32) {
+        (self.origin.0 + x, self.origin.1 - y)
+    }
+}
+
+impl OutlinePen for GlyphPen {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.map(x, y);
+        self.builder.move_to(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.map(x, y);
+        self.builder.line_to(x, y);
+    }
+
+    fn quad_to(&mut self, cx0: f32, cy0: f32, x: f32, y: f32) {
+        let (cx0, cy0) = self.map(cx0, cy0);
+        let (x, y) = self.map(x, y);
+        self.builder.quad_to(cx0, cy0, x, y);
+    }
+
+    fn curve_to(&mut self, cx0: f32, cy0: f32, cx1: f32, cy1: f32, x: f32, y: f32) {
+        let (cx0, cy0) = self.map(cx0, cy0);
+        let (cx1, cy1) = self.map(cx1, cy1);
+        let (x, y) = self.map(x, y);
+        self.builder.cubic_to(cx0, cy0, cx1, cy1, x, y);
+    }
+
+    fn close(&mut self) {
+        self.builder.close();
+    }
+}
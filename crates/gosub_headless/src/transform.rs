@@ -0,0 +1,139 @@
+use std::ops::{Mul, MulAssign};
+
+use gosub_interface::render_backend::Transform as TTransform;
+use gosub_shared::geo::{Point, FP};
+use tiny_skia::Transform as SkiaTransform;
+
+/// Affine transform. Composition follows the same rules as the other backends: `a * b` applies `b` first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform(pub(crate) SkiaTransform);
+
+impl From<SkiaTransform> for Transform {
+    fn from(transform: SkiaTransform) -> Self {
+        Transform(transform)
+    }
+}
+
+impl Transform {
+    const fn from_row(sx: f32, ky: f32, kx: f32, sy: f32, tx: f32, ty: f32) -> Self {
+        Transform(SkiaTransform { sx, kx, ky, sy, tx, ty })
+    }
+}
+
+impl Mul<Self> for Transform {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Transform(self.0.pre_concat(rhs.0))
+    }
+}
+
+impl MulAssign for Transform {
+    fn mul_assign(&mut self, rhs: Self) {
+        self.0 = self.0.pre_concat(rhs.0);
+    }
+}
+
+impl TTransform for Transform {
+    const IDENTITY: Self = Transform::from_row(1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+    const FLIP_X: Self = Transform::from_row(1.0, 0.0, 0.0, -1.0, 0.0, 0.0);
+    const FLIP_Y: Self = Transform::from_row(-1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+
+    fn scale(s: FP) -> Self {
+        SkiaTransform::from_scale(s, s).into()
+    }
+
+    fn scale_xy(sx: FP, sy: FP) -> Self {
+        SkiaTransform::from_scale(sx, sy).into()
+    }
+
+    fn translate(x: FP, y: FP) -> Self {
+        SkiaTransform::from_translate(x, y).into()
+    }
+
+    fn rotate(angle: FP) -> Self {
+        SkiaTransform::from_rotate(angle.to_degrees()).into()
+    }
+
+    fn rotate_around(angle: FP, center: Point) -> Self {
+        SkiaTransform::from_rotate_at(angle.to_degrees(), center.x, center.y).into()
+    }
+
+    fn skew_x(angle: FP) -> Self {
+        SkiaTransform::from_skew(angle, 0.0).into()
+    }
+
+    fn skew_y(angle: FP) -> Self {
+        SkiaTransform::from_skew(0.0, angle).into()
+    }
+
+    fn skew_xy(angle_x: FP, angle_y: FP) -> Self {
+        SkiaTransform::from_skew(angle_x, angle_y).into()
+    }
+
+    fn pre_scale(self, s: FP) -> Self {
+        self.0.pre_scale(s, s).into()
+    }
+
+    fn pre_scale_xy(self, sx: FP, sy: FP) -> Self {
+        self.0.pre_scale(sx, sy).into()
+    }
+
+    fn pre_translate(self, x: FP, y: FP) -> Self {
+        self.0.pre_translate(x, y).into()
+    }
+
+    fn pre_rotate(self, angle: FP) -> Self {
+        self.0.pre_rotate(angle.to_degrees()).into()
+    }
+
+    fn pre_rotate_around(self, angle: FP, center: Point) -> Self {
+        self.0.pre_rotate_at(angle.to_degrees(), center.x, center.y).into()
+    }
+
+    fn then_scale(self, s: FP) -> Self {
+        self.0.post_scale(s, s).into()
+    }
+
+    fn then_scale_xy(self, sx: FP, sy: FP) -> Self {
+        self.0.post_scale(sx, sy).into()
+    }
+
+    fn then_translate(self, x: FP, y: FP) -> Self {
+        self.0.post_translate(x, y).into()
+    }
+
+    fn then_rotate(self, angle: FP) -> Self {
+        self.0.post_rotate(angle.to_degrees()).into()
+    }
+
+    fn then_rotate_around(self, angle: FP, center: Point) -> Self {
+        self.0.post_rotate_at(angle.to_degrees(), center.x, center.y).into()
+    }
+
+    fn as_matrix(&self) -> [FP; 6] {
+        let t = self.0;
+        [t.sx, t.ky, t.kx, t.sy, t.tx, t.ty]
+    }
+
+    fn from_matrix(matrix: [FP; 6]) -> Self {
+        SkiaTransform::from_row(matrix[0], matrix[1], matrix[2], matrix[3], matrix[4], matrix[5]).into()
+    }
+
+    fn determinant(&self) -> FP {
+        self.0.sx * self.0.sy - self.0.ky * self.0.kx
+    }
+
+    fn inverse(self) -> Self {
+        self.0.invert().unwrap_or_default().into()
+    }
+
+    fn with_translation(&self, translation: Point) -> Self {
+        let mut this = *self;
+
+        this.0.tx = translation.x;
+        this.0.ty = translation.y;
+
+        this
+    }
+}
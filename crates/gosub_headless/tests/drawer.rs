@@ -0,0 +1,126 @@
+//! Draws pages with the tree drawer onto the headless backend, and checks the pixels of the encoded PNG image.
+use gosub_css3::system::Css3System;
+use gosub_headless::{render_drawer, HeadlessBackend, HeadlessEventLoop};
+use gosub_html5::document::builder::DocumentBuilderImpl;
+use gosub_html5::document::document_impl::DocumentImpl;
+use gosub_html5::document::fragment::DocumentFragmentImpl;
+use gosub_html5::parser::Html5Parser;
+use gosub_interface::config::{
+    HasCssSystem, HasDocument, HasHtmlParser, HasLayouter, HasRenderBackend, HasRenderTree, HasScriptExecutor,
+    HasTreeDrawer,
+};
+use gosub_interface::draw::TreeDrawer;
+use gosub_interface::eventloop::EventLoopHandle;
+use gosub_interface::font::HasFontManager;
+use gosub_interface::html5::{Script, ScriptExecutor};
+use gosub_renderer::draw::TreeDrawerImpl;
+use gosub_rendering::render_tree::RenderTree;
+use gosub_shared::geo::SizeU32;
+use gosub_shared::types::Result;
+use gosub_taffy::TaffyLayouter;
+use std::time::Duration;
+use url::Url;
+
+#[derive(Clone, Debug, PartialEq)]
+struct Config;
+
+impl HasCssSystem for Config {
+    type CssSystem = Css3System;
+}
+impl HasDocument for Config {
+    type Document = DocumentImpl<Self>;
+    type DocumentFragment = DocumentFragmentImpl<Self>;
+    type DocumentBuilder = DocumentBuilderImpl;
+}
+impl HasHtmlParser for Config {
+    type HtmlParser = Html5Parser<'static, Self>;
+}
+impl HasLayouter for Config {
+    type Layouter = TaffyLayouter;
+    type LayoutTree = RenderTree<Self>;
+}
+impl HasRenderTree for Config {
+    type RenderTree = RenderTree<Self>;
+}
+impl HasTreeDrawer for Config {
+    type TreeDrawer = TreeDrawerImpl<Self>;
+}
+impl HasRenderBackend for Config {
+    type RenderBackend = HeadlessBackend;
+}
+impl HasFontManager for Config {
+    type FontManager = gosub_fontmanager::FontManager;
+}
+impl HasScriptExecutor for Config {
+    type ScriptExecutor = NoScripts;
+}
+
+/// The pages are drawn from their source, which never runs scripts
+struct NoScripts;
+
+impl ScriptExecutor<Config> for NoScripts {
+    fn new(_url: &Url) -> Result<Self> {
+        Ok(Self)
+    }
+
+    fn execute(&mut self, _script: &Script, _document: &mut DocumentImpl<Config>) -> Result<()> {
+        Ok(())
+    }
+}
+
+const WIDTH: u32 = 100;
+const HEIGHT: u32 = 100;
+
+fn page(color: &str) -> String {
+    format!(
+        r#"<html><head><style>
+            body {{ margin: 0; }}
+            div {{ width: 50px; height: 50px; background-color: {color}; }}
+        </style></head><body><div></div></body></html>"#
+    )
+}
+
+fn load(html: &str) -> (TreeDrawerImpl<Config>, DocumentImpl<Config>) {
+    let url = Url::parse("https://example.com/").unwrap();
+    TreeDrawerImpl::<Config>::from_source(url, html, TaffyLayouter, false).unwrap()
+}
+
+/// Renders the drawer, and decodes the encoded PNG again
+fn render_png(drawer: &mut TreeDrawerImpl<Config>) -> image::RgbaImage {
+    let fb = render_drawer::<Config>(drawer, SizeU32::new(WIDTH, HEIGHT), Duration::ZERO).unwrap();
+    let png = fb.encode_png().unwrap();
+
+    image::load_from_memory_with_format(&png, image::ImageFormat::Png)
+        .unwrap()
+        .to_rgba8()
+}
+
+#[test]
+fn draw_page_to_png() {
+    let (mut drawer, _doc) = load(&page("#ff0000"));
+    let img = render_png(&mut drawer);
+
+    assert_eq!(img.dimensions(), (WIDTH, HEIGHT));
+    assert_eq!(img.get_pixel(25, 25).0, [255, 0, 0, 255]);
+    assert_eq!(img.get_pixel(75, 25).0, [255, 255, 255, 255]);
+    assert_eq!(img.get_pixel(25, 75).0, [255, 255, 255, 255]);
+}
+
+#[test]
+fn reload_from_event_loop() {
+    let (mut drawer, _doc) = load(&page("#ff0000"));
+    let (_, doc) = load(&page("#0000ff"));
+
+    let el = HeadlessEventLoop::<Config>::new();
+    assert!(el.take_tree().is_none());
+
+    el.reload_from(RenderTree::from_document(&doc));
+    assert!(el.take_redraw());
+
+    drawer.reload_from(el.take_tree().unwrap());
+    assert!(el.take_tree().is_none());
+
+    let img = render_png(&mut drawer);
+    assert_eq!(img.get_pixel(25, 25).0, [0, 0, 255, 255]);
+    assert_eq!(img.get_pixel(75, 75).0, [255, 255, 255, 255]);
+}
@@ -0,0 +1,94 @@
+//! Reftests
+//!
+//! Renders every test and reference file in `tests/data/reftests` of the workspace with the headless backend, and
+//! fails when a pair does not compare as expected.
+use std::path::PathBuf;
+
+use gosub_css3::system::Css3System;
+use gosub_headless::reftest::{discover, run_reftest, RefTestOptions};
+use gosub_headless::HeadlessBackend;
+use gosub_html5::document::builder::DocumentBuilderImpl;
+use gosub_html5::document::document_impl::DocumentImpl;
+use gosub_html5::document::fragment::DocumentFragmentImpl;
+use gosub_html5::parser::Html5Parser;
+use gosub_interface::config::{
+    HasCssSystem, HasDocument, HasHtmlParser, HasLayouter, HasRenderBackend, HasRenderTree, HasScriptExecutor,
+    HasTreeDrawer,
+};
+use gosub_interface::font::HasFontManager;
+use gosub_interface::html5::{Script, ScriptExecutor};
+use gosub_renderer::draw::TreeDrawerImpl;
+use gosub_rendering::render_tree::RenderTree;
+use gosub_shared::types::Result;
+use gosub_taffy::TaffyLayouter;
+use url::Url;
+
+#[derive(Clone, Debug, PartialEq)]
+struct Config;
+
+impl HasCssSystem for Config {
+    type CssSystem = Css3System;
+}
+impl HasDocument for Config {
+    type Document = DocumentImpl<Self>;
+    type DocumentFragment = DocumentFragmentImpl<Self>;
+    type DocumentBuilder = DocumentBuilderImpl;
+}
+impl HasHtmlParser for Config {
+    type HtmlParser = Html5Parser<'static, Self>;
+}
+impl HasLayouter for Config {
+    type Layouter = TaffyLayouter;
+    type LayoutTree = RenderTree<Self>;
+}
+impl HasRenderTree for Config {
+    type RenderTree = RenderTree<Self>;
+}
+impl HasTreeDrawer for Config {
+    type TreeDrawer = TreeDrawerImpl<Self>;
+}
+impl HasRenderBackend for Config {
+    type RenderBackend = HeadlessBackend;
+}
+impl HasFontManager for Config {
+    type FontManager = gosub_fontmanager::FontManager;
+}
+impl HasScriptExecutor for Config {
+    type ScriptExecutor = NoScripts;
+}
+
+/// The reftests are rendered from their source, which never runs scripts
+struct NoScripts;
+
+impl ScriptExecutor<Config> for NoScripts {
+    fn new(_url: &Url) -> Result<Self> {
+        Ok(Self)
+    }
+
+    fn execute(&mut self, _script: &Script, _document: &mut DocumentImpl<Config>) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn reftests() {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../tests/data/reftests");
+    let tests = discover(&path).unwrap();
+    assert!(!tests.is_empty(), "no reftests found in {}", path.display());
+
+    let failures = tests
+        .iter()
+        .filter_map(
+            |test| match run_reftest::<Config>(test, TaffyLayouter, &RefTestOptions::default()) {
+                Ok(result) if result.passed => None,
+                Ok(result) => Some(format!(
+                    "{test}: {} pixels differ, max channel difference {}",
+                    result.diff.differing_pixels, result.diff.max_channel_diff
+                )),
+                Err(e) => Some(format!("{test}: {e}")),
+            },
+        )
+        .collect::<Vec<_>>();
+
+    assert!(failures.is_empty(), "failing reftests:\n{}", failures.join("\n"));
+}
@@ -1,11 +1,13 @@
 use core::fmt::Debug;
 use gosub_interface::document::{Document as OtherDocument, Document, DocumentType};
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::fmt::{Display, Formatter};
 use url::Url;
 
+use crate::document::mutation::MutationObservers;
+use crate::document::query::DocumentQuery;
 use crate::document::task_queue::is_valid_id_attribute_value;
 use crate::node::arena::NodeArena;
 use crate::node::data::comment::CommentData;
@@ -15,11 +17,16 @@ use crate::node::data::element::{ClassListImpl, ElementData};
 use crate::node::data::text::TextData;
 use crate::node::node_impl::{NodeDataTypeInternal, NodeImpl};
 use crate::node::visitor::Visitor;
+use crate::parser::query::SearchType;
+use crate::writer::DocumentWriter;
 use gosub_interface::config::HasDocument;
+use gosub_interface::css3::{CssStylesheet, MediaEnvironment};
+use gosub_interface::mutation::{MutationObserverId, MutationObserverInit, MutationRecord};
 use gosub_interface::node::Node;
 use gosub_interface::node::QuirksMode;
 use gosub_shared::byte_stream::Location;
 use gosub_shared::node::NodeId;
+use gosub_shared::types::Result;
 
 /// Defines a document
 #[derive(Debug)]
@@ -37,6 +44,10 @@ pub struct DocumentImpl<C: HasDocument> {
     pub quirks_mode: QuirksMode,
     /// Loaded stylesheets as extracted from the document
     pub stylesheets: Vec<C::Stylesheet>,
+    /// Environment the media queries of the stylesheets are evaluated against
+    pub media: MediaEnvironment,
+    /// Observers of the mutations of the nodes
+    mutation_observers: MutationObservers,
 }
 
 impl<C: HasDocument> PartialEq for DocumentImpl<C> {
@@ -63,6 +74,8 @@ impl<C: HasDocument<Document = Self>> Document<C> for DocumentImpl<C> {
             doctype: document_type,
             quirks_mode: QuirksMode::NoQuirks,
             stylesheets: Vec::new(),
+            media: MediaEnvironment::default(),
+            mutation_observers: MutationObservers::default(),
         };
 
         if let Some(node) = root_node {
@@ -127,6 +140,21 @@ impl<C: HasDocument<Document = Self>> Document<C> for DocumentImpl<C> {
         self.stylesheets.push(stylesheet);
     }
 
+    fn media_environment(&self) -> &MediaEnvironment {
+        &self.media
+    }
+
+    fn set_media_environment(&mut self, env: MediaEnvironment) -> bool {
+        let changed = self
+            .stylesheets
+            .iter()
+            .any(|sheet| sheet.media_changed(&self.media, &env));
+
+        self.media = env;
+
+        changed
+    }
+
     /// returns the root node
     fn get_root(&self) -> &Self::Node {
         self.arena.node_ref(NodeId::root()).expect("Root node not found !?")
@@ -204,6 +232,7 @@ impl<C: HasDocument<Document = Self>> Document<C> for DocumentImpl<C> {
         }
 
         self.on_document_node_mutation(&node);
+        self.queue_mutation_records(&node);
         self.arena.update_node(node);
     }
 
@@ -214,6 +243,7 @@ impl<C: HasDocument<Document = Self>> Document<C> for DocumentImpl<C> {
         }
 
         self.on_document_node_mutation(node);
+        self.queue_mutation_records(node);
         self.arena.update_node(node.clone());
     }
 
@@ -344,12 +374,45 @@ impl<C: HasDocument<Document = Self>> Document<C> for DocumentImpl<C> {
         )
     }
 
+    fn query_selector(&self, selector: &str) -> Result<Option<NodeId>> {
+        let found_ids = DocumentQuery::<C>::select(self, NodeId::root(), selector, SearchType::FindFirst)?;
+        Ok(found_ids.first().copied())
+    }
+
+    fn query_selector_all(&self, selector: &str) -> Result<Vec<NodeId>> {
+        DocumentQuery::<C>::select(self, NodeId::root(), selector, SearchType::FindAll)
+    }
+
+    fn matches(&self, node_id: NodeId, selector: &str) -> Result<bool> {
+        DocumentQuery::<C>::matches(self, node_id, selector)
+    }
+
+    fn closest(&self, node_id: NodeId, selector: &str) -> Result<Option<NodeId>> {
+        DocumentQuery::<C>::closest(self, node_id, selector)
+    }
+
+    fn create_mutation_observer(&mut self) -> MutationObserverId {
+        self.mutation_observers.create()
+    }
+
+    fn observe(&mut self, observer: MutationObserverId, node_id: NodeId, options: MutationObserverInit) -> Result<()> {
+        self.mutation_observers.observe(observer, node_id, options)
+    }
+
+    fn disconnect(&mut self, observer: MutationObserverId) {
+        self.mutation_observers.disconnect(observer);
+    }
+
+    fn take_records(&mut self, observer: MutationObserverId) -> Vec<MutationRecord> {
+        self.mutation_observers.take_records(observer)
+    }
+
     fn write(&self) -> String {
         self.write_from_node(NodeId::root())
     }
 
-    fn write_from_node(&self, _node_id: NodeId) -> String {
-        todo!(); //This should definitely be implemented
+    fn write_from_node(&self, node_id: NodeId) -> String {
+        DocumentWriter::new().outer_html::<C>(node_id, self)
     }
 
     fn cloned_node_by_id(&self, node_id: NodeId) -> Option<Self::Node> {
@@ -364,6 +427,80 @@ impl<C: HasDocument<Document = Self>> DocumentImpl<C> {
         self.on_document_node_mutation_update_named_id(node);
     }
 
+    /// Queues the mutation records for the differences between the node and its version in the arena, which it is
+    /// about to replace
+    fn queue_mutation_records(&mut self, node: &NodeImpl<C>) {
+        if self.mutation_observers.is_idle() {
+            return;
+        }
+        let Some(old) = self.arena.node_ref(node.id) else {
+            return;
+        };
+
+        let mut records = Vec::new();
+
+        if old.children != node.children {
+            let (removed, added) = child_list_changes(&old.children, &node.children);
+
+            if !removed.is_empty() {
+                let (previous, next) = siblings(&old.children, &removed);
+                records.push(MutationRecord::child_list(node.id, Vec::new(), removed, previous, next));
+            }
+            if !added.is_empty() {
+                let (previous, next) = siblings(&node.children, &added);
+                records.push(MutationRecord::child_list(node.id, added, Vec::new(), previous, next));
+            }
+        }
+
+        match (&old.data, &node.data) {
+            (NodeDataTypeInternal::Element(old_data), NodeDataTypeInternal::Element(data)) => {
+                let mut names = old_data
+                    .attributes
+                    .keys()
+                    .chain(data.attributes.keys())
+                    .collect::<Vec<_>>();
+                names.sort();
+                names.dedup();
+
+                for name in names {
+                    let old_value = old_data.attributes.get(name);
+                    // The class list can be changed without changing the attribute
+                    if old_value != data.attributes.get(name)
+                        || (name == "class" && old_data.class_list != data.class_list)
+                    {
+                        records.push(MutationRecord::attributes(node.id, name, old_value.cloned()));
+                    }
+                }
+            }
+            (NodeDataTypeInternal::Text(old_data), NodeDataTypeInternal::Text(data))
+                if old_data.value != data.value =>
+            {
+                records.push(MutationRecord::character_data(node.id, old_data.value.clone()));
+            }
+            (NodeDataTypeInternal::Comment(old_data), NodeDataTypeInternal::Comment(data))
+                if old_data.value != data.value =>
+            {
+                records.push(MutationRecord::character_data(node.id, old_data.value.clone()));
+            }
+            _ => {}
+        }
+
+        if records.is_empty() {
+            return;
+        }
+
+        let mut ancestors = vec![node.id];
+        let mut parent = node.parent;
+        while let Some(parent_id) = parent {
+            ancestors.push(parent_id);
+            parent = self.arena.node_ref(parent_id).and_then(|parent| parent.parent);
+        }
+
+        for record in &records {
+            self.mutation_observers.queue(record, &ancestors);
+        }
+    }
+
     /// Update document's named id structure when the node has ID elements
     fn on_document_node_mutation_update_named_id(&mut self, node: &NodeImpl<C>) {
         if !node.is_element_node() {
@@ -503,6 +640,70 @@ impl<C: HasDocument<Document = Self>> DocumentImpl<C> {
     }
 }
 
+/// Returns the children that are removed from the old list, and the children that are added to the new list. A child
+/// that moves to another position is both removed and added, like a node that is inserted somewhere else. The
+/// children that keep their relative order are the longest subsequence that both lists have in common.
+fn child_list_changes(old: &[NodeId], new: &[NodeId]) -> (Vec<NodeId>, Vec<NodeId>) {
+    let old_positions = old
+        .iter()
+        .enumerate()
+        .map(|(idx, id)| (*id, idx))
+        .collect::<HashMap<_, _>>();
+
+    // Positions in the old list of the children that are in both lists, in their new order
+    let positions = new
+        .iter()
+        .filter_map(|id| old_positions.get(id).copied())
+        .collect::<Vec<_>>();
+    let kept = longest_increasing(&positions)
+        .into_iter()
+        .map(|idx| old[idx])
+        .collect::<HashSet<_>>();
+
+    let removed = old.iter().filter(|id| !kept.contains(id)).copied().collect();
+    let added = new.iter().filter(|id| !kept.contains(id)).copied().collect();
+
+    (removed, added)
+}
+
+/// Returns the longest strictly increasing subsequence of the values
+fn longest_increasing(values: &[usize]) -> Vec<usize> {
+    // The index of the last value of the best subsequence of each length, and the index of the value before each value
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous = vec![None; values.len()];
+
+    for (idx, &value) in values.iter().enumerate() {
+        let len = tails.partition_point(|&tail| values[tail] < value);
+        previous[idx] = len.checked_sub(1).map(|len| tails[len]);
+        if len == tails.len() {
+            tails.push(idx);
+        } else {
+            tails[len] = idx;
+        }
+    }
+
+    let mut subsequence = Vec::with_capacity(tails.len());
+    let mut current = tails.last().copied();
+    while let Some(idx) = current {
+        subsequence.push(values[idx]);
+        current = previous[idx];
+    }
+    subsequence.reverse();
+
+    subsequence
+}
+
+/// Returns the siblings before the first and after the last of the changed children
+fn siblings(children: &[NodeId], changed: &[NodeId]) -> (Option<NodeId>, Option<NodeId>) {
+    let first = children.iter().position(|id| changed.contains(id));
+    let last = children.iter().rposition(|id| changed.contains(id));
+
+    let previous = first.and_then(|first| first.checked_sub(1)).map(|idx| children[idx]);
+    let next = last.and_then(|last| children.get(last + 1)).copied();
+
+    (previous, next)
+}
+
 // Walk the document tree with the given visitor
 pub fn walk_document_tree<C: HasDocument>(doc: &C::Document, visitor: &mut Box<dyn Visitor<C>>) {
     let root = doc.get_root();
@@ -1531,6 +1732,201 @@ mod tests {
         assert_eq!(found_ids, [div_id_2, p_id, p_id_2, p_id_3]);
     }
 
+    #[test]
+    fn query_selector() {
+        let doc = crate::html_compile::<Config>(concat!(
+            "<div id=\"main\"><p class=\"intro\">one</p><p>two <a href=\"/x\">x</a></p>",
+            "<ul><li>a</li><li class=\"skip\">b</li><li>c</li></ul></div><p id=\"last\">three</p>",
+        ));
+
+        let tag_of = |id: &NodeId| {
+            doc.node_by_id(*id)
+                .unwrap()
+                .get_element_data()
+                .unwrap()
+                .name()
+                .to_string()
+        };
+
+        let first = doc.query_selector("p").unwrap().unwrap();
+        assert!(doc
+            .node_by_id(first)
+            .unwrap()
+            .get_element_data()
+            .unwrap()
+            .classlist()
+            .contains("intro"));
+
+        let found_ids = doc.query_selector_all("#main > p, #last").unwrap();
+        assert_eq!(found_ids.len(), 3);
+        assert_eq!(found_ids[2], doc.node_by_named_id("last").unwrap().id());
+
+        // Elements are returned once and in tree order, whichever selector of the list matches
+        let found_ids = doc.query_selector_all("a, div p").unwrap();
+        assert_eq!(found_ids.iter().map(tag_of).collect::<Vec<_>>(), vec!["p", "p", "a"]);
+
+        let found_ids = doc.query_selector_all("li:not(.skip)").unwrap();
+        assert_eq!(found_ids.len(), 2);
+        assert_eq!(doc.query_selector("article").unwrap(), None);
+
+        let link = doc.query_selector("a[href]").unwrap().unwrap();
+        assert!(doc.matches(link, "#main a").unwrap());
+        assert!(!doc.matches(link, "ul a").unwrap());
+        assert_eq!(doc.closest(link, "a").unwrap(), Some(link));
+        assert_eq!(
+            doc.closest(link, "div").unwrap(),
+            Some(doc.node_by_named_id("main").unwrap().id())
+        );
+        assert_eq!(doc.closest(link, "ul").unwrap(), None);
+
+        // Only descendants of the scope are matched
+        let ul = doc.query_selector("ul").unwrap().unwrap();
+        let found_ids = DocumentQuery::<Config>::select(&doc, ul, "li", SearchType::FindAll).unwrap();
+        assert_eq!(found_ids.len(), 3);
+        let found_ids = DocumentQuery::<Config>::select(&doc, ul, "ul, p", SearchType::FindAll).unwrap();
+        assert!(found_ids.is_empty());
+
+        // :scope is the scope element, or the root element for the document
+        let found_ids = DocumentQuery::<Config>::select(&doc, ul, ":scope > li", SearchType::FindAll).unwrap();
+        assert_eq!(found_ids.len(), 3);
+        let found_ids = DocumentQuery::<Config>::select(&doc, ul, ":scope li:not(.skip)", SearchType::FindAll).unwrap();
+        assert_eq!(found_ids.len(), 2);
+        assert_eq!(doc.query_selector_all(":scope > body").unwrap().len(), 1);
+        assert!(doc.query_selector_all(":scope > li").unwrap().is_empty());
+        assert!(doc.matches(link, ":scope").unwrap());
+        assert_eq!(doc.closest(link, ":scope").unwrap(), Some(link));
+
+        // Invalid selectors are errors, like the DOM throws a SyntaxError
+        let err = doc.query_selector_all("div >").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("query: generic error: invalid selector 'div >'"));
+        assert!(doc.query_selector("p:no-such-class").is_err());
+        assert!(doc.matches(link, "").is_err());
+    }
+
+    #[test]
+    fn mutation_observer() {
+        let mut doc =
+            crate::html_compile::<Config>("<div id=\"main\"><p class=\"a\">text</p><p id=\"last\"></p></div>");
+        let main = doc.node_by_named_id("main").unwrap().id();
+        let p = doc.query_selector("p.a").unwrap().unwrap();
+        let last = doc.node_by_named_id("last").unwrap().id();
+        let text = doc.node_by_id(p).unwrap().children()[0];
+
+        let subtree = doc.create_mutation_observer();
+        doc.observe(
+            subtree,
+            main,
+            MutationObserverInit {
+                child_list: true,
+                subtree: true,
+                attribute_old_value: Some(true),
+                character_data_old_value: Some(true),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let own = doc.create_mutation_observer();
+        doc.observe(
+            own,
+            main,
+            MutationObserverInit {
+                attributes: Some(true),
+                character_data: Some(true),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let mut node = doc.cloned_node_by_id(p).unwrap();
+        let data = node.get_element_data_mut().unwrap();
+        data.add_attribute("class", "b");
+        data.add_attribute("title", "new");
+        doc.update_node(node);
+
+        let mut node = doc.cloned_node_by_id(text).unwrap();
+        if let NodeDataTypeInternal::Text(data) = &mut node.data {
+            data.value = "changed".to_owned();
+        }
+        doc.update_node(node);
+
+        let span = Document::new_element_node("span", Some(HTML_NAMESPACE), HashMap::new(), Location::default());
+        let span = doc.register_node_at(span, main, Some(1));
+        doc.detach_node(p);
+
+        let records = doc.take_records(subtree);
+        assert_eq!(
+            records,
+            vec![
+                MutationRecord::attributes(p, "class", Some("a".to_owned())),
+                MutationRecord::attributes(p, "title", None),
+                MutationRecord::character_data(text, "text".to_owned()),
+                MutationRecord::child_list(main, vec![span], vec![], Some(p), Some(last)),
+                MutationRecord::child_list(main, vec![], vec![p], None, Some(span)),
+            ]
+        );
+        assert!(doc.take_records(subtree).is_empty());
+
+        // Without subtree, mutations of the descendants are not observed
+        assert!(doc.take_records(own).is_empty());
+        let mut node = doc.cloned_node_by_id(main).unwrap();
+        node.get_element_data_mut().unwrap().add_attribute("lang", "en");
+        doc.update_node(node);
+        let records = doc.take_records(own);
+        assert_eq!(records.len(), 1);
+        // The old value is only recorded when it is asked for
+        assert_eq!(records[0].attribute_name.as_deref(), Some("lang"));
+        assert_eq!(records[0].old_value, None);
+
+        doc.disconnect(subtree);
+        doc.detach_node(span);
+        assert!(doc.take_records(subtree).is_empty());
+    }
+
+    #[test]
+    fn mutation_observer_reorder() {
+        let mut doc = crate::html_compile::<Config>("<ul id=\"list\"><li>a</li><li>b</li><li>c</li><li>d</li></ul>");
+        let list = doc.node_by_named_id("list").unwrap().id();
+        let items = doc.node_by_id(list).unwrap().children().to_vec();
+
+        let observer = doc.create_mutation_observer();
+        doc.observe(
+            observer,
+            list,
+            MutationObserverInit {
+                child_list: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        // Moving the last item to the front removes it and inserts it again
+        let mut node = doc.cloned_node_by_id(list).unwrap();
+        node.children = vec![items[3], items[0], items[1], items[2]];
+        doc.update_node(node);
+
+        // Swapping two items moves only one of them
+        let mut node = doc.cloned_node_by_id(list).unwrap();
+        node.children = vec![items[3], items[1], items[0], items[2]];
+        doc.update_node(node);
+
+        assert_eq!(
+            doc.take_records(observer),
+            vec![
+                MutationRecord::child_list(list, vec![], vec![items[3]], Some(items[2]), None),
+                MutationRecord::child_list(list, vec![items[3]], vec![], None, Some(items[0])),
+                MutationRecord::child_list(list, vec![], vec![items[1]], Some(items[0]), Some(items[2])),
+                MutationRecord::child_list(list, vec![items[1]], vec![], Some(items[3]), Some(items[0])),
+            ]
+        );
+
+        // The same children in the same order are no change at all
+        let node = doc.cloned_node_by_id(list).unwrap();
+        doc.update_node(node);
+        assert!(doc.take_records(observer).is_empty());
+    }
+
     #[test]
     fn tree_iterator() {
         let mut doc = <DocumentBuilderImpl as DocumentBuilder<Config>>::new_document(None);
@@ -0,0 +1,207 @@
+//! Mutation observers of a document
+//!
+//! Keeps the observers that are registered on the nodes of a document, and queues the mutation records for the
+//! observers that are interested in them, following "queue a mutation record" of the DOM specification.
+use crate::errors::Error;
+use gosub_interface::mutation::{MutationObserverId, MutationObserverInit, MutationRecord, MutationType};
+use gosub_shared::node::NodeId;
+use gosub_shared::types::Result;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default)]
+struct Observer {
+    /// Nodes that are observed, with the options they are observed with
+    registrations: Vec<(NodeId, MutationObserverInit)>,
+    records: Vec<MutationRecord>,
+}
+
+/// The mutation observers of a document
+#[derive(Debug, Default)]
+pub struct MutationObservers {
+    observers: BTreeMap<MutationObserverId, Observer>,
+    next_id: u64,
+}
+
+impl MutationObservers {
+    pub fn create(&mut self) -> MutationObserverId {
+        let id = MutationObserverId(self.next_id);
+        self.next_id += 1;
+        self.observers.insert(id, Observer::default());
+        id
+    }
+
+    /// Registers the observer for the node. The options are completed and validated like `observe()` of the DOM
+    /// specification does.
+    pub fn observe(
+        &mut self,
+        observer: MutationObserverId,
+        node_id: NodeId,
+        mut options: MutationObserverInit,
+    ) -> Result<()> {
+        if options.attributes.is_none() && (options.attribute_old_value.is_some() || options.attribute_filter.is_some())
+        {
+            options.attributes = Some(true);
+        }
+        if options.character_data.is_none() && options.character_data_old_value.is_some() {
+            options.character_data = Some(true);
+        }
+
+        let attributes = options.attributes.unwrap_or(false);
+        let character_data = options.character_data.unwrap_or(false);
+        if !options.child_list && !attributes && !character_data {
+            return Err(
+                Error::Mutation("one of childList, attributes or characterData must be observed".to_owned()).into(),
+            );
+        }
+        if !attributes && (options.attribute_old_value == Some(true) || options.attribute_filter.is_some()) {
+            return Err(Error::Mutation("attribute options given while attributes are not observed".to_owned()).into());
+        }
+        if !character_data && options.character_data_old_value == Some(true) {
+            return Err(
+                Error::Mutation("characterDataOldValue given while character data is not observed".to_owned()).into(),
+            );
+        }
+
+        let Some(observer) = self.observers.get_mut(&observer) else {
+            return Err(Error::Mutation(format!("unknown mutation observer {}", observer.0)).into());
+        };
+
+        match observer.registrations.iter_mut().find(|(id, _)| *id == node_id) {
+            Some(registration) => registration.1 = options,
+            None => observer.registrations.push((node_id, options)),
+        }
+
+        Ok(())
+    }
+
+    pub fn disconnect(&mut self, observer: MutationObserverId) {
+        if let Some(observer) = self.observers.get_mut(&observer) {
+            observer.registrations.clear();
+            observer.records.clear();
+        }
+    }
+
+    pub fn take_records(&mut self, observer: MutationObserverId) -> Vec<MutationRecord> {
+        self.observers
+            .get_mut(&observer)
+            .map(|observer| std::mem::take(&mut observer.records))
+            .unwrap_or_default()
+    }
+
+    /// Returns true when no node is observed, so no records have to be made
+    pub fn is_idle(&self) -> bool {
+        self.observers
+            .values()
+            .all(|observer| observer.registrations.is_empty())
+    }
+
+    /// Queues the record for every observer that is interested in it. `ancestors` are the inclusive ancestors of
+    /// the target of the record, starting with the target itself. The old value of the record is only kept for
+    /// the observers that asked for it.
+    pub fn queue(&mut self, record: &MutationRecord, ancestors: &[NodeId]) {
+        for observer in self.observers.values_mut() {
+            let mut interested = false;
+            let mut old_value = false;
+
+            for (node_id, options) in &observer.registrations {
+                if !ancestors.contains(node_id) {
+                    continue;
+                }
+                if *node_id != record.target && !options.subtree {
+                    continue;
+                }
+
+                match record.mutation_type {
+                    MutationType::ChildList if !options.child_list => continue,
+                    MutationType::Attributes => {
+                        if options.attributes != Some(true) {
+                            continue;
+                        }
+                        if let (Some(filter), Some(name)) = (&options.attribute_filter, &record.attribute_name) {
+                            if !filter.contains(name) {
+                                continue;
+                            }
+                        }
+                        old_value |= options.attribute_old_value == Some(true);
+                    }
+                    MutationType::CharacterData => {
+                        if options.character_data != Some(true) {
+                            continue;
+                        }
+                        old_value |= options.character_data_old_value == Some(true);
+                    }
+                    MutationType::ChildList => {}
+                }
+
+                interested = true;
+            }
+
+            if interested {
+                let mut record = record.clone();
+                if !old_value {
+                    record.old_value = None;
+                }
+                observer.records.push(record);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options() {
+        let mut observers = MutationObservers::default();
+        let observer = observers.create();
+        assert!(observers.is_idle());
+
+        assert!(observers
+            .observe(observer, NodeId::root(), MutationObserverInit::default())
+            .is_err());
+        assert!(observers
+            .observe(
+                observer,
+                NodeId::root(),
+                MutationObserverInit {
+                    attributes: Some(false),
+                    attribute_old_value: Some(true),
+                    ..Default::default()
+                }
+            )
+            .is_err());
+        assert!(observers.is_idle());
+
+        // An attribute filter implies that attributes are observed
+        observers
+            .observe(
+                observer,
+                NodeId::from(1u64),
+                MutationObserverInit {
+                    attribute_filter: Some(vec!["class".to_owned()]),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert!(!observers.is_idle());
+
+        let ancestors = [NodeId::from(1u64), NodeId::root()];
+        observers.queue(
+            &MutationRecord::attributes(NodeId::from(1u64), "id", Some("a".to_owned())),
+            &ancestors,
+        );
+        observers.queue(
+            &MutationRecord::attributes(NodeId::from(1u64), "class", Some("a".to_owned())),
+            &ancestors,
+        );
+        let records = observers.take_records(observer);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].attribute_name.as_deref(), Some("class"));
+        assert_eq!(records[0].old_value, None);
+        assert!(observers.take_records(observer).is_empty());
+
+        observers.disconnect(observer);
+        assert!(observers.is_idle());
+    }
+}
@@ -1,6 +1,9 @@
 use crate::document::document_impl::TreeIterator;
 use crate::errors::Error;
 use crate::parser::query::{Condition, Query, SearchType};
+use gosub_css3::matcher::styling::{match_selector, match_selector_in_scope};
+use gosub_css3::stylesheet::CssSelector;
+use gosub_css3::Css3;
 use gosub_interface::config::HasDocument;
 use gosub_interface::document::Document;
 
@@ -45,6 +48,87 @@ impl<C: HasDocument> DocumentQuery<C> {
         Ok(found_ids)
     }
 
+    /// Returns the elements below the scope node that match the CSS selector, in tree order. With
+    /// `SearchType::FindFirst` this is `querySelector()`, otherwise it is `querySelectorAll()`.
+    pub fn select(
+        doc: &C::Document,
+        scope: NodeId,
+        selector: &str,
+        search_type: SearchType,
+    ) -> gosub_shared::types::Result<Vec<NodeId>> {
+        if search_type == SearchType::Uninitialized {
+            return Err(Error::Query("Query predicate is uninitialized".to_owned()).into());
+        }
+
+        let selector = Self::parse_selector(selector)?;
+
+        let mut found_ids = Vec::new();
+        let mut stack = match doc.node_by_id(scope) {
+            Some(node) => node.children().iter().rev().copied().collect::<Vec<_>>(),
+            None => Vec::new(),
+        };
+        while let Some(node_id) = stack.pop() {
+            let Some(node) = doc.node_by_id(node_id) else {
+                continue;
+            };
+
+            if Self::matches_selector_in_scope(doc, node, &selector, scope) {
+                found_ids.push(node_id);
+                if search_type == SearchType::FindFirst {
+                    break;
+                }
+            }
+
+            stack.extend(node.children().iter().rev());
+        }
+
+        Ok(found_ids)
+    }
+
+    /// Returns true when the node is an element that matches the CSS selector, like `Element.matches()`
+    pub fn matches(doc: &C::Document, node_id: NodeId, selector: &str) -> gosub_shared::types::Result<bool> {
+        let selector = Self::parse_selector(selector)?;
+
+        Ok(doc
+            .node_by_id(node_id)
+            .is_some_and(|node| Self::matches_selector_in_scope(doc, node, &selector, node_id)))
+    }
+
+    /// Returns the node itself or its nearest ancestor that matches the CSS selector, like `Element.closest()`
+    pub fn closest(doc: &C::Document, node_id: NodeId, selector: &str) -> gosub_shared::types::Result<Option<NodeId>> {
+        let selector = Self::parse_selector(selector)?;
+
+        let mut current = doc.node_by_id(node_id);
+        while let Some(node) = current {
+            if Self::matches_selector_in_scope(doc, node, &selector, node_id) {
+                return Ok(Some(node.id()));
+            }
+            current = node.parent_id().and_then(|parent_id| doc.node_by_id(parent_id));
+        }
+
+        Ok(None)
+    }
+
+    fn parse_selector(selector: &str) -> gosub_shared::types::Result<CssSelector> {
+        Css3::parse_selector_str(selector)
+            .map_err(|err| Error::Query(format!("invalid selector '{selector}': {}", err.message)).into())
+    }
+
+    /// Returns true when the node is an element that matches the selector, where `:scope` is the scope element. The
+    /// scope of a document is its root element, which `:scope` matches by default.
+    fn matches_selector_in_scope(doc: &C::Document, node: &C::Node, selector: &CssSelector, scope: NodeId) -> bool {
+        if !node.is_element_node() {
+            return false;
+        }
+
+        match doc.node_by_id(scope) {
+            Some(scope) if scope.is_element_node() => {
+                match_selector_in_scope::<C>(doc, node.id(), selector, scope.id()).0
+            }
+            _ => match_selector::<C>(doc, node.id(), selector).0,
+        }
+    }
+
     /// Check if a given node's children contain a certain tag name
     pub fn contains_child_tag(doc: &C::Document, node_id: NodeId, tag: &str) -> bool {
         if let Some(node) = doc.node_by_id(node_id) {
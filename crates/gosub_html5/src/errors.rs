@@ -24,4 +24,7 @@ pub enum Error {
 
     #[error("query: generic error: {0}")]
     Query(String),
+
+    #[error("mutation observer error: {0}")]
+    Mutation(String),
 }
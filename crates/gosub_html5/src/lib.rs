@@ -16,7 +16,6 @@ pub mod node;
 pub mod parser;
 pub mod testing;
 pub mod tokenizer;
-#[allow(dead_code)]
 pub mod writer;
 
 /// Parses the given HTML string and returns a handle to the resulting DOM tree.
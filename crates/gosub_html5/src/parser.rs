@@ -10,6 +10,8 @@ use crate::parser::attr_replacements::{
     MATHML_ADJUSTMENTS, SVG_ADJUSTMENTS_ATTRIBUTES, SVG_ADJUSTMENTS_TAGS, XML_ADJUSTMENTS,
 };
 use crate::parser::errors::{ErrorLogger, ParserError};
+use crate::parser::scripts::{PendingScript, Script};
+use crate::parser::streaming::StreamingParser;
 use crate::tokenizer::state::State;
 use crate::tokenizer::token::Token;
 use crate::tokenizer::{ParserData, Tokenizer, CHAR_REPLACEMENT};
@@ -18,10 +20,10 @@ use gosub_interface::config::HasDocument;
 use gosub_interface::css3::{CssOrigin, CssSystem};
 use gosub_interface::document::{Document, DocumentFragment, DocumentType};
 
-use gosub_interface::html5::ParserOptions;
+use gosub_interface::html5::{ParseState, ParserOptions, ScriptExecutor};
 use gosub_interface::node::TextDataType;
 use gosub_interface::node::{ElementDataType, Node, QuirksMode};
-use gosub_shared::byte_stream::{ByteStream, Location};
+use gosub_shared::byte_stream::{encoding_from_content_type, ByteStream, Encoding, Location, Stream};
 use gosub_shared::config::{Context, ParserConfig};
 use gosub_shared::node::NodeId;
 use gosub_shared::types::{ParseError, Result};
@@ -33,6 +35,8 @@ mod attr_replacements;
 pub mod errors;
 pub mod query;
 mod quirks;
+pub mod scripts;
+pub mod streaming;
 pub mod tree_builder;
 
 // ------------------------------------------------------------
@@ -135,6 +139,7 @@ impl ActiveElement {
     }
 }
 
+#[derive(Clone, Copy)]
 pub struct Html5ParserOptions {
     pub scripting_enabled: bool,
 }
@@ -209,6 +214,21 @@ pub struct Html5Parser<'tokens, C: HasDocument> {
     token_queue: Vec<Token>,
     /// When true, the parser is finished and should not consume more tokens (there aren't any)
     parser_finished: bool,
+    /// When true, a meta element changed the encoding of the stream and the document must be parsed again
+    encoding_changed: bool,
+    /// Executor that runs the scripts found in the document (if any)
+    script_executor: Option<&'tokens mut dyn ScriptExecutor<C>>,
+    /// External script whose source the parser waits for
+    pending_script: Option<PendingScript>,
+    /// Number of script elements that have been prepared
+    scripts_prepared: usize,
+    /// Number of script elements that are not prepared again, as they have been prepared before the document was
+    /// started over
+    skip_scripts: usize,
+    /// Scripts that will execute when the document has finished parsing
+    deferred_scripts: Vec<Script>,
+    /// Scripts that will execute as soon as possible
+    async_scripts: Vec<Script>,
     /// Context node id for fragment parsing
     context_node: Option<C::Node>,
     // /// Context document for the context_node_id
@@ -217,6 +237,10 @@ pub struct Html5Parser<'tokens, C: HasDocument> {
 
 impl<C: HasDocument> gosub_interface::html5::Html5Parser<C> for Html5Parser<'_, C> {
     type Options = Html5ParserOptions;
+    type StreamingParser<'b>
+        = StreamingParser<'b, C>
+    where
+        Self: 'b;
 
     fn parse(stream: &mut ByteStream, doc: &mut C::Document, opts: Option<Self::Options>) -> Result<Vec<ParseError>> {
         Self::parse_document(stream, doc, opts)
@@ -231,6 +255,22 @@ impl<C: HasDocument> gosub_interface::html5::Html5Parser<C> for Html5Parser<'_,
     ) -> Result<Vec<ParseError>> {
         Self::parse_fragment(stream, doc, context_node, options, start_location)
     }
+
+    fn parse_streaming<'b>(
+        stream: &'b mut ByteStream,
+        doc: &'b mut C::Document,
+        opts: Option<Self::Options>,
+        script_executor: Option<&'b mut dyn ScriptExecutor<C>>,
+    ) -> Self::StreamingParser<'b>
+    where
+        Self: 'b,
+    {
+        let parser = StreamingParser::new(stream, doc, opts);
+        match script_executor {
+            Some(script_executor) => parser.with_script_executor(script_executor),
+            None => parser,
+        }
+    }
 }
 
 /// Defines the scopes for in_scope()
@@ -286,6 +326,13 @@ impl<'a, C: HasDocument> Html5Parser<'a, C> {
             ignore_lf: false,
             token_queue: vec![],
             parser_finished: false,
+            encoding_changed: false,
+            script_executor: None,
+            pending_script: None,
+            scripts_prepared: 0,
+            skip_scripts: 0,
+            deferred_scripts: vec![],
+            async_scripts: vec![],
             context_node: None,
         }
     }
@@ -325,6 +372,13 @@ impl<'a, C: HasDocument> Html5Parser<'a, C> {
             ignore_lf: false,
             token_queue: vec![],
             parser_finished: false,
+            encoding_changed: false,
+            script_executor: None,
+            pending_script: None,
+            scripts_prepared: 0,
+            skip_scripts: 0,
+            deferred_scripts: vec![],
+            async_scripts: vec![],
             context_node: None,
         }
     }
@@ -419,14 +473,68 @@ impl<'a, C: HasDocument> Html5Parser<'a, C> {
         let tokenizer = Tokenizer::new(stream, None, error_logger.clone(), Location::default());
         let mut parser = Html5Parser::<C>::init(tokenizer, document, error_logger, options);
 
-        let ret = parser.do_parse();
+        let mut ret = parser.do_parse();
+        if parser.encoding_changed {
+            // A meta element changed the encoding, so the stream has been reset and we start over with a fresh
+            // document. The encoding is now certain, so this will happen only once.
+            let mut fresh = C::Document::new(DocumentType::HTML, document.url(), None);
+            fresh.set_media_environment(document.media_environment().clone());
+            *document = fresh;
+
+            let error_logger = Rc::new(RefCell::new(ErrorLogger::new()));
+            let tokenizer = Tokenizer::new(stream, None, error_logger.clone(), Location::default());
+            let mut parser = Html5Parser::<C>::init(tokenizer, document, error_logger, options);
+            ret = parser.do_parse();
+        }
         timing_stop!(t_id);
 
         ret
     }
 
+    /// Parses a document while its input arrives. Whenever the available input has been parsed, `feed` is called
+    /// with the stream and the partial document, and appends the next input to the stream or closes it. When it
+    /// does neither, the stream is closed and the document is finished.
+    pub fn parse_document_streaming(
+        stream: &mut ByteStream,
+        document: &mut C::Document,
+        options: Option<Html5ParserOptions>,
+        feed: &mut dyn FnMut(&mut ByteStream, &mut C::Document),
+    ) -> Result<Vec<ParseError>> {
+        let t_id = match document.url() {
+            Some(url) => timing_start!("html5.parse", url.as_str()),
+            None => timing_start!("html5.parse", "unknown"),
+        };
+
+        let mut parser = StreamingParser::<C>::new(stream, document, options);
+        while parser.parse_available() != ParseState::Finished {
+            parser.with_stream(|stream, document| {
+                let available = stream.bytes_available();
+                feed(stream, document);
+                if !stream.closed() && stream.bytes_available() == available {
+                    stream.close();
+                }
+            });
+        }
+        timing_stop!(t_id);
+
+        Ok(parser.errors())
+    }
+
     /// Internal parser function that does the actual parsing
     fn do_parse(&mut self) -> Result<Vec<ParseError>> {
+        // The stream is parsed as a whole, so no more input will be appended to it
+        self.tokenizer.stream.close();
+        self.parse_available();
+
+        let result = Ok(self.error_logger.borrow().get_errors().clone());
+        result
+    }
+
+    /// Parses the input that is available in the stream, until the document has been parsed, the stream is still
+    /// open and the parser waits for more input, or the parser waits for the source of an external script. Parsing
+    /// continues where it left off when this is called again after input has been appended or the script has been
+    /// provided.
+    fn parse_available(&mut self) -> ParseState {
         let mut dispatcher_mode = DispatcherMode::Html;
 
         loop {
@@ -435,9 +543,21 @@ impl<'a, C: HasDocument> Html5Parser<'a, C> {
                 break;
             }
 
+            if let Some(url) = self.pending_script_url() {
+                return ParseState::NeedsScript(url);
+            }
+
+            // Async scripts run in between tokens, as long as we are not running a script already
+            if self.script_nesting_level == 0 && !self.async_scripts.is_empty() {
+                self.run_async_scripts();
+            }
+
             // If reprocess_token is true, we should process the same token again
             if !self.reprocess_token {
-                self.current_token = self.fetch_next_token();
+                let Some(token) = self.fetch_next_token() else {
+                    return ParseState::NeedsInput;
+                };
+                self.current_token = token;
 
                 // If we reprocess a given token, the dispatcher mode should stay the same and
                 // should not be re-evaluated
@@ -460,8 +580,7 @@ impl<'a, C: HasDocument> Html5Parser<'a, C> {
             self.display_debug_info();
         }
 
-        let result = Ok(self.error_logger.borrow().get_errors().clone());
-        result
+        ParseState::Finished
     }
 
     // Process token in foreign content (svg, mathml)
@@ -1037,7 +1156,7 @@ impl<'a, C: HasDocument> Html5Parser<'a, C> {
                     }
                     Token::EndTag { name, .. } if name == "script" => {
                         // @todo: If the active speculative HTML parser is null and the JavaScript execution context stack is empty, then perform a microtask checkpoint.
-                        let _script_node = current_node!(self);
+                        let script_node_id = current_node!(self).id();
 
                         self.open_elements.pop();
                         self.insertion_mode = self.original_insertion_mode;
@@ -1047,10 +1166,10 @@ impl<'a, C: HasDocument> Html5Parser<'a, C> {
 
                         self.script_nesting_level += 1;
 
-                        // do script stuff
+                        self.prepare_script(script_node_id);
 
                         self.script_nesting_level -= 1;
-                        if self.script_nesting_level == 0 {
+                        if self.script_nesting_level == 0 && self.pending_script.is_none() {
                             self.parser_pause_flag = false;
                         }
 
@@ -3008,15 +3127,24 @@ impl<'a, C: HasDocument> Html5Parser<'a, C> {
                 self.open_elements.pop();
             }
             Token::StartTag {
-                name, is_self_closing, ..
+                name,
+                is_self_closing,
+                attributes,
+                ..
             } if name == "meta" => {
                 self.acknowledge_closing_tag(*is_self_closing);
 
                 self.insert_html_element(&self.current_token.clone());
                 self.open_elements.pop();
 
-                // @TODO: if active speculative html parser is null then...
-                // we probably want to change the encoding if the element has a charset attribute and the current encoding is "tentative"
+                // We have no speculative html parser, so we can change the encoding right away when the meta element
+                // defines one and the current encoding is still tentative.
+                if let Some(encoding) = Self::meta_encoding(attributes) {
+                    if self.tokenizer.stream.change_encoding(encoding) {
+                        self.encoding_changed = true;
+                        self.parser_finished = true;
+                    }
+                }
             }
             Token::StartTag { name, .. } if name == "title" => {
                 self.parse_rcdata();
@@ -3647,6 +3775,7 @@ impl<'a, C: HasDocument> Html5Parser<'a, C> {
     }
 
     fn stop_parsing(&mut self) {
+        self.run_deferred_scripts();
         self.parser_finished = true;
     }
 
@@ -3829,11 +3958,14 @@ impl<'a, C: HasDocument> Html5Parser<'a, C> {
 
     /// Fetches the next token from the tokenizer. However, if the token is a text token AND
     /// it starts with one or more whitespaces, the token is split into 2 tokens: the whitespace part
-    /// and the remainder.
-    fn fetch_next_token(&mut self) -> Token {
+    /// and the remainder. Returns None when the stream needs more input for the next token.
+    fn fetch_next_token(&mut self) -> Option<Token> {
         // If there are no tokens to fetch, fetch the next token from the tokenizer
         if self.token_queue.is_empty() {
-            let token = self.tokenizer.next_token(self.parser_data()).expect("tokenizer error");
+            let token = self
+                .tokenizer
+                .try_next_token(self.parser_data())
+                .expect("tokenizer error")?;
 
             if let Token::Text { text: value, location } = token {
                 self.token_queue.push(Token::Text { text: value, location });
@@ -3842,14 +3974,14 @@ impl<'a, C: HasDocument> Html5Parser<'a, C> {
                 // }
             } else {
                 // Simply return the token
-                return token;
+                return Some(token);
             }
         }
 
         let token = self.token_queue.first().cloned();
         self.token_queue.remove(0);
 
-        token.expect("no token found")
+        Some(token.expect("no token found"))
     }
 
     fn get_adjusted_current_node(&self) -> <C::Document as Document<C>>::Node {
@@ -4178,6 +4310,21 @@ impl<'a, C: HasDocument> Html5Parser<'a, C> {
         }
     }
 
+    /// Returns the encoding defined by a meta element, either by its charset attribute or by a content attribute
+    /// with a charset in combination with `http-equiv="content-type"`.
+    fn meta_encoding(attributes: &HashMap<String, String>) -> Option<Encoding> {
+        if let Some(charset) = attributes.get("charset") {
+            return Encoding::for_label(charset);
+        }
+
+        let http_equiv = attributes.get("http-equiv")?;
+        if !http_equiv.eq_ignore_ascii_case("content-type") {
+            return None;
+        }
+
+        encoding_from_content_type(attributes.get("content")?)
+    }
+
     fn handle_link_element(&mut self, attributes: HashMap<String, String>) {
         if attributes.contains_key("rel") && attributes.contains_key("itemprop") {
             // cannot have them both
@@ -4265,7 +4412,7 @@ mod test {
     use gosub_css3::system::Css3System;
     use gosub_interface::config::HasCssSystem;
     use gosub_interface::node::ClassList;
-    use gosub_shared::byte_stream::Encoding;
+    use gosub_shared::byte_stream::{Confidence, Encoding};
 
     #[derive(Clone, Debug, PartialEq)]
     struct Config;
@@ -4618,4 +4765,40 @@ mod test {
         assert_eq!(div.id, NodeId::from(4usize));
         assert_eq!(div.get_element_data().unwrap().name(), "div");
     }
+
+    #[test]
+    fn meta_charset_changes_encoding() {
+        let texts = |doc: &DocumentImpl<Config>| {
+            (0..doc.node_count())
+                .filter_map(|id| doc.node_by_id(NodeId::from(id)))
+                .filter_map(|node| node.get_text_data().map(|t| t.value().to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        // The meta element is found while parsing, as the encoding is only a tentative guess
+        let mut stream = ByteStream::new(Encoding::UTF8, None);
+        stream
+            .read_from_bytes(b"<html><head><meta charset=\"windows-1252\"></head><body><p>caf\xE9</p></body></html>")
+            .unwrap();
+        stream.set_confidence(Confidence::Tentative);
+
+        let mut doc = <DocumentBuilderImpl as DocumentBuilder<Config>>::new_document(None);
+        let _ = Parser::parse_document(&mut stream, &mut doc, None);
+        assert_eq!(Some(stream.encoding()), Encoding::for_label("windows-1252"));
+        assert_eq!(texts(&doc), vec!["café".to_string()]);
+
+        // A certain encoding is never changed
+        let mut stream = ByteStream::new(Encoding::UTF8, None);
+        stream
+            .read_from_bytes(
+                "<html><head><meta charset=\"shift_jis\"></head><body><p>日本</p></body></html>".as_bytes(),
+            )
+            .unwrap();
+        stream.sniff_encoding(Some(Encoding::UTF8));
+
+        let mut doc = <DocumentBuilderImpl as DocumentBuilder<Config>>::new_document(None);
+        let _ = Parser::parse_document(&mut stream, &mut doc, None);
+        assert_eq!(stream.encoding(), Encoding::UTF8);
+        assert_eq!(texts(&doc), vec!["日本".to_string()]);
+    }
 }
@@ -159,6 +159,16 @@ impl ErrorLogger {
         self.errors.clone()
     }
 
+    /// Returns the number of errors
+    pub fn error_count(&self) -> usize {
+        self.errors.len()
+    }
+
+    /// Removes the errors after the first `len` errors
+    pub fn truncate(&mut self, len: usize) {
+        self.errors.truncate(len);
+    }
+
     /// Adds a new error to the error logger
     pub fn add_error(&mut self, location: Location, message: &str) {
         // Check if the error already exists, if so, don't add it again
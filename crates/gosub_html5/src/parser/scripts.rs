@@ -0,0 +1,422 @@
+use crate::parser::Html5Parser;
+use cow_utils::CowUtils;
+use gosub_interface::config::HasDocument;
+use gosub_interface::document::Document;
+use gosub_interface::node::{ElementDataType, Node, TextDataType};
+use gosub_shared::node::NodeId;
+use gosub_shared::types::Result;
+use gosub_webexecutor::js::{WebContext, WebRuntime};
+use log::warn;
+use url::Url;
+
+pub use gosub_interface::html5::{Script, ScriptExecutor, ScriptKind};
+
+/// MIME types that define a classic (javascript) script
+const JAVASCRIPT_MIME_TYPES: [&str; 16] = [
+    "application/ecmascript",
+    "application/javascript",
+    "application/x-ecmascript",
+    "application/x-javascript",
+    "text/ecmascript",
+    "text/javascript",
+    "text/javascript1.0",
+    "text/javascript1.1",
+    "text/javascript1.2",
+    "text/javascript1.3",
+    "text/javascript1.4",
+    "text/javascript1.5",
+    "text/jscript",
+    "text/livescript",
+    "text/x-ecmascript",
+    "text/x-javascript",
+];
+
+/// Executes the scripts of a document in a javascript runtime. The document is not exposed to the scripts, so
+/// they only see the javascript builtins.
+pub struct JsScriptExecutor<RT: WebRuntime> {
+    context: RT::Context,
+}
+
+impl<C: HasDocument, RT: WebRuntime + Default> ScriptExecutor<C> for JsScriptExecutor<RT> {
+    fn new(_url: &Url) -> Result<Self> {
+        Ok(Self {
+            context: RT::default().new_context()?,
+        })
+    }
+
+    fn execute(&mut self, script: &Script, _document: &mut C::Document) -> Result<()> {
+        self.context.run(&script.source)?;
+        Ok(())
+    }
+}
+
+/// Defines when a prepared script will be executed
+#[derive(Debug, Clone, Copy)]
+enum ScriptTiming {
+    /// Execute right away, while the parser waits
+    Immediate,
+    /// Execute when the document has finished parsing, in document order
+    Deferred,
+    /// Execute as soon as possible, without blocking the parser
+    Async,
+}
+
+/// An external script whose source has to be fetched before it can be executed
+pub(crate) struct PendingScript {
+    kind: ScriptKind,
+    url: Url,
+    node_id: NodeId,
+    timing: ScriptTiming,
+}
+
+impl<C: HasDocument> Html5Parser<'_, C> {
+    /// Prepares the script element that has just been closed and executes it, or queues it for execution when it is
+    /// a deferred or async script. The parser is paused when the source of an external script has to be fetched
+    /// first.
+    ///
+    /// https://html.spec.whatwg.org/multipage/scripting.html#prepare-the-script-element
+    pub(crate) fn prepare_script(&mut self, node_id: NodeId) {
+        // Scripts inserted in the fragment case are marked as already started and never run
+        if self.script_already_started {
+            self.script_already_started = false;
+            return;
+        }
+
+        if !self.scripting_enabled || self.script_executor.is_none() {
+            return;
+        }
+
+        // Scripts that were prepared before the document was started over have run already
+        self.scripts_prepared += 1;
+        if self.scripts_prepared <= self.skip_scripts {
+            return;
+        }
+
+        let Some(node) = self.document.node_by_id(node_id) else {
+            return;
+        };
+        let Some(element) = node.get_element_data() else {
+            return;
+        };
+        let attributes = element.attributes().clone();
+
+        let source = node
+            .children()
+            .iter()
+            .filter_map(|child_id| self.document.node_by_id(*child_id))
+            .filter_map(|child| child.get_text_data().map(|text| text.value().to_string()))
+            .collect::<String>();
+
+        // 8. - 12. Determine the type of the script
+        let script_type = match (attributes.get("type"), attributes.get("language")) {
+            (Some(script_type), _) if !script_type.is_empty() => script_type.trim().to_string(),
+            (None, Some(language)) if !language.is_empty() => format!("text/{language}"),
+            _ => "text/javascript".to_string(),
+        };
+        let script_type = script_type.cow_to_ascii_lowercase();
+
+        let kind = if JAVASCRIPT_MIME_TYPES.contains(&script_type.as_ref()) {
+            ScriptKind::Classic
+        } else if script_type == "module" {
+            ScriptKind::Module
+        } else {
+            // Data blocks (like JSON or templates) are never executed
+            return;
+        };
+
+        // 18. We support module scripts, so classic scripts with nomodule are for older user agents
+        if kind == ScriptKind::Classic && attributes.contains_key("nomodule") {
+            return;
+        }
+
+        let is_async = attributes.contains_key("async");
+        let is_defer = attributes.contains_key("defer");
+
+        let url = match attributes.get("src") {
+            Some(src) => match self.resolve_script_url(src) {
+                Some(url) => Some(url),
+                None => return,
+            },
+            None if source.is_empty() => return,
+            None => None,
+        };
+
+        // 31. Inline classic scripts always run right away, others depend on the async and defer attributes
+        let timing = match (kind, url.is_some()) {
+            (ScriptKind::Classic, false) => ScriptTiming::Immediate,
+            _ if is_async => ScriptTiming::Async,
+            (ScriptKind::Module, _) => ScriptTiming::Deferred,
+            (ScriptKind::Classic, true) if is_defer => ScriptTiming::Deferred,
+            (ScriptKind::Classic, true) => ScriptTiming::Immediate,
+        };
+
+        let Some(url) = url else {
+            self.run_script(
+                Script {
+                    kind,
+                    source,
+                    url: None,
+                    node_id,
+                },
+                timing,
+            );
+            return;
+        };
+
+        // The parser is paused until the source has been fetched. It is given with `provide_script()`.
+        self.pending_script = Some(PendingScript {
+            kind,
+            url,
+            node_id,
+            timing,
+        });
+        self.parser_pause_flag = true;
+    }
+
+    /// Returns the URL of the external script the parser waits for, if any
+    pub(crate) fn pending_script_url(&self) -> Option<Url> {
+        self.pending_script.as_ref().map(|pending| pending.url.clone())
+    }
+
+    /// Continues with the pending external script now that its source has been fetched, or skips it when it could not
+    /// be loaded. This unpauses the parser.
+    pub(crate) fn provide_script(&mut self, source: Option<String>) {
+        let Some(pending) = self.pending_script.take() else {
+            return;
+        };
+        self.parser_pause_flag = false;
+
+        let Some(source) = source else {
+            warn!("Could not load script from {}", pending.url);
+            return;
+        };
+
+        self.script_nesting_level += 1;
+        self.run_script(
+            Script {
+                kind: pending.kind,
+                source,
+                url: Some(pending.url),
+                node_id: pending.node_id,
+            },
+            pending.timing,
+        );
+        self.script_nesting_level -= 1;
+    }
+
+    fn run_script(&mut self, script: Script, timing: ScriptTiming) {
+        match timing {
+            ScriptTiming::Immediate => self.execute_script(&script),
+            ScriptTiming::Deferred => self.deferred_scripts.push(script),
+            ScriptTiming::Async => self.async_scripts.push(script),
+        }
+    }
+
+    /// Executes all async scripts that are ready to run. This is done between tokens, so these scripts never block
+    /// the parser.
+    pub(crate) fn run_async_scripts(&mut self) {
+        for script in std::mem::take(&mut self.async_scripts) {
+            self.execute_script(&script);
+        }
+    }
+
+    /// Executes the scripts that must run when the document has finished parsing, in document order
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#the-end
+    pub(crate) fn run_deferred_scripts(&mut self) {
+        self.run_async_scripts();
+
+        for script in std::mem::take(&mut self.deferred_scripts) {
+            self.execute_script(&script);
+        }
+    }
+
+    fn execute_script(&mut self, script: &Script) {
+        let Some(executor) = self.script_executor.as_deref_mut() else {
+            return;
+        };
+
+        if let Err(err) = executor.execute(script, self.document) {
+            match &script.url {
+                Some(url) => warn!("Error while executing script {}: {}", url, err),
+                None => warn!("Error while executing inline script: {}", err),
+            }
+        }
+    }
+
+    fn resolve_script_url(&self, src: &str) -> Option<Url> {
+        match Url::parse(src) {
+            Ok(url) => Some(url),
+            Err(_) => match self.document.url().map(|base_url| base_url.join(src)) {
+                Some(Ok(url)) => Some(url),
+                _ => {
+                    self.parse_error("script element with a src that cannot be resolved");
+                    None
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::builder::DocumentBuilderImpl;
+    use crate::document::document_impl::DocumentImpl;
+    use crate::document::fragment::DocumentFragmentImpl;
+    use crate::parser::streaming::StreamingParser;
+    use crate::parser::Html5ParserOptions;
+    use gosub_css3::system::Css3System;
+    use gosub_interface::config::HasCssSystem;
+    use gosub_interface::document::DocumentBuilder;
+    use gosub_interface::html5::ParseState;
+    use gosub_shared::byte_stream::{ByteStream, Confidence, Encoding};
+    use std::collections::HashMap;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Config;
+
+    impl HasCssSystem for Config {
+        type CssSystem = Css3System;
+    }
+    impl HasDocument for Config {
+        type Document = DocumentImpl<Self>;
+        type DocumentFragment = DocumentFragmentImpl<Self>;
+        type DocumentBuilder = DocumentBuilderImpl;
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        executed: Vec<(ScriptKind, String)>,
+        documents: Vec<String>,
+    }
+
+    impl ScriptExecutor<Config> for Recorder {
+        fn new(_url: &Url) -> Result<Self> {
+            Ok(Self::default())
+        }
+
+        fn execute(&mut self, script: &Script, document: &mut DocumentImpl<Config>) -> Result<()> {
+            self.executed.push((script.kind, script.source.clone()));
+            self.documents.push(document.write());
+            Ok(())
+        }
+    }
+
+    /// Parses the document and provides the external scripts from the given sources
+    fn run(
+        stream: &mut ByteStream,
+        sources: &HashMap<&str, &str>,
+        options: Option<Html5ParserOptions>,
+    ) -> (Recorder, Vec<Url>) {
+        let url = Url::parse("https://example.com/index.html").unwrap();
+        let mut recorder = Recorder::default();
+        let mut requested = vec![];
+
+        let mut doc = <DocumentBuilderImpl as DocumentBuilder<Config>>::new_document(Some(url));
+        let mut parser = StreamingParser::<Config>::new(stream, &mut doc, options).with_script_executor(&mut recorder);
+        loop {
+            match parser.parse_available() {
+                ParseState::Finished => break,
+                ParseState::NeedsInput => parser.close(),
+                ParseState::NeedsScript(url) => {
+                    parser.provide_script(sources.get(url.path()).map(|source| source.to_string()));
+                    requested.push(url);
+                }
+            }
+        }
+        drop(parser);
+
+        (recorder, requested)
+    }
+
+    fn stream(html: &str) -> ByteStream {
+        let mut stream = ByteStream::new(Encoding::UTF8, None);
+        stream.read_from_str(html, Some(Encoding::UTF8));
+        stream
+    }
+
+    #[test]
+    fn script_execution_order() {
+        let sources = HashMap::from([
+            ("/blocking.js", "blocking"),
+            ("/defer.js", "defer"),
+            ("/async.js", "async"),
+        ]);
+
+        let html = r#"<html><head>
+            <script defer src="defer.js"></script>
+            <script>inline 1</script>
+            <script src="blocking.js"></script>
+            <script type="module">module</script>
+            <script async src="async.js"></script>
+            <script type="application/json">{"data": true}</script>
+            <script nomodule>legacy</script>
+            <script src="missing.js"></script>
+        </head><body><script defer>inline 2</script></body></html>"#;
+
+        let (recorder, requested) = run(&mut stream(html), &sources, None);
+        assert_eq!(
+            recorder.executed,
+            vec![
+                (ScriptKind::Classic, "inline 1".to_string()),
+                (ScriptKind::Classic, "blocking".to_string()),
+                (ScriptKind::Classic, "async".to_string()),
+                (ScriptKind::Classic, "inline 2".to_string()),
+                (ScriptKind::Classic, "defer".to_string()),
+                (ScriptKind::Module, "module".to_string()),
+            ]
+        );
+        assert_eq!(
+            requested.iter().map(|url| url.path()).collect::<Vec<_>>(),
+            vec!["/defer.js", "/blocking.js", "/async.js", "/missing.js"]
+        );
+
+        let (recorder, requested) = run(
+            &mut stream(html),
+            &sources,
+            Some(Html5ParserOptions {
+                scripting_enabled: false,
+            }),
+        );
+        assert!(recorder.executed.is_empty());
+        assert!(requested.is_empty());
+    }
+
+    #[test]
+    fn blocking_script_pauses_parser() {
+        let sources = HashMap::from([("/blocking.js", "blocking")]);
+        let html = r#"<p>before</p><script src="blocking.js"></script><p>after</p>"#;
+
+        let (recorder, _) = run(&mut stream(html), &sources, None);
+        assert_eq!(recorder.executed, vec![(ScriptKind::Classic, "blocking".to_string())]);
+        assert_eq!(
+            recorder.documents,
+            vec![r#"<html><head></head><body><p>before</p><script src="blocking.js"></script></body></html>"#]
+        );
+    }
+
+    #[test]
+    fn scripts_run_once_after_encoding_change() {
+        let sources = HashMap::from([("/external.js", "external")]);
+
+        let mut stream = ByteStream::new(Encoding::UTF8, None);
+        stream.append_bytes(b"<html><head><script>inline</script><script src=\"external.js\"></script>");
+        stream.append_bytes(b"<meta charset=\"windows-1252\"></head><body>caf\xe9<script>after</script>");
+        stream.sniff_encoding(None);
+        stream.set_confidence(Confidence::Tentative);
+
+        // The meta element starts the document over, but the scripts before it are not executed again
+        let (recorder, requested) = run(&mut stream, &sources, None);
+        assert_eq!(
+            recorder.executed,
+            vec![
+                (ScriptKind::Classic, "inline".to_string()),
+                (ScriptKind::Classic, "external".to_string()),
+                (ScriptKind::Classic, "after".to_string()),
+            ]
+        );
+        assert_eq!(requested.len(), 1);
+        assert!(recorder.documents[2].contains("caf\u{e9}"), "{}", recorder.documents[2]);
+    }
+}
@@ -0,0 +1,311 @@
+//! Parsing a document while its input arrives
+//!
+//! The streaming parser keeps the state of the tokenizer and the tree builder between chunks of input. Each call to
+//! `parse_available()` parses as far as the input goes, so the document can be shown before it has been loaded
+//! completely. When the parser finds an external script, it waits until the caller has fetched the source and given
+//! it with `provide_script()`.
+use crate::parser::errors::ErrorLogger;
+use crate::parser::{Html5Parser, Html5ParserOptions};
+use crate::tokenizer::Tokenizer;
+use gosub_interface::config::HasDocument;
+use gosub_interface::document::{Document, DocumentType};
+use gosub_interface::html5::{Html5StreamingParser, ParseState, ScriptExecutor};
+use gosub_shared::byte_stream::{ByteStream, Location, Stream};
+use gosub_shared::types::ParseError;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Parses a document from a stream that is filled while parsing
+pub struct StreamingParser<'a, C: HasDocument> {
+    /// The parser, which is only taken out when it is restarted
+    parser: Option<Html5Parser<'a, C>>,
+    options: Option<Html5ParserOptions>,
+    finished: bool,
+}
+
+impl<'a, C: HasDocument> StreamingParser<'a, C> {
+    /// Creates a parser for a whole document. The stream may already contain the first input.
+    pub fn new(stream: &'a mut ByteStream, document: &'a mut C::Document, options: Option<Html5ParserOptions>) -> Self {
+        let error_logger = Rc::new(RefCell::new(ErrorLogger::new()));
+        let tokenizer = Tokenizer::new(stream, None, error_logger.clone(), Location::default());
+
+        Self {
+            parser: Some(Html5Parser::init(tokenizer, document, error_logger, options)),
+            options,
+            finished: false,
+        }
+    }
+
+    fn parser(&self) -> &Html5Parser<'a, C> {
+        self.parser.as_ref().expect("parser is always set")
+    }
+
+    fn parser_mut(&mut self) -> &mut Html5Parser<'a, C> {
+        self.parser.as_mut().expect("parser is always set")
+    }
+
+    /// Executes the scripts of the document with the given executor while parsing
+    pub fn with_script_executor(mut self, script_executor: &'a mut dyn ScriptExecutor<C>) -> Self {
+        self.parser_mut().script_executor = Some(script_executor);
+        self
+    }
+
+    /// Appends undecoded input in the encoding of the stream
+    pub fn append_bytes(&mut self, bytes: &[u8]) {
+        self.parser_mut().tokenizer.stream.append_bytes(bytes);
+    }
+
+    /// Appends decoded input
+    pub fn append_str(&mut self, s: &str) {
+        self.parser_mut().tokenizer.stream.append_str(s);
+    }
+
+    /// Marks the end of the input. The next call to `parse_available()` finishes the document.
+    pub fn close(&mut self) {
+        self.parser_mut().tokenizer.stream.close();
+    }
+
+    /// Returns true when all input has been appended
+    pub fn is_closed(&self) -> bool {
+        self.parser().tokenizer.stream.closed()
+    }
+
+    /// Parses the input that is available, until the document has been parsed completely, or the parser waits for
+    /// more input or for the source of an external script
+    pub fn parse_available(&mut self) -> ParseState {
+        while !self.finished {
+            let state = self.parser_mut().parse_available();
+            if state != ParseState::Finished {
+                return state;
+            }
+            self.finished = true;
+
+            if !self.parser().encoding_changed {
+                break;
+            }
+
+            // A meta element changed the encoding, which has reset the stream. The document is parsed again from
+            // the start with a fresh parser.
+            self.restart();
+            self.finished = false;
+        }
+
+        ParseState::Finished
+    }
+
+    /// Gives the parser the source of the external script it waits for, or `None` when it could not be loaded
+    pub fn provide_script(&mut self, source: Option<String>) {
+        self.parser_mut().provide_script(source);
+    }
+
+    fn restart(&mut self) {
+        let Html5Parser {
+            tokenizer,
+            document,
+            script_executor,
+            scripts_prepared,
+            deferred_scripts,
+            async_scripts,
+            ..
+        } = self.parser.take().expect("parser is always set");
+
+        let mut fresh = C::Document::new(DocumentType::HTML, document.url(), None);
+        fresh.set_media_environment(document.media_environment().clone());
+        *document = fresh;
+
+        let error_logger = Rc::new(RefCell::new(ErrorLogger::new()));
+        let tokenizer = Tokenizer::new(tokenizer.stream, None, error_logger.clone(), Location::default());
+        let mut parser = Html5Parser::init(tokenizer, document, error_logger, self.options);
+
+        // Scripts that have been executed or queued already are not run again for the new document
+        parser.script_executor = script_executor;
+        parser.skip_scripts = scripts_prepared;
+        parser.deferred_scripts = deferred_scripts;
+        parser.async_scripts = async_scripts;
+        self.parser = Some(parser);
+    }
+
+    /// Returns the document as far as it has been parsed
+    pub fn document(&self) -> &C::Document {
+        self.parser().document
+    }
+
+    /// Calls the function with the stream and the document as far as it has been parsed, so it can render the
+    /// document and append the next input to the stream
+    pub fn with_stream(&mut self, f: impl FnOnce(&mut ByteStream, &mut C::Document)) {
+        let parser = self.parser_mut();
+        f(parser.tokenizer.stream, parser.document);
+    }
+
+    /// Returns the parse errors found so far
+    pub fn errors(&self) -> Vec<ParseError> {
+        self.parser().error_logger.borrow().get_errors()
+    }
+}
+
+impl<C: HasDocument> Html5StreamingParser<C> for StreamingParser<'_, C> {
+    fn append_bytes(&mut self, bytes: &[u8]) {
+        self.append_bytes(bytes);
+    }
+
+    fn close(&mut self) {
+        self.close();
+    }
+
+    fn parse_available(&mut self) -> ParseState {
+        self.parse_available()
+    }
+
+    fn provide_script(&mut self, source: Option<String>) {
+        self.provide_script(source);
+    }
+
+    fn document_mut(&mut self) -> &mut C::Document {
+        self.parser_mut().document
+    }
+
+    fn errors(&self) -> Vec<ParseError> {
+        self.errors()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::builder::DocumentBuilderImpl;
+    use crate::document::document_impl::DocumentImpl;
+    use crate::document::fragment::DocumentFragmentImpl;
+    use crate::html_compile;
+    use gosub_css3::system::Css3System;
+    use gosub_interface::config::HasCssSystem;
+    use gosub_interface::document::DocumentBuilder;
+    use gosub_shared::byte_stream::Encoding;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Config;
+
+    impl HasCssSystem for Config {
+        type CssSystem = Css3System;
+    }
+    impl HasDocument for Config {
+        type Document = DocumentImpl<Self>;
+        type DocumentFragment = DocumentFragmentImpl<Self>;
+        type DocumentBuilder = DocumentBuilderImpl;
+    }
+
+    const HTML: &str = concat!(
+        "<!DOCTYPE html>\r\n<html><head><title>Streaming &amp; parsing</title>",
+        "<style>p > a { color: red }</style></head>\r\n",
+        "<body><p class=\"intro\" data-x='1'>Caf\u{e9} &eacute; &#x263A; &notin; &noti</p>",
+        "<!-- a comment --><pre>\nline</pre><table><tr><td>cell</table>",
+        "<textarea>a < b</textarea><script>if (a < b) { x = '</p>'; }</script>",
+        "<svg><circle r=\"1\"/><![CDATA[data]]></svg><p>end",
+    );
+
+    /// Parses the HTML in chunks of the given number of bytes
+    fn parse_chunked(html: &str, chunk_size: usize) -> (DocumentImpl<Config>, usize) {
+        let mut stream = ByteStream::new(Encoding::UTF8, None);
+        let mut doc = <DocumentBuilderImpl as DocumentBuilder<Config>>::new_document(None);
+        let mut parser = StreamingParser::<Config>::new(&mut stream, &mut doc, None);
+
+        let mut partial_documents = 0;
+        for chunk in html.as_bytes().chunks(chunk_size) {
+            parser.append_bytes(chunk);
+            assert_eq!(parser.parse_available(), ParseState::NeedsInput);
+            if parser.document().node_count() > 1 {
+                partial_documents += 1;
+            }
+        }
+        parser.close();
+        assert_eq!(parser.parse_available(), ParseState::Finished);
+        drop(parser);
+
+        (doc, partial_documents)
+    }
+
+    #[test]
+    fn chunked_input() {
+        let expected = html_compile::<Config>(HTML).write();
+
+        for chunk_size in [1, 2, 3, 7, 64, 1000] {
+            let (doc, partial_documents) = parse_chunked(HTML, chunk_size);
+            assert_eq!(doc.write(), expected, "chunks of {chunk_size} bytes");
+            assert!(partial_documents > 0);
+        }
+    }
+
+    #[test]
+    fn feed_callback() {
+        let mut chunks = HTML.as_bytes().chunks(16);
+        let mut calls = 0;
+
+        let mut stream = ByteStream::new(Encoding::UTF8, None);
+        let mut doc = <DocumentBuilderImpl as DocumentBuilder<Config>>::new_document(None);
+        Html5Parser::<Config>::parse_document_streaming(&mut stream, &mut doc, None, &mut |stream, _| {
+            calls += 1;
+            // Not appending anything when the chunks run out finishes the document
+            if let Some(chunk) = chunks.next() {
+                stream.append_bytes(chunk);
+            }
+        })
+        .unwrap();
+
+        assert_eq!(calls, HTML.len().div_ceil(16) + 1);
+        assert_eq!(doc.write(), html_compile::<Config>(HTML).write());
+    }
+
+    #[test]
+    fn partial_document() {
+        let mut stream = ByteStream::new(Encoding::UTF8, None);
+        let mut doc = <DocumentBuilderImpl as DocumentBuilder<Config>>::new_document(None);
+        let mut parser = StreamingParser::<Config>::new(&mut stream, &mut doc, None);
+
+        // Text is added to the document while the rest of the input has not arrived
+        parser.append_str("<p>first para");
+        assert_eq!(parser.parse_available(), ParseState::NeedsInput);
+        assert_eq!(
+            parser.document().write(),
+            "<html><head></head><body><p>first para</p></body></html>"
+        );
+
+        parser.append_str("graph</p><p>sec");
+        assert_eq!(parser.parse_available(), ParseState::NeedsInput);
+        assert_eq!(
+            parser.document().write(),
+            "<html><head></head><body><p>first paragraph</p><p>sec</p></body></html>"
+        );
+
+        parser.with_stream(|stream, _| {
+            stream.append_str("ond");
+            stream.close();
+        });
+        assert!(parser.is_closed());
+        assert_eq!(parser.parse_available(), ParseState::Finished);
+        assert_eq!(
+            parser.document().write(),
+            "<html><head></head><body><p>first paragraph</p><p>second</p></body></html>"
+        );
+        assert!(!parser.errors().is_empty());
+    }
+
+    #[test]
+    fn encoding_change() {
+        let mut stream = ByteStream::new(Encoding::UTF8, None);
+        stream.append_bytes(b"<html><head>");
+        stream.sniff_encoding(None);
+        stream.set_confidence(gosub_shared::byte_stream::Confidence::Tentative);
+
+        let mut doc = <DocumentBuilderImpl as DocumentBuilder<Config>>::new_document(None);
+        let mut parser = StreamingParser::<Config>::new(&mut stream, &mut doc, None);
+        assert_eq!(parser.parse_available(), ParseState::NeedsInput);
+
+        // The meta element switches to windows-1252, and the document is parsed again
+        parser.append_bytes(b"<meta charset=\"windows-1252\"></head><body>caf\xe9");
+        assert_eq!(parser.parse_available(), ParseState::NeedsInput);
+        parser.close();
+        assert_eq!(parser.parse_available(), ParseState::Finished);
+
+        let html = parser.document().write();
+        assert!(html.ends_with("<body>caf\u{e9}</body></html>"), "{html}");
+    }
+}
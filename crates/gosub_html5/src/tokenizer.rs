@@ -13,7 +13,7 @@ use crate::parser::errors::{ErrorLogger, ParserError};
 use crate::tokenizer::state::State;
 use crate::tokenizer::token::Token;
 use cow_utils::CowUtils;
-use gosub_shared::byte_stream::Character::{Ch, StreamEnd};
+use gosub_shared::byte_stream::Character::{Ch, StreamEmpty, StreamEnd};
 use gosub_shared::byte_stream::{ByteStream, Character, Location, LocationHandler, Stream};
 use gosub_shared::types::Result;
 use std::cell::{Ref, RefCell};
@@ -29,6 +29,10 @@ pub const CHAR_FF: char = '\u{000C}';
 pub const CHAR_SPACE: char = '\u{0020}';
 pub const CHAR_REPLACEMENT: char = '\u{FFFD}';
 
+/// Number of bytes an open stream must have available before a state that looks ahead runs without a snapshot. The
+/// longest look ahead is a named character reference.
+const LOOKAHEAD_MARGIN: usize = 64;
+
 /// The tokenizer will read the input stream and emit tokens that can be used by the parser.
 pub struct Tokenizer<'tokens> {
     /// HTML character input stream
@@ -59,6 +63,25 @@ pub struct Tokenizer<'tokens> {
     pub last_char: Character,
     /// Error logger to log errors to
     pub error_logger: Rc<RefCell<ErrorLogger>>,
+    /// Set when a character was read from an open stream that has no more input yet
+    starved: bool,
+}
+
+/// State of the tokenizer before a state runs near the end of an open stream. When the state runs out of input, the
+/// tokenizer is restored, and the state runs again once more input has been appended.
+struct Snapshot {
+    position: usize,
+    location_handler: LocationHandler,
+    state: State,
+    current_attr_name: String,
+    current_attr_value: String,
+    current_attrs: HashMap<String, String>,
+    current_token: Option<Token>,
+    temporary_buffer: String,
+    last_start_token: String,
+    last_token_location: Location,
+    last_char: Character,
+    error_count: usize,
 }
 
 impl Tokenizer<'_> {
@@ -73,6 +96,7 @@ impl Tokenizer<'_> {
 /// This struct is a gateway between the parser and the tokenizer. It holds data that can be needed
 /// by the tokenizer in certain cases. See https://github.com/gosub-browser/gosub-engine/issues/230 for
 /// more information and how we should refactor this properly.
+#[derive(Clone)]
 pub struct ParserData {
     pub adjusted_node_namespace: String,
 }
@@ -133,6 +157,7 @@ impl<'stream> Tokenizer<'stream> {
             temporary_buffer: String::new(),
             last_char: StreamEnd,
             error_logger,
+            starved: false,
         }
     }
 
@@ -142,17 +167,34 @@ impl<'stream> Tokenizer<'stream> {
         self.location_handler.cur_location
     }
 
-    /// Retrieves the next token from the input stream or Token::EOF when the end is reached
+    /// Retrieves the next token from the input stream or Token::EOF when the end is reached. A stream that is
+    /// not closed yet is considered complete, and is closed when all of its input has been read.
     pub fn next_token(&mut self, parser_data: ParserData) -> Result<Token> {
-        self.consume_stream(parser_data)?;
+        if let Some(token) = self.try_next_token(parser_data.clone())? {
+            return Ok(token);
+        }
+
+        self.stream.close();
+        Ok(self.try_next_token(parser_data)?.unwrap_or(Token::Eof {
+            location: self.get_location(),
+        }))
+    }
+
+    /// Retrieves the next token from the input stream, like `next_token()`. When the stream is not closed yet and
+    /// more input is needed for the next token, None is returned. Once more input has been appended to the stream,
+    /// the tokenizer continues where it left off.
+    pub fn try_next_token(&mut self, parser_data: ParserData) -> Result<Option<Token>> {
+        if !self.consume_stream(parser_data)? {
+            return Ok(None);
+        }
 
         if self.token_queue.is_empty() {
-            return Ok(Token::Eof {
+            return Ok(Some(Token::Eof {
                 location: self.get_location(),
-            });
+            }));
         }
 
-        Ok(self.token_queue.remove(0))
+        Ok(Some(self.token_queue.remove(0)))
     }
 
     /// Returns the error logger
@@ -165,12 +207,43 @@ impl<'stream> Tokenizer<'stream> {
         self.state = state;
     }
 
-    /// Consumes the input stream. Continues until the stream is completed or a token has been generated.
-    fn consume_stream(&mut self, parser_data: ParserData) -> Result<()> {
+    /// Consumes the input stream. Continues until the stream is completed or a token has been generated. Returns
+    /// false when the stream is still open and more input is needed.
+    fn consume_stream(&mut self, parser_data: ParserData) -> Result<bool> {
+        let mut snapshot = None;
+
         loop {
+            // The state that just ran needed input that has not arrived yet, so it is undone
+            if let Some(snapshot) = snapshot.take() {
+                if self.starved {
+                    self.restore(snapshot);
+                    return Ok(false);
+                }
+            }
+
             // Something is already in the token buffer, so we can return it.
             if !self.token_queue.is_empty() {
-                return Ok(());
+                return Ok(true);
+            }
+
+            if !self.stream.closed() && self.stream.bytes_available() < LOOKAHEAD_MARGIN {
+                if self.is_text_state() {
+                    // Text states read a single character. When there is none, the text read so far is emitted, so
+                    // the parser can add it to the document while waiting for more input.
+                    if !self.next_char_available() {
+                        if !self.emit_consumed() {
+                            return Ok(false);
+                        }
+                        continue;
+                    }
+                } else {
+                    // Emitting the text first keeps the snapshot small
+                    if self.emit_consumed() {
+                        continue;
+                    }
+                    self.starved = false;
+                    snapshot = Some(self.snapshot());
+                }
             }
 
             match self.state {
@@ -1223,7 +1296,7 @@ impl<'stream> Tokenizer<'stream> {
                     }
                 }
                 State::MarkupDeclarationOpen => {
-                    if Character::slice_to_string(self.stream.get_slice(2)) == "--" {
+                    if Character::slice_to_string(self.stream_get_slice(2)) == "--" {
                         self.current_token = Some(Token::Comment {
                             comment: String::new(),
                             location: self.get_location(),
@@ -1236,13 +1309,13 @@ impl<'stream> Tokenizer<'stream> {
                         continue;
                     }
 
-                    if Character::slice_to_string(self.stream.get_slice(7)).cow_to_uppercase() == "DOCTYPE" {
+                    if Character::slice_to_string(self.stream_get_slice(7)).cow_to_uppercase() == "DOCTYPE" {
                         self.stream_next_n(7);
                         self.state = State::DOCTYPE;
                         continue;
                     }
 
-                    if Character::slice_to_string(self.stream.get_slice(7)) == "[CDATA[" {
+                    if Character::slice_to_string(self.stream_get_slice(7)) == "[CDATA[" {
                         self.stream_next_n(6);
                         let loc = self.get_location();
                         self.stream_next_n(1);
@@ -1606,12 +1679,12 @@ impl<'stream> Tokenizer<'stream> {
                         }
                         _ => {
                             self.stream_prev();
-                            if Character::slice_to_string(self.stream.get_slice(6)).cow_to_uppercase() == "PUBLIC" {
+                            if Character::slice_to_string(self.stream_get_slice(6)).cow_to_uppercase() == "PUBLIC" {
                                 self.stream_next_n(6);
                                 self.state = State::AfterDOCTYPEPublicKeyword;
                                 continue;
                             }
-                            if Character::slice_to_string(self.stream.get_slice(6)).cow_to_uppercase() == "SYSTEM" {
+                            if Character::slice_to_string(self.stream_get_slice(6)).cow_to_uppercase() == "SYSTEM" {
                                 self.stream_next_n(6);
                                 self.state = State::AfterDOCTYPESystemKeyword;
                                 continue;
@@ -2129,18 +2202,79 @@ impl<'stream> Tokenizer<'stream> {
         }
 
         // If there is any consumed data, emit this first as a text token
-        if self.has_consumed_data() {
-            let value = self.get_consumed_str().to_string();
+        self.emit_consumed();
 
-            self.token_queue.push(Token::Text {
-                text: value.to_string(),
-                location: self.last_token_location,
-            });
+        self.token_queue.push(token);
+    }
 
-            self.clear_consume_buffer();
+    /// Emits the consumed data as a text token. Returns false when there is nothing to emit.
+    fn emit_consumed(&mut self) -> bool {
+        if !self.has_consumed_data() {
+            return false;
         }
 
-        self.token_queue.push(token);
+        let value = self.get_consumed_str().to_string();
+        self.token_queue.push(Token::Text {
+            text: value,
+            location: self.last_token_location,
+        });
+        self.clear_consume_buffer();
+
+        true
+    }
+
+    /// Returns true for the states that read a single character of text at a time
+    fn is_text_state(&self) -> bool {
+        matches!(
+            self.state,
+            State::Data | State::RCDATA | State::RAWTEXT | State::ScriptData | State::PLAINTEXT
+        )
+    }
+
+    /// Returns true when the next character can be read from the stream
+    fn next_char_available(&self) -> bool {
+        let position = self.stream.tell_bytes();
+        let c = self.stream.read_and_next();
+        self.stream.seek_bytes(position);
+
+        c != StreamEmpty
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            position: self.stream.tell_bytes(),
+            location_handler: self.location_handler.clone(),
+            state: self.state,
+            current_attr_name: self.current_attr_name.clone(),
+            current_attr_value: self.current_attr_value.clone(),
+            current_attrs: self.current_attrs.clone(),
+            current_token: self.current_token.clone(),
+            temporary_buffer: self.temporary_buffer.clone(),
+            last_start_token: self.last_start_token.clone(),
+            last_token_location: self.last_token_location,
+            last_char: self.last_char,
+            error_count: self.error_logger.borrow().error_count(),
+        }
+    }
+
+    /// Restores the tokenizer to the snapshot. Snapshots are only taken when no text is consumed and no tokens are
+    /// queued.
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.stream.seek_bytes(snapshot.position);
+        self.location_handler = snapshot.location_handler;
+        self.state = snapshot.state;
+        self.consumed.clear();
+        self.current_attr_name = snapshot.current_attr_name;
+        self.current_attr_value = snapshot.current_attr_value;
+        self.current_attrs = snapshot.current_attrs;
+        self.current_token = snapshot.current_token;
+        self.temporary_buffer = snapshot.temporary_buffer;
+        self.token_queue.clear();
+        self.last_start_token = snapshot.last_start_token;
+        self.last_token_location = snapshot.last_token_location;
+        self.last_char = snapshot.last_char;
+        self.error_logger.borrow_mut().truncate(snapshot.error_count);
+        self.starved = false;
     }
 
     // Consumes the given character
@@ -2280,13 +2414,16 @@ impl<'stream> Tokenizer<'stream> {
 
     fn stream_read_and_next(&mut self) -> Character {
         let c = self.stream.read_and_next();
+        if c == StreamEmpty {
+            self.starved = true;
+        }
         self.last_char = c;
         self.location_handler.inc(c);
         c
     }
 
     fn stream_prev(&mut self) {
-        if self.last_char == StreamEnd {
+        if matches!(self.last_char, StreamEnd | StreamEmpty) {
             return;
         }
 
@@ -2299,4 +2436,22 @@ impl<'stream> Tokenizer<'stream> {
             self.stream_read_and_next();
         }
     }
+
+    /// Returns the next characters in the stream, without moving the stream
+    fn stream_get_slice(&mut self, len: usize) -> Vec<Character> {
+        let slice = self.stream.get_slice(len);
+        if slice.contains(&StreamEmpty) {
+            self.starved = true;
+        }
+        slice
+    }
+
+    /// Returns the character at the offset from the current position, without moving the stream
+    fn stream_look_ahead(&mut self, offset: usize) -> Character {
+        let c = self.stream.look_ahead(offset);
+        if c == StreamEmpty {
+            self.starved = true;
+        }
+        c
+    }
 }
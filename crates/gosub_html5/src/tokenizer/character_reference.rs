@@ -3,8 +3,8 @@ extern crate lazy_static;
 use crate::parser::errors::ParserError;
 use crate::tokenizer::replacement_tables::{TOKEN_NAMED_CHARS, TOKEN_REPLACEMENTS};
 use crate::tokenizer::{Tokenizer, CHAR_REPLACEMENT};
+use gosub_shared::byte_stream::Character;
 use gosub_shared::byte_stream::Character::Ch;
-use gosub_shared::byte_stream::{Character, Stream};
 use lazy_static::lazy_static;
 
 /// Different states for the character references
@@ -59,7 +59,7 @@ impl Tokenizer<'_> {
                 CcrState::NamedCharacterReference => {
                     if let Some(entity) = self.find_entity() {
                         self.stream_next_n(entity.len());
-                        let c = self.stream.look_ahead(0);
+                        let c = self.stream_look_ahead(0);
 
                         if as_attribute
                             && !entity.ends_with(';')
@@ -329,7 +329,7 @@ impl Tokenizer<'_> {
     /// Finds the longest entity from the current position in the stream. Returns the entity
     /// replacement OR None when no entity has been found.
     fn find_entity(&mut self) -> Option<String> {
-        let chars = self.stream.get_slice(*LONGEST_ENTITY_LENGTH);
+        let chars = self.stream_get_slice(*LONGEST_ENTITY_LENGTH);
 
         for i in (0..=chars.len()).rev() {
             if let Some(slice) = chars.get(0..i) {
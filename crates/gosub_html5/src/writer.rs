@@ -1,4 +1,8 @@
-use crate::node::visitor::Visitor;
+//! HTML serialization
+//!
+//! Converts (parts of) a document back to HTML, following the HTML fragment serialization algorithm of the HTML
+//! specification (13.3, "Serializing HTML fragments"). Parsing the output results in the same tree again.
+use crate::node::HTML_NAMESPACE;
 use gosub_interface::config::HasDocument;
 use gosub_interface::document::Document;
 
@@ -6,124 +10,260 @@ use gosub_interface::node::ElementDataType;
 use gosub_interface::node::{CommentDataType, DocTypeDataType, Node, NodeType, TextDataType};
 use gosub_shared::node::NodeId;
 
+/// HTML elements that are serialized without contents and end tag
+const VOID_ELEMENTS: [&str; 18] = [
+    "area", "base", "basefont", "bgsound", "br", "col", "embed", "frame", "hr", "img", "input", "keygen", "link",
+    "meta", "param", "source", "track", "wbr",
+];
+
+/// HTML elements of which the text is serialized without escaping, as it is parsed as raw text
+const RAW_TEXT_ELEMENTS: [&str; 7] = ["style", "script", "xmp", "iframe", "noembed", "noframes", "plaintext"];
+
 // Writer to convert a document to a string
 pub struct DocumentWriter {
     /// The buffer to write to
     buffer: String,
-    /// Whether to include comments in the output
-    comments: bool,
+    /// Whether the document was parsed with scripting enabled, in which case `noscript` contains raw text
+    scripting_enabled: bool,
+}
+
+impl Default for DocumentWriter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DocumentWriter {
-    pub fn write_from_node<C: HasDocument>(node: NodeId, doc: &C::Document) -> String {
-        let mut w = Self {
-            comments: false,
+    /// Creates a writer for documents that are parsed with scripting enabled, which is the default of the parser
+    pub fn new() -> Self {
+        Self::with_scripting(true)
+    }
+
+    /// Creates a writer for documents that are parsed with scripting enabled or disabled
+    pub fn with_scripting(scripting_enabled: bool) -> Self {
+        Self {
             buffer: String::new(),
-        };
+            scripting_enabled,
+        }
+    }
 
-        w.visit_node::<C>(node, doc);
-        w.buffer
+    /// Returns the HTML of the node and its descendants, like `outerHTML`. For the document node, this is the
+    /// whole document.
+    pub fn outer_html<C: HasDocument>(mut self, node_id: NodeId, doc: &C::Document) -> String {
+        self.write_node::<C>(node_id, doc);
+        self.buffer
     }
 
-    pub fn visit_node<C: HasDocument>(&mut self, id: NodeId, doc: &C::Document) {
-        let node = match doc.node_by_id(id) {
-            Some(node) => node,
-            None => return,
+    /// Returns the HTML of the descendants of the node, like `innerHTML`
+    pub fn inner_html<C: HasDocument>(mut self, node_id: NodeId, doc: &C::Document) -> String {
+        self.write_children::<C>(node_id, doc);
+        self.buffer
+    }
+
+    fn write_node<C: HasDocument>(&mut self, id: NodeId, doc: &C::Document) {
+        let Some(node) = doc.node_by_id(id) else {
+            return;
         };
 
         match node.type_of() {
-            NodeType::DocumentNode => {
-                // self.document_enter(node);
-                <DocumentWriter as Visitor<C>>::document_enter(self, node);
-
-                self.visit_children::<C>(node.children(), doc);
-                <DocumentWriter as Visitor<C>>::document_leave(self, node);
-            }
+            NodeType::DocumentNode => self.write_children::<C>(id, doc),
             NodeType::DocTypeNode => {
-                <DocumentWriter as Visitor<C>>::doctype_enter(self, node);
-                self.visit_children::<C>(node.children(), doc);
-                <DocumentWriter as Visitor<C>>::doctype_leave(self, node);
+                if let Some(data) = node.get_doctype_data() {
+                    self.buffer.push_str("<!DOCTYPE ");
+                    self.buffer.push_str(data.name());
+                    self.buffer.push('>');
+                }
             }
             NodeType::TextNode => {
-                <DocumentWriter as Visitor<C>>::text_enter(self, node);
-                self.visit_children::<C>(node.children(), doc);
-                <DocumentWriter as Visitor<C>>::text_leave(self, node);
+                if let Some(data) = node.get_text_data() {
+                    let raw = node
+                        .parent_id()
+                        .and_then(|parent| doc.node_by_id(parent))
+                        .is_some_and(|parent| self.has_raw_text::<C>(parent));
+
+                    if raw {
+                        self.buffer.push_str(data.value());
+                    } else {
+                        escape_into(&mut self.buffer, data.value(), false);
+                    }
+                }
             }
             NodeType::CommentNode => {
-                <DocumentWriter as Visitor<C>>::comment_enter(self, node);
-                self.visit_children::<C>(node.children(), doc);
-                <DocumentWriter as Visitor<C>>::comment_leave(self, node);
+                if let Some(data) = node.get_comment_data() {
+                    self.buffer.push_str("<!--");
+                    self.buffer.push_str(data.value());
+                    self.buffer.push_str("-->");
+                }
             }
             NodeType::ElementNode => {
-                <DocumentWriter as Visitor<C>>::element_enter(self, node);
-                self.visit_children::<C>(node.children(), doc);
-                <DocumentWriter as Visitor<C>>::element_leave(self, node);
+                let Some(data) = node.get_element_data() else {
+                    return;
+                };
+
+                self.buffer.push('<');
+                self.buffer.push_str(data.name());
+
+                // Attributes are not kept in source order, so they are sorted to keep the output stable
+                let mut attributes = data.attributes().iter().collect::<Vec<_>>();
+                attributes.sort();
+                for (name, value) in attributes {
+                    self.buffer.push(' ');
+                    self.buffer.push_str(name);
+                    self.buffer.push_str("=\"");
+                    escape_into(&mut self.buffer, value, true);
+                    self.buffer.push('"');
+                }
+
+                self.buffer.push('>');
+
+                // Elements in other namespaces always have an end tag, even when they are empty
+                if data.is_namespace(HTML_NAMESPACE) && VOID_ELEMENTS.contains(&data.name()) {
+                    return;
+                }
+
+                // The contents of a template are the children of the template element
+                self.write_children::<C>(id, doc);
+
+                self.buffer.push_str("</");
+                self.buffer.push_str(data.name());
+                self.buffer.push('>');
             }
         }
     }
 
-    pub fn visit_children<C: HasDocument>(&mut self, children: &[NodeId], doc: &C::Document) {
-        for child in children {
-            self.visit_node::<C>(*child, doc);
+    fn write_children<C: HasDocument>(&mut self, id: NodeId, doc: &C::Document) {
+        let Some(node) = doc.node_by_id(id) else {
+            return;
+        };
+
+        for child in node.children() {
+            self.write_node::<C>(*child, doc);
         }
     }
-}
 
-impl<C: HasDocument> Visitor<C> for DocumentWriter {
-    fn document_enter(&mut self, _node: &C::Node) {}
+    /// Returns true when the text in the element is written as is
+    fn has_raw_text<C: HasDocument>(&self, node: &C::Node) -> bool {
+        let Some(data) = node.get_element_data() else {
+            return false;
+        };
+        if !data.is_namespace(HTML_NAMESPACE) {
+            return false;
+        }
 
-    fn document_leave(&mut self, _node: &C::Node) {}
+        RAW_TEXT_ELEMENTS.contains(&data.name()) || (data.name() == "noscript" && self.scripting_enabled)
+    }
+}
 
-    fn doctype_enter(&mut self, node: &C::Node) {
-        if let Some(data) = node.get_doctype_data() {
-            self.buffer.push_str("<!DOCTYPE ");
-            self.buffer.push_str(data.name());
-            self.buffer.push('>');
+/// Escapes text, or an attribute value in attribute mode
+fn escape_into(buffer: &mut String, value: &str, attribute_mode: bool) {
+    for c in value.chars() {
+        match c {
+            '&' => buffer.push_str("&amp;"),
+            '\u{a0}' => buffer.push_str("&nbsp;"),
+            '<' => buffer.push_str("&lt;"),
+            '>' => buffer.push_str("&gt;"),
+            '"' if attribute_mode => buffer.push_str("&quot;"),
+            c => buffer.push(c),
         }
     }
+}
 
-    fn doctype_leave(&mut self, _node: &C::Node) {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::builder::DocumentBuilderImpl;
+    use crate::document::document_impl::DocumentImpl;
+    use crate::document::fragment::DocumentFragmentImpl;
+    use crate::html_compile;
+    use gosub_css3::system::Css3System;
+    use gosub_interface::config::HasCssSystem;
 
-    fn text_enter(&mut self, node: &C::Node) {
-        if let Some(data) = node.get_text_data() {
-            self.buffer.push_str(data.value());
-        }
-    }
+    #[derive(Clone, Debug, PartialEq)]
+    struct Config;
 
-    fn text_leave(&mut self, _node: &C::Node) {}
+    impl HasCssSystem for Config {
+        type CssSystem = Css3System;
+    }
+    impl HasDocument for Config {
+        type Document = DocumentImpl<Self>;
+        type DocumentFragment = DocumentFragmentImpl<Self>;
+        type DocumentBuilder = DocumentBuilderImpl;
+    }
 
-    fn comment_enter(&mut self, node: &C::Node) {
-        if let Some(data) = node.get_comment_data() {
-            self.buffer.push_str("<!--");
-            self.buffer.push_str(data.value());
-            self.buffer.push_str("-->");
-        }
+    fn serialize(html: &str) -> String {
+        let doc = html_compile::<Config>(html);
+        doc.write()
     }
 
-    fn comment_leave(&mut self, _node: &C::Node) {}
+    #[test]
+    fn serialize_document() {
+        let html = concat!(
+            "<!DOCTYPE html><html><head><title>a &amp; b &lt;c&gt;</title>",
+            "<style>p > a { content: \"&\" }</style></head>",
+            "<body><p id=\"p\" class=\"x\" data-v=\"a&quot;b&amp;c\">1 &lt; 2<br>&nbsp;<!-- comment --></p>",
+            "<script>if (a < b && c) {}</script><noscript><b>js</b></noscript>",
+            "<textarea>&lt;/textarea&gt;</textarea><img src=\"a.png\"><input value=\"&lt;x&gt;\">",
+            "<template><p>in template</p></template>",
+            "<svg viewBox=\"0 0 1 1\"><circle r=\"1\"/><foreignObject><br></foreignObject></svg>",
+            "<math><mi>x</mi></math></body></html>",
+        );
 
-    fn element_enter(&mut self, node: &C::Node) {
-        if let Some(data) = node.get_element_data() {
-            self.buffer.push('<');
-            self.buffer.push_str(data.name());
+        assert_eq!(
+            serialize(html),
+            concat!(
+                "<!DOCTYPE html><html><head><title>a &amp; b &lt;c&gt;</title>",
+                "<style>p > a { content: \"&\" }</style></head>",
+                "<body><p class=\"x\" data-v=\"a&quot;b&amp;c\" id=\"p\">1 &lt; 2<br>&nbsp;<!-- comment --></p>",
+                "<script>if (a < b && c) {}</script><noscript><b>js</b></noscript>",
+                "<textarea>&lt;/textarea&gt;</textarea><img src=\"a.png\"><input value=\"&lt;x&gt;\">",
+                "<template><p>in template</p></template>",
+                "<svg viewBox=\"0 0 1 1\"><circle r=\"1\"></circle><foreignObject><br></foreignObject></svg>",
+                "<math><mi>x</mi></math></body></html>",
+            )
+        );
+    }
 
-            for (name, value) in data.attributes() {
-                self.buffer.push(' ');
-                self.buffer.push_str(name);
-                self.buffer.push_str("=\"");
-                self.buffer.push_str(value);
-                self.buffer.push('"');
-            }
+    #[test]
+    fn round_trip() {
+        let html = "<ul><li>one<li>two</ul><table><tr><td>cell</table><p>a<b>b<i>c</b>d</i>";
 
-            self.buffer.push('>');
-        }
+        // The first serialization adds what the parser inserted, after that it is stable
+        let serialized = serialize(html);
+        assert_eq!(serialize(&serialized), serialized);
+        assert_eq!(
+            serialized,
+            concat!(
+                "<html><head></head><body><ul><li>one</li><li>two</li></ul>",
+                "<table><tbody><tr><td>cell</td></tr></tbody></table><p>a<b>b<i>c</i></b><i>d</i></p></body></html>",
+            )
+        );
     }
 
-    fn element_leave(&mut self, node: &C::Node) {
-        if let Some(data) = node.get_element_data() {
-            self.buffer.push_str("</");
-            self.buffer.push_str(data.name().to_string().as_str());
-            self.buffer.push('>');
-        }
+    #[test]
+    fn inner_and_outer_html() {
+        let doc = html_compile::<Config>("<div id=\"d\"><span>a</span> &amp; <em>b</em></div>");
+        let div = doc.node_by_named_id("d").unwrap().id();
+
+        assert_eq!(
+            DocumentWriter::new().outer_html::<Config>(div, &doc),
+            "<div id=\"d\"><span>a</span> &amp; <em>b</em></div>"
+        );
+        assert_eq!(
+            doc.write_from_node(div),
+            "<div id=\"d\"><span>a</span> &amp; <em>b</em></div>"
+        );
+        assert_eq!(
+            DocumentWriter::new().inner_html::<Config>(div, &doc),
+            "<span>a</span> &amp; <em>b</em>"
+        );
+
+        // With scripting, the parser keeps the contents of noscript as raw text
+        let doc = html_compile::<Config>("<noscript>a < b</noscript>");
+        assert!(DocumentWriter::with_scripting(true)
+            .outer_html::<Config>(NodeId::root(), &doc)
+            .contains("<noscript>a < b</noscript>"));
+        assert!(DocumentWriter::with_scripting(false)
+            .outer_html::<Config>(NodeId::root(), &doc)
+            .contains("<noscript>a &lt; b</noscript>"));
     }
 }
@@ -0,0 +1,568 @@
+//! Session history of an engine instance
+//!
+//! Every navigation adds an entry to the history, and back and forward traverse it. Entries keep what is needed to
+//! show the page like the user left it: the scroll position and the values of its form controls. The pages of the
+//! most recently used entries are kept in memory, so they can be shown again without fetching them.
+use gosub_interface::config::HasDocument;
+use gosub_interface::document::Document;
+use gosub_interface::node::{ElementDataType, Node, TextDataType};
+use gosub_shared::byte_stream::Location;
+use gosub_shared::geo::Point;
+use gosub_shared::node::NodeId;
+use url::Url;
+
+/// Number of entries that keep their page in memory
+const MAX_CACHED_PAGES: usize = 4;
+
+/// Values of the form controls of a page, in tree order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FormState {
+    controls: Vec<FormControl>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct FormControl {
+    /// Tag and name of the control, so a value is only restored into the same control
+    key: String,
+    value: ControlValue,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum ControlValue {
+    /// The `value` and `checked` attributes of an input
+    Input { value: Option<String>, checked: bool },
+    /// The text of a textarea
+    Text(String),
+    /// Indices of the selected options of a select
+    Selected(Vec<usize>),
+}
+
+impl FormState {
+    /// Reads the values of the form controls of the document
+    pub fn capture<C: HasDocument>(document: &C::Document) -> Self {
+        let controls = form_controls::<C>(document)
+            .into_iter()
+            .filter_map(|id| {
+                let data = document.node_by_id(id)?.get_element_data()?;
+                let value = match data.name() {
+                    "textarea" => ControlValue::Text(text_content::<C>(document, id)),
+                    "select" => ControlValue::Selected(
+                        descendants::<C>(document, id, "option")
+                            .into_iter()
+                            .enumerate()
+                            .filter(|(_, option)| {
+                                document
+                                    .node_by_id(*option)
+                                    .and_then(|node| node.get_element_data())
+                                    .is_some_and(|data| data.attribute("selected").is_some())
+                            })
+                            .map(|(index, _)| index)
+                            .collect(),
+                    ),
+                    _ => ControlValue::Input {
+                        value: data.attribute("value").cloned(),
+                        checked: data.attribute("checked").is_some(),
+                    },
+                };
+
+                Some(FormControl {
+                    key: control_key::<C>(data),
+                    value,
+                })
+            })
+            .collect();
+
+        Self { controls }
+    }
+
+    /// Writes the values into the form controls of the document. A control only gets its value back when the
+    /// document still has the same control at the same place. Returns true when any value has changed.
+    pub fn restore<C: HasDocument>(&self, document: &mut C::Document) -> bool {
+        let mut changed = false;
+
+        for (id, control) in form_controls::<C>(document).into_iter().zip(&self.controls) {
+            let Some(data) = document.node_by_id(id).and_then(|node| node.get_element_data()) else {
+                continue;
+            };
+            if control_key::<C>(data) != control.key {
+                continue;
+            }
+
+            changed |= match &control.value {
+                ControlValue::Input { value, checked } => set_attributes::<C>(
+                    document,
+                    id,
+                    &[("value", value.clone()), ("checked", checked.then(String::new))],
+                ),
+                ControlValue::Text(text) => set_text_content::<C>(document, id, text),
+                ControlValue::Selected(selected) => {
+                    let mut changed = false;
+                    for (index, option) in descendants::<C>(document, id, "option").into_iter().enumerate() {
+                        let selected = selected.contains(&index).then(String::new);
+                        changed |= set_attributes::<C>(document, option, &[("selected", selected)]);
+                    }
+                    changed
+                }
+            };
+        }
+
+        changed
+    }
+}
+
+/// Returns the form controls of the document in tree order
+fn form_controls<C: HasDocument>(document: &C::Document) -> Vec<NodeId> {
+    let mut controls = Vec::new();
+    let mut stack = vec![document.get_root().id()];
+
+    while let Some(id) = stack.pop() {
+        let Some(node) = document.node_by_id(id) else {
+            continue;
+        };
+        if node
+            .get_element_data()
+            .is_some_and(|data| matches!(data.name(), "input" | "textarea" | "select"))
+        {
+            controls.push(id);
+        }
+        stack.extend(node.children().iter().rev());
+    }
+
+    controls
+}
+
+/// Returns the descendants of the node with the tag name, in tree order
+fn descendants<C: HasDocument>(document: &C::Document, id: NodeId, name: &str) -> Vec<NodeId> {
+    let mut found = Vec::new();
+    let mut stack = document
+        .node_by_id(id)
+        .map(|node| node.children().iter().rev().copied().collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    while let Some(id) = stack.pop() {
+        let Some(node) = document.node_by_id(id) else {
+            continue;
+        };
+        if node.get_element_data().is_some_and(|data| data.name() == name) {
+            found.push(id);
+        }
+        stack.extend(node.children().iter().rev());
+    }
+
+    found
+}
+
+fn control_key<C: HasDocument>(data: &<<C::Document as Document<C>>::Node as Node<C>>::ElementData) -> String {
+    format!(
+        "{}:{}",
+        data.name(),
+        data.attribute("name").map(String::as_str).unwrap_or_default()
+    )
+}
+
+/// Sets the attributes of the element, or removes the ones without a value. Returns true when any has changed.
+fn set_attributes<C: HasDocument>(
+    document: &mut C::Document,
+    id: NodeId,
+    attributes: &[(&str, Option<String>)],
+) -> bool {
+    let Some(mut node) = document.cloned_node_by_id(id) else {
+        return false;
+    };
+    let Some(data) = node.get_element_data_mut() else {
+        return false;
+    };
+    if attributes
+        .iter()
+        .all(|(name, value)| data.attribute(name) == value.as_ref())
+    {
+        return false;
+    }
+
+    for (name, value) in attributes {
+        match value {
+            Some(value) => data.add_attribute(name, value),
+            None => data.remove_attribute(name),
+        }
+    }
+    document.update_node(node);
+
+    true
+}
+
+/// Returns the text of the text children of the node
+fn text_content<C: HasDocument>(document: &C::Document, id: NodeId) -> String {
+    let Some(node) = document.node_by_id(id) else {
+        return String::new();
+    };
+
+    node.children()
+        .iter()
+        .filter_map(|child| {
+            document
+                .node_by_id(*child)?
+                .get_text_data()
+                .map(|t| t.value().to_string())
+        })
+        .collect()
+}
+
+/// Replaces the children of the node by a single text node. Returns true when the text has changed.
+fn set_text_content<C: HasDocument>(document: &mut C::Document, id: NodeId, text: &str) -> bool {
+    if text_content::<C>(document, id) == text {
+        return false;
+    }
+
+    let children = document
+        .node_by_id(id)
+        .map(|node| node.children().to_vec())
+        .unwrap_or_default();
+    if let [child] = children[..] {
+        if let Some(mut node) = document.cloned_node_by_id(child) {
+            if let Some(data) = node.get_text_data_mut() {
+                *data.value_mut() = text.to_string();
+                document.update_node(node);
+                return true;
+            }
+        }
+    }
+
+    for child in children {
+        document.detach_node(child);
+    }
+    if !text.is_empty() {
+        let node = <C::Document as Document<C>>::new_text_node(text, Location::default());
+        document.register_node_at(node, id, None);
+    }
+
+    true
+}
+
+/// Returns the text of the `title` element of the document, with its white space collapsed
+pub fn document_title<C: HasDocument>(document: &C::Document) -> Option<String> {
+    let mut stack = vec![document.get_root().id()];
+
+    while let Some(id) = stack.pop() {
+        let node = document.node_by_id(id)?;
+        if node.get_element_data().is_some_and(|data| data.name() == "title") {
+            let text = node
+                .children()
+                .iter()
+                .filter_map(|child| {
+                    document
+                        .node_by_id(*child)?
+                        .get_text_data()
+                        .map(|t| t.value().to_string())
+                })
+                .collect::<String>();
+            let title = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+            return (!title.is_empty()).then_some(title);
+        }
+        stack.extend(node.children().iter().rev());
+    }
+
+    None
+}
+
+/// An entry in the session history. `P` is the page of the entry, while it is kept in memory.
+pub struct HistoryEntry<P> {
+    pub url: Url,
+    pub title: String,
+    /// Scroll position when the entry was left
+    pub scroll: Point,
+    /// Values of the form controls when the entry was left
+    pub form_state: FormState,
+    /// Identifies the document of the entry. Entries that are added by fragment navigations share the document of
+    /// the entry they were navigated from.
+    document: u64,
+    page: Option<P>,
+}
+
+impl<P> HistoryEntry<P> {
+    fn new(url: Url, document: u64) -> Self {
+        Self {
+            title: url.to_string(),
+            url,
+            scroll: Point::ZERO,
+            form_state: FormState::default(),
+            document,
+            page: None,
+        }
+    }
+}
+
+/// The session history of an instance, as shown by the chrome
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryList {
+    pub entries: Vec<HistoryItem>,
+    /// Index of the entry that is shown
+    pub current: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryItem {
+    pub url: Url,
+    pub title: String,
+}
+
+/// List of the pages that have been visited in an instance
+pub struct SessionHistory<P> {
+    entries: Vec<HistoryEntry<P>>,
+    current: usize,
+    next_document: u64,
+}
+
+impl<P> SessionHistory<P> {
+    /// Creates a history with an entry for the initial page
+    pub fn new(url: Url) -> Self {
+        Self {
+            entries: vec![HistoryEntry::new(url, 0)],
+            current: 0,
+            next_document: 1,
+        }
+    }
+
+    /// Index of the entry that is shown
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    pub fn current(&self) -> &HistoryEntry<P> {
+        &self.entries[self.current]
+    }
+
+    pub fn current_mut(&mut self) -> &mut HistoryEntry<P> {
+        &mut self.entries[self.current]
+    }
+
+    pub fn get(&self, index: usize) -> Option<&HistoryEntry<P>> {
+        self.entries.get(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the index of the entry that is `delta` entries away from the current one, if there is one
+    pub fn offset(&self, delta: isize) -> Option<usize> {
+        let index = self.current.checked_add_signed(delta)?;
+        (index < self.entries.len()).then_some(index)
+    }
+
+    /// Returns true when the URL only differs from the URL of the current entry in its fragment, so navigating to
+    /// it scrolls the current document instead of loading a new one
+    pub fn is_fragment_navigation(&self, url: &Url) -> bool {
+        if url.fragment().is_none() {
+            return false;
+        }
+
+        let mut current = self.current().url.clone();
+        current.set_fragment(url.fragment());
+        &current == url
+    }
+
+    /// Returns true when the entry shows the same document as the current entry
+    pub fn is_same_document(&self, index: usize) -> bool {
+        self.entries
+            .get(index)
+            .is_some_and(|entry| entry.document == self.current().document)
+    }
+
+    /// Adds an entry for a newly loaded document after the current entry. Entries that could be reached by going
+    /// forward are removed.
+    pub fn push(&mut self, url: Url) {
+        let document = self.next_document;
+        self.next_document += 1;
+        self.push_entry(HistoryEntry::new(url, document));
+    }
+
+    /// Adds an entry for a fragment navigation, which shares the document of the current entry
+    pub fn push_fragment(&mut self, url: Url) {
+        let mut entry = HistoryEntry::new(url, self.current().document);
+        entry.title.clone_from(&self.current().title);
+        self.push_entry(entry);
+    }
+
+    fn push_entry(&mut self, entry: HistoryEntry<P>) {
+        self.entries.truncate(self.current + 1);
+        self.entries.push(entry);
+        self.current = self.entries.len() - 1;
+    }
+
+    /// Makes the entry at the index the current entry
+    pub fn set_current(&mut self, index: usize) {
+        if index < self.entries.len() {
+            self.current = index;
+        }
+    }
+
+    /// Keeps the page of the document of the entry in memory. Pages of the entries furthest from the current
+    /// entry are dropped when there are too many.
+    pub fn store_page(&mut self, index: usize, page: P) {
+        let Some(entry) = self.entries.get_mut(index) else {
+            return;
+        };
+        entry.page = Some(page);
+
+        let mut cached = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.page.is_some())
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        cached.sort_by_key(|index| index.abs_diff(self.current));
+        for index in cached.into_iter().skip(MAX_CACHED_PAGES) {
+            self.entries[index].page = None;
+        }
+    }
+
+    /// Takes the page of the entry, when it is kept in memory. The page is shared by all entries of its document.
+    pub fn take_page(&mut self, index: usize) -> Option<P> {
+        let document = self.entries.get(index)?.document;
+        self.entries
+            .iter_mut()
+            .filter(|entry| entry.document == document)
+            .find_map(|entry| entry.page.take())
+    }
+
+    /// Returns the entries for the chrome
+    pub fn list(&self) -> HistoryList {
+        HistoryList {
+            entries: self
+                .entries
+                .iter()
+                .map(|entry| HistoryItem {
+                    url: entry.url.clone(),
+                    title: entry.title.clone(),
+                })
+                .collect(),
+            current: self.current,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gosub_css3::system::Css3System;
+    use gosub_html5::document::builder::DocumentBuilderImpl;
+    use gosub_html5::document::document_impl::DocumentImpl;
+    use gosub_html5::document::fragment::DocumentFragmentImpl;
+    use gosub_html5::parser::Html5Parser;
+    use gosub_interface::config::HasCssSystem;
+    use gosub_interface::document::DocumentBuilder;
+    use gosub_shared::byte_stream::{ByteStream, Encoding};
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Config;
+
+    impl HasCssSystem for Config {
+        type CssSystem = Css3System;
+    }
+    impl HasDocument for Config {
+        type Document = DocumentImpl<Self>;
+        type DocumentFragment = DocumentFragmentImpl<Self>;
+        type DocumentBuilder = DocumentBuilderImpl;
+    }
+
+    fn parse(html: &str) -> DocumentImpl<Config> {
+        let mut stream = ByteStream::new(Encoding::UTF8, None);
+        stream.read_from_str(html, Some(Encoding::UTF8));
+        stream.close();
+
+        let mut doc = <DocumentBuilderImpl as DocumentBuilder<Config>>::new_document(None);
+        Html5Parser::<Config>::parse_document(&mut stream, &mut doc, None).unwrap();
+        doc
+    }
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn navigation_and_traversal() {
+        let mut history = SessionHistory::<()>::new(url("https://example.com/"));
+        history.push(url("https://example.com/a"));
+        history.push(url("https://example.com/b"));
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.current_index(), 2);
+
+        assert_eq!(history.offset(-1), Some(1));
+        assert_eq!(history.offset(1), None);
+        history.set_current(0);
+        assert_eq!(history.offset(-1), None);
+        assert!(!history.is_same_document(1));
+
+        // Navigating removes the entries that could be reached by going forward
+        history.push(url("https://example.com/c"));
+        let list = history.list();
+        let urls = list.entries.iter().map(|e| e.url.path()).collect::<Vec<_>>();
+        assert_eq!(urls, vec!["/", "/c"]);
+        assert_eq!(list.current, 1);
+    }
+
+    #[test]
+    fn fragment_navigation() {
+        let mut history = SessionHistory::<()>::new(url("https://example.com/page"));
+        history.current_mut().title = "Page".to_string();
+
+        assert!(history.is_fragment_navigation(&url("https://example.com/page#section")));
+        assert!(!history.is_fragment_navigation(&url("https://example.com/page")));
+        assert!(!history.is_fragment_navigation(&url("https://example.com/other#section")));
+
+        history.push_fragment(url("https://example.com/page#section"));
+        assert_eq!(history.current().title, "Page");
+        assert!(history.is_same_document(0));
+        assert!(history.is_fragment_navigation(&url("https://example.com/page#other")));
+    }
+
+    #[test]
+    fn cached_pages() {
+        let mut history = SessionHistory::new(url("https://example.com/0"));
+        for i in 1..=6 {
+            history.store_page(history.current_index(), i - 1);
+            history.push(url(&format!("https://example.com/{i}")));
+        }
+
+        // Only the pages closest to the current entry are kept
+        assert_eq!(history.take_page(0), None);
+        assert_eq!(history.take_page(1), None);
+        assert_eq!(history.take_page(5), Some(5));
+        assert_eq!(history.take_page(5), None);
+
+        // The page of a document is shared by its fragment entries
+        history.push_fragment(url("https://example.com/6#top"));
+        history.store_page(6, 6);
+        assert_eq!(history.take_page(7), Some(6));
+    }
+
+    #[test]
+    fn form_state() {
+        let left = parse(concat!(
+            r#"<input name="a" value="2"><input type="checkbox" name="b" checked>"#,
+            r#"<textarea name="c">new text</textarea><textarea name="d">filled</textarea>"#,
+            r#"<select name="e"><option>x<optgroup><option>y</optgroup><option selected>z</select>"#,
+        ));
+        let state = FormState::capture::<Config>(&left);
+
+        // The page is loaded again with the initial values of its controls
+        let mut doc = parse(concat!(
+            r#"<input name="a" value="1"><input type="checkbox" name="b">"#,
+            r#"<textarea name="c">old</textarea><textarea name="d"></textarea>"#,
+            r#"<select name="e"><option>x<optgroup><option selected>y</optgroup><option>z</select>"#,
+        ));
+        assert!(state.restore::<Config>(&mut doc));
+        assert_eq!(FormState::capture::<Config>(&doc), state);
+        assert!(!state.restore::<Config>(&mut doc));
+
+        // Values are not restored into other controls
+        let mut doc = parse(r#"<input name="x" value="1"><textarea name="c">old</textarea>"#);
+        assert!(!state.restore::<Config>(&mut doc));
+    }
+}
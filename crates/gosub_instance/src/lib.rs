@@ -1,13 +1,17 @@
 use gosub_interface::chrome::ChromeHandle;
 use gosub_interface::config::{HasTreeDrawer, ModuleConfiguration};
+use gosub_interface::document::Document;
 use gosub_interface::draw::TreeDrawer;
 use gosub_interface::eventloop::EventLoopHandle;
 use gosub_interface::input::InputEvent;
 use gosub_interface::instance::{Handles, InstanceId};
 use gosub_interface::layout::LayoutTree;
-use gosub_interface::render_backend::{ImageBuffer, NodeDesc};
+use gosub_interface::mutation::MutationObserverInit;
+use gosub_interface::render_backend::{ImageBuffer, ImgCache, NodeDesc};
+use gosub_interface::render_tree::RenderTree;
 use gosub_net::http::fetcher::Fetcher;
 use gosub_shared::geo::SizeU32;
+use gosub_shared::node::NodeId;
 use gosub_shared::types::Result;
 use gosub_web_platform::{WebEventLoop, WebEventLoopHandle, WebEventLoopMessage};
 use log::warn;
@@ -16,17 +20,40 @@ use std::sync::Arc;
 use tokio::runtime::{Builder, Handle, Runtime};
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::task;
-use tokio::task::LocalSet;
+use tokio::task::{JoinHandle, LocalSet};
 use url::Url;
 
+pub mod history;
+
+use history::{document_title, FormState, SessionHistory};
+pub use history::{HistoryItem, HistoryList};
+
 /// Represents a running instance of the engine. This can be a tab in a browser or a webview
 pub struct EngineInstance<C: ModuleConfiguration> {
     pub title: String,
     pub url: Url,
     pub data: C::TreeDrawer,
+    /// The document that is currently displayed
+    document: C::Document,
     web: WebEventLoopHandle,
     rx: Receiver<InstanceMessage>,
     irx: Receiver<InternalInstanceMessage<C>>,
+    /// Documents loaded by navigations and reloads. These are not `Send`, so they don't go through `El`
+    dtx: Sender<(u64, C::Document)>,
+    drx: Receiver<(u64, C::Document)>,
+    /// A load has delivered its render tree, but not yet its document
+    tree_loaded: bool,
+    /// A load has delivered its document, but not yet its render tree
+    document_loaded: bool,
+    /// The load that is in progress, and what it is loaded for
+    load: Option<(JoinHandle<()>, Load)>,
+    /// Id of the most recent load. The messages of a load carry its id, so the ones of a load that has been aborted
+    /// are dropped.
+    load_id: u64,
+    /// Render tree and document of the page that is replaced by the load in progress
+    leaving_tree: Option<C::RenderTree>,
+    leaving_document: Option<C::Document>,
+    history: SessionHistory<Page<C>>,
     el: El<C>,
     id: InstanceId,
     handles: Handles<C>,
@@ -59,20 +86,36 @@ impl<C: ModuleConfiguration> EngineInstance<C> {
         handles: Handles<C>,
     ) -> Result<Self> {
         let fetcher = Arc::new(Fetcher::new(url.clone()));
-        let (data, _handle) = C::TreeDrawer::with_fetcher(url.clone(), fetcher.clone(), layouter, false).await?;
+        let (data, document) = C::TreeDrawer::with_fetcher(url.clone(), fetcher.clone(), layouter, false).await?;
 
         let (itx, irx) = tokio::sync::mpsc::channel(128);
+        let (dtx, drx) = tokio::sync::mpsc::channel(8);
 
         let web = WebEventLoop::new_on_thread(handles.clone());
 
+        let mut history = SessionHistory::new(url.clone());
+        if let Some(title) = document_title::<C>(&document) {
+            history.current_mut().title = title;
+        }
+
         Ok(EngineInstance {
-            title: "Gosub".to_string(),
+            title: history.current().title.clone(),
             web,
             url,
             data,
+            document,
             rx,
-            el: El(itx),
+            el: El { tx: itx, load: 0 },
             irx,
+            dtx,
+            drx,
+            tree_loaded: false,
+            document_loaded: false,
+            load: None,
+            load_id: 0,
+            leaving_tree: None,
+            leaving_document: None,
+            history,
             id,
             handles,
             fetcher,
@@ -109,9 +152,35 @@ impl<C: ModuleConfiguration> EngineInstance<C> {
         let set = LocalSet::new();
 
         set.block_on(rt, async move {
-            while let Some(message) = self.rx.recv().await {
-                if let Err(e) = self.handle_message(message).await {
-                    warn!("Error: {:?}", e);
+            loop {
+                tokio::select! {
+                    message = self.rx.recv() => {
+                        let Some(message) = message else {
+                            break;
+                        };
+                        if let Err(e) = self.handle_message(message).await {
+                            warn!("Error: {:?}", e);
+                        }
+                    }
+
+                    message = self.irx.recv() => {
+                        let Some(message) = message else {
+                            break;
+                        };
+                        self.handle_internal_message(message);
+                    }
+
+                    document = self.drx.recv() => {
+                        let Some((load, document)) = document else {
+                            break;
+                        };
+                        if !self.is_current_load(load) {
+                            continue;
+                        }
+                        self.leaving_document = Some(std::mem::replace(&mut self.document, document));
+                        self.document_loaded = true;
+                        self.finish_load();
+                    }
                 }
             }
         });
@@ -121,35 +190,59 @@ impl<C: ModuleConfiguration> EngineInstance<C> {
     async fn handle_message(&mut self, message: InstanceMessage) -> Result<()> {
         match message {
             InstanceMessage::Redraw(size) => {
-                self.size = size;
+                if size != self.size {
+                    self.size = size;
+                    self.update_media();
+                }
+
                 let scene = self.data.draw(size, &self.el);
 
                 self.handles.chrome.draw_scene(scene, size, self.id);
             }
 
             InstanceMessage::Navigate(url) => {
-                let el = self.el.clone();
-
-                task::spawn_local(self.data.navigate(url, el));
+                if self.history.is_fragment_navigation(&url) {
+                    self.save_entry_state();
+                    self.history.push_fragment(url.clone());
+                    self.data.scroll_to_fragment(url.fragment().unwrap_or_default());
+                    self.url = url;
+                    self.redraw();
+                } else {
+                    self.start_load(url.clone(), Load::Navigate(url));
+                }
             }
 
             InstanceMessage::Back => {
-                //TODO
+                if let Some(index) = self.history.offset(-1) {
+                    self.traverse(index);
+                }
             }
 
             InstanceMessage::Forward => {
-                //TODO
+                if let Some(index) = self.history.offset(1) {
+                    self.traverse(index);
+                }
+            }
+
+            InstanceMessage::GoTo(index) => {
+                if index < self.history.len() && index != self.history.current_index() {
+                    self.traverse(index);
+                }
             }
 
             InstanceMessage::Reload => {
-                let el = self.el.clone();
+                let url = self.history.current().url.clone();
+                self.start_load(url, Load::Traverse(self.history.current_index()));
+            }
 
-                task::spawn_local(self.data.reload(el));
+            InstanceMessage::History(sender) => {
+                let _ = sender.send(self.history.list());
             }
 
             InstanceMessage::Close => {
                 self.rx.close();
                 self.irx.close();
+                self.drx.close();
             }
 
             InstanceMessage::Debug(event) => {
@@ -210,13 +303,259 @@ impl<C: ModuleConfiguration> EngineInstance<C> {
         Ok(())
     }
 
+    /// Handles a message sent from within the instance (the tree drawer or a load task)
+    fn handle_internal_message(&mut self, message: InternalInstanceMessage<C>) {
+        match message {
+            InternalInstanceMessage::Image(url, buf, size) => {
+                self.data.get_img_cache().add(url.to_string(), buf, size);
+                self.data.make_dirty();
+            }
+            InternalInstanceMessage::Redraw => {}
+            InternalInstanceMessage::ReloadFrom(load, tree) => {
+                if !self.is_current_load(load) {
+                    return;
+                }
+                self.show_loaded_tree(tree);
+                self.tree_loaded = true;
+                self.finish_load();
+            }
+            InternalInstanceMessage::PartialTree(load, tree) => {
+                // A partial tree that arrives after the tree of the loaded page is outdated
+                if !self.is_current_load(load) || self.tree_loaded {
+                    return;
+                }
+                self.show_loaded_tree(tree);
+            }
+        }
+
+        if self.size != SizeU32::new(0, 0) {
+            self.redraw();
+        }
+    }
+
+    /// Shows a render tree of the page that is loaded. Only the first tree of a load replaces the tree of the page
+    /// that is left, later ones replace the partial trees of the same load.
+    fn show_loaded_tree(&mut self, tree: C::RenderTree) {
+        let tree = self.data.replace_tree(tree);
+        if self.leaving_tree.is_none() {
+            self.leaving_tree = Some(tree);
+        }
+    }
+
+    /// Returns true when the message of the load with the id belongs to the load in progress
+    fn is_current_load(&self, load: u64) -> bool {
+        self.load.is_some() && load == self.load_id
+    }
+
+    /// Loads the page at the URL. When it has loaded, `finish_load` updates the session history.
+    fn start_load(&mut self, url: Url, load: Load) {
+        // A new load replaces the one in progress
+        self.abort_load();
+        self.save_entry_state();
+
+        self.load_id += 1;
+        let el = El {
+            tx: self.el.tx.clone(),
+            load: self.load_id,
+        };
+        let future = self.data.navigate(url, el);
+        let dtx = self.dtx.clone();
+        let id = self.load_id;
+
+        let task = task::spawn_local(async move {
+            if let Ok(document) = future.await {
+                let _ = dtx.send((id, document)).await;
+            }
+        });
+        self.load = Some((task, load));
+    }
+
+    /// Stops the load in progress. When it has already replaced the render tree or the document of the page, the
+    /// page is shown again as it was.
+    fn abort_load(&mut self) {
+        if let Some((task, _)) = self.load.take() {
+            task.abort();
+        }
+        self.tree_loaded = false;
+        self.document_loaded = false;
+
+        if let Some(tree) = self.leaving_tree.take() {
+            self.data.reload_from(tree);
+        }
+        if let Some(document) = self.leaving_document.take() {
+            self.document = document;
+        }
+    }
+
+    /// Shows the entry of the session history at the index. Entries of the current document are scrolled to,
+    /// pages that are kept in memory are shown directly, and other pages are loaded again.
+    fn traverse(&mut self, index: usize) {
+        if self.history.is_same_document(index) {
+            self.save_entry_state();
+            self.history.set_current(index);
+            self.data.scroll_to(self.history.current().scroll);
+            self.url = self.history.current().url.clone();
+            self.redraw();
+            return;
+        }
+
+        let Some(page) = self.history.take_page(index) else {
+            let url = self.history.get(index).map(|entry| entry.url.clone());
+            if let Some(url) = url {
+                self.start_load(url, Load::Traverse(index));
+            }
+            return;
+        };
+
+        self.abort_load();
+        self.save_entry_state();
+
+        let tree = self.data.replace_tree(page.tree);
+        let document = std::mem::replace(&mut self.document, page.document);
+        let current = self.history.current_index();
+        self.history.store_page(current, Page { tree, document });
+
+        self.history.set_current(index);
+        self.show_current_entry();
+    }
+
+    /// Remembers the scroll position and form values of the current entry, before it is left
+    fn save_entry_state(&mut self) {
+        let scroll = self.data.scroll_position();
+        let form_state = FormState::capture::<C>(&self.document);
+
+        let entry = self.history.current_mut();
+        entry.scroll = scroll;
+        entry.form_state = form_state;
+    }
+
+    /// Called when either half of a load (render tree or document) arrives. Once both have arrived, the session
+    /// history is updated, and the media queries are evaluated against the viewport, as the render tree of a freshly
+    /// loaded document has been generated for the default media environment.
+    fn finish_load(&mut self) {
+        if !(self.tree_loaded && self.document_loaded) {
+            return;
+        }
+
+        self.tree_loaded = false;
+        self.document_loaded = false;
+
+        let leaving = match (self.leaving_tree.take(), self.leaving_document.take()) {
+            (Some(tree), Some(document)) => Some(Page { tree, document }),
+            _ => None,
+        };
+        let current = self.history.current_index();
+
+        match self.load.take().map(|(_, load)| load) {
+            Some(Load::Navigate(url)) => {
+                if let Some(page) = leaving {
+                    self.history.store_page(current, page);
+                }
+                // The document has the URL after redirects
+                self.history.push(self.document.url().unwrap_or(url));
+                if let Some(title) = document_title::<C>(&self.document) {
+                    self.history.current_mut().title = title;
+                }
+                self.url = self.history.current().url.clone();
+                self.title = self.history.current().title.clone();
+
+                self.update_media();
+                if let Some(fragment) = self.url.fragment() {
+                    self.data.scroll_to_fragment(fragment);
+                }
+            }
+            Some(Load::Traverse(index)) => {
+                // A reload loads the current entry again, and does not keep the page it replaces
+                if index != current {
+                    if let Some(page) = leaving {
+                        self.history.store_page(current, page);
+                    }
+                }
+                self.history.set_current(index);
+                if let Some(title) = document_title::<C>(&self.document) {
+                    self.history.current_mut().title = title;
+                }
+
+                // The loaded document has the initial values of its form controls. Only the controls that get
+                // their values back have to be styled again, so the render tree is updated from the mutations.
+                let observer = self.document.create_mutation_observer();
+                let options = MutationObserverInit {
+                    child_list: true,
+                    attributes: Some(true),
+                    character_data: Some(true),
+                    subtree: true,
+                    ..Default::default()
+                };
+                if let Err(e) = self.document.observe(observer, NodeId::root(), options) {
+                    warn!("Could not observe the form controls of the document: {e}");
+                }
+                if self.history.current().form_state.restore::<C>(&mut self.document) {
+                    let records = self.document.take_records(observer);
+                    self.data.update_from(&self.document, &records);
+                }
+                self.document.disconnect(observer);
+                self.show_current_entry();
+            }
+            None => self.update_media(),
+        }
+    }
+
+    /// Shows the page of the current entry of the session history like it was left
+    fn show_current_entry(&mut self) {
+        let entry = self.history.current();
+        self.url = entry.url.clone();
+        self.title = entry.title.clone();
+        let scroll = entry.scroll;
+
+        // The viewport may have changed while the page was not shown
+        self.update_media();
+        self.data.scroll_to(scroll);
+        self.redraw();
+    }
+
+    /// Evaluates the media queries of the document against the current viewport size, and regenerates the
+    /// render tree when any of them changed
+    fn update_media(&mut self) {
+        if self.size == SizeU32::new(0, 0) {
+            return;
+        }
+
+        let env = self
+            .document
+            .media_environment()
+            .with_size(self.size.width as f32, self.size.height as f32);
+
+        if self.document.set_media_environment(env) {
+            let tree = <C::RenderTree as RenderTree<C>>::from_document(&self.document);
+            self.data.reload_from(tree);
+        }
+    }
+
     fn redraw(&mut self) {
+        if self.size == SizeU32::new(0, 0) {
+            return;
+        }
+
         let scene = self.data.draw(self.size, &self.el);
 
         self.handles.chrome.draw_scene(scene, self.size, self.id);
     }
 }
 
+/// Render tree and document of a page that is kept in the session history
+struct Page<C: ModuleConfiguration> {
+    tree: C::RenderTree,
+    document: C::Document,
+}
+
+/// What a load is for
+enum Load {
+    /// Navigation to a new page, which is added to the session history
+    Navigate(Url),
+    /// Traversal to the entry of the session history at the index, or a reload of the current entry
+    Traverse(usize),
+}
+
 pub struct InstanceHandle {
     pub tx: Sender<InstanceMessage>,
 }
@@ -231,8 +570,12 @@ pub enum InstanceMessage {
     Back,
     /// Navigate forward in history
     Forward,
+    /// Navigate to the entry in history at the given index
+    GoTo(usize),
     /// Reload the current page
     Reload,
+    /// Send the session history to the given sender
+    History(SyncSender<HistoryList>),
     /// Close the instance
     Close,
 
@@ -242,8 +585,12 @@ pub enum InstanceMessage {
     Debug(DebugEvent),
 }
 
+/// Handle of the tree drawer to the instance. The handle that is given to a load has the id of the load.
 #[derive(Clone)]
-struct El<C: ModuleConfiguration>(Sender<InternalInstanceMessage<C>>);
+struct El<C: ModuleConfiguration> {
+    tx: Sender<InternalInstanceMessage<C>>,
+    load: u64,
+}
 
 impl<C: ModuleConfiguration> EventLoopHandle<C> for El<C> {
     fn redraw(&self) {
@@ -255,13 +602,17 @@ impl<C: ModuleConfiguration> EventLoopHandle<C> for El<C> {
     }
 
     fn reload_from(&self, rt: C::RenderTree) {
-        self.send(InternalInstanceMessage::ReloadFrom(rt));
+        self.send(InternalInstanceMessage::ReloadFrom(self.load, rt));
+    }
+
+    fn show_partial(&self, rt: C::RenderTree) {
+        self.send(InternalInstanceMessage::PartialTree(self.load, rt));
     }
 }
 
 impl<C: ModuleConfiguration> El<C> {
     fn send(&self, message: InternalInstanceMessage<C>) {
-        let send = self.0.clone();
+        let send = self.tx.clone();
 
         if let Ok(handle) = Handle::try_current() {
             handle.spawn(async move {
@@ -278,8 +629,10 @@ pub enum InternalInstanceMessage<C: HasTreeDrawer> {
     Image(Url, ImageBuffer<C::RenderBackend>, Option<SizeU32>),
     /// Redraw the instance
     Redraw,
-    /// Reload the instance from the given tree
-    ReloadFrom(C::RenderTree),
+    /// Reload the instance from the given tree, which has been loaded by the load with the id
+    ReloadFrom(u64, C::RenderTree),
+    /// Show the tree of a page that is still being loaded by the load with the id
+    PartialTree(u64, C::RenderTree),
 }
 
 pub enum DebugEvent {
@@ -300,3 +653,242 @@ pub enum DebugEvent {
     /// Clear the debug buffers so the next draw will be a full redraw
     ClearBuffers,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gosub_css3::system::Css3System;
+    use gosub_headless::HeadlessBackend;
+    use gosub_html5::document::builder::DocumentBuilderImpl;
+    use gosub_html5::document::document_impl::DocumentImpl;
+    use gosub_html5::document::fragment::DocumentFragmentImpl;
+    use gosub_html5::parser::Html5Parser;
+    use gosub_interface::config::{
+        HasChrome, HasCssSystem, HasDocument, HasHtmlParser, HasLayouter, HasRenderBackend, HasRenderTree,
+        HasScriptExecutor,
+    };
+    use gosub_interface::font::HasFontManager;
+    use gosub_interface::html5::{Script, ScriptExecutor};
+    use gosub_interface::render_backend::RenderBackend;
+    use gosub_interface::request::RequestServerHandle;
+    use gosub_renderer::draw::TreeDrawerImpl;
+    use gosub_taffy::TaffyLayouter;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Config;
+
+    impl HasCssSystem for Config {
+        type CssSystem = Css3System;
+    }
+    impl HasDocument for Config {
+        type Document = DocumentImpl<Self>;
+        type DocumentFragment = DocumentFragmentImpl<Self>;
+        type DocumentBuilder = DocumentBuilderImpl;
+    }
+    impl HasHtmlParser for Config {
+        type HtmlParser = Html5Parser<'static, Self>;
+    }
+    impl HasLayouter for Config {
+        type Layouter = TaffyLayouter;
+        type LayoutTree = gosub_rendering::render_tree::RenderTree<Self>;
+    }
+    impl HasRenderTree for Config {
+        type RenderTree = gosub_rendering::render_tree::RenderTree<Self>;
+    }
+    impl HasTreeDrawer for Config {
+        type TreeDrawer = TreeDrawerImpl<Self>;
+    }
+    impl HasRenderBackend for Config {
+        type RenderBackend = HeadlessBackend;
+    }
+    impl HasFontManager for Config {
+        type FontManager = gosub_fontmanager::FontManager;
+    }
+    impl HasChrome for Config {
+        type ChromeHandle = Chrome;
+    }
+    impl HasScriptExecutor for Config {
+        type ScriptExecutor = NoScripts;
+    }
+    impl ModuleConfiguration for Config {}
+
+    struct NoScripts;
+
+    impl ScriptExecutor<Config> for NoScripts {
+        fn new(_url: &Url) -> Result<Self> {
+            Ok(Self)
+        }
+
+        fn execute(&mut self, _script: &Script, _document: &mut DocumentImpl<Config>) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(Clone)]
+    struct Chrome;
+
+    impl ChromeHandle<Config> for Chrome {
+        fn draw_scene(&self, _: <HeadlessBackend as RenderBackend>::Scene, _: SizeU32, _: InstanceId) {}
+    }
+
+    /// Serves pages that consist of two parts. The second part is sent a while after the first one, so the instance
+    /// shows the first part on its own.
+    fn serve(pages: &'static [(&'static str, &'static str, &'static str)]) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let path = line.split(' ').nth(1).unwrap_or_default().to_string();
+                while line != "\r\n" {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                }
+
+                let (_, first, rest) = pages.iter().find(|(p, _, _)| *p == path).unwrap();
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nCache-Control: no-store\r\nConnection: close\r\nContent-Length: {}\r\n\r\n",
+                    first.len() + rest.len()
+                );
+                stream.write_all(head.as_bytes()).unwrap();
+                stream.write_all(first.as_bytes()).unwrap();
+                stream.flush().unwrap();
+                thread::sleep(Duration::from_millis(300));
+                stream.write_all(rest.as_bytes()).unwrap();
+            }
+        });
+
+        url
+    }
+
+    fn history(handle: &InstanceHandle) -> HistoryList {
+        let (tx, rx) = mpsc::channel();
+        handle.tx.blocking_send(InstanceMessage::History(tx)).unwrap();
+        rx.recv_timeout(Duration::from_secs(10)).unwrap()
+    }
+
+    /// Waits until the instance shows the entry at the index of a history with the given length
+    fn wait_for_entry(handle: &InstanceHandle, len: usize, current: usize) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let list = history(handle);
+            if list.entries.len() == len && list.current == current {
+                return;
+            }
+            assert!(Instant::now() < deadline, "history did not reach entry {current}");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    /// Returns the texts of the render tree that the instance shows
+    fn texts(handle: &InstanceHandle) -> Vec<String> {
+        fn collect(node: &NodeDesc, texts: &mut Vec<String>) {
+            if let Some(text) = node.text.as_deref().map(str::trim).filter(|text| !text.is_empty()) {
+                texts.push(text.to_string());
+            }
+            for child in &node.children {
+                collect(child, texts);
+            }
+        }
+
+        let (tx, rx) = mpsc::channel();
+        handle
+            .tx
+            .blocking_send(InstanceMessage::Debug(DebugEvent::SendNodes(tx)))
+            .unwrap();
+        let mut texts = Vec::new();
+        collect(&rx.recv_timeout(Duration::from_secs(10)).unwrap(), &mut texts);
+
+        texts
+    }
+
+    #[test]
+    fn back_and_forward_after_partial_trees() {
+        let base = serve(&[
+            ("/a", "<p>page a</p>", "<p>end of a</p>"),
+            ("/b", "<p>page b</p>", "<p>end of b</p>"),
+        ]);
+        let handles = Handles {
+            chrome: Chrome,
+            request: RequestServerHandle,
+        };
+        let handle =
+            EngineInstance::<Config>::new_on_thread(base.join("a").unwrap(), TaffyLayouter, InstanceId(0), handles)
+                .unwrap();
+        assert_eq!(texts(&handle), ["page a", "end of a"]);
+
+        // The instance shows the first part of the page before it has loaded
+        handle
+            .tx
+            .blocking_send(InstanceMessage::Navigate(base.join("b").unwrap()))
+            .unwrap();
+        wait_for_entry(&handle, 2, 1);
+        assert_eq!(texts(&handle), ["page b", "end of b"]);
+
+        // The pages are kept with their own render trees
+        handle.tx.blocking_send(InstanceMessage::Back).unwrap();
+        wait_for_entry(&handle, 2, 0);
+        assert_eq!(texts(&handle), ["page a", "end of a"]);
+
+        handle.tx.blocking_send(InstanceMessage::Forward).unwrap();
+        wait_for_entry(&handle, 2, 1);
+        assert_eq!(texts(&handle), ["page b", "end of b"]);
+    }
+
+    #[test]
+    fn navigate_during_load() {
+        let base = serve(&[
+            ("/a", "<p>page a</p>", "<p>end of a</p>"),
+            ("/b", "<p>page b</p>", "<p>end of b</p>"),
+            ("/c", "<p>page c</p>", "<p>end of c</p>"),
+        ]);
+        let handles = Handles {
+            chrome: Chrome,
+            request: RequestServerHandle,
+        };
+        let handle =
+            EngineInstance::<Config>::new_on_thread(base.join("a").unwrap(), TaffyLayouter, InstanceId(0), handles)
+                .unwrap();
+        assert_eq!(texts(&handle), ["page a", "end of a"]);
+
+        // The load of b is aborted once it shows its first part
+        handle
+            .tx
+            .blocking_send(InstanceMessage::Navigate(base.join("b").unwrap()))
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while texts(&handle) != ["page b"] {
+            assert!(Instant::now() < deadline, "the first part of b was not shown");
+            thread::sleep(Duration::from_millis(5));
+        }
+        handle
+            .tx
+            .blocking_send(InstanceMessage::Navigate(base.join("c").unwrap()))
+            .unwrap();
+        wait_for_entry(&handle, 2, 1);
+        assert_eq!(texts(&handle), ["page c", "end of c"]);
+
+        // Nothing of b ends up in the history, or replaces c after it has loaded
+        thread::sleep(Duration::from_millis(500));
+        let urls = history(&handle)
+            .entries
+            .iter()
+            .map(|entry| entry.url.path().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(urls, ["/a", "/c"]);
+        assert_eq!(texts(&handle), ["page c", "end of c"]);
+
+        handle.tx.blocking_send(InstanceMessage::Back).unwrap();
+        wait_for_entry(&handle, 2, 0);
+        assert_eq!(texts(&handle), ["page a", "end of a"]);
+    }
+}
@@ -20,6 +20,7 @@ pub trait ModuleConfiguration:
     + HasCssSystem
     + HasDocument
     + HasHtmlParser
+    + HasScriptExecutor
     + HasFontManager
     + HasLayouter
     + HasRenderTree
@@ -1,6 +1,6 @@
 use crate::config::css_system::HasCssSystem;
 use crate::document::{Document, DocumentBuilder, DocumentFragment};
-use crate::html5::Html5Parser;
+use crate::html5::{Html5Parser, ScriptExecutor};
 use crate::node::{CommentDataType, DocTypeDataType, DocumentDataType, ElementDataType, Node, TextDataType};
 use std::fmt::Debug;
 
@@ -31,6 +31,10 @@ pub trait HasHtmlParser: HasDocument {
     type HtmlParser: Html5Parser<Self>;
 }
 
+pub trait HasScriptExecutor: HasDocument {
+    type ScriptExecutor: ScriptExecutor<Self>;
+}
+
 pub trait HasDocumentExt<C: HasDocument> {
     type Node: Node<C>;
     type DocumentData: DocumentDataType;
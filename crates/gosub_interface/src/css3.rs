@@ -1,4 +1,5 @@
 use crate::config::{HasDocument, HasRenderTree};
+use crate::render_tree::RenderTree;
 use gosub_shared::async_executor::WasmNotSend;
 use gosub_shared::config::ParserConfig;
 use gosub_shared::errors::CssResult;
@@ -6,7 +7,7 @@ use gosub_shared::node::NodeId;
 use std::fmt::{Debug, Display};
 
 /// Defines the origin of the stylesheet (or declaration)
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum CssOrigin {
     /// Browser/user agent defined stylesheets
     UserAgent,
@@ -16,6 +17,51 @@ pub enum CssOrigin {
     User,
 }
 
+/// Preferred color scheme of the user, used by the `prefers-color-scheme` media feature
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ColorScheme {
+    #[default]
+    Light,
+    Dark,
+}
+
+/// Environment in which media queries (`@media`) are evaluated
+#[derive(Debug, PartialEq, Clone)]
+pub struct MediaEnvironment {
+    /// Media type, like "screen" or "print"
+    pub media_type: String,
+    /// Width of the viewport in CSS pixels
+    pub width: f32,
+    /// Height of the viewport in CSS pixels
+    pub height: f32,
+    /// Number of device pixels per CSS pixel
+    pub resolution: f32,
+    pub color_scheme: ColorScheme,
+}
+
+impl Default for MediaEnvironment {
+    fn default() -> Self {
+        Self {
+            media_type: "screen".to_string(),
+            width: 800.0,
+            height: 600.0,
+            resolution: 1.0,
+            color_scheme: ColorScheme::default(),
+        }
+    }
+}
+
+impl MediaEnvironment {
+    /// Returns a copy of the environment with the given viewport size
+    pub fn with_size(&self, width: f32, height: f32) -> Self {
+        Self {
+            width,
+            height,
+            ..self.clone()
+        }
+    }
+}
+
 /// The CssSystem trait is a trait that defines all things CSS3 that are used by other non-css3 crates. This is the main trait that
 /// is used to parse CSS3 files. It contains sub elements like the Stylesheet trait that is used in for instance the Document trait.
 pub trait CssSystem: Clone + Debug + 'static {
@@ -38,7 +84,28 @@ pub trait CssSystem: Clone + Debug + 'static {
         id: NodeId,
     ) -> Option<Self::PropertyMap>;
 
-    fn inheritance<C: HasRenderTree<CssSystem = Self>>(tree: &mut C::RenderTree);
+    /// Returns the properties of a pseudo element (like "before", "after" or "marker") of the node. These are only
+    /// the properties declared for the pseudo element; it is up to the caller to decide if the box is generated.
+    /// If `None` is returned, the node cannot have pseudo elements
+    fn pseudo_element_properties_from_node<C: HasDocument<CssSystem = Self>>(
+        node: &C::Node,
+        sheets: &[Self::Stylesheet],
+        doc: &C::Document,
+        id: NodeId,
+        pseudo_element: &str,
+    ) -> Option<Self::PropertyMap>;
+
+    /// Resolves the inherited and computed values of all nodes in the render tree. Viewport-relative values are
+    /// resolved against the given media environment.
+    fn inheritance<C: HasRenderTree<CssSystem = Self>>(tree: &mut C::RenderTree, media: &MediaEnvironment);
+
+    /// Resolves the inherited and computed values of the descendants of the node, like `inheritance()` does for the
+    /// whole tree. The node and its ancestors must have been resolved already, as the descendants inherit from them.
+    fn inheritance_from<C: HasRenderTree<CssSystem = Self>>(
+        tree: &mut C::RenderTree,
+        node_id: <C::RenderTree as RenderTree<C>>::NodeId,
+        media: &MediaEnvironment,
+    );
 
     fn load_default_useragent_stylesheet() -> Self::Stylesheet;
 }
@@ -49,6 +116,13 @@ pub trait CssStylesheet: PartialEq + Debug {
 
     /// Returns the source URL of the stylesheet
     fn url(&self) -> &str;
+
+    /// Returns true when any media query in the stylesheet evaluates differently in the two environments
+    fn media_changed(&self, from: &MediaEnvironment, to: &MediaEnvironment) -> bool;
+
+    /// Returns true when the style of an element can depend on elements outside the subtree of its parent, like
+    /// with `:has()` or CSS counters. A change of the document can then change the style anywhere in the document.
+    fn has_nonlocal_dependencies(&self) -> bool;
 }
 
 pub trait CssPropertyMap<S: CssSystem>: Default + Debug + WasmNotSend {
@@ -87,6 +161,11 @@ pub trait CssProperty<S: CssSystem>: Debug + Display + Sized + From<S::Value> {
 
     fn as_function(&self) -> Option<(&str, &[S::Value])>;
 
+    /// Resolves a math function (like `calc(100% - 20px)`) that depends on layout to pixels. Percentages are
+    /// resolved against the given basis, and font-relative units against the font size of the element. Returns
+    /// `None` when the property is not a math function, or when it holds percentages and the basis is unknown.
+    fn resolve_calc(&self, percentage_basis: Option<f32>, font_size: f32) -> Option<f32>;
+
     fn is_none(&self) -> bool;
 }
 
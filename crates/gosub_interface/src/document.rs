@@ -1,7 +1,10 @@
 use crate::config::HasDocument;
+use crate::css3::MediaEnvironment;
+use crate::mutation::{MutationObserverId, MutationObserverInit, MutationRecord};
 use crate::node::{Node, QuirksMode};
 use gosub_shared::byte_stream::Location;
 use gosub_shared::node::NodeId;
+use gosub_shared::types::Result;
 use std::collections::HashMap;
 use std::fmt::{Debug, Display};
 use url::Url;
@@ -49,6 +52,12 @@ pub trait Document<C: HasDocument<Document = Self>>: Sized + Display + Debug + P
     fn stylesheets(&self) -> &Vec<C::Stylesheet>;
     fn add_stylesheet(&mut self, stylesheet: C::Stylesheet);
 
+    /// Returns the environment the media queries of the stylesheets are evaluated against
+    fn media_environment(&self) -> &MediaEnvironment;
+    /// Sets a new media environment. Returns true when the result of any media query has changed, which means the
+    /// styles of the document need to be recalculated.
+    fn set_media_environment(&mut self, env: MediaEnvironment) -> bool;
+
     /// Return the root node of the document
     fn get_root(&self) -> &Self::Node;
 
@@ -92,6 +101,25 @@ pub trait Document<C: HasDocument<Document = Self>>: Sized + Display + Debug + P
         location: Location,
     ) -> Self::Node;
 
+    /// Returns the first element that matches the CSS selector in tree order, like `querySelector()`
+    fn query_selector(&self, selector: &str) -> Result<Option<NodeId>>;
+    /// Returns all elements that match the CSS selector in tree order, like `querySelectorAll()`
+    fn query_selector_all(&self, selector: &str) -> Result<Vec<NodeId>>;
+    /// Returns true when the node is an element that matches the CSS selector
+    fn matches(&self, node_id: NodeId, selector: &str) -> Result<bool>;
+    /// Returns the node or its nearest ancestor that matches the CSS selector
+    fn closest(&self, node_id: NodeId, selector: &str) -> Result<Option<NodeId>>;
+
+    /// Creates a mutation observer, which does not observe any node until `observe()` is called
+    fn create_mutation_observer(&mut self) -> MutationObserverId;
+    /// Registers the observer for mutations of the node, like `MutationObserver.observe()`. Observing a node again
+    /// replaces the options. Fails when the options do not ask for any kind of mutation, or contradict each other.
+    fn observe(&mut self, observer: MutationObserverId, node_id: NodeId, options: MutationObserverInit) -> Result<()>;
+    /// Stops the observer from observing any node and drops its pending records
+    fn disconnect(&mut self, observer: MutationObserverId);
+    /// Returns and removes the records that have been queued for the observer since the last call
+    fn take_records(&mut self, observer: MutationObserverId) -> Vec<MutationRecord>;
+
     fn write(&self) -> String;
     fn write_from_node(&self, node_id: NodeId) -> String;
     fn cloned_node_by_id(&self, node_id: NodeId) -> Option<Self::Node>;
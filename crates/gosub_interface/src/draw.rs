@@ -1,6 +1,7 @@
-use crate::config::{HasDocument, HasDrawComponents, HasHtmlParser};
+use crate::config::{HasDocument, HasDrawComponents, HasHtmlParser, HasScriptExecutor};
 use crate::eventloop::EventLoopHandle;
 use crate::layout::LayoutTree;
+use crate::mutation::MutationRecord;
 use crate::render_backend::{ImgCache, NodeDesc, RenderBackend};
 use gosub_net::http::fetcher::Fetcher;
 use gosub_shared::geo::{Point, SizeU32, FP};
@@ -17,6 +18,12 @@ pub trait TreeDrawer<C: HasDrawComponents> {
     fn mouse_move(&mut self, x: FP, y: FP) -> bool;
 
     fn scroll(&mut self, point: Point);
+    /// Returns how far the document is scrolled
+    fn scroll_position(&self) -> Point;
+    /// Scrolls the document to the given position, once the render tree has been laid out
+    fn scroll_to(&mut self, position: Point);
+    /// Scrolls the document to the element with the given id or anchor name, once the render tree has been laid out
+    fn scroll_to_fragment(&mut self, fragment: &str);
     fn from_url(
         url: Url,
         layouter: C::Layouter,
@@ -24,7 +31,7 @@ pub trait TreeDrawer<C: HasDrawComponents> {
     ) -> impl Future<Output = gosub_shared::types::Result<(Self, C::Document)>>
     where
         Self: Sized,
-        C: HasDocument + HasHtmlParser;
+        C: HasDocument + HasHtmlParser + HasScriptExecutor;
 
     fn from_source(
         // The initial url that the source was loaded from
@@ -38,7 +45,7 @@ pub trait TreeDrawer<C: HasDrawComponents> {
     ) -> Result<(Self, C::Document)>
     where
         Self: Sized,
-        C: HasDocument + HasHtmlParser;
+        C: HasDocument + HasHtmlParser + HasScriptExecutor;
 
     fn with_fetcher(
         // The initial url that the source was loaded from
@@ -52,7 +59,7 @@ pub trait TreeDrawer<C: HasDrawComponents> {
     ) -> impl Future<Output = Result<(Self, C::Document)>>
     where
         Self: Sized,
-        C: HasDocument + HasHtmlParser;
+        C: HasDocument + HasHtmlParser + HasScriptExecutor;
 
     fn clear_buffers(&mut self);
     fn toggle_debug(&mut self);
@@ -73,7 +80,7 @@ pub trait TreeDrawer<C: HasDrawComponents> {
 
     fn reload(&mut self, el: impl EventLoopHandle<C>) -> impl Future<Output = Result<C::Document>> + 'static
     where
-        C: HasDocument + HasHtmlParser;
+        C: HasDocument + HasHtmlParser + HasScriptExecutor;
 
     fn navigate(
         &mut self,
@@ -81,7 +88,16 @@ pub trait TreeDrawer<C: HasDrawComponents> {
         el: impl EventLoopHandle<C>,
     ) -> impl Future<Output = Result<C::Document>> + 'static
     where
-        C: HasDocument + HasHtmlParser;
+        C: HasDocument + HasHtmlParser + HasScriptExecutor;
 
     fn reload_from(&mut self, tree: C::RenderTree);
+
+    /// Updates the render tree for the mutations of the document, instead of replacing it. Only the changed parts
+    /// of the tree are styled again, and the scroll position is kept.
+    fn update_from(&mut self, doc: &C::Document, records: &[MutationRecord])
+    where
+        C: HasDocument;
+
+    /// Replaces the render tree like `reload_from`, and returns the previous render tree
+    fn replace_tree(&mut self, tree: C::RenderTree) -> C::RenderTree;
 }
@@ -13,4 +13,7 @@ pub trait EventLoopHandle<C: HasDrawComponents>: WasmNotSendSync + Clone + 'stat
 
     /// Reload the instance from the given render tree
     fn reload_from(&self, rt: C::RenderTree);
+
+    /// Show the render tree of a page that is still loading. The tree of the loaded page follows with `reload_from`.
+    fn show_partial(&self, rt: C::RenderTree);
 }
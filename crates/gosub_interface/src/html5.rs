@@ -1,10 +1,14 @@
 use crate::config::HasDocument;
 use gosub_shared::byte_stream::{ByteStream, Location};
-
+use gosub_shared::node::NodeId;
 use gosub_shared::types::{ParseError, Result};
+use url::Url;
 
 pub trait Html5Parser<C: HasDocument> {
     type Options: ParserOptions;
+    type StreamingParser<'a>: Html5StreamingParser<C>
+    where
+        Self: 'a;
 
     fn parse(stream: &mut ByteStream, doc: &mut C::Document, opts: Option<Self::Options>) -> Result<Vec<ParseError>>;
 
@@ -16,6 +20,83 @@ pub trait Html5Parser<C: HasDocument> {
         options: Option<Self::Options>,
         start_location: Location,
     ) -> Result<Vec<ParseError>>;
+
+    /// Starts parsing a document whose input arrives in parts. The stream may already contain the first input, the
+    /// rest is appended to the returned parser. The scripts of the document are executed with the executor, if any.
+    fn parse_streaming<'a>(
+        stream: &'a mut ByteStream,
+        doc: &'a mut C::Document,
+        opts: Option<Self::Options>,
+        script_executor: Option<&'a mut dyn ScriptExecutor<C>>,
+    ) -> Self::StreamingParser<'a>
+    where
+        Self: 'a;
+}
+
+/// Parses a document while its input arrives, so it can be shown before it has been loaded completely
+pub trait Html5StreamingParser<C: HasDocument> {
+    /// Appends undecoded input in the encoding of the stream
+    fn append_bytes(&mut self, bytes: &[u8]);
+
+    /// Marks the end of the input. The next call to `parse_available()` finishes the document.
+    fn close(&mut self);
+
+    /// Parses the input that is available, until the document has been parsed completely or the parser has to wait
+    fn parse_available(&mut self) -> ParseState;
+
+    /// Gives the parser the source of the script it waits for, or `None` when the script could not be loaded
+    fn provide_script(&mut self, source: Option<String>);
+
+    /// Returns the document as far as it has been parsed
+    fn document_mut(&mut self) -> &mut C::Document;
+
+    /// Returns the parse errors found so far
+    fn errors(&self) -> Vec<ParseError>;
+}
+
+/// What a streaming parser waits for after it has parsed the available input
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseState {
+    /// Nothing, the document has been parsed completely
+    Finished,
+    /// More input, or the end of the input
+    NeedsInput,
+    /// The source of the external script at the URL. Parsing is paused until it is given with `provide_script()`.
+    NeedsScript(Url),
+}
+
+/// The type of script as defined by the type attribute of the script element
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScriptKind {
+    /// Regular javascript script
+    Classic,
+    /// Javascript module script
+    Module,
+}
+
+/// A script that is ready to be executed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script {
+    /// Type of the script
+    pub kind: ScriptKind,
+    /// Source code of the script
+    pub source: String,
+    /// URL the script was fetched from, or None for inline scripts
+    pub url: Option<Url>,
+    /// The script element in the document
+    pub node_id: NodeId,
+}
+
+/// Executes the scripts that are found by the parser
+pub trait ScriptExecutor<C: HasDocument> {
+    /// Creates an executor for the scripts of the document at the URL
+    fn new(url: &Url) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Executes the given script against the document, as far as it has been parsed. Errors are logged by the
+    /// parser, but will not stop the parsing.
+    fn execute(&mut self, script: &Script, document: &mut C::Document) -> Result<()>;
 }
 
 pub trait ParserOptions {
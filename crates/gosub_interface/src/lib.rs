@@ -9,6 +9,7 @@ pub mod html5;
 pub mod input;
 pub mod instance;
 pub mod layout;
+pub mod mutation;
 pub mod node;
 pub mod render_backend;
 pub mod render_tree;
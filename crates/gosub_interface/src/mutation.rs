@@ -0,0 +1,109 @@
+//! Mutation records of a document
+//!
+//! Documents report changes to their tree as mutation records, following the semantics of the DOM `MutationObserver`
+//! interface. An observer registers for a node with a set of options, and collects the records of the mutations
+//! it is interested in until they are taken.
+use gosub_shared::node::NodeId;
+
+/// Identifies a mutation observer of a document
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MutationObserverId(pub u64);
+
+/// Options of an observer registration, like the `MutationObserverInit` dictionary. Options that are `None` have not
+/// been given, which matters for the defaults that are derived from the other options.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MutationObserverInit {
+    /// Observe children being added to or removed from the node
+    pub child_list: bool,
+    /// Observe changes to the attributes of the node
+    pub attributes: Option<bool>,
+    /// Observe changes to the data of text and comment nodes
+    pub character_data: Option<bool>,
+    /// Observe the descendants of the node as well
+    pub subtree: bool,
+    /// Record the previous value of changed attributes
+    pub attribute_old_value: Option<bool>,
+    /// Record the previous data of changed text and comment nodes
+    pub character_data_old_value: Option<bool>,
+    /// Only observe the attributes with these names
+    pub attribute_filter: Option<Vec<String>>,
+}
+
+/// The kind of change of a mutation record
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MutationType {
+    /// Children have been added or removed
+    ChildList,
+    /// An attribute has been added, changed or removed
+    Attributes,
+    /// The data of a text or comment node has changed
+    CharacterData,
+}
+
+/// A single change to the document, like the DOM `MutationRecord` interface
+#[derive(Clone, Debug, PartialEq)]
+pub struct MutationRecord {
+    pub mutation_type: MutationType,
+    /// The node whose children, attributes or data have changed
+    pub target: NodeId,
+    pub added_nodes: Vec<NodeId>,
+    pub removed_nodes: Vec<NodeId>,
+    /// Sibling before the added or removed nodes
+    pub previous_sibling: Option<NodeId>,
+    /// Sibling after the added or removed nodes
+    pub next_sibling: Option<NodeId>,
+    /// Name of the changed attribute
+    pub attribute_name: Option<String>,
+    /// Previous attribute value or data, when the observer asked for it. `None` for an attribute that did not exist.
+    pub old_value: Option<String>,
+}
+
+impl MutationRecord {
+    /// Creates a record for children that have been added to or removed from the target
+    pub fn child_list(
+        target: NodeId,
+        added_nodes: Vec<NodeId>,
+        removed_nodes: Vec<NodeId>,
+        previous_sibling: Option<NodeId>,
+        next_sibling: Option<NodeId>,
+    ) -> Self {
+        Self {
+            mutation_type: MutationType::ChildList,
+            target,
+            added_nodes,
+            removed_nodes,
+            previous_sibling,
+            next_sibling,
+            attribute_name: None,
+            old_value: None,
+        }
+    }
+
+    /// Creates a record for a changed attribute of the target
+    pub fn attributes(target: NodeId, name: &str, old_value: Option<String>) -> Self {
+        Self {
+            mutation_type: MutationType::Attributes,
+            target,
+            added_nodes: Vec::new(),
+            removed_nodes: Vec::new(),
+            previous_sibling: None,
+            next_sibling: None,
+            attribute_name: Some(name.to_string()),
+            old_value,
+        }
+    }
+
+    /// Creates a record for changed data of the target
+    pub fn character_data(target: NodeId, old_value: String) -> Self {
+        Self {
+            mutation_type: MutationType::CharacterData,
+            target,
+            added_nodes: Vec::new(),
+            removed_nodes: Vec::new(),
+            previous_sibling: None,
+            next_sibling: None,
+            attribute_name: None,
+            old_value: Some(old_value),
+        }
+    }
+}
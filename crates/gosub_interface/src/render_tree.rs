@@ -1,6 +1,7 @@
 use crate::config::{HasDocument, HasLayouter};
 use crate::css3::CssSystem;
 use crate::layout::Layouter;
+use crate::mutation::MutationRecord;
 use std::collections::HashMap;
 use std::fmt::Debug;
 
@@ -17,11 +18,19 @@ pub trait RenderTree<C: HasLayouter>: Send + 'static {
 
     fn get_children(&self, id: Self::NodeId) -> Option<Vec<Self::NodeId>>;
 
+    fn get_parent(&self, id: Self::NodeId) -> Option<Self::NodeId>;
+
     fn get_layout(&self, id: Self::NodeId) -> Option<&<C::Layouter as Layouter<C>>::Layout>;
 
     fn from_document(doc: &C::Document) -> Self
     where
         C: HasDocument;
+
+    /// Updates the tree for the mutations of the document, restyling and invalidating the layout of only the parts
+    /// of the tree that are affected. Returns true when the tree has changed.
+    fn update_from_document(&mut self, doc: &C::Document, records: &[MutationRecord]) -> bool
+    where
+        C: HasDocument;
 }
 
 pub type TextLayoutRef<'a, C> = &'a [<<C as HasLayouter>::Layouter as Layouter<C>>::TextLayout];
@@ -1,9 +1,15 @@
+use core::fmt;
 use core::str::FromStr;
+use std::future::Future;
 use std::net::IpAddr;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::{Arc, LazyLock};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use derive_more::Display;
-use log::{debug, info};
+use futures::channel::oneshot;
+use log::{debug, info, warn};
+use url::Url;
 
 use gosub_config::{config, config_store};
 use gosub_shared::types::Result;
@@ -11,9 +17,16 @@ use gosub_shared::types::Result;
 use crate::errors::Error;
 
 mod cache;
+mod doh;
+mod dot;
 mod local;
+mod message;
 mod remote;
 
+pub use doh::{DohMethod, DohResolver};
+pub use dot::{DotResolver, DotServer};
+pub use local::LocalTableResolver;
+
 /// A DNS entry is a mapping of a domain to zero or more IP address mapping
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct DnsEntry {
@@ -38,7 +51,7 @@ pub struct DnsEntry {
 impl DnsEntry {
     /// Instantiate a new domain name entry with set of ips
     #[must_use]
-    pub(crate) fn new(domain: &str, ips: Vec<&str>) -> Self {
+    pub fn new(domain: &str, ips: Vec<&str>) -> Self {
         let mut entry = Self {
             domain: domain.to_owned(),
             ..Default::default()
@@ -59,27 +72,72 @@ impl DnsEntry {
         entry
     }
 
-    /// Returns true if the dns entry has expired
+    /// Instantiate a new domain name entry with addresses that may be used for the given number of seconds
+    #[must_use]
+    pub fn with_ttl(domain: &str, ips: Vec<IpAddr>, ttl: u32) -> Self {
+        Self {
+            domain: domain.to_owned(),
+            has_ipv4: ips.iter().any(|ip| ip.is_ipv4()),
+            has_ipv6: ips.iter().any(|ip| ip.is_ipv6()),
+            ips,
+            expires: now() + u64::from(ttl),
+            ..Default::default()
+        }
+    }
+
+    /// Returns true if the dns entry has expired. An entry with a TTL of 0 expires directly.
     pub fn expired(&self) -> bool {
-        self.expires < SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+        self.expires <= now()
     }
 
-    #[allow(dead_code)]
-    fn ipv4(&self) -> Vec<IpAddr> {
+    /// Returns the ipv4 addresses of the domain
+    pub fn ipv4(&self) -> Vec<IpAddr> {
         self.ips.iter().filter(|x| x.is_ipv4()).copied().collect()
     }
 
-    #[allow(dead_code)]
-    fn ipv6(&self) -> Vec<IpAddr> {
+    /// Returns the ipv6 addresses of the domain
+    pub fn ipv6(&self) -> Vec<IpAddr> {
         self.ips.iter().filter(|x| x.is_ipv6()).copied().collect()
     }
 
-    #[allow(dead_code)]
-    fn iter(&self) -> impl Iterator<Item = &IpAddr> {
+    /// Returns all addresses of the domain
+    pub fn iter(&self) -> impl Iterator<Item = &IpAddr> {
         self.ips.iter()
     }
 }
 
+/// Returns the current time in seconds after epoch
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// Answer of a DNS server that a domain has no addresses. These answers are cached as well, so failing lookups are
+/// not repeated (RFC 2308).
+#[derive(Clone, Copy, Debug, Display, PartialEq)]
+pub enum NegativeAnswer {
+    /// The domain does not exist (NXDOMAIN)
+    #[display("domain does not exist")]
+    NxDomain {
+        /// Number of seconds the answer may be cached, when the server sent the SOA record of the zone
+        ttl: Option<u32>,
+    },
+    /// The domain exists, but has no addresses of the requested type (NODATA)
+    #[display("domain has no addresses of the requested type")]
+    NoData {
+        /// Number of seconds the answer may be cached, when the server sent the SOA record of the zone
+        ttl: Option<u32>,
+    },
+}
+
+impl NegativeAnswer {
+    /// Returns the number of seconds the answer may be cached, when known
+    pub fn ttl(&self) -> Option<u32> {
+        match self {
+            Self::NxDomain { ttl } | Self::NoData { ttl } => *ttl,
+        }
+    }
+}
+
 /// Type of DNS resolution
 #[derive(Clone, Debug, Display, PartialEq)]
 pub enum ResolveType {
@@ -91,27 +149,50 @@ pub enum ResolveType {
     Both,
 }
 
-trait DnsResolver {
+/// A source of DNS entries. Custom resolvers can be given to `Dns::with_resolvers`, for instance to resolve without
+/// network access.
+pub trait DnsResolver: Send + Sync {
     /// Resolves a domain name for a given resolver_type
-    fn resolve(&mut self, domain: &str, resolve_type: ResolveType) -> Result<DnsEntry>;
+    fn resolve(&self, domain: &str, resolve_type: ResolveType) -> Result<DnsEntry>;
     /// Announces the resolved dns entry for the domain to a resolver
-    fn announce(&mut self, _domain: &str, _entry: &DnsEntry) {}
+    fn announce(&self, _domain: &str, _entry: &DnsEntry) {}
+    /// Announces that a DNS server answered that the domain has no addresses of the resolve type
+    fn announce_negative(&self, _domain: &str, _resolve_type: &ResolveType, _answer: &NegativeAnswer) {}
     // name for debugging purposes
     fn name(&self) -> &'static str;
+    /// Returns the cache of the resolver, when it keeps entries that can be flushed
+    fn cache(&self) -> Option<&dyn DnsCache> {
+        None
+    }
 }
 
-#[allow(dead_code)]
-trait DnsCache {
+/// Entries that are kept by a resolver
+pub trait DnsCache {
     /// Flush all domains
-    fn flush_all(&mut self);
+    fn flush_all(&self);
     /// Flush a single domain
-    fn flush_entry(&mut self, domain: &str);
+    fn flush_entry(&self, domain: &str);
+}
+
+/// The DNS system that is shared by all fetchers
+static SHARED_DNS: LazyLock<Arc<Dns>> = LazyLock::new(|| Arc::new(Dns::new()));
+
+/// Returns the DNS system that is shared by all fetchers, as configured in the settings
+pub fn shared_dns() -> Arc<Dns> {
+    SHARED_DNS.clone()
 }
 
 pub struct Dns {
     resolvers: Vec<Box<dyn DnsResolver>>,
 }
 
+impl fmt::Debug for Dns {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = self.resolvers.iter().map(|r| r.name()).collect::<Vec<_>>();
+        f.debug_struct("Dns").field("resolvers", &names).finish()
+    }
+}
+
 impl Default for Dns {
     fn default() -> Self {
         Self::new()
@@ -123,26 +204,59 @@ impl Dns {
     pub fn new() -> Self {
         // Cache resolver
         let max_entries = config!(uint "dns.cache.max_entries");
+        let mut cache =
+            cache::CacheResolver::new(max_entries).with_negative_ttl(config!(uint "dns.cache.negative.ttl") as u32);
+        if config!(bool "dns.cache.ttl.override.enabled") {
+            cache = cache.with_ttl_override(config!(uint "dns.cache.ttl.override.seconds") as u32);
+        }
         let mut resolvers: Vec<Box<dyn DnsResolver>> = vec![];
-        resolvers.push(Box::new(cache::CacheResolver::new(max_entries)));
+        resolvers.push(Box::new(cache));
 
         // Local table resolver
         if gosub_config::config!(bool "dns.local.enabled") {
             resolvers.push(Box::new(local::LocalTableResolver::new()));
         }
 
-        // Remove resolver
-        let mut opts = remote::RemoteResolverOptions::default();
-        let configured_nameservers = gosub_config::config!(map "dns.remote.nameservers");
-        if !configured_nameservers.is_empty() {
-            opts.nameservers = configured_nameservers;
+        let timeout = Duration::from_secs(config!(uint "dns.remote.timeout") as u64);
+        let retries = config!(uint "dns.remote.retries");
+
+        // Encrypted resolvers. When one of them is enabled, lookups are never sent unencrypted.
+        let mut encrypted = false;
+        if config!(bool "dns.remote.doh.enabled") {
+            let method = DohMethod::from_setting(&config!(string "dns.remote.doh.method"));
+            resolvers.push(doh_resolver(
+                &config!(map "dns.remote.doh.servers"),
+                method,
+                timeout,
+                retries,
+            ));
+            encrypted = true;
         }
-        opts.timeout = gosub_config::config!(uint "dns.remote.timeout");
-        opts.retries = gosub_config::config!(uint "dns.remote.retries");
-        opts.use_hosts_file = gosub_config::config!(bool "dns.remote.use_hosts_file");
+        if config!(bool "dns.remote.dot.enabled") {
+            resolvers.push(dot_resolver(&config!(map "dns.remote.dot.servers"), timeout, retries));
+            encrypted = true;
+        }
+
+        // Remote resolver
+        if !encrypted {
+            let mut opts = remote::RemoteResolverOptions::default();
+            let configured_nameservers = gosub_config::config!(map "dns.remote.nameservers");
+            if !configured_nameservers.is_empty() {
+                opts.nameservers = configured_nameservers;
+            }
+            opts.timeout = timeout.as_secs() as usize;
+            opts.retries = retries;
+            opts.use_hosts_file = gosub_config::config!(bool "dns.remote.use_hosts_file");
 
-        resolvers.push(Box::new(remote::RemoteResolver::new(opts)));
+            resolvers.push(Box::new(remote::RemoteResolver::new(opts)));
+        }
+
+        Self { resolvers }
+    }
 
+    /// Creates a DNS system that only uses the given resolvers, in the given order
+    #[must_use]
+    pub fn with_resolvers(resolvers: Vec<Box<dyn DnsResolver>>) -> Self {
         Self { resolvers }
     }
 
@@ -154,69 +268,315 @@ impl Dns {
     /// The second resolver is usually the local table resolver, which resolves any local overrides
     /// The third resolver is usually the remote resolver, which resolves any remote entries by querying external DNS server(s)
     ///
-    pub fn resolve(&mut self, domain: &str, resolve_type: ResolveType) -> Result<DnsEntry> {
+    pub fn resolve(&self, domain: &str, resolve_type: ResolveType) -> Result<DnsEntry> {
         let mut entry = None;
+        let mut misconfigured = None;
 
         info!("Resolving {domain} for {resolve_type:?}");
 
-        for resolver in &mut self.resolvers {
+        for resolver in &self.resolvers {
             debug!("Trying resolver: {}", resolver.name());
 
-            if let Ok(e) = resolver.resolve(domain, resolve_type.clone()) {
-                debug!("Found entry {e:?}");
-                entry = Some(e);
-                break;
+            match resolver.resolve(domain, resolve_type.clone()) {
+                Ok(e) => {
+                    debug!("Found entry {e:?}");
+                    entry = Some(e);
+                    break;
+                }
+                Err(e) => {
+                    // The lookup fails with the misconfiguration, unless another resolver finds the domain
+                    if let Some(Error::DnsMisconfigured(_)) = e.downcast_ref::<Error>() {
+                        misconfigured = Some(e);
+                        continue;
+                    }
+
+                    // A DNS server answered that there are no addresses, so the next resolvers are not asked
+                    if let Some(Error::DnsNegative(answer)) = e.downcast_ref::<Error>() {
+                        debug!("Negative answer: {answer}");
+                        for resolver in &self.resolvers {
+                            resolver.announce_negative(domain, &resolve_type, answer);
+                        }
+                        return Err(e);
+                    }
+                }
             }
         }
 
         if entry.is_none() {
-            return Err(Error::DnsDomainNotFound.into());
+            return Err(misconfigured.unwrap_or_else(|| Error::DnsDomainNotFound.into()));
         }
 
         // Iterate all resolvers and add to all cache systems (normally, this is only the first resolver)
-        for resolver in &mut self.resolvers {
+        for resolver in &self.resolvers {
             resolver.announce(domain, &entry.clone().unwrap().clone());
         }
 
         Ok(entry.unwrap().clone())
     }
+
+    /// Removes all entries from the caches of the resolvers, so domains are looked up again
+    pub fn flush_all(&self) {
+        for cache in self.resolvers.iter().filter_map(|r| r.cache()) {
+            cache.flush_all();
+        }
+    }
+
+    /// Removes the entries of the domain from the caches of the resolvers, so it is looked up again
+    pub fn flush_entry(&self, domain: &str) {
+        for cache in self.resolvers.iter().filter_map(|r| r.cache()) {
+            cache.flush_entry(domain);
+        }
+    }
+
+    /// Resolves a domain name like `resolve`, but without blocking the caller. Lookups by the remote resolver can
+    /// take seconds, so they run on a thread of their own.
+    pub fn resolve_async(
+        self: &Arc<Self>,
+        domain: &str,
+        resolve_type: ResolveType,
+    ) -> impl Future<Output = Result<DnsEntry>> + Send + 'static {
+        let (tx, rx) = oneshot::channel();
+
+        let dns = self.clone();
+        let domain = domain.to_string();
+        spawn_lookup(move || {
+            let _ = tx.send(dns.resolve(&domain, resolve_type));
+        });
+
+        async move { rx.await.unwrap_or_else(|_| Err(Error::DnsDomainNotFound.into())) }
+    }
+}
+
+/// Returns the resolver for DNS over HTTPS with the configured servers. When none of the servers can be used, the
+/// resolver fails every lookup, so pages do not load instead of falling back to unencrypted DNS.
+fn doh_resolver(servers: &[String], method: DohMethod, timeout: Duration, retries: usize) -> Box<dyn DnsResolver> {
+    let servers = servers
+        .iter()
+        .filter_map(|server| match Url::parse(server) {
+            Ok(url) if url.scheme() == "https" => Some(url),
+            Ok(_) => {
+                warn!("ignoring DoH server {server}, as it does not use https");
+                None
+            }
+            Err(_) => {
+                warn!("ignoring invalid DoH server {server}");
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    if servers.is_empty() {
+        return Box::new(MisconfiguredResolver::new(
+            "DNS over HTTPS is enabled, but there are no valid DoH servers",
+        ));
+    }
+
+    match DohResolver::new(servers, method, timeout, retries) {
+        Ok(resolver) => Box::new(resolver),
+        Err(e) => Box::new(MisconfiguredResolver::new(&format!(
+            "DNS over HTTPS is not available: {e}"
+        ))),
+    }
+}
+
+/// Returns the resolver for DNS over TLS with the configured servers. When none of the servers can be used, the
+/// resolver fails every lookup.
+fn dot_resolver(servers: &[String], timeout: Duration, retries: usize) -> Box<dyn DnsResolver> {
+    let servers = servers
+        .iter()
+        .filter_map(|server| match DotServer::from_str(server) {
+            Ok(server) => Some(server),
+            Err(_) => {
+                warn!("ignoring invalid DoT server {server}");
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    if servers.is_empty() {
+        return Box::new(MisconfiguredResolver::new(
+            "DNS over TLS is enabled, but there are no valid DoT servers",
+        ));
+    }
+
+    Box::new(DotResolver::new(servers, timeout, retries))
+}
+
+/// Resolver that takes the place of an encrypted resolver that could not be set up. It fails every lookup with the
+/// reason, so the misconfiguration shows up instead of lookups failing silently.
+struct MisconfiguredResolver {
+    reason: String,
+}
+
+impl MisconfiguredResolver {
+    fn new(reason: &str) -> Self {
+        warn!("{reason}");
+        Self {
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl DnsResolver for MisconfiguredResolver {
+    fn resolve(&self, _domain: &str, _resolve_type: ResolveType) -> Result<DnsEntry> {
+        Err(Error::DnsMisconfigured(self.reason.clone()).into())
+    }
+
+    fn name(&self) -> &'static str {
+        "misconfigured resolver"
+    }
+}
+
+/// Runs the lookup on a thread of its own. A lookup that hangs until the resolver gives up holds only its own thread,
+/// so it never delays other lookups, even when its caller has stopped waiting for it.
+pub(crate) fn spawn_lookup(lookup: impl FnOnce() + Send + 'static) {
+    if let Err(e) = thread::Builder::new().name("gosub-dns".to_string()).spawn(lookup) {
+        warn!("could not start DNS lookup: {e}");
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::dns::{Dns, ResolveType};
-    use std::time::Instant;
+    use crate::dns::cache::CacheResolver;
+    use crate::dns::{
+        doh_resolver, dot_resolver, Dns, DnsEntry, DnsResolver, DohMethod, LocalTableResolver, NegativeAnswer,
+        ResolveType,
+    };
+    use crate::errors::Error;
+    use gosub_shared::types::Result;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::Duration;
+
+    /// Resolver that counts its lookups, so we can see which requests are answered by the cache. Domains that are not
+    /// in its table do not exist.
+    struct CountingResolver {
+        table: LocalTableResolver,
+        lookups: Arc<AtomicUsize>,
+    }
+
+    impl DnsResolver for CountingResolver {
+        fn resolve(&self, domain: &str, resolve_type: ResolveType) -> Result<DnsEntry> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            self.table
+                .resolve(domain, resolve_type)
+                .map_err(|_| Error::DnsNegative(NegativeAnswer::NxDomain { ttl: Some(60) }).into())
+        }
+
+        fn name(&self) -> &'static str {
+            "counting resolver"
+        }
+    }
 
     #[test]
     fn resolver() {
-        // Add simple logger, if not possible, that's fine too
-        // let _ = SimpleLogger::new().init();
-
-        let mut dns = Dns::new();
+        let mut table = LocalTableResolver::new();
+        table.add_entry(
+            "example.org",
+            vec!["93.184.215.14", "2606:2800:21f:cb07:6820:80da:af6b:8b2c"],
+        );
+
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let counting = CountingResolver {
+            table,
+            lookups: lookups.clone(),
+        };
+        let dns = Arc::new(Dns::with_resolvers(vec![
+            Box::new(CacheResolver::new(10)),
+            Box::new(counting),
+        ]));
 
-        let now = Instant::now();
         let e = dns.resolve("example.org", ResolveType::Ipv4).unwrap();
-        let elapsed_time = now.elapsed();
-        e.ipv4().iter().for_each(|x| println!("ipv4: {}", x));
-        println!("Took {} microseconds.", elapsed_time.as_micros());
+        assert_eq!(e.ipv4(), vec!["93.184.215.14".parse::<std::net::IpAddr>().unwrap()]);
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
 
-        let now = Instant::now();
+        // Answered by the cache
         let e = dns.resolve("example.org", ResolveType::Ipv6).unwrap();
-        let elapsed_time = now.elapsed();
-        e.ipv6().iter().for_each(|x| println!("ipv6: {}", x));
-        println!("Took {} microseconds.", elapsed_time.as_micros());
+        assert_eq!(e.ipv6().len(), 1);
+        let e = dns.resolve("example.org", ResolveType::Both).unwrap();
+        assert_eq!(e.ips.len(), 2);
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+
+        assert!(dns.resolve("unknown.example.org", ResolveType::Both).is_err());
+        assert_eq!(lookups.load(Ordering::SeqCst), 2);
+
+        // The negative answer is answered by the cache as well
+        let err = dns.resolve("unknown.example.org", ResolveType::Ipv4).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::DnsNegative(NegativeAnswer::NxDomain { .. }))
+        ));
+        assert_eq!(lookups.load(Ordering::SeqCst), 2);
+
+        // Flushed domains are looked up again
+        dns.flush_entry("unknown.example.org");
+        assert!(dns.resolve("unknown.example.org", ResolveType::Both).is_err());
+        assert_eq!(lookups.load(Ordering::SeqCst), 3);
+        dns.flush_all();
+        assert!(dns.resolve("example.org", ResolveType::Both).is_ok());
+        assert_eq!(lookups.load(Ordering::SeqCst), 4);
+
+        let e = futures::executor::block_on(dns.resolve_async("example.org", ResolveType::Both)).unwrap();
+        assert_eq!(e.ips.len(), 2);
+        assert!(futures::executor::block_on(dns.resolve_async("unknown.example.org", ResolveType::Ipv4)).is_err());
+    }
 
-        let now = Instant::now();
-        let e = dns.resolve("example.org", ResolveType::Ipv4).unwrap();
-        let elapsed_time = now.elapsed();
-        e.ipv4().iter().for_each(|x| println!("ipv4: {}", x));
-        println!("Took {} microseconds.", elapsed_time.as_micros());
+    /// Resolver that hangs on domains that start with "slow" until it is released
+    struct HangingResolver {
+        table: LocalTableResolver,
+        released: Arc<AtomicBool>,
+    }
 
-        let now = Instant::now();
-        let e = dns.resolve("example.org", ResolveType::Both).unwrap();
-        let elapsed_time = now.elapsed();
-        e.ipv4().iter().for_each(|x| println!("ipv4: {}", x));
-        e.ipv6().iter().for_each(|x| println!("ipv6: {}", x));
-        println!("Took {} microseconds.", elapsed_time.as_micros());
+    impl DnsResolver for HangingResolver {
+        fn resolve(&self, domain: &str, resolve_type: ResolveType) -> Result<DnsEntry> {
+            while domain.starts_with("slow") && !self.released.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(10));
+            }
+            self.table.resolve(domain, resolve_type)
+        }
+
+        fn name(&self) -> &'static str {
+            "hanging resolver"
+        }
+    }
+
+    #[test]
+    fn invalid_doh_config() {
+        let servers = vec!["http://dns.example.com/dns-query".to_string(), "not a url".to_string()];
+        let doh = doh_resolver(&servers, DohMethod::Post, Duration::from_secs(1), 1);
+        let dns = Dns::with_resolvers(vec![Box::new(CacheResolver::new(10)), doh]);
+
+        let err = dns.resolve("example.org", ResolveType::Both).unwrap_err();
+        assert!(
+            matches!(err.downcast_ref::<Error>(), Some(Error::DnsMisconfigured(_))),
+            "{err}"
+        );
+        assert!(err.to_string().contains("encrypted DNS is misconfigured"), "{err}");
+
+        let dot = dot_resolver(&["not a server".to_string()], Duration::from_secs(1), 1);
+        let err = dot.resolve("example.org", ResolveType::Both).unwrap_err();
+        assert!(matches!(err.downcast_ref::<Error>(), Some(Error::DnsMisconfigured(_))));
+    }
+
+    #[test]
+    fn hanging_lookups() {
+        let mut table = LocalTableResolver::new();
+        table.add_entry("example.org", vec!["93.184.215.14"]);
+        let released = Arc::new(AtomicBool::new(false));
+        let dns = Arc::new(Dns::with_resolvers(vec![Box::new(HangingResolver {
+            table,
+            released: released.clone(),
+        })]));
+
+        // Lookups whose callers have given up keep hanging, but other lookups are answered anyway
+        for i in 0..16 {
+            drop(dns.resolve_async(&format!("slow{i}.example.org"), ResolveType::Both));
+        }
+        let (tx, rx) = mpsc::channel();
+        let lookup = dns.resolve_async("example.org", ResolveType::Both);
+        thread::spawn(move || tx.send(futures::executor::block_on(lookup)));
+        let entry = rx.recv_timeout(Duration::from_secs(5)).unwrap().unwrap();
+        assert_eq!(entry.ipv4().len(), 1);
+
+        released.store(true, Ordering::SeqCst);
     }
 }
@@ -1,36 +1,129 @@
-use crate::dns::{DnsCache, DnsEntry, DnsResolver, ResolveType};
+use crate::dns::{now, DnsCache, DnsEntry, DnsResolver, NegativeAnswer, ResolveType};
 use crate::errors::Error;
 use gosub_shared::types::Result;
 use log::trace;
 use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+/// Number of seconds that negative answers are cached at most
+const DEFAULT_NEGATIVE_TTL: u32 = 300;
 
 pub(crate) struct CacheResolver {
-    values: HashMap<String, DnsEntry>,
+    /// Entries and their usage order. The cache is shared by all lookups, so it has its own lock.
+    state: Mutex<CacheState>,
     max_entries: usize,
+    /// When set, entries are cached for this number of seconds instead of the TTL of their records
+    ttl_override: Option<u32>,
+    /// Number of seconds that negative answers are cached at most
+    negative_ttl: u32,
+}
+
+struct CacheState {
+    values: HashMap<String, DnsEntry>,
+    /// Negative answers for domains. A domain can have both an entry and a negative answer, when it only has
+    /// addresses of one type.
+    negatives: HashMap<String, NegativeEntry>,
+    /// Usage order of the domains in either map
     lru: VecDeque<String>,
 }
 
+/// Negative answers that are cached for a domain
+#[derive(Default)]
+struct NegativeEntry {
+    /// True when the domain does not exist at all
+    nxdomain: bool,
+    /// Expiry time after epoch of the answer that the domain has no ipv4 addresses, or 0 without such answer
+    no_ipv4_until: u64,
+    /// Expiry time after epoch of the answer that the domain has no ipv6 addresses, or 0 without such answer
+    no_ipv6_until: u64,
+}
+
+impl NegativeEntry {
+    /// Returns the cached answer for the resolve type, when it has not expired
+    fn answer(&self, resolve_type: &ResolveType) -> Option<NegativeAnswer> {
+        let until = match resolve_type {
+            ResolveType::Ipv4 => self.no_ipv4_until,
+            ResolveType::Ipv6 => self.no_ipv6_until,
+            ResolveType::Both => self.no_ipv4_until.min(self.no_ipv6_until),
+        };
+
+        let now = now();
+        if until <= now {
+            return None;
+        }
+
+        let ttl = Some(u32::try_from(until - now).unwrap_or(u32::MAX));
+        Some(if self.nxdomain {
+            NegativeAnswer::NxDomain { ttl }
+        } else {
+            NegativeAnswer::NoData { ttl }
+        })
+    }
+}
+
+impl CacheState {
+    /// Marks the domain as most recently used, and evicts the least recently used domains to make room for it
+    fn touch(&mut self, domain: &str, max_entries: usize) {
+        self.lru.retain(|x| x != domain);
+
+        while self.lru.len() >= max_entries {
+            let Some(key) = self.lru.pop_front() else {
+                break;
+            };
+            self.values.remove(&key);
+            self.negatives.remove(&key);
+        }
+
+        self.lru.push_back(domain.to_string());
+    }
+
+    fn remove(&mut self, domain: &str) {
+        self.values.remove(domain);
+        self.negatives.remove(domain);
+        self.lru.retain(|x| x != domain);
+    }
+}
+
 impl DnsResolver for CacheResolver {
-    fn resolve(&mut self, domain: &str, resolve_type: ResolveType) -> Result<DnsEntry> {
-        if let Some(entry) = self.values.get(domain) {
-            if !entry.has_ipv4 && !entry.has_ipv6 && resolve_type == ResolveType::Both {
-                trace!("{}: no addresses found in entry", domain);
-                return Err(Error::DnsNoIpAddressFound.into());
-            }
-            if !entry.has_ipv4 && resolve_type == ResolveType::Ipv4 {
-                trace!("{}: no ipv4 addresses found in entry", domain);
-                return Err(Error::DnsNoIpAddressFound.into());
-            }
-            if !entry.has_ipv6 && resolve_type == ResolveType::Ipv6 {
-                trace!("{}: no ipv6 addresses found in entry", domain);
-                return Err(Error::DnsNoIpAddressFound.into());
+    fn resolve(&self, domain: &str, resolve_type: ResolveType) -> Result<DnsEntry> {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+
+        // Entries without a known TTL have no expiry time, and stay until they are evicted
+        if state
+            .values
+            .get(domain)
+            .is_some_and(|entry| entry.expires != 0 && entry.expired())
+        {
+            trace!("{}: entry in cache has expired", domain);
+            state.values.remove(domain);
+            if !state.negatives.contains_key(domain) {
+                state.lru.retain(|x| x != domain);
             }
+        }
+
+        if let Some(entry) = state.values.get(domain) {
+            let found = match resolve_type {
+                ResolveType::Ipv4 => entry.has_ipv4,
+                ResolveType::Ipv6 => entry.has_ipv6,
+                ResolveType::Both => entry.has_ipv4 || entry.has_ipv6,
+            };
 
-            trace!("{}: found in cache with correct resolve type", domain);
-            self.lru.retain(|x| x != domain);
-            self.lru.push_back(domain.to_string());
+            if found {
+                trace!("{}: found in cache with correct resolve type", domain);
+                let entry = entry.clone();
+                state.touch(domain, self.max_entries);
 
-            return Ok(entry.clone());
+                return Ok(entry);
+            }
+            trace!("{}: no {:?} addresses found in entry", domain, resolve_type);
+        }
+
+        if let Some(negative) = state.negatives.get(domain) {
+            if let Some(answer) = negative.answer(&resolve_type) {
+                trace!("{}: negative answer in cache: {}", domain, answer);
+                return Err(Error::DnsNegative(answer).into());
+            }
         }
 
         Err(Error::DnsNoIpAddressFound.into())
@@ -38,19 +131,41 @@ impl DnsResolver for CacheResolver {
 
     /// When a domain is resolved, it will be announced to all resolvers. This cache resolver
     /// will store it into the cache.
-    fn announce(&mut self, domain: &str, entry: &DnsEntry) {
+    fn announce(&self, domain: &str, entry: &DnsEntry) {
         trace!("{}: announcing to cache", domain);
 
-        self.lru.retain(|x| x != domain);
-        self.lru.push_back(domain.to_string());
+        let mut entry = entry.clone();
+        if let Some(ttl) = self.ttl_override {
+            entry.expires = now() + u64::from(ttl);
+        }
+
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+
+        state.touch(domain, self.max_entries);
+
+        // The addresses replace the negative answers for their type
+        if let Some(negative) = state.negatives.get_mut(domain) {
+            negative.nxdomain = false;
+            if entry.has_ipv4 {
+                negative.no_ipv4_until = 0;
+            }
+            if entry.has_ipv6 {
+                negative.no_ipv6_until = 0;
+            }
+        }
 
-        if let Some(current_entry) = self.values.get_mut(domain) {
+        if let Some(current_entry) = state.values.get_mut(domain) {
             trace!("{}: updating existing entry to cache", domain);
 
             trace!("current entries: {:?}", current_entry.ips);
             trace!("new entries: {:?}", entry.ips);
             current_entry.has_ipv4 |= entry.has_ipv4;
             current_entry.has_ipv6 |= entry.has_ipv6;
+            // The merged entry expires with the first of its addresses
+            if current_entry.expires == 0 || (entry.expires != 0 && entry.expires < current_entry.expires) {
+                current_entry.expires = entry.expires;
+            }
 
             for ip in &entry.ips {
                 if current_entry.ips.iter().any(|x| x == ip) {
@@ -61,43 +176,90 @@ impl DnsResolver for CacheResolver {
             trace!("merged entries: {:?}", current_entry.ips);
         } else {
             trace!("adding new entry to cache");
+            state.values.insert(domain.to_string(), entry);
+        }
+    }
+
+    /// Negative answers are cached with the TTL of the answer, but never longer than the negative TTL of the cache
+    fn announce_negative(&self, domain: &str, resolve_type: &ResolveType, answer: &NegativeAnswer) {
+        trace!("{}: announcing negative answer to cache: {}", domain, answer);
+
+        let ttl = answer.ttl().unwrap_or(self.negative_ttl).min(self.negative_ttl);
+        let until = now() + u64::from(ttl);
 
-            // Clear out if we have too many entries
-            if self.values.len() >= self.max_entries {
-                if let Some(key) = self.lru.pop_front() {
-                    self.values.remove(&key);
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+
+        state.touch(domain, self.max_entries);
+
+        let negative = state.negatives.entry(domain.to_string()).or_default();
+        match answer {
+            NegativeAnswer::NxDomain { .. } => {
+                negative.nxdomain = true;
+                negative.no_ipv4_until = until;
+                negative.no_ipv6_until = until;
+                // Addresses of a domain that no longer exists are not used anymore
+                state.values.remove(domain);
+            }
+            NegativeAnswer::NoData { .. } => {
+                negative.nxdomain = false;
+                if resolve_type != &ResolveType::Ipv6 {
+                    negative.no_ipv4_until = until;
+                }
+                if resolve_type != &ResolveType::Ipv4 {
+                    negative.no_ipv6_until = until;
                 }
             }
-
-            self.values.insert(domain.to_string(), entry.clone());
         }
     }
 
     fn name(&self) -> &'static str {
         "cache resolver"
     }
+
+    fn cache(&self) -> Option<&dyn DnsCache> {
+        Some(self)
+    }
 }
 
 impl DnsCache for CacheResolver {
-    fn flush_all(&mut self) {
-        self.values.clear();
-        self.lru.clear();
+    fn flush_all(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.values.clear();
+        state.negatives.clear();
+        state.lru.clear();
     }
 
-    fn flush_entry(&mut self, domain: &str) {
-        self.values.remove(domain);
-        self.lru.retain(|x| x != domain);
+    fn flush_entry(&self, domain: &str) {
+        self.state.lock().unwrap().remove(domain);
     }
 }
 
 impl CacheResolver {
     pub(crate) fn new(max_entries: usize) -> CacheResolver {
         CacheResolver {
-            values: HashMap::with_capacity(max_entries),
+            state: Mutex::new(CacheState {
+                values: HashMap::with_capacity(max_entries),
+                negatives: HashMap::new(),
+                lru: VecDeque::with_capacity(max_entries),
+            }),
             max_entries,
-            lru: VecDeque::with_capacity(max_entries),
+            ttl_override: None,
+            negative_ttl: DEFAULT_NEGATIVE_TTL,
         }
     }
+
+    /// Caches entries for the given number of seconds, instead of the TTL of their records
+    pub(crate) fn with_ttl_override(mut self, ttl: u32) -> Self {
+        self.ttl_override = Some(ttl);
+        self
+    }
+
+    /// Caches negative answers for the given number of seconds at most
+    pub(crate) fn with_negative_ttl(mut self, ttl: u32) -> Self {
+        self.negative_ttl = ttl;
+        self
+    }
 }
 
 #[cfg(test)]
@@ -112,43 +274,145 @@ mod test {
         cache.announce("example.com", &DnsEntry::new("example.com", vec!["127.0.0.1"]));
         cache.announce("example.org", &DnsEntry::new("example.org", vec!["127.0.0.1"]));
 
-        assert_eq!(cache.values.len(), 2);
-        assert_eq!(cache.lru.len(), 2);
-        assert_eq!(cache.lru.capacity(), 3);
-        assert_eq!(cache.lru[0], "example.com");
-        assert_eq!(cache.lru[1], "example.org");
+        assert_eq!(cache.state.get_mut().unwrap().values.len(), 2);
+        assert_eq!(cache.state.get_mut().unwrap().lru.len(), 2);
+        assert_eq!(cache.state.get_mut().unwrap().lru.capacity(), 3);
+        assert_eq!(cache.state.get_mut().unwrap().lru[0], "example.com");
+        assert_eq!(cache.state.get_mut().unwrap().lru[1], "example.org");
 
         cache.announce("example.net", &DnsEntry::new("example.net", vec!["127.0.0.1"]));
-        assert_eq!(cache.values.len(), 3);
-        assert_eq!(cache.lru.len(), 3);
-        assert_eq!(cache.lru[0], "example.com");
-        assert_eq!(cache.lru[1], "example.org");
-        assert_eq!(cache.lru[2], "example.net");
+        assert_eq!(cache.state.get_mut().unwrap().values.len(), 3);
+        assert_eq!(cache.state.get_mut().unwrap().lru.len(), 3);
+        assert_eq!(cache.state.get_mut().unwrap().lru[0], "example.com");
+        assert_eq!(cache.state.get_mut().unwrap().lru[1], "example.org");
+        assert_eq!(cache.state.get_mut().unwrap().lru[2], "example.net");
 
-        println!("lru: {:?}", cache.lru);
+        println!("lru: {:?}", cache.state.get_mut().unwrap().lru);
         let _ = cache.resolve("example.org", ResolveType::Both);
-        println!("lru: {:?}", cache.lru);
-        assert_eq!(cache.lru[0], "example.com");
-        assert_eq!(cache.lru[1], "example.net");
-        assert_eq!(cache.lru[2], "example.org");
+        println!("lru: {:?}", cache.state.get_mut().unwrap().lru);
+        assert_eq!(cache.state.get_mut().unwrap().lru[0], "example.com");
+        assert_eq!(cache.state.get_mut().unwrap().lru[1], "example.net");
+        assert_eq!(cache.state.get_mut().unwrap().lru[2], "example.org");
 
         cache.announce("example.net", &DnsEntry::new("example.net", vec!["127.0.0.1"]));
-        assert_eq!(cache.values.len(), 3);
-        assert_eq!(cache.lru.len(), 3);
-        assert_eq!(cache.lru[0], "example.com");
-        assert_eq!(cache.lru[1], "example.org");
-        assert_eq!(cache.lru[2], "example.net");
+        assert_eq!(cache.state.get_mut().unwrap().values.len(), 3);
+        assert_eq!(cache.state.get_mut().unwrap().lru.len(), 3);
+        assert_eq!(cache.state.get_mut().unwrap().lru[0], "example.com");
+        assert_eq!(cache.state.get_mut().unwrap().lru[1], "example.org");
+        assert_eq!(cache.state.get_mut().unwrap().lru[2], "example.net");
 
         let _ = cache.resolve("example.com", ResolveType::Both);
-        assert_eq!(cache.lru[0], "example.org");
-        assert_eq!(cache.lru[1], "example.net");
-        assert_eq!(cache.lru[2], "example.com");
+        assert_eq!(cache.state.get_mut().unwrap().lru[0], "example.org");
+        assert_eq!(cache.state.get_mut().unwrap().lru[1], "example.net");
+        assert_eq!(cache.state.get_mut().unwrap().lru[2], "example.com");
 
         cache.announce("new.com", &DnsEntry::new("new.com", vec!["127.0.0.1"]));
-        assert_eq!(cache.values.len(), 3);
-        assert_eq!(cache.lru.len(), 3);
-        assert_eq!(cache.lru[0], "example.net");
-        assert_eq!(cache.lru[1], "example.com");
-        assert_eq!(cache.lru[2], "new.com");
+        assert_eq!(cache.state.get_mut().unwrap().values.len(), 3);
+        assert_eq!(cache.state.get_mut().unwrap().lru.len(), 3);
+        assert_eq!(cache.state.get_mut().unwrap().lru[0], "example.net");
+        assert_eq!(cache.state.get_mut().unwrap().lru[1], "example.com");
+        assert_eq!(cache.state.get_mut().unwrap().lru[2], "new.com");
+    }
+
+    #[test]
+    fn ttl_expiry() {
+        let cache = CacheResolver::new(3);
+
+        let ips = vec!["192.0.2.1".parse().unwrap()];
+        cache.announce("example.com", &DnsEntry::with_ttl("example.com", ips.clone(), 300));
+        assert!(cache.resolve("example.com", ResolveType::Ipv4).is_ok());
+
+        // An entry that is announced with a shorter TTL makes the merged entry expire sooner
+        let mut expired = DnsEntry::with_ttl("example.com", ips, 300);
+        expired.expires = 1;
+        cache.announce("example.com", &expired);
+        assert!(cache.resolve("example.com", ResolveType::Ipv4).is_err());
+        assert!(cache.state.lock().unwrap().values.is_empty());
+
+        // Entries without a TTL do not expire
+        cache.announce("example.org", &DnsEntry::new("example.org", vec!["127.0.0.1"]));
+        assert!(cache.resolve("example.org", ResolveType::Ipv4).is_ok());
+    }
+
+    #[test]
+    fn ttl_override() {
+        let cache = CacheResolver::new(3).with_ttl_override(0);
+
+        // Entries expire directly, whatever the TTL of their records
+        let ips = vec!["192.0.2.1".parse().unwrap()];
+        cache.announce("example.com", &DnsEntry::with_ttl("example.com", ips, 300));
+        cache.announce("example.org", &DnsEntry::new("example.org", vec!["127.0.0.1"]));
+        assert!(cache.resolve("example.com", ResolveType::Ipv4).is_err());
+        assert!(cache.resolve("example.org", ResolveType::Ipv4).is_err());
+
+        let cache = CacheResolver::new(3).with_ttl_override(60);
+        cache.announce("example.org", &DnsEntry::new("example.org", vec!["127.0.0.1"]));
+        let entry = cache.resolve("example.org", ResolveType::Ipv4).unwrap();
+        assert_eq!(entry.expires, DnsEntry::with_ttl("", vec![], 60).expires);
+    }
+
+    #[test]
+    fn negative_caching() {
+        let cache = CacheResolver::new(3).with_negative_ttl(60);
+
+        let negative = |domain: &str, resolve_type| match cache.resolve(domain, resolve_type) {
+            Err(e) => match e.downcast_ref::<Error>() {
+                Some(Error::DnsNegative(answer)) => Some(*answer),
+                _ => None,
+            },
+            Ok(_) => None,
+        };
+
+        // The TTL of the answer is capped by the negative TTL of the cache
+        cache.announce_negative(
+            "missing.com",
+            &ResolveType::Both,
+            &NegativeAnswer::NxDomain { ttl: Some(3600) },
+        );
+        assert!(matches!(
+            negative("missing.com", ResolveType::Ipv6),
+            Some(NegativeAnswer::NxDomain { ttl: Some(59..=60) })
+        ));
+
+        // A domain with only ipv4 addresses
+        let ips = vec!["192.0.2.1".parse().unwrap()];
+        cache.announce("ipv4.com", &DnsEntry::with_ttl("ipv4.com", ips, 300));
+        cache.announce_negative("ipv4.com", &ResolveType::Ipv6, &NegativeAnswer::NoData { ttl: None });
+        assert!(cache.resolve("ipv4.com", ResolveType::Ipv4).is_ok());
+        assert!(cache.resolve("ipv4.com", ResolveType::Both).is_ok());
+        assert!(matches!(
+            negative("ipv4.com", ResolveType::Ipv6),
+            Some(NegativeAnswer::NoData { .. })
+        ));
+
+        // Negative answers without TTL are not cached when the negative TTL is 0
+        let cache = CacheResolver::new(3).with_negative_ttl(0);
+        cache.announce_negative(
+            "missing.com",
+            &ResolveType::Both,
+            &NegativeAnswer::NxDomain { ttl: None },
+        );
+        assert!(matches!(
+            cache
+                .resolve("missing.com", ResolveType::Both)
+                .unwrap_err()
+                .downcast_ref::<Error>(),
+            Some(Error::DnsNoIpAddressFound)
+        ));
+
+        // Addresses that are announced later replace the negative answer
+        let cache = CacheResolver::new(3);
+        cache.announce_negative(
+            "example.com",
+            &ResolveType::Both,
+            &NegativeAnswer::NxDomain { ttl: Some(60) },
+        );
+        cache.announce("example.com", &DnsEntry::new("example.com", vec!["127.0.0.1"]));
+        assert!(cache.resolve("example.com", ResolveType::Ipv4).is_ok());
+        assert!(cache.resolve("example.com", ResolveType::Ipv6).is_err());
+
+        cache.flush_entry("example.com");
+        assert!(cache.resolve("example.com", ResolveType::Ipv4).is_err());
+        assert!(cache.state.lock().unwrap().lru.is_empty());
     }
 }
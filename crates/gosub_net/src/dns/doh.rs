@@ -0,0 +1,237 @@
+//! DNS over HTTPS (RFC 8484)
+//!
+//! Queries are sent in wire format to the DoH endpoint of a resolver, either in the `dns` parameter of a GET request
+//! or as the body of a POST request. The host name of the endpoint itself is resolved by the system resolver.
+use std::time::Duration;
+
+use anyhow::bail;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ureq::Agent;
+use url::Url;
+
+use gosub_shared::types::Result;
+
+use crate::dns::message::{self, record_types};
+use crate::dns::{DnsEntry, DnsResolver, ResolveType};
+
+/// Media type of DNS messages in wire format
+const DNS_MESSAGE: &str = "application/dns-message";
+
+/// DNS messages are never larger than this
+const MAX_MESSAGE_SIZE: u64 = 65535;
+
+/// HTTP method that is used for DoH queries
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DohMethod {
+    /// The query is sent in the URL, so responses can be cached by HTTP caches
+    Get,
+    /// The query is sent as the request body
+    Post,
+}
+
+impl DohMethod {
+    /// Returns the method for the `dns.remote.doh.method` setting. Anything but "post" means GET.
+    pub fn from_setting(value: &str) -> Self {
+        if value.eq_ignore_ascii_case("post") {
+            Self::Post
+        } else {
+            Self::Get
+        }
+    }
+}
+
+/// Resolver that queries DoH endpoints, in the order they are given
+pub struct DohResolver {
+    servers: Vec<Url>,
+    method: DohMethod,
+    attempts: usize,
+    agent: Agent,
+}
+
+impl DnsResolver for DohResolver {
+    fn resolve(&self, domain: &str, resolve_type: ResolveType) -> Result<DnsEntry> {
+        // The id is 0, so identical queries have identical URLs (RFC 8484, section 4.1)
+        let queries = record_types(&resolve_type)
+            .into_iter()
+            .map(|record_type| message::query(domain, record_type, 0))
+            .collect::<Result<Vec<_>>>()?;
+
+        message::resolve_with(domain, &queries, &self.servers, self.attempts, |server, queries| {
+            queries.iter().map(|query| self.exchange(server, query)).collect()
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "dns over https resolver"
+    }
+}
+
+impl DohResolver {
+    /// Instantiates a resolver for the given endpoints, like `https://cloudflare-dns.com/dns-query`. Fails when an
+    /// endpoint does not use https, as its lookups would be sent unencrypted.
+    pub fn new(servers: Vec<Url>, method: DohMethod, timeout: Duration, attempts: usize) -> Result<Self> {
+        if let Some(server) = servers.iter().find(|server| server.scheme() != "https") {
+            bail!("DoH endpoint {server} does not use https");
+        }
+
+        Ok(Self::with_servers(servers, method, timeout, attempts))
+    }
+
+    /// Instantiates a resolver that also accepts plain HTTP endpoints, so tests can run a local endpoint
+    #[cfg(test)]
+    pub(crate) fn new_insecure(servers: Vec<Url>, method: DohMethod, timeout: Duration, attempts: usize) -> Self {
+        Self::with_servers(servers, method, timeout, attempts)
+    }
+
+    fn with_servers(servers: Vec<Url>, method: DohMethod, timeout: Duration, attempts: usize) -> Self {
+        let agent = Agent::config_builder()
+            .timeout_global(Some(timeout))
+            .http_status_as_error(false)
+            .build()
+            .new_agent();
+
+        Self {
+            servers,
+            method,
+            attempts,
+            agent,
+        }
+    }
+
+    /// Sends a single query to the endpoint, and returns the response message
+    fn exchange(&self, server: &Url, query: &[u8]) -> Result<Vec<u8>> {
+        let response = match self.method {
+            DohMethod::Get => {
+                let mut url = server.clone();
+                url.query_pairs_mut().append_pair("dns", &URL_SAFE_NO_PAD.encode(query));
+                self.agent.get(url.as_str()).header("Accept", DNS_MESSAGE).call()?
+            }
+            DohMethod::Post => self
+                .agent
+                .post(server.as_str())
+                .header("Accept", DNS_MESSAGE)
+                .header("Content-Type", DNS_MESSAGE)
+                .send(query)?,
+        };
+
+        if response.status() != 200 {
+            bail!("endpoint responded with status {}", response.status());
+        }
+        let content_type = response
+            .headers()
+            .get("content-type")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if !content_type.starts_with(DNS_MESSAGE) {
+            bail!("endpoint responded with content type {content_type:?}");
+        }
+
+        Ok(response
+            .into_body()
+            .with_config()
+            .limit(MAX_MESSAGE_SIZE)
+            .read_to_vec()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::test_answer;
+    use crate::dns::NegativeAnswer;
+    use crate::errors::Error;
+    use cow_utils::CowUtils;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{IpAddr, TcpListener};
+    use std::thread;
+
+    /// Starts a DoH endpoint on a local port, that answers over plain HTTP
+    fn doh_server() -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/dns-query", listener.local_addr().unwrap())).unwrap();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut reader = BufReader::new(stream.unwrap());
+
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.cow_to_ascii_lowercase().strip_prefix("content-length:") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+
+                let query = if request_line.starts_with("POST") {
+                    let mut body = vec![0; content_length];
+                    reader.read_exact(&mut body).unwrap();
+                    body
+                } else {
+                    let target = request_line.split(' ').nth(1).unwrap();
+                    let url = Url::parse("http://localhost").unwrap().join(target).unwrap();
+                    let (_, dns) = url.query_pairs().find(|(name, _)| name == "dns").unwrap();
+                    URL_SAFE_NO_PAD.decode(dns.as_bytes()).unwrap()
+                };
+
+                let answer = test_answer(&query);
+                let mut stream = reader.into_inner();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: {DNS_MESSAGE}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    answer.len()
+                )
+                .unwrap();
+                stream.write_all(&answer).unwrap();
+            }
+        });
+
+        url
+    }
+
+    #[test]
+    fn https_only() {
+        let timeout = Duration::from_secs(5);
+        let https = Url::parse("https://cloudflare-dns.com/dns-query").unwrap();
+        let http = Url::parse("http://cloudflare-dns.com/dns-query").unwrap();
+
+        assert!(DohResolver::new(vec![https.clone()], DohMethod::Get, timeout, 1).is_ok());
+        assert!(DohResolver::new(vec![https, http], DohMethod::Get, timeout, 1).is_err());
+    }
+
+    #[test]
+    fn doh_lookups() {
+        let server = doh_server();
+
+        for method in [DohMethod::Get, DohMethod::Post] {
+            let resolver = DohResolver::new_insecure(vec![server.clone()], method, Duration::from_secs(5), 1);
+
+            let entry = resolver.resolve("example.test", ResolveType::Both).unwrap();
+            assert_eq!(entry.ipv4(), vec!["192.0.2.1".parse::<IpAddr>().unwrap()]);
+            assert_eq!(entry.ipv6(), vec!["2001:db8::1".parse::<IpAddr>().unwrap()]);
+            assert!(!entry.expired());
+
+            let err = resolver.resolve("missing.test", ResolveType::Ipv4).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::DnsNegative(NegativeAnswer::NxDomain { .. }))
+            ));
+        }
+
+        // The second endpoint is used when the first one does not answer
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let closed = Url::parse(&format!("http://{closed}/dns-query")).unwrap();
+        let resolver =
+            DohResolver::new_insecure(vec![closed.clone(), server], DohMethod::Get, Duration::from_secs(5), 1);
+        assert!(resolver.resolve("example.test", ResolveType::Ipv4).is_ok());
+
+        let resolver = DohResolver::new_insecure(vec![closed], DohMethod::Get, Duration::from_secs(5), 2);
+        assert!(resolver.resolve("example.test", ResolveType::Ipv4).is_err());
+    }
+}
@@ -2,12 +2,19 @@ use crate::dns::{DnsCache, DnsEntry, DnsResolver, ResolveType};
 use crate::errors::Error;
 use core::fmt;
 use domain_lookup_tree::DomainLookupTree;
+use gosub_config::{config, config_store};
 use gosub_shared::types::Result;
 use log::trace;
 use std::collections::HashMap;
+use std::sync::RwLock;
 
 /// Local override table that can be used instead of using /etc/hosts or similar 3rd party dns system.
 pub struct LocalTableResolver {
+    /// The table can be reloaded while it is shared by lookups, so it has its own lock
+    table: RwLock<LocalTable>,
+}
+
+struct LocalTable {
     /// Entries in the local override table.
     entries: HashMap<String, DnsEntry>,
     /// Domaintree is a hierarchical lookup tree for quick scanning of (wildcard) domains
@@ -17,15 +24,19 @@ pub struct LocalTableResolver {
 impl Default for LocalTableResolver {
     fn default() -> Self {
         Self {
-            entries: HashMap::new(),
-            tree: DomainLookupTree::new(),
+            table: RwLock::new(LocalTable {
+                entries: HashMap::new(),
+                tree: DomainLookupTree::new(),
+            }),
         }
     }
 }
 
 impl DnsResolver for LocalTableResolver {
-    fn resolve(&mut self, domain: &str, _resolve_type: ResolveType) -> Result<DnsEntry> {
-        let Some(domain_entry) = self.tree.lookup(domain) else {
+    fn resolve(&self, domain: &str, _resolve_type: ResolveType) -> Result<DnsEntry> {
+        let table = self.table.read().unwrap();
+
+        let Some(domain_entry) = table.tree.lookup(domain) else {
             trace!("{domain}: not found in local table");
             return Err(Error::DnsDomainNotFound.into());
         };
@@ -34,7 +45,7 @@ impl DnsResolver for LocalTableResolver {
 
         // domain_entry could be "com" if you ask for just "com" and it's part of the tree (it normally is). So in
         // that case we still have to check if the domain is actually in the entries list.
-        if let Some(entry) = self.entries.get(&domain_entry) {
+        if let Some(entry) = table.entries.get(&domain_entry) {
             return Ok(entry.clone());
         }
 
@@ -45,15 +56,19 @@ impl DnsResolver for LocalTableResolver {
     fn name(&self) -> &'static str {
         "local table resolver"
     }
+
+    fn cache(&self) -> Option<&dyn DnsCache> {
+        Some(self)
+    }
 }
 
 impl DnsCache for LocalTableResolver {
-    fn flush_all(&mut self) {
+    fn flush_all(&self) {
         // flushing the local table means reloading the entries
         self.reload_table_entries();
     }
 
-    fn flush_entry(&mut self, domain: &str) {
+    fn flush_entry(&self, domain: &str) {
         self.reload_table_entry(domain);
     }
 }
@@ -62,33 +77,68 @@ impl LocalTableResolver {
     /// Instantiates a new local override table
     #[must_use]
     pub fn new() -> Self {
-        let mut table = Self {
-            entries: HashMap::new(),
-            tree: DomainLookupTree::new(),
-        };
-
+        let table = Self::default();
         table.reload_table_entries();
         table
     }
 
     /// Helper function to add an entry to the local override table. It will figure out which
     /// elements are ipv4 and ipv6 and add them accordingly
-    #[allow(dead_code)]
     pub fn add_entry(&mut self, domain: &str, ips: Vec<&str>) {
+        self.table.get_mut().unwrap().add_entry(domain, ips);
+    }
+
+    /// Regenerates the entries table from the `dns.local.table` setting. Each value of the setting has the form
+    /// `domain=ip [ip...]`, for instance `example.com=127.0.0.1 ::1` or `.wildcard.com=10.0.0.1`.
+    pub fn reload_table_entries(&self) {
+        let mut table = self.table.write().unwrap();
+        table.entries.clear();
+        table.tree = DomainLookupTree::new();
+
+        for (domain, ips) in configured_entries() {
+            table.add_entry(&domain, ips.iter().map(String::as_str).collect());
+        }
+    }
+
+    /// Reloads the entry of a single domain from the `dns.local.table` setting
+    pub fn reload_table_entry(&self, domain: &str) {
+        let mut table = self.table.write().unwrap();
+        match configured_entries().into_iter().find(|(d, _)| d == domain) {
+            Some((domain, ips)) => table.add_entry(&domain, ips.iter().map(String::as_str).collect()),
+            None => {
+                // The lookup tree has no removal, but a domain without entry is not resolved
+                table.entries.remove(domain);
+            }
+        }
+    }
+}
+
+impl LocalTable {
+    fn add_entry(&mut self, domain: &str, ips: Vec<&str>) {
         let entry = DnsEntry::new(domain, ips);
 
         self.entries.insert(domain.to_string(), entry);
         self.tree.insert(domain);
     }
+}
 
-    /// Regenerates the new entries table
-    pub fn reload_table_entries(&mut self) {
-        // @todo: this should reload all table entries from the configuration into the self.entries list
-    }
+/// Returns the domains and their addresses from the `dns.local.table` setting
+fn configured_entries() -> Vec<(String, Vec<String>)> {
+    let mut entries = Vec::new();
 
-    pub fn reload_table_entry(&mut self, _domain: &str) {
-        // @todo: this should reload a single entry from the configuration into the self.entries
+    for value in config!(map "dns.local.table") {
+        let Some((domain, ips)) = value.split_once('=') else {
+            continue;
+        };
+        let ips = ips.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+        if domain.trim().is_empty() || ips.is_empty() {
+            continue;
+        }
+
+        entries.push((domain.trim().to_string(), ips));
     }
+
+    entries
 }
 
 impl fmt::Debug for LocalTableResolver {
@@ -0,0 +1,213 @@
+//! DNS messages in wire format
+//!
+//! The encrypted resolvers (DNS over HTTPS and DNS over TLS) send their own queries instead of letting hickory do
+//! it, so they need to build the query messages and read the addresses from the responses. The encoding itself is
+//! done by hickory-proto.
+use std::fmt::Display;
+use std::net::IpAddr;
+
+use hickory_proto::op::{Message, MessageType, OpCode, Query, ResponseCode};
+use hickory_proto::rr::{Name, RData, RecordType};
+use log::{trace, warn};
+
+use gosub_shared::types::Result;
+
+use crate::dns::{DnsEntry, NegativeAnswer, ResolveType};
+use crate::errors::Error;
+
+/// Returns the record types that must be queried for the resolve type
+pub(crate) fn record_types(resolve_type: &ResolveType) -> Vec<RecordType> {
+    match resolve_type {
+        ResolveType::Ipv4 => vec![RecordType::A],
+        ResolveType::Ipv6 => vec![RecordType::AAAA],
+        ResolveType::Both => vec![RecordType::A, RecordType::AAAA],
+    }
+}
+
+/// Returns a recursive query for the records of the domain, in wire format
+pub(crate) fn query(domain: &str, record_type: RecordType, id: u16) -> Result<Vec<u8>> {
+    let mut name = Name::from_ascii(domain).map_err(|e| Error::DnsGeneric(e.to_string()))?;
+    name.set_fqdn(true);
+
+    let mut message = Message::new();
+    message
+        .set_id(id)
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(true)
+        .add_query(Query::query(name, record_type));
+
+    Ok(message.to_vec().map_err(|e| Error::DnsGeneric(e.to_string()))?)
+}
+
+/// Returns the id of a message in wire format
+pub(crate) fn message_id(data: &[u8]) -> Option<u16> {
+    Some(u16::from_be_bytes([*data.first()?, *data.get(1)?]))
+}
+
+/// Returns the number of seconds that a negative answer may be cached, from the SOA record in the authority section
+/// of the response (RFC 2308, section 5)
+fn negative_ttl(message: &Message) -> Option<u32> {
+    message.name_servers().iter().find_map(|record| match record.data() {
+        Some(RData::SOA(soa)) => Some(record.ttl().min(soa.minimum())),
+        _ => None,
+    })
+}
+
+/// Reads the addresses from the responses to the queries for a domain. The entry expires when the first of the
+/// answers expires. Without addresses, the negative answer of the server is returned.
+pub(crate) fn entry_from_responses(domain: &str, responses: &[Vec<u8>]) -> Result<DnsEntry> {
+    let mut ips = vec![];
+    let mut ttl = None;
+    let mut no_data_ttl = None;
+
+    for data in responses {
+        let message = Message::from_vec(data).map_err(|e| Error::DnsGeneric(e.to_string()))?;
+        if message.message_type() != MessageType::Response {
+            return Err(Error::DnsGeneric("message is not a response".into()).into());
+        }
+
+        match message.response_code() {
+            ResponseCode::NoError => {}
+            ResponseCode::NXDomain => {
+                let ttl = negative_ttl(&message);
+                return Err(Error::DnsNegative(NegativeAnswer::NxDomain { ttl }).into());
+            }
+            code => return Err(Error::DnsGeneric(format!("server responded with {code}")).into()),
+        }
+
+        if let Some(ttl) = negative_ttl(&message) {
+            no_data_ttl = Some(no_data_ttl.map_or(ttl, |no_data_ttl: u32| no_data_ttl.min(ttl)));
+        }
+
+        // Answers can contain the CNAME records that lead to the addresses as well, which count for the TTL too
+        for record in message.answers() {
+            ttl = Some(ttl.map_or(record.ttl(), |ttl: u32| ttl.min(record.ttl())));
+
+            let ip = match record.data() {
+                Some(RData::A(a)) => IpAddr::V4(a.0),
+                Some(RData::AAAA(aaaa)) => IpAddr::V6(aaaa.0),
+                _ => continue,
+            };
+
+            trace!("{domain}: found address {ip} with ttl {}", record.ttl());
+            if !ips.contains(&ip) {
+                ips.push(ip);
+            }
+        }
+    }
+
+    if ips.is_empty() {
+        return Err(Error::DnsNegative(NegativeAnswer::NoData { ttl: no_data_ttl }).into());
+    }
+
+    Ok(DnsEntry::with_ttl(domain, ips, ttl.unwrap_or_default()))
+}
+
+/// Sends the queries to each server in turn, until one of them answers, and returns the entry from its responses.
+/// The list of servers is tried the given number of times. A domain that does not exist is not tried again.
+pub(crate) fn resolve_with<S: Display>(
+    domain: &str,
+    queries: &[Vec<u8>],
+    servers: &[S],
+    attempts: usize,
+    exchange: impl Fn(&S, &[Vec<u8>]) -> Result<Vec<Vec<u8>>>,
+) -> Result<DnsEntry> {
+    let mut last_error = Error::DnsNoIpAddressFound.into();
+
+    for _ in 0..attempts.max(1) {
+        for server in servers {
+            match exchange(server, queries) {
+                Ok(responses) => return entry_from_responses(domain, &responses),
+                Err(e) => {
+                    warn!("{domain}: lookup with {server} failed: {e}");
+                    last_error = e;
+                }
+            }
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Answers queries like a recursive resolver would, for the stand-in servers in the tests. The domain
+/// `example.test` has an IPv4 and an IPv6 address, `ipv4.test` only has an IPv4 address, and every other domain
+/// does not exist. Negative answers may be cached for 30 seconds.
+#[cfg(test)]
+pub(crate) fn test_answer(data: &[u8]) -> Vec<u8> {
+    use hickory_proto::rr::rdata::{A, AAAA, SOA};
+    use hickory_proto::rr::Record;
+
+    let query = Message::from_vec(data).unwrap();
+    let question = query.queries()[0].clone();
+
+    let mut response = Message::new();
+    response
+        .set_id(query.id())
+        .set_message_type(MessageType::Response)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(true)
+        .set_recursion_available(true)
+        .add_query(question.clone());
+
+    let zone = Name::from_ascii("test.").unwrap();
+    let soa = SOA::new(zone.clone(), zone.clone(), 1, 3600, 600, 86400, 30);
+    let soa = Record::from_rdata(zone, 3600, RData::SOA(soa));
+
+    let name = question.name().clone();
+    match (name.to_ascii().as_str(), question.query_type()) {
+        ("example.test." | "ipv4.test.", RecordType::A) => {
+            response.add_answer(Record::from_rdata(name, 300, RData::A(A::new(192, 0, 2, 1))))
+        }
+        ("example.test.", RecordType::AAAA) => response.add_answer(Record::from_rdata(
+            name,
+            60,
+            RData::AAAA(AAAA::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+        )),
+        ("example.test." | "ipv4.test.", _) => response.add_name_server(soa),
+        _ => response.set_response_code(ResponseCode::NXDomain).add_name_server(soa),
+    };
+
+    response.to_vec().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_and_response() {
+        let a = query("example.test", RecordType::A, 7).unwrap();
+        assert_eq!(message_id(&a), Some(7));
+        let aaaa = query("example.test", RecordType::AAAA, 8).unwrap();
+
+        let entry = entry_from_responses("example.test", &[test_answer(&a), test_answer(&aaaa)]).unwrap();
+        assert_eq!(entry.ipv4(), vec!["192.0.2.1".parse::<IpAddr>().unwrap()]);
+        assert_eq!(entry.ipv6(), vec!["2001:db8::1".parse::<IpAddr>().unwrap()]);
+        // The AAAA record has the shortest TTL
+        assert!(!entry.expired());
+        assert!(entry.expires <= DnsEntry::with_ttl("", vec![], 60).expires);
+
+        let missing = query("missing.test", RecordType::A, 9).unwrap();
+        let err = entry_from_responses("missing.test", &[test_answer(&missing)]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::DnsNegative(NegativeAnswer::NxDomain { ttl: Some(30) }))
+        ));
+
+        // The AAAA query for a domain with only an IPv4 address has no answers
+        let a = query("ipv4.test", RecordType::A, 10).unwrap();
+        let aaaa = query("ipv4.test", RecordType::AAAA, 11).unwrap();
+        let entry = entry_from_responses("ipv4.test", &[test_answer(&a), test_answer(&aaaa)]).unwrap();
+        assert_eq!(entry.ips.len(), 1);
+        let err = entry_from_responses("ipv4.test", &[test_answer(&aaaa)]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::DnsNegative(NegativeAnswer::NoData { ttl: Some(30) }))
+        ));
+
+        // A query is not an answer
+        assert!(entry_from_responses("ipv4.test", &[a]).is_err());
+        assert!(entry_from_responses("example.test", &[vec![1, 2, 3]]).is_err());
+    }
+}
@@ -1,70 +1,63 @@
-use crate::dns::{DnsEntry, DnsResolver, ResolveType};
+use crate::dns::{DnsEntry, DnsResolver, NegativeAnswer, ResolveType};
 use crate::errors::Error;
 use core::str::FromStr;
 use gosub_shared::types::Result;
 use hickory_resolver::config::Protocol::Udp;
-use hickory_resolver::config::{NameServerConfig, ResolverConfig, ResolverOpts};
+use hickory_resolver::config::{LookupIpStrategy, NameServerConfig, ResolverConfig, ResolverOpts};
+use hickory_resolver::error::{ResolveError, ResolveErrorKind};
+use hickory_resolver::proto::op::ResponseCode;
+use hickory_resolver::system_conf::read_system_conf;
 use hickory_resolver::Resolver;
 use log::trace;
 use std::net::{IpAddr, SocketAddr};
+use std::time::Instant;
 
 pub struct RemoteResolver {
     hickory: Resolver,
 }
 
 impl DnsResolver for RemoteResolver {
-    fn resolve(&mut self, domain: &str, resolve_type: ResolveType) -> Result<DnsEntry> {
-        let mut entry = DnsEntry::new(domain, vec![]);
+    fn resolve(&self, domain: &str, resolve_type: ResolveType) -> Result<DnsEntry> {
+        trace!("{domain}: resolving with {resolve_type:?}");
 
-        let mut ip_types = Vec::new();
-        match resolve_type {
-            ResolveType::Ipv4 => {
-                ip_types.push(ResolveType::Ipv4);
-            }
-            ResolveType::Ipv6 => {
-                ip_types.push(ResolveType::Ipv6);
-            }
-            ResolveType::Both => {
-                ip_types.push(ResolveType::Ipv6);
-                ip_types.push(ResolveType::Ipv4);
-            }
-        }
+        let lookup = match resolve_type {
+            ResolveType::Ipv4 => self.hickory.ipv4_lookup(domain).map(|lookup| {
+                let ips = lookup.iter().map(|ip| IpAddr::V4(ip.0)).collect::<Vec<_>>();
+                (ips, lookup.valid_until())
+            }),
+            ResolveType::Ipv6 => self.hickory.ipv6_lookup(domain).map(|lookup| {
+                let ips = lookup.iter().map(|ip| IpAddr::V6(ip.0)).collect::<Vec<_>>();
+                (ips, lookup.valid_until())
+            }),
+            // The A and AAAA queries are sent in parallel, as recommended by RFC 8305
+            ResolveType::Both => self
+                .hickory
+                .lookup_ip(domain)
+                .map(|lookup| (lookup.iter().collect::<Vec<_>>(), lookup.valid_until())),
+        };
 
-        trace!("{domain}: resolving with {ip_types:?}");
-
-        for ip_type in &ip_types {
-            match *ip_type {
-                ResolveType::Ipv4 => {
-                    let e = self.hickory.ipv4_lookup(domain);
-                    if e.is_err() {
-                        continue;
-                    }
-                    e.unwrap().iter().for_each(|ip| {
-                        trace!("{domain}: found ipv4 address {ip}");
-                        entry.ips.push(IpAddr::from_str(ip.to_string().as_str()).unwrap());
-                        entry.has_ipv4 = true;
-                    });
-                }
-                ResolveType::Ipv6 => {
-                    let e = self.hickory.ipv6_lookup(domain);
-                    if e.is_err() {
-                        continue;
-                    }
-                    e.unwrap().iter().for_each(|ip| {
-                        trace!("{domain}: found ipv6 address {ip}");
-                        entry.ips.push(IpAddr::from_str(ip.to_string().as_str()).unwrap());
-                        entry.has_ipv6 = true;
-                    });
+        let (ips, valid_until) = match lookup {
+            Ok(lookup) => lookup,
+            Err(e) => {
+                trace!("{domain}: lookup failed: {e}");
+                return Err(match negative_answer(&e) {
+                    Some(answer) => Error::DnsNegative(answer),
+                    None => Error::DnsGeneric(e.to_string()),
                 }
-                ResolveType::Both => {}
+                .into());
             }
-        }
+        };
 
-        if !entry.has_ipv4 && !entry.has_ipv6 {
+        for ip in &ips {
+            trace!("{domain}: found address {ip}");
+        }
+        if ips.is_empty() {
             return Err(Error::DnsNoIpAddressFound.into());
         }
 
-        Ok(entry)
+        // The lookup is valid until the first of its records expires
+        let ttl = valid_until.saturating_duration_since(Instant::now()).as_secs();
+        Ok(DnsEntry::with_ttl(domain, ips, u32::try_from(ttl).unwrap_or(u32::MAX)))
     }
 
     fn name(&self) -> &'static str {
@@ -73,10 +66,9 @@ impl DnsResolver for RemoteResolver {
 }
 
 impl RemoteResolver {
-    /// Instantiates a new local override table
+    /// Instantiates a new remote resolver. When no nameservers are given, the nameservers of the system are used.
     pub fn new(dns_opts: RemoteResolverOptions) -> Self {
-        // @todo: do something with the options
-        let mut config = ResolverConfig::default();
+        let mut config = ResolverConfig::new();
         let mut opts = ResolverOpts::default();
 
         for nameserver in &dns_opts.nameservers {
@@ -85,9 +77,19 @@ impl RemoteResolver {
                 continue;
             }
         }
+        if config.name_servers().is_empty() {
+            config = match read_system_conf() {
+                Ok((system_config, _)) => system_config,
+                Err(_) => ResolverConfig::default(),
+            };
+        }
+
         opts.use_hosts_file = dns_opts.use_hosts_file;
         opts.timeout = std::time::Duration::from_secs(dns_opts.timeout as u64);
         opts.attempts = dns_opts.retries;
+        opts.ip_strategy = LookupIpStrategy::Ipv4AndIpv6;
+        // Lookups are cached by the cache resolver, where they can be flushed
+        opts.cache_size = 0;
 
         Self {
             hickory: Resolver::new(config, opts).unwrap(),
@@ -95,6 +97,22 @@ impl RemoteResolver {
     }
 }
 
+/// Returns the negative answer of the DNS server when the lookup failed because there are no records
+fn negative_answer(error: &ResolveError) -> Option<NegativeAnswer> {
+    match error.kind() {
+        ResolveErrorKind::NoRecordsFound {
+            response_code,
+            negative_ttl,
+            ..
+        } => Some(if *response_code == ResponseCode::NXDomain {
+            NegativeAnswer::NxDomain { ttl: *negative_ttl }
+        } else {
+            NegativeAnswer::NoData { ttl: *negative_ttl }
+        }),
+        _ => None,
+    }
+}
+
 /// Options for the remote resolver
 pub struct RemoteResolverOptions {
     pub timeout: usize,
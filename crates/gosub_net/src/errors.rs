@@ -25,6 +25,12 @@ pub enum Error {
     #[error("dns: domain not found")]
     DnsDomainNotFound,
 
+    #[error("dns: {0}")]
+    DnsNegative(crate::dns::NegativeAnswer),
+
+    #[error("dns: encrypted DNS is misconfigured: {0}")]
+    DnsMisconfigured(String),
+
     #[error("there was a problem: {0}")]
     Generic(String),
 
@@ -1,9 +1,15 @@
 use crate::http::fetcher::RequestAgent;
 
+pub mod blob;
+pub mod cache;
+pub mod cookies;
+pub mod data_url;
 pub mod fetcher;
 pub mod headers;
+pub mod policy;
 pub mod request;
 mod request_impl;
 pub mod response;
+pub mod transport;
 
 pub type HttpError = <request_impl::RequestImpl as RequestAgent>::Error;
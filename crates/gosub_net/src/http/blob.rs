@@ -0,0 +1,112 @@
+//! `blob:` URLs
+//!
+//! Blob URLs point to data that is held in memory by the browser, for instance a `Blob` or `File` for which a script
+//! called `URL.createObjectURL()`. The data is registered in the blob URL store, and can be fetched through the URL
+//! until it is revoked.
+//!
+//! https://w3c.github.io/FileAPI/#url-model
+use std::collections::HashMap;
+use std::sync::{LazyLock, RwLock};
+
+use url::Url;
+use uuid::Uuid;
+
+use crate::http::response::Response;
+
+/// All blob URLs that are registered and not yet revoked
+static BLOB_STORE: LazyLock<RwLock<HashMap<String, Blob>>> = LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// Data behind a blob URL
+#[derive(Clone, Debug, PartialEq)]
+pub struct Blob {
+    pub data: Vec<u8>,
+    /// MIME type of the data, or an empty string when the type is unknown
+    pub mime_type: String,
+}
+
+impl Blob {
+    pub fn new(data: Vec<u8>, mime_type: &str) -> Self {
+        Self {
+            data,
+            mime_type: mime_type.to_string(),
+        }
+    }
+
+    /// Returns a response with the data of the blob
+    pub fn to_response(&self) -> Response {
+        let mut resp = Response::from(self.data.clone());
+        resp.headers.set("Content-Length", &self.data.len().to_string());
+        if !self.mime_type.is_empty() {
+            resp.headers.set("Content-Type", &self.mime_type);
+        }
+        resp
+    }
+}
+
+/// Registers the blob for a document with the given origin, and returns the new blob URL that points to it
+pub fn register_blob(origin: &Url, blob: Blob) -> Url {
+    let url = format!("blob:{}/{}", origin.origin().ascii_serialization(), Uuid::new_v4());
+    let url = Url::parse(&url).expect("blob URL is always valid");
+
+    BLOB_STORE.write().unwrap().insert(store_key(&url), blob);
+
+    url
+}
+
+/// Removes the blob URL from the store, so it can no longer be fetched
+pub fn revoke_blob(url: &Url) {
+    BLOB_STORE.write().unwrap().remove(&store_key(url));
+}
+
+/// Returns the blob the URL points to, or None when the URL was never registered or has been revoked
+pub fn resolve_blob(url: &Url) -> Option<Blob> {
+    if url.scheme() != "blob" {
+        return None;
+    }
+
+    BLOB_STORE.read().unwrap().get(&store_key(url)).cloned()
+}
+
+/// Blob URLs are stored without their fragment, as the fragment does not point to different data
+fn store_key(url: &Url) -> String {
+    let mut url = url.clone();
+    url.set_fragment(None);
+    url.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_and_revoke() {
+        let origin = Url::parse("https://example.com/page.html").unwrap();
+        let url = register_blob(&origin, Blob::new(b"hello".to_vec(), "text/plain"));
+        assert!(url.as_str().starts_with("blob:https://example.com/"));
+
+        let blob = resolve_blob(&url).unwrap();
+        assert_eq!(blob.data, b"hello");
+
+        let mut with_fragment = url.clone();
+        with_fragment.set_fragment(Some("top"));
+        assert_eq!(resolve_blob(&with_fragment), Some(blob.clone()));
+
+        let resp = blob.to_response();
+        assert_eq!(resp.headers.get("Content-Type"), Some(&"text/plain".to_string()));
+        assert_eq!(resp.headers.get("Content-Length"), Some(&"5".to_string()));
+
+        revoke_blob(&url);
+        assert!(resolve_blob(&url).is_none());
+
+        // Documents with an opaque origin get a "null" origin
+        let url = register_blob(&Url::parse("data:,x").unwrap(), Blob::new(vec![], ""));
+        assert!(url.as_str().starts_with("blob:null/"));
+        assert!(resolve_blob(&url)
+            .unwrap()
+            .to_response()
+            .headers
+            .get("Content-Type")
+            .is_none());
+        revoke_blob(&url);
+    }
+}
@@ -0,0 +1,743 @@
+//! HTTP cache
+//!
+//! This is a private (browser) cache as defined in RFC 9111. Responses to GET requests are stored in memory, and
+//! optionally on disk, and are served for as long as they are fresh. Stale responses are revalidated with a
+//! conditional request (`If-None-Match` / `If-Modified-Since`) so a `304 Not Modified` can reuse the stored body.
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock, Mutex};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use cow_utils::CowUtils;
+use log::{debug, trace, warn};
+
+use gosub_config::{config, config_store};
+
+use crate::http::headers::Headers;
+use crate::http::request::Request;
+use crate::http::response::Response;
+
+mod disk;
+mod memory;
+
+/// The cache that is shared by all fetchers, as configured in the settings
+static SHARED_CACHE: LazyLock<Option<Arc<Mutex<HttpCache>>>> =
+    LazyLock::new(|| HttpCache::from_config().map(|cache| Arc::new(Mutex::new(cache))));
+
+/// Returns the cache that is shared by all fetchers, or None when caching is disabled
+pub fn shared_cache() -> Option<Arc<Mutex<HttpCache>>> {
+    SHARED_CACHE.clone()
+}
+
+/// Status codes that are cacheable by default, and can be given a heuristic freshness lifetime
+const HEURISTICALLY_CACHEABLE: [u16; 11] = [200, 203, 204, 300, 301, 308, 404, 405, 410, 414, 501];
+
+/// Headers of a 304 response that must not replace the headers of the stored response
+const EXCLUDED_UPDATE_HEADERS: [&str; 3] = ["content-length", "content-encoding", "transfer-encoding"];
+
+/// A stored response together with the information needed to calculate its age and match its variant
+#[derive(Clone, Debug, PartialEq)]
+pub struct CacheEntry {
+    /// URL of the request (without fragment)
+    pub url: String,
+    /// The request headers named in the Vary header of the response, with their value in the original request
+    pub vary: Vec<(String, Option<String>)>,
+    /// Time in seconds after epoch the request was sent
+    pub request_time: u64,
+    /// Time in seconds after epoch the response was received
+    pub response_time: u64,
+    pub status: u16,
+    pub status_text: String,
+    pub version: String,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+impl CacheEntry {
+    /// Approximate number of bytes this entry takes
+    pub fn size(&self) -> usize {
+        let headers = self.headers.all().iter().map(|(k, v)| k.len() + v.len()).sum::<usize>();
+        self.url.len() + headers + self.body.len()
+    }
+
+    fn to_response(&self) -> Response {
+        Response {
+            status: self.status,
+            status_text: self.status_text.clone(),
+            version: self.version.clone(),
+            headers: self.headers.clone(),
+            cookies: Default::default(),
+            set_cookies: vec![],
+            body: self.body.clone(),
+            url: None,
+            redirects: vec![],
+        }
+    }
+
+    /// Returns true when the stored variant matches the headers of the given request
+    fn matches(&self, req: &Request) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| req.headers.get_ignore_case(name) == value.as_ref())
+    }
+
+    /// Returns the current age of the response in seconds
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc9111#section-4.2.3
+    fn current_age(&self, now: u64) -> u64 {
+        let date = self
+            .headers
+            .get_ignore_case("date")
+            .and_then(|date| parse_http_date(date))
+            .unwrap_or(self.response_time);
+        let age = self
+            .headers
+            .get_ignore_case("age")
+            .and_then(|age| age.trim().parse::<u64>().ok())
+            .unwrap_or(0);
+
+        let apparent_age = self.response_time.saturating_sub(date);
+        let response_delay = self.response_time.saturating_sub(self.request_time);
+        let corrected_initial_age = apparent_age.max(age + response_delay);
+        let resident_time = now.saturating_sub(self.response_time);
+
+        corrected_initial_age + resident_time
+    }
+
+    /// Returns the freshness lifetime of the response in seconds
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc9111#section-4.2.1
+    fn freshness_lifetime(&self) -> u64 {
+        freshness_lifetime(self.status, &self.headers, self.response_time)
+    }
+
+    /// Returns true when the response may be served without revalidation
+    fn is_fresh(&self, now: u64) -> bool {
+        if CacheControl::from_headers(&self.headers).no_cache {
+            return false;
+        }
+
+        self.freshness_lifetime() > self.current_age(now)
+    }
+
+    /// Returns true when the response has a validator that can be used for a conditional request
+    fn has_validator(&self) -> bool {
+        self.headers.get_ignore_case("etag").is_some() || self.headers.get_ignore_case("last-modified").is_some()
+    }
+}
+
+/// A store that holds cache entries by their URL
+pub trait CacheStore {
+    /// Returns the entry for the given key (if any)
+    fn get(&mut self, key: &str) -> Option<CacheEntry>;
+    /// Stores the entry under the given key, evicting other entries when the store is full
+    fn put(&mut self, key: &str, entry: &CacheEntry);
+    /// Removes the entry for the given key
+    fn remove(&mut self, key: &str);
+    /// Removes all entries from the store
+    fn flush(&mut self);
+}
+
+/// The result of looking up a request in the cache
+#[derive(Debug)]
+pub enum CacheLookup {
+    /// A fresh response that can be used as-is
+    Fresh(Response),
+    /// A stale response was found, and must be revalidated by sending this conditional request
+    Revalidate(Request),
+    /// Nothing usable was found, and the request must be sent to the network
+    Miss,
+}
+
+/// Cache directives of a `Cache-Control` header
+#[derive(Debug, Default, PartialEq)]
+struct CacheControl {
+    no_store: bool,
+    no_cache: bool,
+    max_age: Option<u64>,
+}
+
+impl CacheControl {
+    fn from_headers(headers: &Headers) -> Self {
+        let mut cc = Self::default();
+
+        let Some(value) = headers.get_ignore_case("cache-control") else {
+            return cc;
+        };
+
+        for directive in value.split(',') {
+            let (name, arg) = match directive.split_once('=') {
+                Some((name, arg)) => (name.trim(), Some(arg.trim().trim_matches('"'))),
+                None => (directive.trim(), None),
+            };
+
+            match name.cow_to_ascii_lowercase().as_ref() {
+                "no-store" => cc.no_store = true,
+                "no-cache" => cc.no_cache = true,
+                "max-age" => cc.max_age = arg.and_then(|arg| arg.parse().ok()),
+                _ => {}
+            }
+        }
+
+        cc
+    }
+}
+
+/// HTTP cache with an in-memory store and an optional on-disk store
+#[derive(Debug)]
+pub struct HttpCache {
+    memory: memory::MemoryStore,
+    disk: Option<disk::DiskStore>,
+    /// Responses with a larger body are not stored
+    max_entry_size: usize,
+}
+
+impl HttpCache {
+    /// Creates a new cache with only an in-memory store
+    pub fn new(memory_size: usize, max_entry_size: usize) -> Self {
+        Self {
+            memory: memory::MemoryStore::new(memory_size),
+            disk: None,
+            max_entry_size,
+        }
+    }
+
+    /// Creates a new cache with an in-memory store and an on-disk store in the given directory
+    pub fn with_disk(memory_size: usize, max_entry_size: usize, path: PathBuf, disk_size: usize) -> Self {
+        let disk = match disk::DiskStore::new(path.clone(), disk_size) {
+            Ok(disk) => Some(disk),
+            Err(err) => {
+                warn!("Could not open disk cache at {}: {}", path.display(), err);
+                None
+            }
+        };
+
+        Self {
+            memory: memory::MemoryStore::new(memory_size),
+            disk,
+            max_entry_size,
+        }
+    }
+
+    /// Creates a cache from the `http.cache.*` settings, or None when caching is disabled
+    pub fn from_config() -> Option<Self> {
+        if !config!(bool "http.cache.enabled") {
+            return None;
+        }
+
+        let memory_size = config!(uint "http.cache.memory.max_size");
+        let max_entry_size = config!(uint "http.cache.max_entry_size");
+
+        if !config!(bool "http.cache.disk.enabled") {
+            return Some(Self::new(memory_size, max_entry_size));
+        }
+
+        let path = match config!(string "http.cache.disk.path") {
+            path if path.is_empty() => std::env::temp_dir().join("gosub-http-cache"),
+            path => PathBuf::from(path),
+        };
+
+        Some(Self::with_disk(
+            memory_size,
+            max_entry_size,
+            path,
+            config!(uint "http.cache.disk.max_size"),
+        ))
+    }
+
+    /// Looks up the request in the cache
+    pub fn lookup(&mut self, req: &Request) -> CacheLookup {
+        self.lookup_at(req, now())
+    }
+
+    /// Looks up the request in the cache at the given time (in seconds after epoch)
+    pub fn lookup_at(&mut self, req: &Request, now: u64) -> CacheLookup {
+        if !req.method.eq_ignore_ascii_case("GET") {
+            return CacheLookup::Miss;
+        }
+
+        let request_cc = CacheControl::from_headers(&req.headers);
+        if request_cc.no_store {
+            return CacheLookup::Miss;
+        }
+
+        let key = cache_key(&req.uri);
+        let Some(entry) = self.get(&key) else {
+            trace!("{}: not found in cache", key);
+            return CacheLookup::Miss;
+        };
+
+        if !entry.matches(req) {
+            trace!("{}: cached variant does not match the request", key);
+            return CacheLookup::Miss;
+        }
+
+        let pragma_no_cache = req
+            .headers
+            .get_ignore_case("pragma")
+            .is_some_and(|pragma| pragma.eq_ignore_ascii_case("no-cache"));
+        let must_revalidate = request_cc.no_cache || request_cc.max_age == Some(0) || pragma_no_cache;
+
+        if !must_revalidate && entry.is_fresh(now) {
+            debug!("{}: fresh response found in cache", key);
+            let mut resp = entry.to_response();
+            resp.headers.remove_ignore_case("age");
+            resp.headers.set("age", &entry.current_age(now).to_string());
+            return CacheLookup::Fresh(resp);
+        }
+
+        if !entry.has_validator() {
+            trace!("{}: stale response without validators", key);
+            return CacheLookup::Miss;
+        }
+
+        debug!("{}: stale response found in cache, revalidating", key);
+
+        let mut conditional = req.clone();
+        if let Some(etag) = entry.headers.get_ignore_case("etag") {
+            conditional.headers.set("If-None-Match", etag);
+        }
+        if let Some(last_modified) = entry.headers.get_ignore_case("last-modified") {
+            conditional.headers.set("If-Modified-Since", last_modified);
+        }
+
+        CacheLookup::Revalidate(conditional)
+    }
+
+    /// Handles the response of the network for the given request, which was sent at `request_time`. A `304 Not
+    /// Modified` response is merged with the stored response which is returned instead. Other responses are stored
+    /// when they are cacheable. Successful unsafe requests (like POST) invalidate the stored response of the URL.
+    pub fn update(&mut self, req: &Request, resp: Response, request_time: u64) -> Response {
+        self.update_at(req, resp, request_time, now())
+    }
+
+    /// Handles the response of the network like [`HttpCache::update`], received at the given time
+    pub fn update_at(&mut self, req: &Request, resp: Response, request_time: u64, now: u64) -> Response {
+        let key = cache_key(&req.uri);
+
+        if !req.method.eq_ignore_ascii_case("GET") {
+            // https://www.rfc-editor.org/rfc/rfc9111#section-4.4
+            if !req.method.eq_ignore_ascii_case("HEAD") && resp.status < 400 {
+                trace!("{}: invalidated by {} request", key, req.method);
+                self.remove(&key);
+            }
+            return resp;
+        }
+
+        if resp.status == 304 {
+            if let Some(mut entry) = self.get(&key) {
+                debug!("{}: not modified, updating stored response", key);
+
+                // https://www.rfc-editor.org/rfc/rfc9111#section-3.2
+                for (name, value) in resp.headers.all() {
+                    if EXCLUDED_UPDATE_HEADERS.contains(&name.cow_to_ascii_lowercase().as_ref()) {
+                        continue;
+                    }
+                    entry.headers.remove_ignore_case(name);
+                    entry.headers.set(name, value);
+                }
+                entry.request_time = request_time;
+                entry.response_time = now;
+
+                self.put(&key, &entry);
+                return entry.to_response();
+            }
+
+            return resp;
+        }
+
+        if !self.is_storable(req, &resp, now) {
+            // A response that may not be stored, may not be served from a previous response either
+            self.remove(&key);
+            return resp;
+        }
+
+        let vary = resp
+            .headers
+            .get_ignore_case("vary")
+            .map(|vary| {
+                vary.split(',')
+                    .map(|name| name.trim().cow_to_ascii_lowercase().to_string())
+                    .filter(|name| !name.is_empty())
+                    .map(|name| {
+                        let value = req.headers.get_ignore_case(&name).cloned();
+                        (name, value)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let entry = CacheEntry {
+            url: key.clone(),
+            vary,
+            request_time,
+            response_time: now,
+            status: resp.status,
+            status_text: resp.status_text.clone(),
+            version: resp.version.clone(),
+            headers: resp.headers.clone(),
+            body: resp.body.clone(),
+        };
+
+        debug!("{}: storing response in cache", key);
+        self.put(&key, &entry);
+
+        resp
+    }
+
+    /// Removes all entries from the cache
+    pub fn flush(&mut self) {
+        self.memory.flush();
+        if let Some(disk) = self.disk.as_mut() {
+            disk.flush();
+        }
+    }
+
+    /// Removes the entry for the given URL from the cache
+    pub fn flush_entry(&mut self, url: &str) {
+        self.remove(&cache_key(url));
+    }
+
+    /// Returns true when the response to the request may be stored
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc9111#section-3
+    fn is_storable(&self, req: &Request, resp: &Response, now: u64) -> bool {
+        if resp.body.len() > self.max_entry_size {
+            return false;
+        }
+
+        if CacheControl::from_headers(&req.headers).no_store || CacheControl::from_headers(&resp.headers).no_store {
+            return false;
+        }
+
+        if resp
+            .headers
+            .get_ignore_case("vary")
+            .is_some_and(|vary| vary.trim() == "*")
+        {
+            return false;
+        }
+
+        let explicit = CacheControl::from_headers(&resp.headers).max_age.is_some()
+            || resp.headers.get_ignore_case("expires").is_some();
+        if !explicit && !HEURISTICALLY_CACHEABLE.contains(&resp.status) {
+            return false;
+        }
+
+        // There is no use in storing a response that is never fresh and cannot be revalidated
+        freshness_lifetime(resp.status, &resp.headers, now) > 0
+            || resp.headers.get_ignore_case("etag").is_some()
+            || resp.headers.get_ignore_case("last-modified").is_some()
+    }
+
+    fn get(&mut self, key: &str) -> Option<CacheEntry> {
+        if let Some(entry) = self.memory.get(key) {
+            return Some(entry);
+        }
+
+        let entry = self.disk.as_mut()?.get(key)?;
+        self.memory.put(key, &entry);
+
+        Some(entry)
+    }
+
+    fn put(&mut self, key: &str, entry: &CacheEntry) {
+        self.memory.put(key, entry);
+        if let Some(disk) = self.disk.as_mut() {
+            disk.put(key, entry);
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.memory.remove(key);
+        if let Some(disk) = self.disk.as_mut() {
+            disk.remove(key);
+        }
+    }
+}
+
+/// Returns the freshness lifetime in seconds of a response with the given status and headers. The date is used when
+/// the response has no Date header.
+fn freshness_lifetime(status: u16, headers: &Headers, response_time: u64) -> u64 {
+    if let Some(max_age) = CacheControl::from_headers(headers).max_age {
+        return max_age;
+    }
+
+    let date = headers
+        .get_ignore_case("date")
+        .and_then(|date| parse_http_date(date))
+        .unwrap_or(response_time);
+
+    if let Some(expires) = headers.get_ignore_case("expires") {
+        // An invalid date (like "0") means the response has already expired
+        return parse_http_date(expires).map_or(0, |expires| expires.saturating_sub(date));
+    }
+
+    // https://www.rfc-editor.org/rfc/rfc9111#section-4.2.2
+    if HEURISTICALLY_CACHEABLE.contains(&status) {
+        if let Some(last_modified) = headers
+            .get_ignore_case("last-modified")
+            .and_then(|last_modified| parse_http_date(last_modified))
+        {
+            return date.saturating_sub(last_modified) / 10;
+        }
+    }
+
+    0
+}
+
+/// Returns the key under which the response for the URL is stored, which is the URL without its fragment
+fn cache_key(url: &str) -> String {
+    match url.split_once('#') {
+        Some((url, _)) => url.to_string(),
+        None => url.to_string(),
+    }
+}
+
+/// Returns the current time in seconds after epoch
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Returns the current time in seconds after epoch. The system time is not available in the browser.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn now() -> u64 {
+    (js_sys::Date::now() / 1000.0) as u64
+}
+
+/// Parses an HTTP date in any of the three formats HTTP allows, and returns it as seconds after epoch:
+///
+///   Sun, 06 Nov 1994 08:49:37 GMT    (IMF-fixdate)
+///   Sunday, 06-Nov-94 08:49:37 GMT   (obsolete RFC 850 format)
+///   Sun Nov  6 08:49:37 1994         (ANSI C's asctime() format)
+///
+/// https://www.rfc-editor.org/rfc/rfc9110#section-5.6.7
+pub fn parse_http_date(value: &str) -> Option<u64> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+
+    let tokens = value
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '-' || c == ':')
+        .filter(|t| !t.is_empty())
+        .collect::<Vec<_>>();
+
+    let month_idx = tokens.iter().position(|t| {
+        let t = t.cow_to_ascii_lowercase();
+        MONTHS.contains(&t.as_ref())
+    })?;
+    let month = MONTHS.iter().position(|m| tokens[month_idx].eq_ignore_ascii_case(m))? as u64 + 1;
+
+    let numbers = tokens.iter().filter_map(|t| t.parse::<u64>().ok()).collect::<Vec<_>>();
+    if numbers.len() != 5 {
+        return None;
+    }
+
+    // In the asctime format the month comes before the day, and the year is last
+    let day_first = tokens[..month_idx].iter().any(|t| t.parse::<u64>().is_ok());
+    let (day, mut year, hour, minute, second) = if day_first {
+        (numbers[0], numbers[1], numbers[2], numbers[3], numbers[4])
+    } else {
+        (numbers[0], numbers[4], numbers[1], numbers[2], numbers[3])
+    };
+
+    // Two digit years of the RFC 850 format
+    if year < 100 {
+        year += if year < 70 { 2000 } else { 1900 };
+    }
+
+    if !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 || year < 1970 {
+        return None;
+    }
+
+    // Days since epoch from the civil date
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * m + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    Some(days * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn response(status: u16, headers: &[(&str, &str)], body: &str) -> Response {
+        let mut resp = Response::new();
+        resp.status = status;
+        for (key, value) in headers {
+            resp.headers.set(key, value);
+        }
+        resp.body = body.as_bytes().to_vec();
+        resp
+    }
+
+    fn get(url: &str) -> Request {
+        Request::new("GET", url, "HTTP/1.1")
+    }
+
+    #[test]
+    fn http_dates() {
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(784111777));
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), Some(784111777));
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), Some(784111777));
+        assert_eq!(parse_http_date("Tue, 14 Nov 2023 22:13:20 GMT"), Some(NOW));
+        assert_eq!(parse_http_date("0"), None);
+        assert_eq!(parse_http_date("yesterday"), None);
+    }
+
+    #[test]
+    fn freshness() {
+        let mut cache = HttpCache::new(1024, 1024);
+        let url = "http://example.com/a#fragment";
+
+        let resp = response(200, &[("cache-control", "max-age=60")], "a");
+        cache.update_at(&get(url), resp, NOW, NOW);
+
+        match cache.lookup_at(&get("http://example.com/a"), NOW + 59) {
+            CacheLookup::Fresh(resp) => {
+                assert_eq!(resp.body, b"a");
+                assert_eq!(resp.headers.get("age").unwrap(), "59");
+            }
+            lookup => panic!("expected fresh response, got {:?}", lookup),
+        }
+
+        // Stale without validators, so it needs a full request
+        assert!(matches!(cache.lookup_at(&get(url), NOW + 60), CacheLookup::Miss));
+
+        // Expires relative to Date, and the Age the response already had
+        let resp = response(
+            200,
+            &[
+                ("date", "Tue, 14 Nov 2023 22:13:20 GMT"),
+                ("expires", "Tue, 14 Nov 2023 22:14:20 GMT"),
+                ("age", "30"),
+            ],
+            "b",
+        );
+        cache.update_at(&get("http://example.com/b"), resp, NOW, NOW);
+        assert!(matches!(
+            cache.lookup_at(&get("http://example.com/b"), NOW + 29),
+            CacheLookup::Fresh(_)
+        ));
+        assert!(matches!(
+            cache.lookup_at(&get("http://example.com/b"), NOW + 30),
+            CacheLookup::Miss
+        ));
+
+        // Heuristic freshness is 10% of the time since the last modification
+        let resp = response(200, &[("last-modified", "Tue, 14 Nov 2023 22:03:20 GMT")], "c");
+        cache.update_at(&get("http://example.com/c"), resp, NOW, NOW);
+        assert!(matches!(
+            cache.lookup_at(&get("http://example.com/c"), NOW + 59),
+            CacheLookup::Fresh(_)
+        ));
+        assert!(matches!(
+            cache.lookup_at(&get("http://example.com/c"), NOW + 60),
+            CacheLookup::Revalidate(_)
+        ));
+    }
+
+    #[test]
+    fn storability() {
+        let mut cache = HttpCache::new(1024, 4);
+        let url = "http://example.com/";
+
+        for headers in [
+            vec![("cache-control", "no-store, max-age=60")],
+            vec![("cache-control", "max-age=60"), ("vary", "*")],
+            vec![("etag", "\"1\"")],
+        ] {
+            let status = if headers[0].0 == "etag" { 500 } else { 200 };
+            cache.update_at(&get(url), response(status, &headers, "a"), NOW, NOW);
+            assert!(matches!(cache.lookup_at(&get(url), NOW), CacheLookup::Miss));
+        }
+
+        // Too large
+        let resp = response(200, &[("cache-control", "max-age=60")], "large");
+        cache.update_at(&get(url), resp, NOW, NOW);
+        assert!(matches!(cache.lookup_at(&get(url), NOW), CacheLookup::Miss));
+
+        // Request that does not want a cached response
+        let resp = response(200, &[("cache-control", "max-age=60")], "a");
+        cache.update_at(&get(url), resp, NOW, NOW);
+        let mut req = get(url);
+        req.add_header("Cache-Control", "no-store");
+        assert!(matches!(cache.lookup_at(&req, NOW), CacheLookup::Miss));
+        assert!(matches!(cache.lookup_at(&get(url), NOW), CacheLookup::Fresh(_)));
+
+        // Unsafe methods invalidate the stored response
+        let post = Request::new("POST", url, "HTTP/1.1");
+        cache.update_at(&post, response(201, &[], ""), NOW, NOW);
+        assert!(matches!(cache.lookup_at(&get(url), NOW), CacheLookup::Miss));
+    }
+
+    #[test]
+    fn revalidation() {
+        let mut cache = HttpCache::new(1024, 1024);
+        let url = "http://example.com/";
+
+        let resp = response(
+            200,
+            &[
+                ("cache-control", "no-cache"),
+                ("etag", "\"v1\""),
+                ("last-modified", "Tue, 14 Nov 2023 22:03:20 GMT"),
+                ("x-version", "1"),
+            ],
+            "body",
+        );
+        cache.update_at(&get(url), resp, NOW, NOW);
+
+        let CacheLookup::Revalidate(conditional) = cache.lookup_at(&get(url), NOW) else {
+            panic!("expected revalidation");
+        };
+        assert_eq!(conditional.headers.get("If-None-Match").unwrap(), "\"v1\"");
+        assert_eq!(
+            conditional.headers.get("If-Modified-Since").unwrap(),
+            "Tue, 14 Nov 2023 22:03:20 GMT"
+        );
+
+        let not_modified = response(304, &[("x-version", "2"), ("content-length", "0")], "");
+        let resp = cache.update_at(&get(url), not_modified, NOW + 10, NOW + 10);
+        assert_eq!(resp.status, 200);
+        assert_eq!(resp.body, b"body");
+        assert_eq!(resp.headers.get_ignore_case("x-version").unwrap(), "2");
+        assert_eq!(resp.headers.get_ignore_case("content-length"), None);
+    }
+
+    #[test]
+    fn vary() {
+        let mut cache = HttpCache::new(1024, 1024);
+        let url = "http://example.com/";
+
+        let mut req = get(url);
+        req.add_header("Accept-Language", "nl");
+        let resp = response(
+            200,
+            &[("cache-control", "max-age=60"), ("vary", "accept-language")],
+            "hallo",
+        );
+        cache.update_at(&req, resp, NOW, NOW);
+
+        assert!(matches!(cache.lookup_at(&req, NOW), CacheLookup::Fresh(_)));
+        assert!(matches!(cache.lookup_at(&get(url), NOW), CacheLookup::Miss));
+
+        let mut other = get(url);
+        other.add_header("accept-language", "en");
+        assert!(matches!(cache.lookup_at(&other, NOW), CacheLookup::Miss));
+    }
+}
@@ -0,0 +1,305 @@
+use crate::http::cache::{CacheEntry, CacheStore};
+use crate::http::headers::Headers;
+use gosub_shared::types::Result;
+use log::{trace, warn};
+use std::collections::{HashMap, VecDeque};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::PathBuf;
+
+/// Extension of the files in the cache directory
+const EXTENSION: &str = "cache";
+
+/// On-disk store that keeps every entry in its own file in the cache directory. When the total size of the files
+/// exceeds the maximum size, the least recently used files are removed. Files that are already in the directory
+/// are picked up when the store is opened, oldest first.
+///
+/// Each file holds the metadata of the entry as `name value` lines, followed by an empty line and the body.
+#[derive(Debug)]
+pub(crate) struct DiskStore {
+    path: PathBuf,
+    /// Size of each file in the store by its file name
+    files: HashMap<String, usize>,
+    lru: VecDeque<String>,
+    size: usize,
+    max_size: usize,
+}
+
+impl DiskStore {
+    pub(crate) fn new(path: PathBuf, max_size: usize) -> Result<Self> {
+        fs::create_dir_all(&path)?;
+
+        let mut found = Vec::new();
+        for dir_entry in fs::read_dir(&path)? {
+            let dir_entry = dir_entry?;
+            let file_path = dir_entry.path();
+            if file_path.extension() != Some(OsStr::new(EXTENSION)) {
+                continue;
+            }
+
+            let metadata = dir_entry.metadata()?;
+            let modified = metadata.modified().ok();
+            found.push((
+                dir_entry.file_name().to_string_lossy().to_string(),
+                metadata.len() as usize,
+                modified,
+            ));
+        }
+        found.sort_by_key(|(_, _, modified)| *modified);
+
+        let mut store = Self {
+            path,
+            files: HashMap::new(),
+            lru: VecDeque::new(),
+            size: 0,
+            max_size,
+        };
+
+        for (name, size, _) in found {
+            store.size += size;
+            store.files.insert(name.clone(), size);
+            store.lru.push_back(name);
+        }
+        store.evict(0);
+
+        Ok(store)
+    }
+
+    /// Removes the least recently used files until there is room for the given number of bytes
+    fn evict(&mut self, needed: usize) {
+        while self.size + needed > self.max_size {
+            let Some(name) = self.lru.pop_front() else {
+                break;
+            };
+            trace!("{}: evicted from disk cache", name);
+            self.remove_file(&name);
+        }
+    }
+
+    fn remove_file(&mut self, name: &str) {
+        if let Some(size) = self.files.remove(name) {
+            self.size -= size;
+        }
+        self.lru.retain(|x| x != name);
+
+        if let Err(err) = fs::remove_file(self.path.join(name)) {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                warn!("Could not remove cache file {}: {}", name, err);
+            }
+        }
+    }
+
+    fn touch(&mut self, name: &str) {
+        self.lru.retain(|x| x != name);
+        self.lru.push_back(name.to_string());
+    }
+}
+
+impl CacheStore for DiskStore {
+    fn get(&mut self, key: &str) -> Option<CacheEntry> {
+        let name = file_name(key);
+        if !self.files.contains_key(&name) {
+            return None;
+        }
+
+        let entry = match fs::read(self.path.join(&name)) {
+            Ok(data) => decode(&data),
+            Err(err) => {
+                warn!("Could not read cache file {}: {}", name, err);
+                None
+            }
+        };
+
+        match entry {
+            // Different urls can end up in the same file
+            Some(entry) if entry.url == key => {
+                self.touch(&name);
+                Some(entry)
+            }
+            Some(_) => None,
+            None => {
+                self.remove_file(&name);
+                None
+            }
+        }
+    }
+
+    fn put(&mut self, key: &str, entry: &CacheEntry) {
+        let data = encode(entry);
+        if data.len() > self.max_size {
+            return;
+        }
+
+        let name = file_name(key);
+        self.remove_file(&name);
+        self.evict(data.len());
+
+        // Write to a temporary file first, so a crash never leaves a partial entry behind
+        let tmp_path = self.path.join(format!("{}.tmp", name));
+        if let Err(err) = fs::write(&tmp_path, &data).and_then(|_| fs::rename(&tmp_path, self.path.join(&name))) {
+            warn!("Could not write cache file {}: {}", name, err);
+            _ = fs::remove_file(tmp_path);
+            return;
+        }
+
+        self.size += data.len();
+        self.files.insert(name.clone(), data.len());
+        self.lru.push_back(name);
+    }
+
+    fn remove(&mut self, key: &str) {
+        let name = file_name(key);
+        if self.files.contains_key(&name) {
+            self.remove_file(&name);
+        }
+    }
+
+    fn flush(&mut self) {
+        for name in self.files.keys().cloned().collect::<Vec<_>>() {
+            self.remove_file(&name);
+        }
+    }
+}
+
+/// Returns the name of the file for the given key, which is the FNV-1a hash of the key
+fn file_name(key: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in key.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    format!("{:016x}.{}", hash, EXTENSION)
+}
+
+fn encode(entry: &CacheEntry) -> Vec<u8> {
+    let mut meta = String::new();
+    meta.push_str(&format!("url {}\n", entry.url));
+    meta.push_str(&format!("request-time {}\n", entry.request_time));
+    meta.push_str(&format!("response-time {}\n", entry.response_time));
+    meta.push_str(&format!("status {} {}\n", entry.status, entry.status_text));
+    meta.push_str(&format!("version {}\n", entry.version));
+    for (name, value) in &entry.vary {
+        match value {
+            Some(value) => meta.push_str(&format!("vary {}: {}\n", name, value)),
+            None => meta.push_str(&format!("vary {}\n", name)),
+        }
+    }
+    for (name, value) in entry.headers.sorted() {
+        meta.push_str(&format!("header {}: {}\n", name, value));
+    }
+    meta.push('\n');
+
+    let mut data = meta.into_bytes();
+    data.extend_from_slice(&entry.body);
+    data
+}
+
+fn decode(data: &[u8]) -> Option<CacheEntry> {
+    let split = data.windows(2).position(|w| w == b"\n\n")?;
+    let meta = std::str::from_utf8(&data[..split]).ok()?;
+
+    let mut entry = CacheEntry {
+        url: String::new(),
+        vary: Vec::new(),
+        request_time: 0,
+        response_time: 0,
+        status: 0,
+        status_text: String::new(),
+        version: String::new(),
+        headers: Headers::new(),
+        body: data[split + 2..].to_vec(),
+    };
+
+    for line in meta.lines() {
+        let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+        match name {
+            "url" => entry.url = value.to_string(),
+            "request-time" => entry.request_time = value.parse().ok()?,
+            "response-time" => entry.response_time = value.parse().ok()?,
+            "status" => {
+                let (status, text) = value.split_once(' ').unwrap_or((value, ""));
+                entry.status = status.parse().ok()?;
+                entry.status_text = text.to_string();
+            }
+            "version" => entry.version = value.to_string(),
+            "vary" => match value.split_once(": ") {
+                Some((name, value)) => entry.vary.push((name.to_string(), Some(value.to_string()))),
+                None => entry.vary.push((value.to_string(), None)),
+            },
+            "header" => {
+                let (name, value) = value.split_once(": ")?;
+                entry.headers.set(name, value);
+            }
+            _ => return None,
+        }
+    }
+
+    if entry.url.is_empty() {
+        return None;
+    }
+
+    Some(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(url: &str, body: &str) -> CacheEntry {
+        let mut headers = Headers::new();
+        headers.set("Content-Type", "text/plain");
+        headers.set("ETag", "\"1\"");
+
+        CacheEntry {
+            url: url.to_string(),
+            vary: vec![
+                ("accept-language".to_string(), Some("nl".to_string())),
+                ("accept".to_string(), None),
+            ],
+            request_time: 1,
+            response_time: 2,
+            status: 200,
+            status_text: "OK".to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers,
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = std::env::temp_dir().join(format!("gosub-disk-cache-{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+
+        let a = entry("http://example.com/a", "body\n\nwith empty lines");
+        let b = entry("http://example.com/b", "b");
+
+        let mut store = DiskStore::new(dir.clone(), 4096).unwrap();
+        store.put(&a.url, &a);
+        store.put(&b.url, &b);
+        assert_eq!(store.get(&a.url), Some(a.clone()));
+        assert_eq!(store.get("http://example.com/c"), None);
+
+        // Entries survive reopening the store
+        let mut store = DiskStore::new(dir.clone(), 4096).unwrap();
+        assert_eq!(store.get(&a.url), Some(a.clone()));
+        assert_eq!(store.get(&b.url), Some(b.clone()));
+
+        store.remove(&b.url);
+        assert_eq!(store.get(&b.url), None);
+
+        // Only room for a single entry, so the least recently used entry is evicted
+        let size = encode(&a).len();
+        let mut store = DiskStore::new(dir.clone(), size + 10).unwrap();
+        assert_eq!(store.get(&a.url), Some(a.clone()));
+        store.put(&b.url, &b);
+        assert_eq!(store.get(&a.url), None);
+        assert_eq!(store.get(&b.url), Some(b));
+
+        store.flush();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
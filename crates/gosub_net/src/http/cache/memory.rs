@@ -0,0 +1,123 @@
+use crate::http::cache::{CacheEntry, CacheStore};
+use log::trace;
+use std::collections::{HashMap, VecDeque};
+
+/// In-memory store that evicts the least recently used entries when the total size of the entries exceeds the
+/// maximum size.
+#[derive(Debug)]
+pub(crate) struct MemoryStore {
+    values: HashMap<String, CacheEntry>,
+    lru: VecDeque<String>,
+    size: usize,
+    max_size: usize,
+}
+
+impl MemoryStore {
+    pub(crate) fn new(max_size: usize) -> Self {
+        Self {
+            values: HashMap::new(),
+            lru: VecDeque::new(),
+            size: 0,
+            max_size,
+        }
+    }
+
+    fn touch(&mut self, key: &str) {
+        self.lru.retain(|x| x != key);
+        self.lru.push_back(key.to_string());
+    }
+}
+
+impl CacheStore for MemoryStore {
+    fn get(&mut self, key: &str) -> Option<CacheEntry> {
+        let entry = self.values.get(key)?.clone();
+        self.touch(key);
+
+        Some(entry)
+    }
+
+    fn put(&mut self, key: &str, entry: &CacheEntry) {
+        if entry.size() > self.max_size {
+            return;
+        }
+
+        self.remove(key);
+
+        // Clear out until the new entry fits
+        while self.size + entry.size() > self.max_size {
+            let Some(lru_key) = self.lru.pop_front() else {
+                break;
+            };
+            if let Some(evicted) = self.values.remove(&lru_key) {
+                trace!("{}: evicted from memory cache", lru_key);
+                self.size -= evicted.size();
+            }
+        }
+
+        self.size += entry.size();
+        self.values.insert(key.to_string(), entry.clone());
+        self.touch(key);
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.values.remove(key) {
+            self.size -= entry.size();
+            self.lru.retain(|x| x != key);
+        }
+    }
+
+    fn flush(&mut self) {
+        self.values.clear();
+        self.lru.clear();
+        self.size = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::headers::Headers;
+
+    fn entry(url: &str, body: &str) -> CacheEntry {
+        CacheEntry {
+            url: url.to_string(),
+            vary: vec![],
+            request_time: 0,
+            response_time: 0,
+            status: 200,
+            status_text: "OK".to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers: Headers::new(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn eviction() {
+        // Each entry is 1 byte of url and 4 bytes of body
+        let mut store = MemoryStore::new(10);
+
+        store.put("a", &entry("a", "aaaa"));
+        store.put("b", &entry("b", "bbbb"));
+        assert!(store.get("a").is_some());
+
+        // "b" is the least recently used entry
+        store.put("c", &entry("c", "cccc"));
+        assert!(store.get("a").is_some());
+        assert!(store.get("b").is_none());
+        assert!(store.get("c").is_some());
+
+        // Replacing an entry does not count twice
+        store.put("c", &entry("c", "cccc"));
+        assert!(store.get("a").is_some());
+
+        // Too large to store at all
+        store.put("d", &entry("d", "dddddddddddd"));
+        assert!(store.get("d").is_none());
+        assert!(store.get("a").is_some());
+
+        store.flush();
+        assert!(store.get("a").is_none());
+        assert_eq!(store.size, 0);
+    }
+}
@@ -0,0 +1,807 @@
+//! Cookie store
+//!
+//! Cookies that are set by servers through the `Set-Cookie` header are stored here, and are sent back with the
+//! requests they apply to. Storing and retrieving cookies follows the storage model of RFC 6265bis. Persistent cookies
+//! are saved through a storage adapter of `gosub_config` (sqlite or json), so they survive a restart.
+//!
+//! https://httpwg.org/http-extensions/draft-ietf-httpbis-rfc6265bis.html
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, LazyLock, Mutex};
+
+use cookie::{Cookie, SameSite};
+use cow_utils::CowUtils;
+use log::{debug, trace, warn};
+use url::{Host, Url};
+
+use gosub_config::settings::Setting;
+use gosub_config::storage::JsonStorageAdapter;
+#[cfg(not(target_arch = "wasm32"))]
+use gosub_config::storage::SqliteStorageAdapter;
+use gosub_config::{config, config_store, StorageAdapter};
+
+use crate::http::cache::now;
+use crate::http::response::Response;
+
+pub use public_suffix::PublicSuffixList;
+
+mod public_suffix;
+
+/// Cookies may not expire later than 400 days after they are set
+const MAX_AGE_LIMIT: u64 = 400 * 24 * 60 * 60;
+
+/// The combined length of the name and value of a cookie may not exceed this number of bytes
+const MAX_NAME_VALUE_SIZE: usize = 4096;
+
+/// Attributes (like path or domain) may not exceed this number of bytes
+const MAX_ATTRIBUTE_SIZE: usize = 1024;
+
+/// Prefix of the keys under which cookies are persisted in the storage
+const STORAGE_PREFIX: &str = "cookie";
+
+/// The cookie store that is shared by all fetchers, as configured in the settings
+static SHARED_COOKIE_STORE: LazyLock<Option<Arc<Mutex<CookieStore>>>> =
+    LazyLock::new(|| CookieStore::from_config().map(|store| Arc::new(Mutex::new(store))));
+
+/// Returns the cookie store that is shared by all fetchers, or None when cookies are disabled
+pub fn shared_cookie_store() -> Option<Arc<Mutex<CookieStore>>> {
+    SHARED_COOKIE_STORE.clone()
+}
+
+/// A cookie in the store
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoredCookie {
+    pub name: String,
+    pub value: String,
+    /// Domain the cookie applies to, without leading dot
+    pub domain: String,
+    pub path: String,
+    /// Time in seconds after epoch the cookie expires, or None for a session cookie
+    pub expiry: Option<u64>,
+    /// Time in seconds after epoch the cookie was first set
+    pub creation_time: u64,
+    /// Time in seconds after epoch the cookie was last sent with a request
+    pub last_access_time: u64,
+    /// The cookie applies to the domain only, and not to its subdomains
+    pub host_only: bool,
+    /// The cookie is only sent over secure connections
+    pub secure: bool,
+    /// The cookie is not available to scripts
+    pub http_only: bool,
+    /// The SameSite attribute, or None when it was not set
+    pub same_site: Option<SameSite>,
+}
+
+impl StoredCookie {
+    fn is_expired(&self, now: u64) -> bool {
+        self.expiry.is_some_and(|expiry| expiry <= now)
+    }
+
+    /// Returns true when both cookies have the same name, domain, host-only flag and path, so one replaces the other
+    fn same_identity(&self, other: &StoredCookie) -> bool {
+        self.name == other.name
+            && self.domain == other.domain
+            && self.host_only == other.host_only
+            && self.path == other.path
+    }
+
+    /// Returns true when the cookie must be sent with a request to the given url
+    fn applies_to(&self, host: &str, path: &str, secure: bool) -> bool {
+        let domain_ok = if self.host_only {
+            host == self.domain
+        } else {
+            domain_match(host, &self.domain)
+        };
+
+        domain_ok && path_match(path, &self.path) && (!self.secure || secure)
+    }
+
+    fn storage_key(&self) -> String {
+        format!("{};{};{};{}", STORAGE_PREFIX, self.domain, self.path, self.name)
+    }
+
+    /// Serializes the cookie for the storage. Fields are separated by newlines, which can never occur in a cookie.
+    fn serialize(&self) -> String {
+        let same_site = match self.same_site {
+            Some(SameSite::Strict) => "strict",
+            Some(SameSite::Lax) => "lax",
+            Some(SameSite::None) => "none",
+            None => "",
+        };
+
+        [
+            self.name.clone(),
+            self.value.clone(),
+            self.domain.clone(),
+            self.path.clone(),
+            self.expiry.unwrap_or_default().to_string(),
+            self.creation_time.to_string(),
+            self.last_access_time.to_string(),
+            (self.host_only as u8).to_string(),
+            (self.secure as u8).to_string(),
+            (self.http_only as u8).to_string(),
+            same_site.to_string(),
+        ]
+        .join("\n")
+    }
+
+    fn deserialize(data: &str) -> Option<Self> {
+        let fields = data.split('\n').collect::<Vec<_>>();
+        let [name, value, domain, path, expiry, creation_time, last_access_time, host_only, secure, http_only, same_site] =
+            fields.as_slice()
+        else {
+            return None;
+        };
+
+        Some(Self {
+            name: name.to_string(),
+            value: value.to_string(),
+            domain: domain.to_string(),
+            path: path.to_string(),
+            expiry: Some(expiry.parse().ok()?),
+            creation_time: creation_time.parse().ok()?,
+            last_access_time: last_access_time.parse().ok()?,
+            host_only: *host_only == "1",
+            secure: *secure == "1",
+            http_only: *http_only == "1",
+            same_site: match *same_site {
+                "strict" => Some(SameSite::Strict),
+                "lax" => Some(SameSite::Lax),
+                "none" => Some(SameSite::None),
+                _ => None,
+            },
+        })
+    }
+}
+
+/// Json storage that writes every change to the file right away, like the sqlite storage does, so no cookies are
+/// lost when the browser is not closed normally
+struct JsonCookieStorage(JsonStorageAdapter);
+
+impl StorageAdapter for JsonCookieStorage {
+    fn get(&self, key: &str) -> Option<Setting> {
+        self.0.get(key)
+    }
+
+    fn set(&self, key: &str, value: Setting) {
+        self.0.set(key, value);
+        self.0.flush();
+    }
+
+    fn remove(&self, key: &str) {
+        self.0.remove(key);
+        self.0.flush();
+    }
+
+    fn all(&self) -> gosub_shared::types::Result<std::collections::HashMap<String, Setting>> {
+        self.0.all()
+    }
+}
+
+/// Store of all cookies received from servers
+pub struct CookieStore {
+    cookies: Vec<StoredCookie>,
+    public_suffixes: PublicSuffixList,
+    /// Storage for persistent cookies. Session cookies are never persisted.
+    storage: Option<Box<dyn StorageAdapter>>,
+    max_per_domain: usize,
+    max_total: usize,
+}
+
+impl Debug for CookieStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CookieStore")
+            .field("cookies", &self.cookies)
+            .field("persistent", &self.storage.is_some())
+            .field("max_per_domain", &self.max_per_domain)
+            .field("max_total", &self.max_total)
+            .finish()
+    }
+}
+
+impl CookieStore {
+    /// Creates a new, empty, cookie store that only holds the cookies of the current session
+    pub fn new(public_suffixes: PublicSuffixList, max_per_domain: usize, max_total: usize) -> Self {
+        Self {
+            cookies: Vec::new(),
+            public_suffixes,
+            storage: None,
+            max_per_domain,
+            max_total,
+        }
+    }
+
+    /// Persists cookies in the given storage, and loads the (unexpired) cookies that are already stored in it
+    pub fn with_storage(mut self, storage: Box<dyn StorageAdapter>) -> Self {
+        let now = now();
+
+        match storage.all() {
+            Ok(settings) => {
+                for (key, setting) in settings {
+                    if !key.starts_with(STORAGE_PREFIX) {
+                        continue;
+                    }
+
+                    match StoredCookie::deserialize(&setting.to_string()) {
+                        Some(cookie) if !cookie.is_expired(now) => self.cookies.push(cookie),
+                        _ => storage.remove(&key),
+                    }
+                }
+            }
+            Err(err) => warn!("Could not load stored cookies: {}", err),
+        }
+
+        self.storage = Some(storage);
+        self.evict(now);
+
+        self
+    }
+
+    /// Creates a cookie store from the `http.cookies.*` settings, or None when cookies are disabled
+    pub fn from_config() -> Option<Self> {
+        if !config!(bool "http.cookies.enabled") {
+            return None;
+        }
+
+        let store = Self::new(
+            PublicSuffixList::load(&config!(string "http.cookies.public_suffix_list")),
+            config!(uint "http.cookies.max_per_domain"),
+            config!(uint "http.cookies.max_total"),
+        );
+
+        let path = config!(string "http.cookies.storage.path");
+        if path.is_empty() {
+            return Some(store);
+        }
+
+        let storage: gosub_shared::types::Result<Box<dyn StorageAdapter>> = if path.ends_with(".json") {
+            JsonStorageAdapter::try_from(&path).map(|s| Box::new(JsonCookieStorage(s)) as Box<dyn StorageAdapter>)
+        } else {
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                SqliteStorageAdapter::try_from(&path).map(|s| Box::new(s) as Box<dyn StorageAdapter>)
+            }
+            #[cfg(target_arch = "wasm32")]
+            {
+                Err(anyhow::anyhow!("sqlite storage is not available"))
+            }
+        };
+
+        match storage {
+            Ok(storage) => Some(store.with_storage(storage)),
+            Err(err) => {
+                warn!("Could not open cookie storage {}: {}", path, err);
+                Some(store)
+            }
+        }
+    }
+
+    /// Returns all cookies in the store
+    pub fn cookies(&self) -> &[StoredCookie] {
+        &self.cookies
+    }
+
+    /// Stores the cookies of all `Set-Cookie` headers of a response to a request for the given url
+    pub fn store_response_cookies(&mut self, url: &Url, response: &Response) {
+        for header in &response.set_cookies {
+            self.set_cookie(url, header);
+        }
+    }
+
+    /// Stores the cookie of a `Set-Cookie` header received from the given url. Returns false when the cookie is
+    /// ignored.
+    pub fn set_cookie(&mut self, url: &Url, header: &str) -> bool {
+        self.set_cookie_at(url, header, now())
+    }
+
+    /// Stores the cookie like [`CookieStore::set_cookie`], at the given time in seconds after epoch
+    ///
+    /// https://httpwg.org/http-extensions/draft-ietf-httpbis-rfc6265bis.html#name-storage-model
+    pub fn set_cookie_at(&mut self, url: &Url, header: &str, now: u64) -> bool {
+        let Some(mut cookie) = self.parse_cookie(url, header, now) else {
+            trace!("{}: ignoring cookie {}", url, header);
+            return false;
+        };
+
+        let secure_scheme = is_secure_scheme(url);
+
+        if !secure_scheme {
+            // An insecure origin may not overwrite (or shadow) secure cookies
+            let shadows_secure = self.cookies.iter().any(|c| {
+                c.secure
+                    && c.name == cookie.name
+                    && (domain_match(&cookie.domain, &c.domain) || domain_match(&c.domain, &cookie.domain))
+                    && path_match(&cookie.path, &c.path)
+            });
+            if shadows_secure {
+                trace!("{}: cookie {} would overwrite a secure cookie", url, cookie.name);
+                return false;
+            }
+        }
+
+        if let Some(pos) = self.cookies.iter().position(|c| c.same_identity(&cookie)) {
+            let old = self.cookies.remove(pos);
+            cookie.creation_time = old.creation_time;
+            self.unpersist(&old);
+        }
+
+        if cookie.is_expired(now) {
+            debug!("{}: cookie {} removed", url, cookie.name);
+            return true;
+        }
+
+        debug!("{}: cookie {} stored for {}", url, cookie.name, cookie.domain);
+        self.persist(&cookie);
+        self.cookies.push(cookie);
+        self.evict(now);
+
+        true
+    }
+
+    /// Returns the value for the `Cookie` header of a request to the given url, or None when there are no cookies
+    /// to send. The initiator is the url of the document that requests a subresource, or None for a navigation.
+    pub fn cookie_header(&mut self, url: &Url, initiator: Option<&Url>) -> Option<String> {
+        self.cookie_header_at(url, initiator, now())
+    }
+
+    /// Returns the `Cookie` header like [`CookieStore::cookie_header`], at the given time in seconds after epoch
+    ///
+    /// https://httpwg.org/http-extensions/draft-ietf-httpbis-rfc6265bis.html#name-retrieval-algorithm
+    pub fn cookie_header_at(&mut self, url: &Url, initiator: Option<&Url>, now: u64) -> Option<String> {
+        let host = url.host_str()?.cow_to_ascii_lowercase();
+        let path = if url.path().is_empty() { "/" } else { url.path() };
+        let secure = is_secure_scheme(url);
+        let same_site = match initiator {
+            Some(initiator) => self.is_same_site(url, initiator),
+            None => true,
+        };
+
+        self.remove_expired(now);
+
+        // Cookies with a SameSite attribute of Strict or Lax are not sent with subresource requests of another site
+        let mut cookies = (0..self.cookies.len())
+            .filter(|&idx| {
+                let cookie = &self.cookies[idx];
+                cookie.applies_to(&host, path, secure)
+                    && (same_site || !matches!(cookie.same_site, Some(SameSite::Strict | SameSite::Lax)))
+            })
+            .collect::<Vec<_>>();
+        if cookies.is_empty() {
+            return None;
+        }
+
+        // Cookies with longer paths are listed first, then the ones that were created first
+        cookies.sort_by(|&a, &b| {
+            let (a, b) = (&self.cookies[a], &self.cookies[b]);
+            b.path
+                .len()
+                .cmp(&a.path.len())
+                .then(a.creation_time.cmp(&b.creation_time))
+        });
+
+        let mut header = Vec::with_capacity(cookies.len());
+        for idx in cookies {
+            self.cookies[idx].last_access_time = now;
+
+            let cookie = &self.cookies[idx];
+            self.persist(cookie);
+            header.push(if cookie.name.is_empty() {
+                cookie.value.clone()
+            } else {
+                format!("{}={}", cookie.name, cookie.value)
+            });
+        }
+
+        Some(header.join("; "))
+    }
+
+    /// Returns true when both urls are of the same site: they have the same scheme and registrable domain
+    ///
+    /// https://html.spec.whatwg.org/multipage/browsers.html#same-site
+    fn is_same_site(&self, a: &Url, b: &Url) -> bool {
+        let (Some(host_a), Some(host_b)) = (a.host_str(), b.host_str()) else {
+            return false;
+        };
+        let (host_a, host_b) = (host_a.cow_to_ascii_lowercase(), host_b.cow_to_ascii_lowercase());
+
+        let site_a = self.public_suffixes.registrable_domain(&host_a).unwrap_or(&host_a);
+        let site_b = self.public_suffixes.registrable_domain(&host_b).unwrap_or(&host_b);
+
+        is_secure_scheme(a) == is_secure_scheme(b) && site_a == site_b
+    }
+
+    /// Removes all cookies from the store (and the storage)
+    pub fn clear(&mut self) {
+        for cookie in std::mem::take(&mut self.cookies) {
+            self.unpersist(&cookie);
+        }
+    }
+
+    /// Removes all cookies that have expired
+    pub fn remove_expired(&mut self, now: u64) {
+        let (expired, cookies) = std::mem::take(&mut self.cookies)
+            .into_iter()
+            .partition::<Vec<_>, _>(|c| c.is_expired(now));
+
+        self.cookies = cookies;
+        for cookie in expired {
+            self.unpersist(&cookie);
+        }
+    }
+
+    /// Parses the `Set-Cookie` header, and returns the cookie as it must be stored, or None when it must be ignored
+    fn parse_cookie(&self, url: &Url, header: &str, now: u64) -> Option<StoredCookie> {
+        // Control characters (except for tabs) make the whole cookie invalid
+        if header.chars().any(|c| c.is_ascii_control() && c != '\t') {
+            return None;
+        }
+
+        let parsed = Cookie::parse(header).ok()?;
+        let name = parsed.name();
+        let value = parsed.value();
+        if name.len() + value.len() > MAX_NAME_VALUE_SIZE || (name.is_empty() && value.is_empty()) {
+            return None;
+        }
+
+        let host = url.host_str()?.cow_to_ascii_lowercase().to_string();
+        let secure_scheme = is_secure_scheme(url);
+
+        // Max-Age has precedence over Expires
+        let expiry = if let Some(max_age) = parsed.max_age() {
+            let max_age = max_age.whole_seconds();
+            Some(if max_age <= 0 {
+                0
+            } else {
+                now + (max_age as u64).min(MAX_AGE_LIMIT)
+            })
+        } else {
+            parsed
+                .expires_datetime()
+                .map(|expires| (expires.unix_timestamp().max(0) as u64).min(now + MAX_AGE_LIMIT))
+        };
+
+        let mut domain = parsed
+            .domain()
+            .filter(|domain| !domain.is_empty() && domain.len() <= MAX_ATTRIBUTE_SIZE)
+            .map(|domain| domain.trim_end_matches('.').cow_to_ascii_lowercase().to_string());
+
+        if let Some(cookie_domain) = &domain {
+            if self.public_suffixes.is_public_suffix(cookie_domain) {
+                if *cookie_domain != host {
+                    return None;
+                }
+                // A public suffix itself may set a cookie for its own host only
+                domain = None;
+            }
+        }
+
+        let (domain, host_only) = match domain {
+            Some(domain) => {
+                if !domain_match(&host, &domain) {
+                    return None;
+                }
+                (domain, false)
+            }
+            None => (host, true),
+        };
+
+        let path = match parsed.path() {
+            Some(path) if path.starts_with('/') && path.len() <= MAX_ATTRIBUTE_SIZE => path.to_string(),
+            _ => default_path(url),
+        };
+
+        let secure = parsed.secure().unwrap_or(false);
+        if secure && !secure_scheme {
+            return None;
+        }
+
+        // SameSite=None is only accepted for secure cookies
+        let same_site = parsed.same_site();
+        if same_site == Some(SameSite::None) && !secure {
+            return None;
+        }
+
+        // Cookie name prefixes
+        // https://httpwg.org/http-extensions/draft-ietf-httpbis-rfc6265bis.html#name-cookie-name-prefixes
+        let lower_name = name.cow_to_ascii_lowercase();
+        if lower_name.starts_with("__secure-") && !secure {
+            return None;
+        }
+        if lower_name.starts_with("__host-") && (!secure || !host_only || path != "/") {
+            return None;
+        }
+        if name.is_empty() && (value.starts_with("__Secure-") || value.starts_with("__Host-")) {
+            return None;
+        }
+
+        Some(StoredCookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            domain,
+            path,
+            expiry,
+            creation_time: now,
+            last_access_time: now,
+            host_only,
+            secure,
+            http_only: parsed.http_only().unwrap_or(false),
+            same_site,
+        })
+    }
+
+    /// Evicts expired cookies, and the least recently used cookies of domains that have too many cookies, and of the
+    /// store when it holds too many cookies
+    fn evict(&mut self, now: u64) {
+        self.remove_expired(now);
+
+        let mut domains = self.cookies.iter().map(|c| c.domain.clone()).collect::<Vec<_>>();
+        domains.sort();
+        domains.dedup();
+
+        for domain in domains {
+            while self.cookies.iter().filter(|c| c.domain == domain).count() > self.max_per_domain {
+                self.evict_least_recently_used(|c| c.domain == domain);
+            }
+        }
+
+        while self.cookies.len() > self.max_total {
+            self.evict_least_recently_used(|_| true);
+        }
+    }
+
+    fn evict_least_recently_used(&mut self, filter: impl Fn(&StoredCookie) -> bool) {
+        let lru = self
+            .cookies
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| filter(c))
+            .min_by_key(|(_, c)| c.last_access_time)
+            .map(|(pos, _)| pos);
+
+        if let Some(pos) = lru {
+            let cookie = self.cookies.remove(pos);
+            trace!("{}: cookie {} evicted", cookie.domain, cookie.name);
+            self.unpersist(&cookie);
+        }
+    }
+
+    fn persist(&self, cookie: &StoredCookie) {
+        if cookie.expiry.is_none() {
+            return;
+        }
+
+        if let Some(storage) = &self.storage {
+            storage.set(&cookie.storage_key(), Setting::String(cookie.serialize()));
+        }
+    }
+
+    fn unpersist(&self, cookie: &StoredCookie) {
+        if cookie.expiry.is_none() {
+            return;
+        }
+
+        if let Some(storage) = &self.storage {
+            storage.remove(&cookie.storage_key());
+        }
+    }
+}
+
+/// Returns true when cookies for this url are sent over a secure connection
+fn is_secure_scheme(url: &Url) -> bool {
+    matches!(url.scheme(), "https" | "wss")
+}
+
+/// Returns true when the host domain-matches the domain: they are identical, or the host is a subdomain of the
+/// domain (and not an IP address)
+fn domain_match(host: &str, domain: &str) -> bool {
+    if host == domain {
+        return true;
+    }
+
+    let is_ip = matches!(Host::parse(host), Ok(Host::Ipv4(_)) | Ok(Host::Ipv6(_)));
+    !is_ip && host.len() > domain.len() && host.ends_with(domain) && host[..host.len() - domain.len()].ends_with('.')
+}
+
+/// Returns true when the request path path-matches the cookie path
+fn path_match(request_path: &str, cookie_path: &str) -> bool {
+    if request_path == cookie_path {
+        return true;
+    }
+
+    request_path.starts_with(cookie_path)
+        && (cookie_path.ends_with('/') || request_path[cookie_path.len()..].starts_with('/'))
+}
+
+/// Returns the default path of a cookie set by the url, which is the directory of the path
+fn default_path(url: &Url) -> String {
+    let path = url.path();
+    if !path.starts_with('/') {
+        return "/".to_string();
+    }
+
+    match path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(pos) => path[..pos].to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gosub_config::storage::MemoryStorageAdapter;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn store() -> CookieStore {
+        CookieStore::new(PublicSuffixList::builtin(), 3, 5)
+    }
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn domain_and_path() {
+        let mut store = store();
+        let site = url("https://www.example.com/account/login");
+
+        assert!(store.set_cookie_at(&site, "host=1", NOW));
+        assert!(store.set_cookie_at(&site, "domain=2; Domain=.Example.COM; Path=/", NOW));
+        assert!(store.set_cookie_at(&site, "deep=3; Path=/account/settings", NOW));
+        assert!(!store.set_cookie_at(&site, "other=4; Domain=example.org", NOW));
+        assert!(!store.set_cookie_at(&site, "suffix=5; Domain=com", NOW));
+
+        // The default path is the directory of the url
+        assert_eq!(store.cookies()[0].path, "/account");
+        assert!(store.cookies()[0].host_only);
+        assert_eq!(store.cookies()[1].domain, "example.com");
+        assert!(!store.cookies()[1].host_only);
+
+        assert_eq!(
+            store.cookie_header_at(&url("https://www.example.com/account/settings/x"), None, NOW),
+            Some("deep=3; host=1; domain=2".to_string())
+        );
+        assert_eq!(
+            store.cookie_header_at(&url("https://www.example.com/accounts"), None, NOW),
+            Some("domain=2".to_string())
+        );
+        assert_eq!(
+            store.cookie_header_at(&url("https://api.example.com/account"), None, NOW),
+            Some("domain=2".to_string())
+        );
+        assert_eq!(store.cookie_header_at(&url("https://example.org/"), None, NOW), None);
+    }
+
+    #[test]
+    fn security_attributes() {
+        let mut store = store();
+        let secure_site = url("https://example.com/");
+        let insecure_site = url("http://example.com/");
+
+        assert!(store.set_cookie_at(&secure_site, "id=1; Secure; HttpOnly; SameSite=Strict", NOW));
+        assert!(!store.set_cookie_at(&insecure_site, "secure=1; Secure", NOW));
+        assert!(!store.set_cookie_at(&secure_site, "cross=1; SameSite=None", NOW));
+        assert!(!store.set_cookie_at(&secure_site, "__Secure-a=1", NOW));
+        assert!(store.set_cookie_at(&secure_site, "__Secure-a=1; Secure", NOW));
+        assert!(!store.set_cookie_at(&secure_site, "__Host-b=1; Secure; Domain=example.com; Path=/", NOW));
+        assert!(store.set_cookie_at(&secure_site, "__Host-b=1; Secure; Path=/", NOW));
+
+        // An insecure origin cannot overwrite a secure cookie
+        assert!(!store.set_cookie_at(&insecure_site, "id=2", NOW));
+
+        let cookie = &store.cookies()[0];
+        assert!(cookie.secure && cookie.http_only);
+        assert_eq!(cookie.same_site, Some(SameSite::Strict));
+
+        assert_eq!(store.cookie_header_at(&insecure_site, None, NOW), None);
+        assert_eq!(
+            store.cookie_header_at(&secure_site, None, NOW),
+            Some("id=1; __Secure-a=1; __Host-b=1".to_string())
+        );
+    }
+
+    #[test]
+    fn expiry_and_eviction() {
+        let mut store = store();
+        let site = url("https://example.com/");
+
+        assert!(store.set_cookie_at(&site, "a=1; Max-Age=60", NOW));
+        assert!(store.set_cookie_at(&site, "b=1; Expires=Tue, 14 Nov 2023 22:14:20 GMT", NOW));
+        assert!(store.set_cookie_at(&site, "c=1; Max-Age=999999999", NOW));
+        assert_eq!(store.cookies()[1].expiry, Some(NOW + 60));
+        assert_eq!(store.cookies()[2].expiry, Some(NOW + MAX_AGE_LIMIT));
+
+        assert_eq!(
+            store.cookie_header_at(&site, None, NOW + 59),
+            Some("a=1; b=1; c=1".to_string())
+        );
+        assert_eq!(store.cookie_header_at(&site, None, NOW + 60), Some("c=1".to_string()));
+
+        // Replacing a cookie with one that has expired removes it
+        assert!(store.set_cookie_at(&site, "c=1; Max-Age=0", NOW));
+        assert!(store.cookies().is_empty());
+
+        // Only 3 cookies per domain, the least recently used is evicted
+        store.set_cookie_at(&site, "a=1", NOW);
+        store.set_cookie_at(&site, "b=1", NOW + 1);
+        store.set_cookie_at(&site, "c=1", NOW + 2);
+        store.cookie_header_at(&site, None, NOW + 3);
+        store.set_cookie_at(&url("https://example.com/x/y"), "a=2", NOW + 4);
+        store.set_cookie_at(&site, "d=1", NOW + 5);
+        let names = store.cookies().iter().map(|c| c.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names.len(), 3);
+        assert!(names.contains(&"d"));
+    }
+
+    #[test]
+    fn persistence() {
+        let storage = Arc::new(MemoryStorageAdapter::new());
+
+        struct Shared(Arc<MemoryStorageAdapter>);
+        impl StorageAdapter for Shared {
+            fn get(&self, key: &str) -> Option<Setting> {
+                self.0.get(key)
+            }
+            fn set(&self, key: &str, value: Setting) {
+                self.0.set(key, value)
+            }
+            fn remove(&self, key: &str) {
+                self.0.remove(key)
+            }
+            fn all(&self) -> gosub_shared::types::Result<std::collections::HashMap<String, Setting>> {
+                self.0.all()
+            }
+        }
+
+        let site = url("https://example.com/");
+        let now = now();
+
+        let mut store = store().with_storage(Box::new(Shared(storage.clone())));
+        store.set_cookie_at(&site, "session=1", now);
+        store.set_cookie_at(&site, "persistent=2; Max-Age=3600; Secure; SameSite=Lax", now);
+        store.set_cookie_at(&site, "removed=3; Max-Age=3600", now);
+        store.set_cookie_at(&site, "removed=3; Max-Age=-1", now);
+        assert_eq!(storage.all().unwrap().len(), 1);
+
+        let mut restored = self::store().with_storage(Box::new(Shared(storage.clone())));
+        assert_eq!(restored.cookies(), &store.cookies()[1..]);
+        assert_eq!(
+            restored.cookie_header_at(&site, None, now + 10),
+            Some("persistent=2".to_string())
+        );
+
+        // The last access time is saved as well, as it decides which cookies are evicted first
+        let restored = self::store().with_storage(Box::new(Shared(storage)));
+        assert_eq!(restored.cookies()[0].last_access_time, now + 10);
+    }
+
+    #[test]
+    fn same_site() {
+        let mut store = CookieStore::new(PublicSuffixList::builtin(), 10, 10);
+        let site = url("https://www.example.com/");
+
+        assert!(store.set_cookie_at(&site, "strict=1; SameSite=Strict", NOW));
+        assert!(store.set_cookie_at(&site, "lax=2; SameSite=Lax", NOW));
+        assert!(store.set_cookie_at(&site, "none=3; SameSite=None; Secure", NOW));
+        assert!(store.set_cookie_at(&site, "default=4", NOW));
+
+        let all = Some("strict=1; lax=2; none=3; default=4".to_string());
+        let cross_site = Some("none=3; default=4".to_string());
+
+        // Navigations and subresources of the same site get all cookies
+        assert_eq!(store.cookie_header_at(&site, None, NOW), all);
+        assert_eq!(
+            store.cookie_header_at(&site, Some(&url("https://static.example.com/page")), NOW),
+            all
+        );
+
+        // Subresources of documents of another site (or scheme) do not get the SameSite cookies
+        assert_eq!(
+            store.cookie_header_at(&site, Some(&url("https://example.org/")), NOW),
+            cross_site
+        );
+        assert_eq!(
+            store.cookie_header_at(&site, Some(&url("http://www.example.com/")), NOW),
+            cross_site
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use cow_utils::CowUtils;
+use log::warn;
+use std::collections::HashSet;
+
+/// Built-in public suffixes, in the format of the public suffix list. Single label suffixes (like "com" or "nl") are
+/// covered by the default rule, so only suffixes with more labels are listed.
+const BUILTIN_LIST: &str = "
+// Country code second level domains
+ac.uk
+co.uk
+gov.uk
+ltd.uk
+me.uk
+net.uk
+org.uk
+plc.uk
+sch.uk
+com.au
+edu.au
+gov.au
+net.au
+org.au
+co.nz
+net.nz
+org.nz
+govt.nz
+ac.jp
+co.jp
+go.jp
+ne.jp
+or.jp
+co.kr
+or.kr
+com.br
+net.br
+org.br
+gov.br
+com.cn
+net.cn
+org.cn
+gov.cn
+com.mx
+org.mx
+com.ar
+com.tr
+co.in
+net.in
+org.in
+gov.in
+co.za
+org.za
+com.sg
+com.hk
+com.tw
+co.il
+com.ua
+co.id
+com.my
+com.ph
+com.vn
+
+// Wildcards and exceptions
+*.ck
+!www.ck
+*.kawasaki.jp
+!city.kawasaki.jp
+
+// Private domains where anyone can register a subdomain
+appspot.com
+azurewebsites.net
+blogspot.com
+cloudfront.net
+github.io
+gitlab.io
+herokuapp.com
+netlify.app
+pages.dev
+s3.amazonaws.com
+vercel.app
+workers.dev
+";
+
+/// A list of public suffixes (like "com" or "co.uk") under which anyone can register a domain. Cookies may not be
+/// set for a public suffix, as they would be shared by all the sites under it.
+///
+/// https://publicsuffix.org/list/
+#[derive(Debug, Default)]
+pub struct PublicSuffixList {
+    rules: HashSet<String>,
+    /// Suffixes of the wildcard rules, without the "*." prefix
+    wildcards: HashSet<String>,
+    /// Exception rules, without the "!" prefix
+    exceptions: HashSet<String>,
+}
+
+impl PublicSuffixList {
+    /// Parses a list in the format of public_suffix_list.dat
+    pub fn parse(data: &str) -> Self {
+        let mut list = Self::default();
+
+        for line in data.lines() {
+            // Only the first word of a line is the rule
+            let Some(rule) = line.split_whitespace().next() else {
+                continue;
+            };
+            if rule.starts_with("//") {
+                continue;
+            }
+
+            let rule = rule.cow_to_ascii_lowercase();
+            if let Some(exception) = rule.strip_prefix('!') {
+                list.exceptions.insert(exception.to_string());
+            } else if let Some(wildcard) = rule.strip_prefix("*.") {
+                list.wildcards.insert(wildcard.to_string());
+            } else {
+                list.rules.insert(rule.to_string());
+            }
+        }
+
+        list
+    }
+
+    /// Returns the built-in list with the most common public suffixes
+    pub fn builtin() -> Self {
+        Self::parse(BUILTIN_LIST)
+    }
+
+    /// Loads the list from the path in the `http.cookies.public_suffix_list` setting, or returns the built-in list
+    /// when no path is set or the file cannot be read.
+    pub fn load(path: &str) -> Self {
+        if path.is_empty() {
+            return Self::builtin();
+        }
+
+        match std::fs::read_to_string(path) {
+            Ok(data) => Self::parse(&data),
+            Err(err) => {
+                warn!("Could not read public suffix list {}: {}", path, err);
+                Self::builtin()
+            }
+        }
+    }
+
+    /// Returns the public suffix of the domain. Domains that match no rule have their top level domain as public
+    /// suffix.
+    pub fn public_suffix<'a>(&self, domain: &'a str) -> &'a str {
+        let domain = domain.trim_end_matches('.');
+
+        // Walk from the longest candidate to the shortest, so the first match is the prevailing rule
+        let mut candidate = domain;
+        loop {
+            let parent = candidate.split_once('.').map(|(_, parent)| parent);
+
+            if self.exceptions.contains(&*candidate.cow_to_ascii_lowercase()) {
+                return parent.unwrap_or(candidate);
+            }
+            if self.rules.contains(&*candidate.cow_to_ascii_lowercase()) {
+                return candidate;
+            }
+            if let Some(parent) = parent {
+                if self.wildcards.contains(&*parent.cow_to_ascii_lowercase()) {
+                    return candidate;
+                }
+            }
+
+            match parent {
+                Some(parent) => candidate = parent,
+                // The default rule is "*"
+                None => return candidate,
+            }
+        }
+    }
+
+    /// Returns true when the domain is a public suffix
+    pub fn is_public_suffix(&self, domain: &str) -> bool {
+        let domain = domain.trim_end_matches('.');
+        !domain.is_empty() && self.public_suffix(domain).len() == domain.len()
+    }
+
+    /// Returns the registrable domain, which is the public suffix plus one label, or None when the domain is a
+    /// public suffix itself.
+    pub fn registrable_domain<'a>(&self, domain: &'a str) -> Option<&'a str> {
+        let domain = domain.trim_end_matches('.');
+        let suffix = self.public_suffix(domain);
+        if suffix.len() == domain.len() {
+            return None;
+        }
+
+        let prefix = &domain[..domain.len() - suffix.len() - 1];
+        let start = prefix.rfind('.').map_or(0, |pos| pos + 1);
+
+        Some(&domain[start..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn public_suffixes() {
+        let list = PublicSuffixList::builtin();
+
+        assert_eq!(list.public_suffix("example.com"), "com");
+        assert_eq!(list.public_suffix("www.example.co.uk"), "co.uk");
+        assert_eq!(list.public_suffix("gosub.github.io"), "github.io");
+        assert_eq!(list.public_suffix("www.example.ck"), "example.ck");
+        assert_eq!(list.public_suffix("www.ck"), "ck");
+        assert_eq!(list.public_suffix("localhost"), "localhost");
+
+        assert!(list.is_public_suffix("com"));
+        assert!(list.is_public_suffix("CO.UK"));
+        assert!(list.is_public_suffix("anything.ck"));
+        assert!(!list.is_public_suffix("www.ck"));
+        assert!(!list.is_public_suffix("example.com"));
+
+        assert_eq!(list.registrable_domain("a.b.example.co.uk"), Some("example.co.uk"));
+        assert_eq!(list.registrable_domain("example.com"), Some("example.com"));
+        assert_eq!(list.registrable_domain("github.io"), None);
+
+        let list = PublicSuffixList::parse("// comment\nexample\n*.wild.example\n!safe.wild.example  trailing\n");
+        assert_eq!(list.public_suffix("a.b.wild.example"), "b.wild.example");
+        assert_eq!(list.public_suffix("a.safe.wild.example"), "wild.example");
+        assert_eq!(list.public_suffix("a.other.example"), "example");
+    }
+}
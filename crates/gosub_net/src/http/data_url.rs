@@ -0,0 +1,221 @@
+//! `data:` URLs
+//!
+//! A data URL holds its content in the URL itself, either percent-encoded or base64 encoded, together with its MIME
+//! type (RFC 2397). They are processed as described by the fetch standard.
+//!
+//! https://fetch.spec.whatwg.org/#data-urls
+use anyhow::bail;
+use cow_utils::CowUtils;
+use url::{Position, Url};
+
+use gosub_shared::types::Result;
+
+use crate::http::response::Response;
+
+/// MIME type of a data URL that does not define a (valid) type
+const DEFAULT_MIME_TYPE: &str = "text/plain;charset=US-ASCII";
+
+/// The content of a data URL
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataUrl {
+    /// MIME type, including any parameters like the charset
+    pub mime_type: String,
+    /// The decoded body
+    pub body: Vec<u8>,
+}
+
+impl DataUrl {
+    /// Processes the data URL and returns its content
+    ///
+    /// https://fetch.spec.whatwg.org/#data-url-processor
+    pub fn parse(url: &Url) -> Result<Self> {
+        if url.scheme() != "data" {
+            bail!("not a data URL: {}", url);
+        }
+
+        // The fragment is not part of the data
+        let input = &url[Position::BeforePath..Position::AfterQuery];
+        let input = input.trim_matches(|c: char| c.is_ascii_whitespace());
+
+        let Some((mime_type, body)) = input.split_once(',') else {
+            bail!("data URL without comma: {}", url);
+        };
+        let mut mime_type = mime_type.trim_matches(|c: char| c.is_ascii_whitespace()).to_string();
+        let mut body = percent_decode(body.as_bytes());
+
+        // A mime type that ends in ";base64" has a base64 encoded body
+        if let Some(pos) = mime_type.rfind(';') {
+            let param = mime_type[pos + 1..].trim_start_matches(' ');
+            if param.eq_ignore_ascii_case("base64") {
+                body = match forgiving_base64_decode(&body) {
+                    Some(body) => body,
+                    None => bail!("data URL with invalid base64: {}", url),
+                };
+                mime_type.truncate(pos);
+            }
+        }
+
+        if mime_type.starts_with(';') {
+            mime_type.insert_str(0, "text/plain");
+        }
+
+        let mime_type = normalize_mime_type(&mime_type).unwrap_or_else(|| DEFAULT_MIME_TYPE.to_string());
+
+        Ok(Self { mime_type, body })
+    }
+
+    /// Returns a response with the content of the data URL
+    pub fn to_response(&self) -> Response {
+        let mut resp = Response::from(self.body.clone());
+        resp.headers.set("Content-Type", &self.mime_type);
+        resp
+    }
+}
+
+/// Decodes all percent-encoded bytes. Percent signs that are not followed by two hex digits are kept as they are.
+fn percent_decode(input: &[u8]) -> Vec<u8> {
+    let hex = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+
+    let mut output = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        if input[i] == b'%' && i + 2 < input.len() {
+            if let (Some(hi), Some(lo)) = (hex(input[i + 1]), hex(input[i + 2])) {
+                output.push(hi << 4 | lo);
+                i += 3;
+                continue;
+            }
+        }
+        output.push(input[i]);
+        i += 1;
+    }
+
+    output
+}
+
+/// Decodes base64 while ignoring whitespace and (some) missing padding, or returns None when the data is invalid
+///
+/// https://infra.spec.whatwg.org/#forgiving-base64-decode
+pub(crate) fn forgiving_base64_decode(input: &[u8]) -> Option<Vec<u8>> {
+    let mut data = input
+        .iter()
+        .copied()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect::<Vec<_>>();
+
+    if data.len() % 4 == 0 {
+        if data.ends_with(b"==") {
+            data.truncate(data.len() - 2);
+        } else if data.ends_with(b"=") {
+            data.truncate(data.len() - 1);
+        }
+    }
+    if data.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut output = Vec::with_capacity(data.len() * 3 / 4);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for b in data {
+        let value = match b {
+            b'A'..=b'Z' => b - b'A',
+            b'a'..=b'z' => b - b'a' + 26,
+            b'0'..=b'9' => b - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+
+        buffer = buffer << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    Some(output)
+}
+
+/// Returns the MIME type with a lowercase type and subtype, or None when it is not a valid MIME type
+fn normalize_mime_type(mime_type: &str) -> Option<String> {
+    let is_token = |s: &str| {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c))
+    };
+
+    let (essence, params) = match mime_type.split_once(';') {
+        Some((essence, params)) => (essence, Some(params)),
+        None => (mime_type, None),
+    };
+    let (type_, subtype) = essence.trim().split_once('/')?;
+    if !is_token(type_) || !is_token(subtype.trim_end()) {
+        return None;
+    }
+
+    let mut result = format!("{}/{}", type_, subtype.trim_end())
+        .cow_to_ascii_lowercase()
+        .to_string();
+    if let Some(params) = params {
+        for param in params.split(';') {
+            let Some((name, value)) = param.split_once('=') else {
+                continue;
+            };
+            let name = name.trim_start();
+            if !is_token(name) || value.is_empty() {
+                continue;
+            }
+
+            result.push(';');
+            result.push_str(&name.cow_to_ascii_lowercase());
+            result.push('=');
+            result.push_str(value);
+        }
+    }
+
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(url: &str) -> Result<DataUrl> {
+        DataUrl::parse(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn data_urls() {
+        let data = parse("data:,Hello%2C%20World%21").unwrap();
+        assert_eq!(data.mime_type, DEFAULT_MIME_TYPE);
+        assert_eq!(data.body, b"Hello, World!");
+
+        let data = parse("data:text/plain;base64,SGVsbG8sIFdvcmxkIQ==").unwrap();
+        assert_eq!(data.mime_type, "text/plain");
+        assert_eq!(data.body, b"Hello, World!");
+
+        // Whitespace and missing padding are allowed in base64
+        let data = parse("data:image/PNG ; BASE64,iVBO Rw0K#fragment").unwrap();
+        assert_eq!(data.mime_type, "image/png");
+        assert_eq!(data.body, vec![0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a]);
+
+        let data = parse("data:text/html;charset=UTF-8,%3Cp%3E%E2%9C%93%3C/p%3E").unwrap();
+        assert_eq!(data.mime_type, "text/html;charset=UTF-8");
+        assert_eq!(data.body, "<p>✓</p>".as_bytes());
+        assert!(data.to_response().encoding().is_some());
+
+        let data = parse("data:;charset=utf-8,100%").unwrap();
+        assert_eq!(data.mime_type, "text/plain;charset=utf-8");
+        assert_eq!(data.body, b"100%");
+
+        let data = parse("data:invalid,x").unwrap();
+        assert_eq!(data.mime_type, DEFAULT_MIME_TYPE);
+
+        assert!(parse("data:text/plain").is_err());
+        assert!(parse("data:;base64,a").is_err());
+        assert!(parse("data:;base64,a*bc").is_err());
+    }
+}
@@ -1,15 +1,25 @@
 use anyhow::bail;
+use cookie::CookieJar;
+use cow_utils::CowUtils;
 use std::error::Error;
 use std::fmt::Debug;
 use std::future::Future;
+use std::io::Read;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 use url::{ParseError, Url};
 
 use gosub_shared::types::Result;
 
+use crate::http::blob;
+use crate::http::cache::{self, CacheLookup, HttpCache};
+use crate::http::cookies::{self, CookieStore};
+use crate::http::data_url::DataUrl;
+use crate::http::policy::FetchPolicy;
 use crate::http::request::Request;
-use crate::http::request_impl::RequestImpl;
+use crate::http::transport::{self, Transport};
 
-use super::response::Response;
+use super::response::{BodyReader, Response};
 
 pub trait RequestAgent: Debug {
     type Error: Error;
@@ -19,59 +29,864 @@ pub trait RequestAgent: Debug {
     fn get_req(&self, req: &Request) -> impl Future<Output = Result<Response>>;
 }
 
+/// Request headers that describe the body, and are removed when a redirect changes the request into a GET
+const BODY_HEADERS: [&str; 5] = [
+    "content-encoding",
+    "content-language",
+    "content-length",
+    "content-location",
+    "content-type",
+];
+
+/// Request headers with credentials, which are removed when a redirect goes to another origin
+const CREDENTIAL_HEADERS: [&str; 3] = ["authorization", "cookie", "proxy-authorization"];
+
 #[derive(Debug)]
 pub struct Fetcher {
-    base_url: Url,
-    client: RequestImpl,
+    /// URL that relative URLs are resolved against. This is updated when a document is redirected.
+    base_url: RwLock<Url>,
+    /// Sends the requests, over the network unless another transport is injected
+    client: Arc<dyn Transport>,
+    cache: Option<Arc<Mutex<HttpCache>>>,
+    cookies: Option<Arc<Mutex<CookieStore>>>,
+    policy: FetchPolicy,
 }
 
 impl Fetcher {
+    /// Creates a new fetcher that uses the shared HTTP cache and cookie store (when enabled in the settings)
     pub fn new(base: Url) -> Self {
+        Self::with_cache(base, cache::shared_cache())
+    }
+
+    /// Creates a new fetcher with the given HTTP cache, or without any caching when None is given
+    pub fn with_cache(base: Url, cache: Option<Arc<Mutex<HttpCache>>>) -> Self {
         Self {
-            base_url: base,
-            client: RequestImpl::new(),
+            base_url: RwLock::new(base),
+            client: transport::default_transport(),
+            cache,
+            cookies: cookies::shared_cookie_store(),
+            policy: FetchPolicy::from_config(),
         }
     }
 
-    pub fn base(&self) -> &Url {
-        &self.base_url
+    /// Uses the given cookie store for the cookies of requests and responses, or no cookies at all when None is given
+    pub fn with_cookie_store(mut self, cookies: Option<Arc<Mutex<CookieStore>>>) -> Self {
+        self.cookies = cookies;
+        self
+    }
+
+    /// Sends all requests with the given transport instead of the default transport
+    pub fn with_transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.client = transport;
+        self
+    }
+
+    /// Uses the given policy for redirects, timeouts, response sizes and the user agent
+    pub fn with_policy(mut self, policy: FetchPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn policy(&self) -> &FetchPolicy {
+        &self.policy
+    }
+
+    pub fn base(&self) -> Url {
+        self.base_url.read().unwrap().clone()
+    }
+
+    /// Sets the URL that relative URLs are resolved against, like the final URL of a redirected document
+    pub fn set_base(&self, base: Url) {
+        *self.base_url.write().unwrap() = base;
     }
 
     pub async fn get_url(&self, url: &Url) -> Result<Response> {
-        let scheme = url.scheme();
+        self.get_url_initiated_by(url, None).await
+    }
 
-        let resp = if scheme == "http" || scheme == "https" {
-            self.client.get(url.as_str()).await?
-        } else if scheme == "file" {
-            let path = &url.as_str()[7..];
+    /// Fetches a subresource of the document at the base URL, like an image or a script. Cookies with a SameSite
+    /// attribute are only sent when the subresource is of the same site as the document.
+    pub async fn get_subresource(&self, url: &Url) -> Result<Response> {
+        self.get_url_initiated_by(url, Some(self.base())).await
+    }
 
-            let body = std::fs::read(path)?;
+    async fn get_url_initiated_by(&self, url: &Url, initiator: Option<Url>) -> Result<Response> {
+        let mut resp = match url.scheme() {
+            "http" | "https" => {
+                let mut req = Request::new("GET", url.as_str(), "HTTP/1.1");
+                req.initiator = initiator;
+                let resp = self.fetch(&req).await?;
+                if resp.status >= 400 {
+                    bail!("{}: status code {}", url, resp.status);
+                }
 
-            Response::from(body)
-        } else {
-            bail!("Unsupported scheme")
+                return Ok(resp);
+            }
+            "file" => read_file(url)?,
+            "data" => DataUrl::parse(url)?.to_response(),
+            "about" => about_page(url)?,
+            "blob" => match blob::resolve_blob(url) {
+                Some(blob) => blob.to_response(),
+                None => bail!("{}: blob URL is not registered", url),
+            },
+            scheme => bail!("Unsupported scheme: {}", scheme),
         };
 
+        resp.url = Some(url.clone());
         Ok(resp)
     }
 
+    /// Fetches the URL like `get_url`, but returns as soon as the head of the response has arrived. The body of the
+    /// returned response is empty, and is read from the returned reader while it arrives.
+    pub async fn get_url_streaming(&self, url: &Url) -> Result<(Response, BodyReader)> {
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Ok(self.get_url(url).await?.into_streaming());
+        }
+
+        let req = Request::new("GET", url.as_str(), "HTTP/1.1");
+        let (resp, body) = self.fetch_streaming(&req).await?;
+        if resp.status >= 400 {
+            bail!("{}: status code {}", url, resp.status);
+        }
+
+        Ok((resp, body))
+    }
+
     pub async fn get(&self, url: &str) -> Result<Response> {
         let url = self.parse_url(url)?;
 
         self.get_url(&url).await
     }
 
-    pub fn get_req(&self, _url: &Request) {
-        todo!()
+    /// Sends the given request, with its method, headers, cookies and body. A relative URI is resolved against the
+    /// base URL of the fetcher.
+    pub async fn get_req(&self, req: &Request) -> Result<Response> {
+        let url = self.parse_url(&req.uri)?;
+
+        match url.scheme() {
+            "http" | "https" => {
+                let mut req = req.clone();
+                req.uri = url.to_string();
+
+                self.fetch(&req).await
+            }
+            "file" | "data" | "about" | "blob" => {
+                let method = req.method.cow_to_ascii_uppercase();
+                if method != "GET" && method != "HEAD" {
+                    bail!("Unsupported method for {} scheme: {}", url.scheme(), req.method);
+                }
+
+                let mut resp = self.get_url(&url).await?;
+                if method == "HEAD" {
+                    resp.body.clear();
+                }
+
+                Ok(resp)
+            }
+            scheme => bail!("Unsupported scheme: {}", scheme),
+        }
+    }
+
+    /// Fetches the request according to the policy of the fetcher: redirects are followed, and every request gets the
+    /// user agent, timeout and body size limit of the policy.
+    async fn fetch(&self, req: &Request) -> Result<Response> {
+        let (mut resp, mut body) = self.fetch_streaming(req).await?;
+        body.read_to_end(&mut resp.body)?;
+
+        Ok(resp)
+    }
+
+    /// Fetches the request like `fetch`, but returns as soon as the head of the final response has arrived
+    async fn fetch_streaming(&self, req: &Request) -> Result<(Response, BodyReader)> {
+        // Instant is not available in the browser, where the browser applies its own timeouts
+        let deadline = if cfg!(target_arch = "wasm32") {
+            None
+        } else {
+            self.policy.total_timeout.map(|timeout| Instant::now() + timeout)
+        };
+
+        let mut current = req.clone();
+        let mut redirects = Vec::new();
+
+        loop {
+            let mut url = Url::parse(&current.uri)?;
+            let fragment = url.fragment().map(|f| f.to_string());
+            url.set_fragment(None);
+
+            let mut hop = current.clone();
+            hop.uri = url.to_string();
+            hop.timeout = self.hop_timeout(deadline)?;
+            hop.max_body_size = Some(self.policy.max_body_size);
+            if hop.headers.get_ignore_case("user-agent").is_none() {
+                hop.headers.set("User-Agent", &self.policy.user_agent);
+            }
+
+            let (mut resp, body) = self.send(&hop).await?;
+            url.set_fragment(fragment.as_deref());
+
+            let location = match resp.is_redirect() {
+                true => resp.headers.get_ignore_case("location").cloned(),
+                false => None,
+            };
+            let Some(location) = location else {
+                resp.url = Some(url);
+                resp.redirects = redirects;
+                return Ok((resp, body));
+            };
+
+            if redirects.len() >= self.policy.max_redirects {
+                bail!("{}: more than {} redirects", req.uri, self.policy.max_redirects);
+            }
+
+            // The fragment of the original URL is kept, unless the location has its own
+            let mut next = url.join(&location)?;
+            if next.fragment().is_none() {
+                next.set_fragment(url.fragment());
+            }
+            if next.scheme() != "http" && next.scheme() != "https" {
+                bail!("{}: redirect to unsupported scheme {}", url, next.scheme());
+            }
+
+            current = redirect_request(&current, &url, &next, resp.status);
+            redirects.push(url);
+        }
+    }
+
+    /// Returns the timeout for the next request, which is the request timeout, or the time that is left of the total
+    /// timeout when that is shorter
+    fn hop_timeout(&self, deadline: Option<Instant>) -> Result<Option<Duration>> {
+        let Some(deadline) = deadline else {
+            return Ok(self.policy.request_timeout);
+        };
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            bail!(
+                "fetch timed out after {:?}",
+                self.policy.total_timeout.unwrap_or_default()
+            );
+        }
+
+        Ok(Some(
+            self.policy.request_timeout.map_or(remaining, |t| t.min(remaining)),
+        ))
+    }
+
+    /// Sends the request over the network, unless a fresh response is found in the cache. Stale responses are
+    /// revalidated with a conditional request. The body of a new response is stored in the cache once it has been
+    /// read completely.
+    async fn send(&self, req: &Request) -> Result<(Response, BodyReader)> {
+        let req = &self.add_cookies(req);
+
+        let Some(cache) = &self.cache else {
+            return self.send_network(req).await;
+        };
+
+        // The cache is never locked while waiting for the network
+        let lookup = match cache.lock() {
+            Ok(mut cache) => cache.lookup(req),
+            Err(_) => CacheLookup::Miss,
+        };
+
+        let request_time = cache::now();
+        let (mut resp, mut body) = match lookup {
+            CacheLookup::Fresh(resp) => return Ok(resp.into_streaming()),
+            CacheLookup::Revalidate(conditional) => self.send_network(&conditional).await?,
+            CacheLookup::Miss => self.send_network(req).await?,
+        };
+
+        if req.method.eq_ignore_ascii_case("GET") && resp.status != 304 && !resp.is_redirect() {
+            let body = CachingReader {
+                cache: cache.clone(),
+                req: req.clone(),
+                resp: Some(resp.clone()),
+                request_time,
+                body,
+            };
+            return Ok((resp, Box::new(body)));
+        }
+
+        body.read_to_end(&mut resp.body)?;
+        match cache.lock() {
+            Ok(mut cache) => Ok(cache.update(req, resp, request_time).into_streaming()),
+            Err(_) => Ok(resp.into_streaming()),
+        }
+    }
+
+    /// Sends the request with the transport, and stores the cookies that are set by the response
+    async fn send_network(&self, req: &Request) -> Result<(Response, BodyReader)> {
+        let (resp, body) = self.client.send_streaming(req).await?;
+
+        if let Some(cookies) = &self.cookies {
+            if let (Ok(url), Ok(mut cookies)) = (Url::parse(&req.uri), cookies.lock()) {
+                cookies.store_response_cookies(&url, &resp);
+            }
+        }
+
+        Ok((resp, body))
+    }
+
+    /// Returns the request with the cookies from the cookie store added to its `Cookie` header
+    fn add_cookies(&self, req: &Request) -> Request {
+        let mut req = req.clone();
+
+        let Some(cookies) = &self.cookies else {
+            return req;
+        };
+        let (Ok(url), Ok(mut cookies)) = (Url::parse(&req.uri), cookies.lock()) else {
+            return req;
+        };
+        let Some(header) = cookies.cookie_header(&url, req.initiator.as_ref()) else {
+            return req;
+        };
+
+        // Cookies that are explicitly set on the request come first
+        let header = match req.headers.get_ignore_case("cookie") {
+            Some(existing) => format!("{existing}; {header}"),
+            None => header,
+        };
+        req.headers.remove_ignore_case("cookie");
+        req.headers.set("Cookie", &header);
+
+        req
     }
 
     pub fn parse_url(&self, url: &str) -> Result<Url> {
         let mut parsed_url = Url::parse(url);
 
         if parsed_url == Err(ParseError::RelativeUrlWithoutBase) {
-            parsed_url = self.base_url.join(url);
+            parsed_url = self.base().join(url);
         }
 
         Ok(parsed_url?)
     }
 }
+
+/// Reads the body of a response from the network, and stores the complete response in the cache at the end of the
+/// body. Nothing is stored when the body is not read completely.
+struct CachingReader {
+    cache: Arc<Mutex<HttpCache>>,
+    req: Request,
+    /// The head of the response, with the body read so far. This is taken when the response is stored.
+    resp: Option<Response>,
+    request_time: u64,
+    body: BodyReader,
+}
+
+impl Read for CachingReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.body.read(buf)?;
+
+        if n > 0 {
+            if let Some(resp) = &mut self.resp {
+                resp.body.extend_from_slice(&buf[..n]);
+            }
+        } else if let Some(resp) = self.resp.take() {
+            if let Ok(mut cache) = self.cache.lock() {
+                cache.update(&self.req, resp, self.request_time);
+            }
+        }
+
+        Ok(n)
+    }
+}
+
+/// Returns the request that follows a redirect with the given status from one URL to the next
+///
+/// https://fetch.spec.whatwg.org/#http-redirect-fetch
+fn redirect_request(req: &Request, from: &Url, to: &Url, status: u16) -> Request {
+    let mut next = req.clone();
+    next.uri = to.to_string();
+
+    let method = req.method.cow_to_ascii_uppercase();
+    let to_get = match status {
+        301 | 302 => method == "POST",
+        303 => method != "GET" && method != "HEAD",
+        _ => false,
+    };
+    if to_get {
+        next.method = "GET".to_string();
+        next.body.clear();
+        for header in BODY_HEADERS {
+            next.headers.remove_ignore_case(header);
+        }
+    }
+
+    // Credentials are not passed on to another origin. Cookies from the cookie store are added again for the new
+    // URL when the request is sent.
+    if from.origin() != to.origin() {
+        for header in CREDENTIAL_HEADERS {
+            next.headers.remove_ignore_case(header);
+        }
+        next.cookies = CookieJar::default();
+    }
+
+    next
+}
+
+/// Reads the file the URL points to. The path of the URL is percent-decoded, so paths with spaces and other special
+/// characters work.
+#[cfg(not(target_arch = "wasm32"))]
+fn read_file(url: &Url) -> Result<Response> {
+    let Ok(path) = url.to_file_path() else {
+        bail!("{}: not a local file path", url);
+    };
+
+    Ok(Response::from(std::fs::read(path)?))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read_file(url: &Url) -> Result<Response> {
+    bail!("{}: local files can not be read in the browser", url)
+}
+
+/// Returns the built-in page for an `about:` URL
+///
+/// https://fetch.spec.whatwg.org/#scheme-fetch
+fn about_page(url: &Url) -> Result<Response> {
+    match url.path() {
+        "blank" | "srcdoc" => {
+            let mut resp = Response::from(Vec::new());
+            resp.headers.set("Content-Type", "text/html;charset=utf-8");
+            Ok(resp)
+        }
+        _ => bail!("Unknown about page: {}", url),
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use cookie::Cookie;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Starts a server that answers a single request with the given status, and returns the raw request it received
+    fn serve_once(status: &'static str) -> (Url, thread::JoinHandle<String>) {
+        let response = format!("HTTP/1.1 {status}\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok");
+        let (url, handle) = serve(vec![response]);
+
+        (url, thread::spawn(move || handle.join().unwrap().remove(0)))
+    }
+
+    /// Starts a server that answers a request with each of the given raw responses in turn, and returns the raw
+    /// requests it received
+    fn serve(responses: Vec<String>) -> (Url, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+
+                let mut request = String::new();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(len) = line.cow_to_ascii_lowercase().strip_prefix("content-length:") {
+                        content_length = len.trim().parse().unwrap();
+                    }
+                    request.push_str(&line);
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                request.push_str(&String::from_utf8(body).unwrap());
+
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+                requests.push(request);
+            }
+
+            requests
+        });
+
+        (url, handle)
+    }
+
+    #[test]
+    fn get_req_sends_method_headers_cookies_and_body() {
+        let (url, server) = serve_once("201 Created");
+        let fetcher = Fetcher::with_cache(url, None).with_cookie_store(None);
+
+        let mut req = Request::new("post", "/form", "HTTP/1.1");
+        req.add_header("Content-Type", "application/x-www-form-urlencoded");
+        req.add_header("X-Custom", "gosub");
+        req.cookies.add(Cookie::new("session", "1234"));
+        req.body(b"name=gosub&lang=rust".to_vec());
+
+        let resp = futures::executor::block_on(fetcher.get_req(&req)).unwrap();
+        assert_eq!(resp.status, 201);
+        assert_eq!(resp.body, b"ok");
+
+        let request = server.join().unwrap();
+        let request = request.cow_to_ascii_lowercase();
+        assert!(request.starts_with("post /form http/1.1\r\n"));
+        assert!(request.contains("content-type: application/x-www-form-urlencoded\r\n"));
+        assert!(request.contains("x-custom: gosub\r\n"));
+        assert!(request.contains("cookie: session=1234\r\n"));
+        assert!(request.ends_with("\r\n\r\nname=gosub&lang=rust"));
+    }
+
+    #[test]
+    fn get_req_returns_error_responses() {
+        let (url, server) = serve_once("404 Not Found");
+        let fetcher = Fetcher::with_cache(url, None).with_cookie_store(None);
+
+        let req = Request::new("DELETE", "/item/1", "HTTP/1.1");
+        let resp = futures::executor::block_on(fetcher.get_req(&req)).unwrap();
+        assert_eq!(resp.status, 404);
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("DELETE /item/1 HTTP/1.1\r\n"));
+
+        let req = Request::new("BREW", "/pot", "HTTP/1.1");
+        assert!(futures::executor::block_on(fetcher.get_req(&req)).is_err());
+    }
+
+    #[test]
+    fn get_uses_cache() {
+        let (url, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nCache-Control: max-age=0\r\nETag: \"v1\"\r\nContent-Length: 5\r\nConnection: close\r\n\r\nfirst".to_string(),
+            "HTTP/1.1 304 Not Modified\r\nCache-Control: max-age=60\r\nConnection: close\r\n\r\n".to_string(),
+        ]);
+        let cache = HttpCache::new(1024, 1024);
+        let fetcher = Fetcher::with_cache(url, Some(Arc::new(Mutex::new(cache))));
+
+        // Stored, but stale right away
+        let resp = futures::executor::block_on(fetcher.get("/page")).unwrap();
+        assert_eq!(resp.body, b"first");
+
+        // Revalidated with the etag, and now fresh for another minute
+        let resp = futures::executor::block_on(fetcher.get("/page")).unwrap();
+        assert_eq!(resp.status, 200);
+        assert_eq!(resp.body, b"first");
+
+        // Served from the cache, the server would not accept another connection
+        let resp = futures::executor::block_on(fetcher.get("/page#top")).unwrap();
+        assert_eq!(resp.body, b"first");
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(!requests[0].cow_to_ascii_lowercase().contains("if-none-match"));
+        assert!(requests[1]
+            .cow_to_ascii_lowercase()
+            .contains("if-none-match: \"v1\"\r\n"));
+    }
+
+    #[test]
+    fn streaming_body() {
+        // A server that sends the second part of the body only when the first part has been read
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while line != "\r\n" {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+            }
+
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nCache-Control: max-age=60\r\nContent-Length: 12\r\n\r\nfirst")
+                .unwrap();
+            rx.recv().unwrap();
+            stream.write_all(b" second").unwrap();
+        });
+
+        let cache = HttpCache::new(1024, 1024);
+        let fetcher = Fetcher::with_cache(url.clone(), Some(Arc::new(Mutex::new(cache))));
+
+        let (resp, mut body) =
+            futures::executor::block_on(fetcher.get_url_streaming(&url.join("/page").unwrap())).unwrap();
+        assert_eq!(resp.status, 200);
+        assert!(resp.body.is_empty());
+
+        let mut first = [0; 5];
+        body.read_exact(&mut first).unwrap();
+        assert_eq!(&first, b"first");
+
+        tx.send(()).unwrap();
+        let mut rest = Vec::new();
+        body.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b" second");
+        server.join().unwrap();
+
+        // The complete body has been stored, the server would not accept another connection
+        let resp = futures::executor::block_on(fetcher.get("/page")).unwrap();
+        assert_eq!(resp.body, b"first second");
+    }
+
+    #[test]
+    fn cookies_are_stored_and_sent() {
+        let (url, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nSet-Cookie: session=1234; Path=/; HttpOnly\r\nSet-Cookie: theme=dark; Path=/settings\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+            "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+        ]);
+        let store = CookieStore::new(cookies::PublicSuffixList::builtin(), 10, 10);
+        let fetcher = Fetcher::with_cache(url, None).with_cookie_store(Some(Arc::new(Mutex::new(store))));
+
+        let resp = futures::executor::block_on(fetcher.get("/login")).unwrap();
+        assert_eq!(resp.cookies.get("session").unwrap(), "1234");
+        assert_eq!(resp.set_cookies.len(), 2);
+
+        let mut req = Request::new("GET", "/home", "HTTP/1.1");
+        req.add_header("Cookie", "explicit=1");
+        futures::executor::block_on(fetcher.get_req(&req)).unwrap();
+
+        let requests = server.join().unwrap();
+        assert!(!requests[0].cow_to_ascii_lowercase().contains("cookie:"));
+        assert!(requests[1]
+            .cow_to_ascii_lowercase()
+            .contains("cookie: explicit=1; session=1234\r\n"));
+    }
+
+    #[test]
+    fn same_site_cookies_of_subresources() {
+        let ok = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string();
+        let (url, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nSet-Cookie: strict=1; SameSite=Strict\r\nSet-Cookie: plain=2\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+            ok.clone(),
+            ok,
+        ]);
+        let store = CookieStore::new(cookies::PublicSuffixList::builtin(), 10, 10);
+        let fetcher = Fetcher::with_cache(url.clone(), None).with_cookie_store(Some(Arc::new(Mutex::new(store))));
+
+        futures::executor::block_on(fetcher.get("/")).unwrap();
+        let image = url.join("/image.png").unwrap();
+        futures::executor::block_on(fetcher.get_subresource(&image)).unwrap();
+
+        fetcher.set_base(Url::parse("http://example.org/").unwrap());
+        futures::executor::block_on(fetcher.get_subresource(&image)).unwrap();
+
+        let requests = server.join().unwrap();
+        assert!(requests[1].contains("cookie: strict=1; plain=2\r\n"));
+        assert!(requests[2].contains("cookie: plain=2\r\n"));
+    }
+
+    fn raw(status: &str, headers: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {status}\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    #[test]
+    fn redirects() {
+        let (url, server) = serve(vec![
+            raw("303 See Other", "Location: /result\r\n", ""),
+            raw("307 Temporary Redirect", "Location: final?x=1\r\n", ""),
+            raw("200 OK", "", "done"),
+        ]);
+        let fetcher = Fetcher::with_cache(url.clone(), None).with_cookie_store(None);
+
+        let mut req = Request::new("POST", "/form#top", "HTTP/1.1");
+        req.add_header("Content-Type", "text/plain");
+        req.body(b"data".to_vec());
+
+        let resp = futures::executor::block_on(fetcher.get_req(&req)).unwrap();
+        assert_eq!(resp.body, b"done");
+        assert_eq!(resp.url, Some(url.join("/final?x=1#top").unwrap()));
+        assert_eq!(
+            resp.redirects,
+            vec![url.join("/form#top").unwrap(), url.join("/result#top").unwrap()]
+        );
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("POST /form HTTP/1.1\r\n"));
+        assert!(requests[0].ends_with("data"));
+        assert!(requests[0].contains(&format!("{}\r\n", fetcher.policy().user_agent)));
+        // A 303 changes the request into a GET without body
+        assert!(requests[1].starts_with("GET /result HTTP/1.1\r\n"));
+        assert!(!requests[1].cow_to_ascii_lowercase().contains("content-type"));
+        assert!(requests[2].starts_with("GET /final?x=1 HTTP/1.1\r\n"));
+
+        // Too many redirects
+        let (url, server) = serve(vec![
+            raw("301 Moved Permanently", "Location: /b\r\n", ""),
+            raw("308 Permanent Redirect", "Location: /c\r\n", ""),
+        ]);
+        let policy = FetchPolicy {
+            max_redirects: 1,
+            ..FetchPolicy::default()
+        };
+        let fetcher = Fetcher::with_cache(url, None)
+            .with_cookie_store(None)
+            .with_policy(policy);
+        assert!(futures::executor::block_on(fetcher.get("/a")).is_err());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn cross_origin_redirect_drops_credentials() {
+        let (other, other_server) = serve(vec![raw("200 OK", "", "done")]);
+        let (url, server) = serve(vec![raw("302 Found", &format!("Location: {other}next\r\n"), "")]);
+        let fetcher = Fetcher::with_cache(url, None).with_cookie_store(None);
+
+        let mut req = Request::new("GET", "/start", "HTTP/1.1");
+        req.add_header("Cookie", "session=1234");
+        req.add_header("Authorization", "Basic Z29zdWI6c2VjcmV0");
+        req.add_header("Proxy-Authorization", "Basic Z29zdWI6c2VjcmV0");
+        req.add_header("X-Custom", "gosub");
+        req.cookies.add(Cookie::new("jar", "5678"));
+
+        let resp = futures::executor::block_on(fetcher.get_req(&req)).unwrap();
+        assert_eq!(resp.body, b"done");
+
+        let first = server.join().unwrap().remove(0).cow_to_ascii_lowercase().into_owned();
+        assert!(first.contains("cookie: session=1234; jar=5678\r\n"));
+        assert!(first.contains("proxy-authorization:"));
+
+        // The port differs, so the redirect goes to another origin
+        let second = other_server
+            .join()
+            .unwrap()
+            .remove(0)
+            .cow_to_ascii_lowercase()
+            .into_owned();
+        assert!(second.starts_with("get /next http/1.1\r\n"));
+        assert!(second.contains("x-custom: gosub\r\n"));
+        assert!(!second.contains("cookie"));
+        assert!(!second.contains("authorization"));
+    }
+
+    #[test]
+    fn timeouts_and_size_limits() {
+        let policy = FetchPolicy {
+            request_timeout: Some(Duration::from_millis(200)),
+            max_body_size: 10,
+            ..FetchPolicy::default()
+        };
+
+        // A server that accepts the connection, but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            thread::sleep(Duration::from_secs(1));
+            drop(stream);
+        });
+
+        let fetcher = Fetcher::with_cache(url, None)
+            .with_cookie_store(None)
+            .with_policy(policy.clone());
+        let start = Instant::now();
+        assert!(futures::executor::block_on(fetcher.get("/slow")).is_err());
+        assert!(start.elapsed() < Duration::from_millis(900));
+        server.join().unwrap();
+
+        let (url, server) = serve(vec![raw("200 OK", "", "this body is too large")]);
+        let fetcher = Fetcher::with_cache(url, None)
+            .with_cookie_store(None)
+            .with_policy(policy);
+        assert!(futures::executor::block_on(fetcher.get("/large")).is_err());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn local_schemes() {
+        let fetcher = Fetcher::with_cache(Url::parse("about:blank").unwrap(), None).with_cookie_store(None);
+
+        let resp = futures::executor::block_on(fetcher.get("data:text/html,%3Ch1%3Ehi%3C/h1%3E")).unwrap();
+        assert_eq!(resp.body, b"<h1>hi</h1>");
+        assert_eq!(resp.headers.get("Content-Type"), Some(&"text/html".to_string()));
+        assert_eq!(resp.url.unwrap().scheme(), "data");
+
+        let resp = futures::executor::block_on(fetcher.get("about:blank")).unwrap();
+        assert_eq!(resp.status, 200);
+        assert!(resp.body.is_empty());
+        assert!(futures::executor::block_on(fetcher.get("about:unknown")).is_err());
+
+        let origin = Url::parse("https://example.com/").unwrap();
+        let url = blob::register_blob(&origin, blob::Blob::new(b"blob data".to_vec(), "text/plain"));
+        let resp = futures::executor::block_on(fetcher.get_url(&url)).unwrap();
+        assert_eq!(resp.body, b"blob data");
+        blob::revoke_blob(&url);
+        assert!(futures::executor::block_on(fetcher.get_url(&url)).is_err());
+
+        // Percent-encoded characters in the path are decoded
+        let dir = std::env::temp_dir().join(format!("gosub-fetcher-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("hello world.html");
+        std::fs::write(&path, b"file data").unwrap();
+
+        let url = Url::from_file_path(&path).unwrap();
+        assert!(url.as_str().ends_with("hello%20world.html"));
+        let resp = futures::executor::block_on(fetcher.get_url(&url)).unwrap();
+        assert_eq!(resp.body, b"file data");
+
+        let mut req = Request::new("HEAD", url.as_str(), "HTTP/1.1");
+        let resp = futures::executor::block_on(fetcher.get_req(&req)).unwrap();
+        assert!(resp.body.is_empty());
+        req.method = "POST".to_string();
+        assert!(futures::executor::block_on(fetcher.get_req(&req)).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(futures::executor::block_on(fetcher.get("ftp://example.com/file")).is_err());
+    }
+
+    #[test]
+    fn injected_transport() {
+        let mut redirect = Response::new();
+        redirect.status = 302;
+        redirect.headers.set("Location", "/home");
+        redirect.add_set_cookie("session=abc");
+
+        let mut transport = transport::ReplayTransport::new();
+        transport.add("GET", &Url::parse("https://example.com/").unwrap(), redirect);
+        transport.add(
+            "GET",
+            &Url::parse("https://example.com/home").unwrap(),
+            Response::from(b"home".to_vec()),
+        );
+
+        let cookies = Arc::new(Mutex::new(CookieStore::new(
+            cookies::PublicSuffixList::builtin(),
+            10,
+            10,
+        )));
+        let fetcher = Fetcher::with_cache(Url::parse("https://example.com/").unwrap(), None)
+            .with_cookie_store(Some(cookies.clone()))
+            .with_transport(Arc::new(transport));
+
+        let resp = futures::executor::block_on(fetcher.get("/")).unwrap();
+        assert_eq!(resp.body, b"home");
+        assert_eq!(resp.url.unwrap().as_str(), "https://example.com/home");
+        assert_eq!(cookies.lock().unwrap().cookies().len(), 1);
+
+        assert!(futures::executor::block_on(fetcher.get("/other")).is_err());
+    }
+
+    #[test]
+    fn resolves_with_dns() {
+        let (url, server) = serve_once("200 OK");
+
+        let mut table = crate::dns::LocalTableResolver::new();
+        table.add_entry("gosub.test", vec!["::1", "127.0.0.1"]);
+        let dns = Arc::new(crate::dns::Dns::with_resolvers(vec![Box::new(table)]));
+
+        // The ipv6 address is tried first, and the connection falls back to ipv4 when nothing listens on it
+        let port = url.port().unwrap();
+        let fetcher = Fetcher::with_cache(Url::parse(&format!("http://gosub.test:{port}/")).unwrap(), None)
+            .with_cookie_store(None)
+            .with_transport(transport::network_transport(dns));
+
+        let resp = futures::executor::block_on(fetcher.get("/page")).unwrap();
+        assert_eq!(resp.body, b"ok");
+
+        let request = server.join().unwrap();
+        assert!(request.cow_to_ascii_lowercase().contains("host: gosub.test"));
+
+        assert!(futures::executor::block_on(fetcher.get("http://unknown.test/")).is_err());
+    }
+}
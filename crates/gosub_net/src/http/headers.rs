@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, PartialEq)]
 pub struct Headers {
     headers: HashMap<String, String>,
 }
@@ -26,6 +26,19 @@ impl Headers {
         self.headers.get(key)
     }
 
+    /// Returns the value of the header, ignoring the case of the key as HTTP header names are case-insensitive
+    pub fn get_ignore_case(&self, key: &str) -> Option<&String> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v)
+    }
+
+    /// Removes the header, ignoring the case of the key
+    pub fn remove_ignore_case(&mut self, key: &str) {
+        self.headers.retain(|k, _| !k.eq_ignore_ascii_case(key));
+    }
+
     /// Returns all the header entries. Note that there is no ordering in here!
     pub fn all(&self) -> &HashMap<String, String> {
         &self.headers
@@ -52,5 +65,10 @@ mod tests {
         headers.set("Content-Type", "text/html");
         assert_eq!(headers.get("Content-Type").unwrap(), "text/html");
         assert_eq!(headers.all().len(), 1);
+
+        assert_eq!(headers.get("content-type"), None);
+        assert_eq!(headers.get_ignore_case("content-type").unwrap(), "text/html");
+        headers.remove_ignore_case("CONTENT-TYPE");
+        assert!(headers.all().is_empty());
     }
 }
@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use gosub_config::{config, config_store};
+
+/// User agent that is sent when no other user agent is configured
+pub const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (compatible; gosub/0.1; +https://gosub.io)";
+
+/// Defines how the fetcher follows redirects, how long it waits for responses and how large responses may be
+#[derive(Clone, Debug, PartialEq)]
+pub struct FetchPolicy {
+    /// Maximum number of redirects that are followed before the fetch fails
+    pub max_redirects: usize,
+    /// Time a single request (one hop of the redirect chain) may take, including reading the body
+    pub request_timeout: Option<Duration>,
+    /// Time the whole fetch may take, including all redirects
+    pub total_timeout: Option<Duration>,
+    /// Maximum number of bytes of a response body
+    pub max_body_size: u64,
+    /// Value of the `User-Agent` header, unless the request sets its own
+    pub user_agent: String,
+}
+
+impl Default for FetchPolicy {
+    fn default() -> Self {
+        Self {
+            max_redirects: 20,
+            request_timeout: Some(Duration::from_secs(30)),
+            total_timeout: Some(Duration::from_secs(120)),
+            max_body_size: 10_000_000,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+        }
+    }
+}
+
+impl FetchPolicy {
+    /// Creates a policy from the `http.fetch.*` settings
+    pub fn from_config() -> Self {
+        let seconds = |secs: usize| (secs > 0).then(|| Duration::from_secs(secs as u64));
+
+        let user_agent = match config!(string "http.fetch.user_agent") {
+            user_agent if user_agent.is_empty() => DEFAULT_USER_AGENT.to_string(),
+            user_agent => user_agent,
+        };
+
+        Self {
+            max_redirects: config!(uint "http.fetch.max_redirects"),
+            request_timeout: seconds(config!(uint "http.fetch.request_timeout")),
+            total_timeout: seconds(config!(uint "http.fetch.total_timeout")),
+            max_body_size: config!(uint "http.fetch.max_body_size") as u64,
+            user_agent,
+        }
+    }
+}
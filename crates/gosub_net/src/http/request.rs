@@ -1,6 +1,8 @@
 use crate::http::headers::Headers;
 use cookie::CookieJar;
 use core::fmt::{Display, Formatter};
+use std::time::Duration;
+use url::Url;
 
 #[derive(Debug, Default, Clone)]
 pub struct Request {
@@ -10,6 +12,13 @@ pub struct Request {
     pub headers: Headers,
     pub cookies: CookieJar,
     pub body: Vec<u8>,
+    /// Time the request may take, including reading the response body, or None to wait forever
+    pub timeout: Option<Duration>,
+    /// Maximum number of bytes of the response body, or None for the default limit of the transport
+    pub max_body_size: Option<u64>,
+    /// URL of the document that requests a subresource (like an image or a script), or None for a navigation. Cookies
+    /// with a SameSite attribute are only sent when the request goes to the same site as this document.
+    pub initiator: Option<Url>,
 }
 
 impl Request {
@@ -21,6 +30,9 @@ impl Request {
             headers: Headers::default(),
             cookies: CookieJar::default(),
             body: vec![],
+            timeout: None,
+            max_body_size: None,
+            initiator: None,
         }
     }
 
@@ -35,6 +47,28 @@ impl Request {
     pub fn cookies(&mut self, cookies: CookieJar) {
         self.cookies = cookies;
     }
+
+    pub fn body(&mut self, body: Vec<u8>) {
+        self.body = body;
+    }
+
+    /// Returns the value of the `Cookie` header for the cookies in the jar, or None when the jar is empty. Cookies
+    /// are sorted by name so the header is stable.
+    pub fn cookie_header(&self) -> Option<String> {
+        let mut cookies = self.cookies.iter().collect::<Vec<_>>();
+        if cookies.is_empty() {
+            return None;
+        }
+
+        cookies.sort_by(|a, b| a.name().cmp(b.name()));
+        Some(
+            cookies
+                .iter()
+                .map(|cookie| format!("{}={}", cookie.name(), cookie.value()))
+                .collect::<Vec<_>>()
+                .join("; "),
+        )
+    }
 }
 
 impl Display for Request {
@@ -95,4 +129,14 @@ mod tests {
         let s = format!("{}", req);
         assert_eq!(s, "GET / HTTP/1.1\nHeaders:\n  Accept: text/html\n  Accept-Encoding: gzip, deflate, br\n  Content-Type: application/json\nCookies:\n  foo=bar\n  qux=wok\nBody: 0 bytes\n");
     }
+
+    #[test]
+    fn test_cookie_header() {
+        let mut req = Request::new("POST", "/", "HTTP/1.1");
+        assert_eq!(req.cookie_header(), None);
+
+        req.cookies.add(Cookie::new("qux", "wok"));
+        req.cookies.add(Cookie::new("foo", "bar"));
+        assert_eq!(req.cookie_header(), Some("foo=bar; qux=wok".to_string()));
+    }
 }
@@ -1,9 +1,24 @@
-use ureq::{http, Agent, Body};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{mpsc, Arc};
 
+use anyhow::bail;
+use cow_utils::CowUtils;
+use ureq::config::Config;
+use ureq::typestate::{WithBody, WithoutBody};
+use ureq::unversioned::resolver::{ResolvedSocketAddrs, Resolver};
+use ureq::unversioned::transport::{ConnectProxyConnector, Connector, NextTimeout, RustlsConnector, SocksConnector};
+use ureq::{http, Agent, Body, RequestBuilder};
+
+use crate::dns::{self, Dns, ResolveType};
 use crate::http::fetcher::RequestAgent;
 use crate::http::headers::Headers;
 use crate::http::request::Request;
-use crate::http::response::Response;
+use crate::http::response::{BodyReader, Response};
+use crate::http::transport::{StreamingFuture, Transport, TransportFuture};
+
+use happy_eyeballs::HappyEyeballsConnector;
+
+mod happy_eyeballs;
 
 #[derive(Debug)]
 pub struct UreqAgent {
@@ -16,11 +31,29 @@ impl From<Agent> for UreqAgent {
     }
 }
 
+impl UreqAgent {
+    /// Creates an agent that resolves host names with the given DNS system, and connects to hosts with multiple
+    /// addresses using happy eyeballs. Connections through a SOCKS proxy are made by the proxy instead.
+    pub fn with_dns(dns: Arc<Dns>) -> Self {
+        Self::with_config(dns, Config::default())
+    }
+
+    fn with_config(dns: Arc<Dns>, config: Config) -> Self {
+        let connector =
+            ().chain(SocksConnector::default())
+                .chain(HappyEyeballsConnector)
+                .chain(RustlsConnector::default())
+                .chain(ConnectProxyConnector::default());
+
+        Agent::with_parts(config, connector, DnsNameResolver { dns }).into()
+    }
+}
+
 impl RequestAgent for UreqAgent {
     type Error = http::Error;
 
     fn new() -> Self {
-        Agent::new_with_defaults().into()
+        Self::with_dns(dns::shared_dns())
     }
 
     async fn get(&self, url: &str) -> gosub_shared::types::Result<Response> {
@@ -28,11 +61,171 @@ impl RequestAgent for UreqAgent {
         response.try_into()
     }
 
-    async fn get_req(&self, _req: &Request) -> gosub_shared::types::Result<Response> {
-        todo!()
+    async fn get_req(&self, req: &Request) -> gosub_shared::types::Result<Response> {
+        to_response(self.call(req)?, req.max_body_size)
+    }
+}
+
+impl UreqAgent {
+    /// Sends the request and returns the response as soon as its head has arrived
+    fn call(&self, req: &Request) -> gosub_shared::types::Result<http::Response<Body>> {
+        let uri = req.uri.as_str();
+
+        let response = match req.method.cow_to_ascii_uppercase().as_ref() {
+            "GET" => send_without_body(self.agent.get(uri), req)?,
+            "HEAD" => send_without_body(self.agent.head(uri), req)?,
+            "DELETE" => send_without_body(self.agent.delete(uri), req)?,
+            "OPTIONS" => send_without_body(self.agent.options(uri), req)?,
+            "POST" => send_with_body(self.agent.post(uri), req)?,
+            "PUT" => send_with_body(self.agent.put(uri), req)?,
+            "PATCH" => send_with_body(self.agent.patch(uri), req)?,
+            method => bail!("Unsupported request method: {}", method),
+        };
+
+        Ok(response)
+    }
+}
+
+impl Transport for UreqAgent {
+    fn send<'a>(&'a self, req: &'a Request) -> TransportFuture<'a> {
+        Box::pin(self.get_req(req))
+    }
+
+    fn send_streaming<'a>(&'a self, req: &'a Request) -> StreamingFuture<'a> {
+        Box::pin(async move {
+            let response = self.call(req)?;
+            let head = to_head(&response);
+
+            // The limit is checked while the body is read, like the limit of a complete response
+            let body: BodyReader = match req.max_body_size {
+                Some(limit) => Box::new(response.into_body().into_with_config().limit(limit).reader()),
+                None => Box::new(response.into_body().into_reader()),
+            };
+
+            Ok((head, body))
+        })
+    }
+}
+
+/// Maximum number of addresses ureq accepts from a resolver
+const MAX_ADDRS: usize = 16;
+
+/// Name resolver that looks up hosts with our own DNS system, so the DNS cache, the local override table and the
+/// configured nameservers are used for all requests
+#[derive(Debug)]
+struct DnsNameResolver {
+    dns: Arc<Dns>,
+}
+
+impl DnsNameResolver {
+    /// Returns the addresses of the host, with the ipv6 addresses first. The lookup runs on a thread of its own, as
+    /// the remote resolver can not block from within an async runtime, and so the timeout can be enforced.
+    fn lookup(&self, host: &str, timeout: NextTimeout) -> Result<Vec<IpAddr>, ureq::Error> {
+        let (tx, rx) = mpsc::sync_channel(1);
+
+        let dns = self.dns.clone();
+        let host = host.to_string();
+        dns::spawn_lookup(move || {
+            let _ = tx.send(dns.resolve(&host, ResolveType::Both));
+        });
+
+        let result = match timeout.not_zero() {
+            Some(after) => rx
+                .recv_timeout(*after)
+                .map_err(|_| ureq::Error::Timeout(timeout.reason))?,
+            None => rx.recv().map_err(|_| ureq::Error::HostNotFound)?,
+        };
+        let entry = result.map_err(|_| ureq::Error::HostNotFound)?;
+
+        let mut ips = entry.ipv6();
+        ips.extend(entry.ipv4());
+        Ok(ips)
+    }
+}
+
+impl Resolver for DnsNameResolver {
+    fn resolve(
+        &self,
+        uri: &http::Uri,
+        config: &Config,
+        timeout: NextTimeout,
+    ) -> Result<ResolvedSocketAddrs, ureq::Error> {
+        let Some(authority) = uri.authority() else {
+            return Err(ureq::Error::BadUri(format!("{uri} has no host")));
+        };
+        let port = match (authority.port_u16(), uri.scheme_str()) {
+            (Some(port), _) => port,
+            (None, Some("http" | "ws")) => 80,
+            (None, Some("https" | "wss")) => 443,
+            (None, Some("socks4" | "socks4a" | "socks5")) => 1080,
+            (None, scheme) => return Err(ureq::Error::BadUri(format!("unknown scheme: {scheme:?}"))),
+        };
+
+        // IP addresses in URLs need no lookup
+        let host = authority.host().trim_start_matches('[').trim_end_matches(']');
+        let ips = match host.parse::<IpAddr>() {
+            Ok(ip) => vec![ip],
+            Err(_) => self.lookup(host, timeout)?,
+        };
+
+        let mut addrs = ResolvedSocketAddrs::from_fn(|_| SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0));
+        let ip_family = config.ip_family();
+        let wanted = ip_family.keep_wanted(ips.into_iter().map(|ip| SocketAddr::new(ip, port)));
+        for addr in wanted.take(MAX_ADDRS) {
+            addrs.push(addr);
+        }
+
+        match addrs.is_empty() {
+            true => Err(ureq::Error::HostNotFound),
+            false => Ok(addrs),
+        }
     }
 }
 
+/// Adds the headers and cookies of the request to the builder. Responses with an error status and redirects are
+/// returned like any other response, as it is up to the caller to handle them.
+fn prepare<B>(mut builder: RequestBuilder<B>, req: &Request) -> RequestBuilder<B> {
+    let mut cookie_header = req.cookie_header();
+
+    for (key, value) in req.headers.sorted() {
+        if key.eq_ignore_ascii_case("cookie") {
+            // Explicit cookies are sent together with the cookies from the jar
+            cookie_header = Some(match cookie_header {
+                Some(cookies) => format!("{value}; {cookies}"),
+                None => value.clone(),
+            });
+            continue;
+        }
+        builder = builder.header(key, value);
+    }
+
+    if let Some(cookies) = cookie_header {
+        builder = builder.header("Cookie", cookies);
+    }
+
+    builder
+        .config()
+        .http_status_as_error(false)
+        .max_redirects(0)
+        .timeout_global(req.timeout)
+        .build()
+}
+
+fn send_without_body(builder: RequestBuilder<WithoutBody>, req: &Request) -> Result<http::Response<Body>, ureq::Error> {
+    let builder = prepare(builder, req);
+
+    // Methods like GET normally have no body, but we send it when the request has one
+    if req.body.is_empty() {
+        builder.call()
+    } else {
+        builder.force_send_body().send(req.body.as_slice())
+    }
+}
+
+fn send_with_body(builder: RequestBuilder<WithBody>, req: &Request) -> Result<http::Response<Body>, ureq::Error> {
+    prepare(builder, req).send(req.body.as_slice())
+}
+
 fn get_headers(http_headers: &http::header::HeaderMap) -> Headers {
     let mut headers = Headers::with_capacity(http_headers.len());
 
@@ -46,21 +239,119 @@ fn get_headers(http_headers: &http::header::HeaderMap) -> Headers {
 impl TryFrom<http::response::Response<Body>> for Response {
     type Error = anyhow::Error;
 
-    fn try_from(mut response: http::response::Response<Body>) -> Result<Self, Self::Error> {
-        Ok(Self {
-            status: response.status().as_u16(),
-            status_text: response.status().to_string(),
-            version: match response.version() {
-                http::Version::HTTP_09 => "http/0.9".into(),
-                http::Version::HTTP_10 => "http/1.0".into(),
-                http::Version::HTTP_11 => "http/1.1".into(),
-                http::Version::HTTP_2 => "http/2.0".into(),
-                http::Version::HTTP_3 => "http/3.0".into(),
-                _ => "http/1.0".into(),
-            },
-            headers: get_headers(response.headers()),
-            body: response.body_mut().read_to_vec()?,
-            cookies: Default::default(),
-        })
+    fn try_from(response: http::response::Response<Body>) -> Result<Self, Self::Error> {
+        to_response(response, None)
+    }
+}
+
+/// Converts the response, reading at most `max_body_size` bytes of the body (or the default limit of ureq)
+fn to_response(mut response: http::response::Response<Body>, max_body_size: Option<u64>) -> anyhow::Result<Response> {
+    let body = match max_body_size {
+        Some(limit) => response.body_mut().with_config().limit(limit).read_to_vec()?,
+        None => response.body_mut().read_to_vec()?,
+    };
+
+    let mut resp = to_head(&response);
+    resp.body = body;
+
+    Ok(resp)
+}
+
+/// Converts the status line and headers of the response, without reading the body
+fn to_head(response: &http::response::Response<Body>) -> Response {
+    let mut resp = Response {
+        status: response.status().as_u16(),
+        status_text: response.status().to_string(),
+        version: match response.version() {
+            http::Version::HTTP_09 => "http/0.9".into(),
+            http::Version::HTTP_10 => "http/1.0".into(),
+            http::Version::HTTP_11 => "http/1.1".into(),
+            http::Version::HTTP_2 => "http/2.0".into(),
+            http::Version::HTTP_3 => "http/3.0".into(),
+            _ => "http/1.0".into(),
+        },
+        headers: get_headers(response.headers()),
+        body: vec![],
+        cookies: Default::default(),
+        set_cookies: vec![],
+        url: None,
+        redirects: vec![],
+    };
+
+    for value in response.headers().get_all(http::header::SET_COOKIE) {
+        if let Ok(value) = value.to_str() {
+            resp.add_set_cookie(value);
+        }
+    }
+
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Starts a SOCKS5 proxy that accepts a single connection, and answers the HTTP request that is sent through it
+    /// itself. Returns the proxy URL and the address the client asked the proxy to connect to.
+    fn socks5_proxy() -> (String, thread::JoinHandle<SocketAddr>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("socks5://{}", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            // Greeting with the authentication methods, of which we pick "no authentication"
+            let mut greeting = [0; 2];
+            stream.read_exact(&mut greeting).unwrap();
+            let mut methods = vec![0; greeting[1] as usize];
+            stream.read_exact(&mut methods).unwrap();
+            assert!(methods.contains(&0));
+            stream.write_all(&[5, 0]).unwrap();
+
+            // Connect request for an ipv4 address
+            let mut request = [0; 10];
+            stream.read_exact(&mut request).unwrap();
+            assert_eq!(&request[..4], &[5, 1, 0, 1]);
+            let ip = Ipv4Addr::new(request[4], request[5], request[6], request[7]);
+            let target = SocketAddr::new(IpAddr::V4(ip), u16::from_be_bytes([request[8], request[9]]));
+            stream.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+
+            let mut reader = BufReader::new(stream);
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nproxy")
+                .unwrap();
+
+            target
+        });
+
+        (url, handle)
+    }
+
+    #[test]
+    fn socks_proxy() {
+        let (proxy, server) = socks5_proxy();
+        let config = Agent::config_builder()
+            .proxy(Some(ureq::Proxy::new(&proxy).unwrap()))
+            .build();
+        let agent = UreqAgent::with_config(Arc::new(Dns::with_resolvers(vec![])), config);
+
+        // The address is never connected to directly, only the proxy connects to it
+        let req = Request::new("GET", "http://192.0.2.1:8080/", "HTTP/1.1");
+        let resp = futures::executor::block_on(agent.get_req(&req)).unwrap();
+        assert_eq!(resp.status, 200);
+        assert_eq!(resp.body, b"proxy");
+
+        assert_eq!(server.join().unwrap(), "192.0.2.1:8080".parse().unwrap());
     }
 }
@@ -0,0 +1,315 @@
+//! Happy eyeballs
+//!
+//! Connects to a host that has both IPv6 and IPv4 addresses by racing connection attempts, as described in RFC 8305.
+//! The addresses are interleaved by family, and when an attempt has not connected after a short delay, the next
+//! attempt is started without cancelling the previous one. The first connection that is established wins. This way a
+//! broken IPv6 (or IPv4) network only delays a connection by the attempt delay, instead of a full connect timeout.
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+
+use log::{debug, trace};
+use mio::{Events, Interest, Poll, Token};
+use ureq::unversioned::transport::{
+    Buffers, ConnectionDetails, Connector, Either, LazyBuffers, NextTimeout, Transport,
+};
+
+/// Time to wait for a connection attempt before the next attempt is started (RFC 8305, section 5)
+pub(crate) const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Connector that opens a TCP connection by racing the resolved addresses
+#[derive(Debug, Default)]
+pub(crate) struct HappyEyeballsConnector;
+
+impl<In: Transport> Connector<In> for HappyEyeballsConnector {
+    type Out = Either<In, TcpTransport>;
+
+    fn connect(&self, details: &ConnectionDetails, chained: Option<In>) -> Result<Option<Self::Out>, ureq::Error> {
+        // A connection that is already made (through a SOCKS proxy) is used as it is
+        if chained.is_some() {
+            return Ok(chained.map(Either::A));
+        }
+
+        let timeout = details.timeout.not_zero().map(|timeout| *timeout);
+        let stream = match connect(&details.addrs, timeout) {
+            Ok(stream) => stream,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => return Err(ureq::Error::Timeout(details.timeout.reason)),
+            Err(e) => return Err(e.into()),
+        };
+
+        let config = details.config;
+        if config.no_delay() {
+            stream.set_nodelay(true)?;
+        }
+
+        let buffers = LazyBuffers::new(config.input_buffer_size(), config.output_buffer_size());
+        Ok(Some(Either::B(TcpTransport::new(stream, buffers))))
+    }
+}
+
+/// Orders the addresses for connection attempts: the families alternate, starting with the family of the first
+/// address (RFC 8305, section 4). The order within each family is kept.
+pub(crate) fn sort_addresses(addrs: &[SocketAddr]) -> Vec<SocketAddr> {
+    let Some(first) = addrs.first() else {
+        return vec![];
+    };
+
+    let (mut preferred, mut other): (Vec<SocketAddr>, Vec<SocketAddr>) =
+        addrs.iter().partition(|addr| addr.is_ipv6() == first.is_ipv6());
+    preferred.reverse();
+    other.reverse();
+
+    let mut sorted = Vec::with_capacity(addrs.len());
+    while !preferred.is_empty() || !other.is_empty() {
+        sorted.extend(preferred.pop());
+        sorted.extend(other.pop());
+    }
+
+    sorted
+}
+
+/// Connects to the first address that accepts a connection. A new attempt is started every time an attempt fails,
+/// or has not connected within the connection attempt delay. The attempts are non-blocking connects that are waited
+/// for together, and the attempts that lose the race are closed when the first connection is made.
+pub(crate) fn connect(addrs: &[SocketAddr], timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let addrs = sort_addresses(addrs);
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(addrs.len().max(1));
+    // The attempts in progress, by the index of their address, which is also the token of their events
+    let mut attempts: Vec<Option<mio::net::TcpStream>> = Vec::with_capacity(addrs.len());
+    let mut pending = 0;
+    let mut next_attempt = Instant::now();
+    let mut last_error = None;
+
+    loop {
+        let now = Instant::now();
+        if attempts.len() < addrs.len() && (pending == 0 || now >= next_attempt) {
+            let addr = addrs[attempts.len()];
+            trace!("connecting to {addr}");
+
+            let attempt = mio::net::TcpStream::connect(addr).and_then(|mut stream| {
+                poll.registry()
+                    .register(&mut stream, Token(attempts.len()), Interest::WRITABLE)?;
+                Ok(stream)
+            });
+            match attempt {
+                Ok(stream) => {
+                    attempts.push(Some(stream));
+                    pending += 1;
+                }
+                Err(e) => {
+                    trace!("could not connect to {addr}: {e}");
+                    attempts.push(None);
+                    last_error = Some(e);
+                }
+            }
+            next_attempt = now + CONNECTION_ATTEMPT_DELAY;
+            continue;
+        }
+
+        if pending == 0 {
+            debug!("failed to connect to any of {addrs:?}");
+            return Err(last_error
+                .unwrap_or_else(|| io::Error::new(io::ErrorKind::AddrNotAvailable, "no addresses to connect to")));
+        }
+
+        let remaining = deadline.map(|deadline| deadline.saturating_duration_since(now));
+        if remaining.is_some_and(|remaining| remaining.is_zero()) {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "connect timed out"));
+        }
+
+        // Wait for an attempt to finish, but no longer than the attempt delay when there is another address to try
+        let wait = match (attempts.len() < addrs.len(), remaining) {
+            (true, Some(remaining)) => Some(remaining.min(next_attempt - now)),
+            (true, None) => Some(next_attempt - now),
+            (false, remaining) => remaining,
+        };
+        match poll.poll(&mut events, wait) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+
+        for event in &events {
+            let Token(index) = event.token();
+            let Some(stream) = attempts.get_mut(index).and_then(Option::take) else {
+                continue;
+            };
+
+            match connected(&stream) {
+                Ok(true) => {
+                    debug!("connected to {}", addrs[index]);
+                    let stream = TcpStream::from(stream);
+                    stream.set_nonblocking(false)?;
+                    return Ok(stream);
+                }
+                // The event was spurious, so the attempt goes on
+                Ok(false) => attempts[index] = Some(stream),
+                Err(e) => {
+                    trace!("could not connect to {}: {e}", addrs[index]);
+                    pending -= 1;
+                    last_error = Some(e);
+                    // The next address is tried right away when an attempt fails
+                    next_attempt = Instant::now();
+                }
+            }
+        }
+    }
+}
+
+/// Returns true when the non-blocking connect of the stream has finished, or the error when it has failed
+fn connected(stream: &mio::net::TcpStream) -> io::Result<bool> {
+    if let Some(e) = stream.take_error()? {
+        return Err(e);
+    }
+
+    match stream.peer_addr() {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotConnected => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Transport over a TCP connection
+pub(crate) struct TcpTransport {
+    stream: TcpStream,
+    buffers: LazyBuffers,
+    timeout_read: Option<Duration>,
+    timeout_write: Option<Duration>,
+}
+
+impl TcpTransport {
+    fn new(stream: TcpStream, buffers: LazyBuffers) -> Self {
+        Self {
+            stream,
+            buffers,
+            timeout_read: None,
+            timeout_write: None,
+        }
+    }
+}
+
+/// Sets the timeout on the stream, unless it is already set to the same value
+fn update_timeout(
+    timeout: NextTimeout,
+    previous: &mut Option<Duration>,
+    stream: &TcpStream,
+    set: impl Fn(&TcpStream, Option<Duration>) -> io::Result<()>,
+) -> io::Result<()> {
+    let timeout = timeout.not_zero().map(|timeout| *timeout);
+    if timeout != *previous {
+        set(stream, timeout)?;
+        *previous = timeout;
+    }
+
+    Ok(())
+}
+
+/// Converts an IO error into a ureq error. Sockets with a timeout report a timeout as `WouldBlock` on some platforms.
+fn io_error(e: io::Error, timeout: NextTimeout) -> ureq::Error {
+    match e.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => ureq::Error::Timeout(timeout.reason),
+        _ => e.into(),
+    }
+}
+
+impl Transport for TcpTransport {
+    fn buffers(&mut self) -> &mut dyn Buffers {
+        &mut self.buffers
+    }
+
+    fn transmit_output(&mut self, amount: usize, timeout: NextTimeout) -> Result<(), ureq::Error> {
+        update_timeout(
+            timeout,
+            &mut self.timeout_write,
+            &self.stream,
+            TcpStream::set_write_timeout,
+        )?;
+
+        let output = &self.buffers.output()[..amount];
+        self.stream.write_all(output).map_err(|e| io_error(e, timeout))
+    }
+
+    fn await_input(&mut self, timeout: NextTimeout) -> Result<bool, ureq::Error> {
+        if self.buffers.can_use_input() {
+            return Ok(true);
+        }
+
+        update_timeout(
+            timeout,
+            &mut self.timeout_read,
+            &self.stream,
+            TcpStream::set_read_timeout,
+        )?;
+
+        let input = self.buffers.input_append_buf();
+        let amount = self.stream.read(input).map_err(|e| io_error(e, timeout))?;
+        self.buffers.input_appended(amount);
+
+        Ok(amount > 0)
+    }
+
+    fn is_open(&mut self) -> bool {
+        // A pooled connection is still usable when there is nothing to read, and reading would block
+        if self.stream.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let mut buf = [0];
+        let open = matches!(self.stream.read(&mut buf), Err(e) if e.kind() == io::ErrorKind::WouldBlock);
+
+        open && self.stream.set_nonblocking(false).is_ok()
+    }
+}
+
+impl std::fmt::Debug for TcpTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TcpTransport")
+            .field("addr", &self.stream.peer_addr().ok())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn address_order() {
+        let addr = |s: &str| s.parse::<SocketAddr>().unwrap();
+        let addrs = vec![
+            addr("[2001:db8::1]:80"),
+            addr("[2001:db8::2]:80"),
+            addr("[2001:db8::3]:80"),
+            addr("192.0.2.1:80"),
+            addr("192.0.2.2:80"),
+        ];
+
+        assert_eq!(
+            sort_addresses(&addrs),
+            vec![addrs[0], addrs[3], addrs[1], addrs[4], addrs[2]]
+        );
+
+        let addrs = vec![addr("192.0.2.1:80"), addr("[2001:db8::1]:80"), addr("192.0.2.2:80")];
+        assert_eq!(sort_addresses(&addrs), vec![addrs[0], addrs[1], addrs[2]]);
+
+        assert!(sort_addresses(&[]).is_empty());
+    }
+
+    #[test]
+    fn connection_racing() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = listener.local_addr().unwrap();
+
+        // A port that refuses connections, as nothing listens on it anymore
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+        let stream = connect(&[closed, open], Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), open);
+
+        assert!(connect(&[closed], Some(Duration::from_secs(5))).is_err());
+        assert!(connect(&[], None).is_err());
+    }
+}
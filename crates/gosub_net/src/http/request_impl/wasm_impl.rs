@@ -2,6 +2,7 @@ use crate::http::fetcher::RequestAgent;
 use crate::http::headers::Headers;
 use crate::http::request::Request;
 use crate::http::response::Response;
+use crate::http::transport::{Transport, TransportFuture};
 use anyhow::anyhow;
 use gosub_shared::types::Result;
 use js_sys::{ArrayBuffer, Uint8Array};
@@ -13,7 +14,7 @@ use std::pin::Pin;
 use std::task::{Context, Poll};
 use wasm_bindgen_futures::JsFuture;
 use web_sys::wasm_bindgen::JsCast;
-use web_sys::{RequestInit, RequestMode};
+use web_sys::{RequestCredentials, RequestInit, RequestMode};
 
 #[derive(Debug)]
 pub struct WasmAgent;
@@ -59,13 +60,41 @@ impl RequestAgent for WasmAgent {
         opts.set_method(&req.method);
         opts.set_mode(RequestMode::Cors);
 
-        opts.set_body(&req.body.clone().into());
+        let headers = web_sys::Headers::new().map_err(|e| anyhow!("{e:?}"))?;
+        for (key, value) in req.headers.sorted() {
+            headers.append(key, value).map_err(|e| anyhow!("{e:?}"))?;
+        }
+        opts.set_headers(&headers);
 
-        //TODO: headers, version, cookies
+        // The browser does not allow us to set the cookie header, but it will send its own cookies with the request
+        if req.cookie_header().is_some() {
+            opts.set_credentials(RequestCredentials::Include);
+        }
 
+        // Fetch does not allow a body for GET and HEAD requests
+        let no_body = req.method.eq_ignore_ascii_case("GET") || req.method.eq_ignore_ascii_case("HEAD");
+        if !no_body && !req.body.is_empty() {
+            opts.set_body(&Uint8Array::from(req.body.as_slice()).into());
+        }
+
+        let max_body_size = req.max_body_size;
         let req = web_sys::Request::new_with_str_and_init(&req.uri, &opts).map_err(|e| anyhow!("{e:?}"))?;
 
-        fetch(req).await
+        // The browser follows redirects itself, and the timeout of the request is not enforced
+        let resp = fetch(req).await?;
+        if let Some(limit) = max_body_size {
+            if resp.body.len() as u64 > limit {
+                return Err(anyhow!("response body exceeds {limit} bytes"));
+            }
+        }
+
+        Ok(resp)
+    }
+}
+
+impl Transport for WasmAgent {
+    fn send<'a>(&'a self, req: &'a Request) -> TransportFuture<'a> {
+        Box::pin(self.get_req(req))
     }
 }
 
@@ -118,6 +147,7 @@ async fn fetch(req: web_sys::Request) -> Result<Response> {
     //     headers.append(&name, &value);
     // }
 
+    // The browser never exposes Set-Cookie headers, it stores the cookies itself
     let cookies = Default::default();
 
     let buf = JsFuture::from(resp.array_buffer().map_err(|e| anyhow!("{e:?}"))?)
@@ -138,6 +168,9 @@ async fn fetch(req: web_sys::Request) -> Result<Response> {
         version: Default::default(),
         headers,
         cookies,
+        set_cookies: vec![],
         body,
+        url: None,
+        redirects: vec![],
     })
 }
@@ -1,16 +1,29 @@
 use core::fmt::{Display, Formatter};
 use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use url::Url;
 
 use crate::http::headers::Headers;
+use cookie::Cookie;
+use gosub_shared::byte_stream::{encoding_from_content_type, Encoding};
 
-#[derive(Debug)]
+/// Body of a response that is read while it arrives
+pub type BodyReader = Box<dyn Read + Send>;
+
+#[derive(Debug, Clone)]
 pub struct Response {
     pub status: u16,
     pub status_text: String,
     pub version: String,
     pub headers: Headers,
     pub cookies: HashMap<String, String>,
+    /// Values of all `Set-Cookie` headers, as they can occur multiple times
+    pub set_cookies: Vec<String>,
     pub body: Vec<u8>,
+    /// The URL the response was received from, after following all redirects
+    pub url: Option<Url>,
+    /// The URLs that were redirected, in order, starting with the requested URL
+    pub redirects: Vec<Url>,
 }
 
 impl Response {
@@ -21,13 +34,43 @@ impl Response {
             version: "HTTP/1.1".to_string(),
             headers: Default::default(),
             cookies: Default::default(),
+            set_cookies: vec![],
             body: vec![],
+            url: None,
+            redirects: vec![],
         }
     }
 
     pub fn is_ok(&self) -> bool {
         self.status >= 200 && self.status < 300
     }
+
+    /// Returns true when the response redirects to another location
+    pub fn is_redirect(&self) -> bool {
+        matches!(self.status, 301 | 302 | 303 | 307 | 308)
+    }
+
+    /// Adds the value of a `Set-Cookie` header, and the name and value of its cookie to the cookies of the response
+    pub fn add_set_cookie(&mut self, header: &str) {
+        if let Ok(cookie) = Cookie::parse(header) {
+            self.cookies
+                .insert(cookie.name().to_string(), cookie.value().to_string());
+        }
+        self.set_cookies.push(header.to_string());
+    }
+
+    /// Returns the encoding of the body as defined by the charset of the `Content-Type` header (if any)
+    pub fn encoding(&self) -> Option<Encoding> {
+        self.headers
+            .get_ignore_case("content-type")
+            .and_then(|value| encoding_from_content_type(value))
+    }
+
+    /// Splits the response into its head, with an empty body, and a reader for the body
+    pub fn into_streaming(mut self) -> (Response, BodyReader) {
+        let body = std::mem::take(&mut self.body);
+        (self, Box::new(Cursor::new(body)))
+    }
 }
 
 impl From<Vec<u8>> for Response {
@@ -38,7 +81,10 @@ impl From<Vec<u8>> for Response {
             version: "HTTP/1.1".to_string(),
             headers: Default::default(),
             cookies: Default::default(),
+            set_cookies: vec![],
             body,
+            url: None,
+            redirects: vec![],
         }
     }
 }
@@ -85,4 +131,25 @@ mod tests {
         let s = format!("{}", response);
         assert_eq!(s, "HTTP/1.1 200\nHeaders:\n  Content-Type: application/json\nCookies:\n  session: 1234567890\nBody: 13 bytes\n");
     }
+
+    #[test]
+    fn set_cookies() {
+        let mut response = Response::new();
+        response.add_set_cookie("session=1234; Path=/; HttpOnly");
+        response.add_set_cookie("theme=dark");
+        response.add_set_cookie("invalid");
+
+        assert_eq!(response.set_cookies.len(), 3);
+        assert_eq!(response.cookies.len(), 2);
+        assert_eq!(response.cookies.get("session").unwrap(), "1234");
+    }
+
+    #[test]
+    fn encoding() {
+        let mut response = Response::new();
+        assert_eq!(response.encoding(), None);
+
+        response.headers.set("content-type", "text/html; charset=Shift_JIS");
+        assert_eq!(response.encoding(), Encoding::for_label("shift_jis"));
+    }
 }
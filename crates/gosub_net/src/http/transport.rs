@@ -0,0 +1,136 @@
+//! Transports
+//!
+//! A transport sends a single request and returns the response, without following redirects, caching or storing
+//! cookies (that is what the fetcher does). By default requests go over the network with ureq (or the fetch API of
+//! the browser), but any transport can be injected into a fetcher. The replay transport serves recorded responses,
+//! so page loads can be tested without network access.
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, LazyLock, RwLock};
+
+use anyhow::anyhow;
+use log::warn;
+
+use gosub_config::{config, config_store};
+use gosub_shared::async_executor::WasmNotSendSync;
+use gosub_shared::types::Result;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::dns::Dns;
+use crate::http::fetcher::RequestAgent;
+use crate::http::request::Request;
+use crate::http::request_impl::RequestImpl;
+use crate::http::response::{BodyReader, Response};
+
+pub use record::RecordingTransport;
+pub use replay::ReplayTransport;
+
+mod record;
+mod replay;
+
+/// Future that resolves to the response of a transport
+#[cfg(not(target_arch = "wasm32"))]
+pub type TransportFuture<'a> = Pin<Box<dyn Future<Output = Result<Response>> + Send + 'a>>;
+
+/// Future that resolves to the response of a transport
+#[cfg(target_arch = "wasm32")]
+pub type TransportFuture<'a> = Pin<Box<dyn Future<Output = Result<Response>> + 'a>>;
+
+/// Future that resolves to the head of a response and a reader for its body
+#[cfg(not(target_arch = "wasm32"))]
+pub type StreamingFuture<'a> = Pin<Box<dyn Future<Output = Result<(Response, BodyReader)>> + Send + 'a>>;
+
+/// Future that resolves to the head of a response and a reader for its body
+#[cfg(target_arch = "wasm32")]
+pub type StreamingFuture<'a> = Pin<Box<dyn Future<Output = Result<(Response, BodyReader)>> + 'a>>;
+
+/// Sends requests and returns their responses
+pub trait Transport: Debug + WasmNotSendSync {
+    /// Sends the request. Error statuses and redirects are returned as responses, only failures to get a response at
+    /// all are returned as errors.
+    fn send<'a>(&'a self, req: &'a Request) -> TransportFuture<'a>;
+
+    /// Sends the request like `send`, but returns as soon as the head of the response has arrived. The body is read
+    /// from the returned reader while it arrives. By default the complete response is received first.
+    fn send_streaming<'a>(&'a self, req: &'a Request) -> StreamingFuture<'a> {
+        Box::pin(async move { Ok(self.send(req).await?.into_streaming()) })
+    }
+}
+
+/// Returns a transport that sends requests over the network, and resolves host names with the given DNS system
+/// instead of the shared one
+#[cfg(not(target_arch = "wasm32"))]
+pub fn network_transport(dns: Arc<Dns>) -> Arc<dyn Transport> {
+    Arc::new(RequestImpl::with_dns(dns))
+}
+
+/// Transport that is used by new fetchers, when it is set with `set_default_transport`
+static DEFAULT_TRANSPORT: LazyLock<RwLock<Option<Arc<dyn Transport>>>> = LazyLock::new(|| RwLock::new(None));
+
+/// Sets the transport that is used by all fetchers that are created from now on, or restores the network transport
+/// when None is given. This makes it possible to load pages through the engine without any network access.
+pub fn set_default_transport(transport: Option<Arc<dyn Transport>>) {
+    *DEFAULT_TRANSPORT.write().unwrap() = transport;
+}
+
+/// Returns the transport for a new fetcher. This is the transport that is set with `set_default_transport`, or else
+/// the transport that is configured in the `http.transport.*` settings.
+pub fn default_transport() -> Arc<dyn Transport> {
+    if let Some(transport) = DEFAULT_TRANSPORT.read().unwrap().as_ref() {
+        return transport.clone();
+    }
+
+    configured_transport(
+        &config!(string "http.transport.replay_path"),
+        &config!(string "http.transport.record_path"),
+    )
+}
+
+/// Returns the transport for the replay and record paths of the settings. When replaying, the network is never
+/// used: fixtures that can't be loaded make every request fail.
+fn configured_transport(replay: &str, record: &str) -> Arc<dyn Transport> {
+    if !replay.is_empty() {
+        return match ReplayTransport::from_path(replay) {
+            Ok(transport) => Arc::new(transport),
+            Err(e) => {
+                warn!("could not load replay fixtures from {replay}: {e}");
+                Arc::new(UnavailableTransport(format!(
+                    "could not load replay fixtures from {replay}: {e}"
+                )))
+            }
+        };
+    }
+
+    let network: Arc<dyn Transport> = Arc::new(RequestImpl::new());
+
+    if !record.is_empty() {
+        return Arc::new(RecordingTransport::new(network, record));
+    }
+
+    network
+}
+
+/// Transport that fails all requests with the reason it is unavailable
+#[derive(Debug)]
+struct UnavailableTransport(String);
+
+impl Transport for UnavailableTransport {
+    fn send<'a>(&'a self, req: &'a Request) -> TransportFuture<'a> {
+        Box::pin(async move { Err(anyhow!("{} {}: {}", req.method, req.uri, self.0)) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_fails_closed() {
+        let transport = configured_transport("/nonexistent/gosub-fixtures", "");
+
+        let req = Request::new("GET", "https://example.com/", "HTTP/1.1");
+        let err = futures::executor::block_on(transport.send(&req)).unwrap_err();
+        assert!(err.to_string().contains("could not load replay fixtures"));
+    }
+}
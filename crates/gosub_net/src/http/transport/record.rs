@@ -0,0 +1,125 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use log::warn;
+use url::Url;
+
+use gosub_shared::types::Result;
+
+use crate::http::request::Request;
+use crate::http::response::Response;
+use crate::http::transport::replay::fixture_path;
+use crate::http::transport::{Transport, TransportFuture};
+
+/// Headers that describe how the body was transferred, which no longer applies to the decoded body that is recorded
+const TRANSFER_HEADERS: [&str; 4] = ["content-encoding", "content-length", "set-cookie", "transfer-encoding"];
+
+/// Transport that sends requests with another transport, and writes each response to a fixture directory. The
+/// fixtures can be served later by a `ReplayTransport`.
+#[derive(Debug)]
+pub struct RecordingTransport {
+    inner: Arc<dyn Transport>,
+    dir: PathBuf,
+}
+
+impl RecordingTransport {
+    pub fn new(inner: Arc<dyn Transport>, dir: impl Into<PathBuf>) -> Self {
+        Self { inner, dir: dir.into() }
+    }
+
+    /// Writes the response to the fixture file of the request
+    fn record(&self, req: &Request, resp: &Response) -> Result<()> {
+        let url = Url::parse(&req.uri)?;
+        let path = self.dir.join(fixture_path(&req.method, &url));
+
+        // The response to a HEAD request has no body, so it would overwrite the fixture of the GET request
+        if req.method.eq_ignore_ascii_case("HEAD") && path.exists() {
+            return Ok(());
+        }
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, fixture(resp))?;
+
+        Ok(())
+    }
+}
+
+impl Transport for RecordingTransport {
+    fn send<'a>(&'a self, req: &'a Request) -> TransportFuture<'a> {
+        Box::pin(async move {
+            let resp = self.inner.send(req).await?;
+
+            if let Err(e) = self.record(req, &resp) {
+                warn!("could not record response for {} {}: {}", req.method, req.uri, e);
+            }
+
+            Ok(resp)
+        })
+    }
+}
+
+/// Returns the response as a fixture: the status line, headers, an empty line and the body
+fn fixture(resp: &Response) -> Vec<u8> {
+    // Some transports include the status code in the status text
+    let status = resp.status.to_string();
+    let reason = resp.status_text.trim_start_matches(&status).trim();
+
+    let mut head = format!("HTTP/1.1 {} {}\r\n", resp.status, reason);
+    for (name, value) in resp.headers.sorted() {
+        if TRANSFER_HEADERS.iter().any(|header| name.eq_ignore_ascii_case(header)) {
+            continue;
+        }
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    for cookie in &resp.set_cookies {
+        head.push_str(&format!("Set-Cookie: {cookie}\r\n"));
+    }
+    head.push_str("\r\n");
+
+    let mut data = head.into_bytes();
+    data.extend_from_slice(&resp.body);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::transport::ReplayTransport;
+
+    #[test]
+    fn record_and_replay() {
+        let url = Url::parse("https://example.com/page?id=1").unwrap();
+
+        let mut resp = Response::from(b"<p>recorded</p>".to_vec());
+        resp.status_text = "200 OK".to_string();
+        resp.headers.set("Content-Type", "text/html");
+        resp.headers.set("Content-Encoding", "gzip");
+        resp.add_set_cookie("a=1; Path=/");
+        resp.add_set_cookie("b=2");
+
+        let mut network = ReplayTransport::new();
+        network.add("GET", &url, resp);
+
+        let dir = std::env::temp_dir().join(format!("gosub-record-{}", std::process::id()));
+        let recorder = RecordingTransport::new(Arc::new(network), &dir);
+
+        let req = Request::new("GET", url.as_str(), "HTTP/1.1");
+        futures::executor::block_on(recorder.send(&req)).unwrap();
+        let req = Request::new("HEAD", url.as_str(), "HTTP/1.1");
+        futures::executor::block_on(recorder.send(&req)).unwrap();
+
+        let replay = ReplayTransport::from_dir(&dir);
+        let req = Request::new("GET", url.as_str(), "HTTP/1.1");
+        let resp = futures::executor::block_on(replay.send(&req)).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(resp.status, 200);
+        assert_eq!(resp.status_text, "OK");
+        assert_eq!(resp.headers.get("Content-Type"), Some(&"text/html".to_string()));
+        assert!(resp.headers.get_ignore_case("content-encoding").is_none());
+        assert_eq!(resp.set_cookies, vec!["a=1; Path=/", "b=2"]);
+        assert_eq!(resp.body, b"<p>recorded</p>");
+    }
+}
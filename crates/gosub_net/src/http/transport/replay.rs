@@ -0,0 +1,372 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail};
+use cow_utils::CowUtils;
+use log::trace;
+use serde_json::Value;
+use url::Url;
+
+use gosub_shared::types::Result;
+
+use crate::http::data_url::forgiving_base64_decode;
+use crate::http::request::Request;
+use crate::http::response::Response;
+use crate::http::transport::{Transport, TransportFuture};
+
+/// Transport that never touches the network, but answers requests with recorded responses
+///
+/// Responses come from a fixture directory (as written by the `RecordingTransport`), from a HAR file, or are added
+/// directly. A request without a recorded response fails. HEAD requests are answered with the response of the GET
+/// request, without its body.
+#[derive(Debug, Default)]
+pub struct ReplayTransport {
+    /// Directory with a fixture file for each request
+    dir: Option<PathBuf>,
+    /// Responses by method and URL
+    responses: HashMap<String, Response>,
+}
+
+impl ReplayTransport {
+    /// Creates a transport without any responses
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a transport that reads the response to each request from its fixture file in the directory
+    pub fn from_dir(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: Some(dir.into()),
+            responses: HashMap::new(),
+        }
+    }
+
+    /// Creates a transport with the responses of all entries in the HAR (HTTP archive) file. When the same request
+    /// occurs more than once, the first response is used.
+    pub fn from_har(path: &Path) -> Result<Self> {
+        let har: Value = serde_json::from_slice(&std::fs::read(path)?)?;
+        let Some(entries) = har["log"]["entries"].as_array() else {
+            bail!("{}: not a HAR file", path.display());
+        };
+
+        let mut transport = Self::new();
+        for entry in entries {
+            let request = &entry["request"];
+            let Some(url) = request["url"].as_str().and_then(|url| Url::parse(url).ok()) else {
+                continue;
+            };
+            let method = request["method"].as_str().unwrap_or("GET");
+
+            // Requests that were blocked or failed have no status
+            let Some(resp) = har_response(&entry["response"]) else {
+                continue;
+            };
+
+            let key = request_key(method, &url);
+            transport.responses.entry(key).or_insert(resp);
+        }
+
+        Ok(transport)
+    }
+
+    /// Creates a transport from a HAR file when the path ends in `.har`, or else from a fixture directory
+    pub fn from_path(path: &str) -> Result<Self> {
+        let path = Path::new(path);
+
+        if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("har")) {
+            return Self::from_har(path);
+        }
+        if !path.is_dir() {
+            bail!("{}: fixture directory does not exist", path.display());
+        }
+
+        Ok(Self::from_dir(path))
+    }
+
+    /// Adds the response for the request with the method and URL
+    pub fn add(&mut self, method: &str, url: &Url, response: Response) {
+        self.responses.insert(request_key(method, url), response);
+    }
+
+    /// Returns the recorded response for the request
+    fn lookup(&self, req: &Request) -> Result<Response> {
+        let url = Url::parse(&req.uri)?;
+
+        if let Some(resp) = self.responses.get(&request_key(&req.method, &url)) {
+            return Ok(resp.clone());
+        }
+
+        if let Some(dir) = &self.dir {
+            let path = dir.join(fixture_path(&req.method, &url));
+            trace!("{} {}: reading fixture {}", req.method, url, path.display());
+
+            if let Ok(data) = std::fs::read(&path) {
+                return parse_fixture(&data);
+            }
+        }
+
+        Err(anyhow!("no recorded response for {} {}", req.method, url))
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn send<'a>(&'a self, req: &'a Request) -> TransportFuture<'a> {
+        Box::pin(async move {
+            let mut resp = self.lookup(req)?;
+            if req.method.eq_ignore_ascii_case("HEAD") {
+                resp.body.clear();
+            }
+
+            Ok(resp)
+        })
+    }
+}
+
+/// Returns the method the response is recorded for. HEAD requests get the response of the GET request.
+fn recorded_method(method: &str) -> String {
+    match method.cow_to_ascii_uppercase() {
+        m if m == "HEAD" => "GET".to_string(),
+        m => m.to_string(),
+    }
+}
+
+/// Returns the key of a request in the responses map. The fragment of the URL is never sent, so it is ignored.
+fn request_key(method: &str, url: &Url) -> String {
+    let mut url = url.clone();
+    url.set_fragment(None);
+
+    format!("{} {}", recorded_method(method), url)
+}
+
+/// Returns the path of the fixture file for the request, relative to the fixture directory
+///
+/// The path is `<host>[_<port>]/<path>[@<query>][.<method>].http`, where a path that ends in a slash gets the name
+/// `index`, and the method is only added for other methods than GET. So `https://example.com/` is stored in
+/// `example.com/index.http`, and a POST to `http://localhost:8080/api/items?page=2` in
+/// `localhost_8080/api/items@page=2.post.http`. The scheme is not part of the path.
+pub(super) fn fixture_path(method: &str, url: &Url) -> PathBuf {
+    // Only characters that are safe in file names on all platforms are kept
+    let sanitize = |s: &str| {
+        let s = s
+            .chars()
+            .map(|c| match c.is_ascii_alphanumeric() || "-_.=%&+,".contains(c) {
+                true => c,
+                false => '_',
+            })
+            .collect::<String>();
+
+        match s.as_str() {
+            "." | ".." => "_".repeat(s.len()),
+            _ => s,
+        }
+    };
+
+    let host = url.host_str().unwrap_or("localhost");
+    let mut path = PathBuf::from(match url.port() {
+        Some(port) => format!("{}_{}", sanitize(host), port),
+        None => sanitize(host),
+    });
+
+    let segments = url.path_segments().map(|s| s.collect::<Vec<_>>()).unwrap_or_default();
+    let (name, dirs) = segments.split_last().unwrap_or((&"", &[]));
+    for dir in dirs {
+        path.push(sanitize(dir));
+    }
+
+    let mut name = match *name {
+        "" => "index".to_string(),
+        name => sanitize(name),
+    };
+    if let Some(query) = url.query() {
+        name.push('@');
+        name.push_str(&sanitize(query));
+    }
+    let method = recorded_method(method);
+    if method != "GET" {
+        name.push('.');
+        name.push_str(&method.cow_to_ascii_lowercase());
+    }
+    name.push_str(".http");
+
+    path.push(name);
+    path
+}
+
+/// Parses a fixture file. A fixture holds a complete HTTP response: a status line, headers, an empty line and the
+/// body. Files that do not start with a status line are served as the body of a `200 OK` response.
+pub(super) fn parse_fixture(data: &[u8]) -> Result<Response> {
+    if !data.starts_with(b"HTTP/") {
+        return Ok(Response::from(data.to_vec()));
+    }
+
+    let (head, body) = match data.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(pos) => (&data[..pos], &data[pos + 4..]),
+        None => match data.windows(2).position(|w| w == b"\n\n") {
+            Some(pos) => (&data[..pos], &data[pos + 2..]),
+            None => (data, &[][..]),
+        },
+    };
+
+    let head = String::from_utf8_lossy(head);
+    let mut lines = head.lines();
+
+    let status_line = lines.next().unwrap_or_default();
+    let mut parts = status_line.splitn(3, ' ');
+    let version = parts.next().unwrap_or_default();
+    let Some(status) = parts.next().and_then(|status| status.parse().ok()) else {
+        bail!("invalid status line in fixture: {}", status_line);
+    };
+
+    let mut resp = Response::from(body.to_vec());
+    resp.status = status;
+    resp.status_text = parts.next().unwrap_or_default().to_string();
+    resp.version = version.to_string();
+
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let (name, value) = (name.trim(), value.trim());
+
+        if name.eq_ignore_ascii_case("set-cookie") {
+            resp.add_set_cookie(value);
+        }
+        resp.headers.set(name, value);
+    }
+
+    Ok(resp)
+}
+
+/// Converts the response of a HAR entry, or returns None when the entry has no response
+fn har_response(response: &Value) -> Option<Response> {
+    let status = response["status"].as_u64().filter(|status| *status > 0)?;
+
+    let content = &response["content"];
+    let text = content["text"].as_str().unwrap_or_default();
+    let body = match content["encoding"].as_str() {
+        Some("base64") => forgiving_base64_decode(text.as_bytes())?,
+        _ => text.as_bytes().to_vec(),
+    };
+
+    let mut resp = Response::from(body);
+    resp.status = status as u16;
+    resp.status_text = response["statusText"].as_str().unwrap_or_default().to_string();
+    if let Some(version) = response["httpVersion"].as_str() {
+        resp.version = version.to_string();
+    }
+
+    for header in response["headers"].as_array().into_iter().flatten() {
+        let (Some(name), Some(value)) = (header["name"].as_str(), header["value"].as_str()) else {
+            continue;
+        };
+
+        if name.eq_ignore_ascii_case("set-cookie") {
+            resp.add_set_cookie(value);
+        }
+        resp.headers.set(name, value);
+    }
+
+    // The headers of HTTP/2 responses have lowercase names, and the body is already decoded
+    if resp.headers.get_ignore_case("content-type").is_none() {
+        if let Some(mime_type) = content["mimeType"].as_str().filter(|m| !m.is_empty()) {
+            resp.headers.set("Content-Type", mime_type);
+        }
+    }
+    resp.headers.remove_ignore_case("content-encoding");
+
+    Some(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send(transport: &ReplayTransport, method: &str, url: &str) -> Result<Response> {
+        futures::executor::block_on(transport.send(&Request::new(method, url, "HTTP/1.1")))
+    }
+
+    #[test]
+    fn fixture_paths() {
+        let path = |method: &str, url: &str| fixture_path(method, &Url::parse(url).unwrap());
+
+        assert_eq!(path("GET", "https://example.com/"), Path::new("example.com/index.http"));
+        assert_eq!(path("HEAD", "https://example.com"), Path::new("example.com/index.http"));
+        assert_eq!(
+            path("GET", "http://example.com/css/main.css#top"),
+            Path::new("example.com/css/main.css.http")
+        );
+        assert_eq!(
+            path("post", "http://localhost:8080/api/items/?page=2"),
+            Path::new("localhost_8080/api/items/index@page=2.post.http")
+        );
+        assert_eq!(
+            path("GET", "http://example.com/a b/x:y"),
+            Path::new("example.com/a%20b/x_y.http")
+        );
+    }
+
+    #[test]
+    fn replay_fixtures() {
+        let dir = std::env::temp_dir().join(format!("gosub-replay-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("example.com/css")).unwrap();
+        std::fs::write(
+            dir.join("example.com/index.http"),
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nSet-Cookie: a=1\r\n\r\n<p>hello</p>",
+        )
+        .unwrap();
+        std::fs::write(dir.join("example.com/css/main.css.http"), "p { color: red }").unwrap();
+
+        let transport = ReplayTransport::from_dir(&dir);
+
+        let resp = send(&transport, "GET", "https://example.com/").unwrap();
+        assert_eq!(resp.status, 200);
+        assert_eq!(resp.status_text, "OK");
+        assert_eq!(resp.headers.get("Content-Type"), Some(&"text/html".to_string()));
+        assert_eq!(resp.set_cookies, vec!["a=1"]);
+        assert_eq!(resp.body, b"<p>hello</p>");
+
+        let resp = send(&transport, "HEAD", "https://example.com/").unwrap();
+        assert_eq!(resp.status, 200);
+        assert!(resp.body.is_empty());
+
+        let resp = send(&transport, "GET", "https://example.com/css/main.css").unwrap();
+        assert_eq!(resp.body, b"p { color: red }");
+
+        assert!(send(&transport, "GET", "https://example.com/missing").is_err());
+        assert!(send(&transport, "POST", "https://example.com/").is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replay_har() {
+        let har = r#"{"log": {"version": "1.2", "entries": [
+            {"request": {"method": "GET", "url": "https://example.com/"},
+             "response": {"status": 200, "statusText": "OK", "httpVersion": "h2",
+                          "headers": [{"name": "content-encoding", "value": "gzip"}],
+                          "content": {"mimeType": "text/html", "text": "<p>first</p>"}}},
+            {"request": {"method": "GET", "url": "https://example.com/"},
+             "response": {"status": 200, "content": {"text": "<p>second</p>"}}},
+            {"request": {"method": "GET", "url": "https://example.com/logo.png"},
+             "response": {"status": 200, "content": {"mimeType": "image/png", "text": "iVBORw0K", "encoding": "base64"}}},
+            {"request": {"method": "GET", "url": "https://tracker.example/"},
+             "response": {"status": 0, "content": {}}}
+        ]}}"#;
+
+        let path = std::env::temp_dir().join(format!("gosub-replay-{}.har", std::process::id()));
+        std::fs::write(&path, har).unwrap();
+        let transport = ReplayTransport::from_path(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let resp = send(&transport, "GET", "https://example.com/#top").unwrap();
+        assert_eq!(resp.body, b"<p>first</p>");
+        assert_eq!(resp.version, "h2");
+        assert_eq!(resp.headers.get("Content-Type"), Some(&"text/html".to_string()));
+        assert!(resp.headers.get_ignore_case("content-encoding").is_none());
+
+        let resp = send(&transport, "GET", "https://example.com/logo.png").unwrap();
+        assert_eq!(resp.body, vec![0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a]);
+
+        assert!(send(&transport, "GET", "https://tracker.example/").is_err());
+    }
+}
@@ -4,12 +4,13 @@ use crate::draw::img_cache::ImageCache;
 use crate::draw::testing::{test_add_element, test_restyle_element};
 use crate::render_tree::{load_html_rendertree, load_html_rendertree_fetcher, load_html_rendertree_source};
 use anyhow::anyhow;
-use gosub_interface::config::{HasDocument, HasDrawComponents, HasHtmlParser};
+use gosub_interface::config::{HasDocument, HasDrawComponents, HasHtmlParser, HasScriptExecutor};
 use gosub_interface::css3::{CssProperty, CssPropertyMap, CssValue};
 
 use gosub_interface::draw::TreeDrawer;
 use gosub_interface::eventloop::EventLoopHandle;
 use gosub_interface::layout::{Layout, LayoutTree, Layouter};
+use gosub_interface::mutation::MutationRecord;
 use gosub_interface::render_backend::{
     Border, BorderSide, BorderStyle, Brush, Color, ImageBuffer, ImgCache, NodeDesc, Rect, RenderBackend, RenderBorder,
     RenderRect, RenderText, Scene as TScene, Text, Transform,
@@ -39,6 +40,13 @@ const DEBUG_BORDER_COLOR: (u8, u8, u8) = (255, 72, 72);
 
 type Point = gosub_shared::types::Point<FP>;
 
+/// Scroll position that is requested before the render tree has been laid out
+#[derive(Debug)]
+enum PendingScroll {
+    Position(Point),
+    Fragment(String),
+}
+
 #[derive(Debug)]
 pub struct TreeDrawerImpl<C: HasDrawComponents> {
     pub(crate) tree: C::RenderTree,
@@ -54,6 +62,7 @@ pub struct TreeDrawerImpl<C: HasDrawComponents> {
     pub(crate) selected_element: Option<NodeId>,
     pub(crate) scene_transform: Option<<C::RenderBackend as RenderBackend>::Transform>,
     pub(crate) img_cache: ImageCache<C::RenderBackend>,
+    pending_scroll: Option<PendingScroll>,
 }
 
 impl<C: HasDrawComponents> TreeDrawerImpl<C> {
@@ -72,12 +81,17 @@ impl<C: HasDrawComponents> TreeDrawerImpl<C> {
             selected_element: None,
             scene_transform: None,
             img_cache: ImageCache::new(),
+            pending_scroll: None,
         }
     }
 }
 
-impl<C: HasDrawComponents<RenderTree = RenderTree<C>, LayoutTree = RenderTree<C>> + HasHtmlParser + HasDocument>
-    TreeDrawer<C> for TreeDrawerImpl<C>
+impl<
+        C: HasDrawComponents<RenderTree = RenderTree<C>, LayoutTree = RenderTree<C>>
+            + HasHtmlParser
+            + HasScriptExecutor
+            + HasDocument,
+    > TreeDrawer<C> for TreeDrawerImpl<C>
 {
     type ImgCache = ImageCache<C::RenderBackend>;
 
@@ -114,6 +128,10 @@ impl<C: HasDrawComponents<RenderTree = RenderTree<C>, LayoutTree = RenderTree<C>
             self.size = Some(size);
         }
 
+        if let Some(pending) = self.pending_scroll.take() {
+            self.apply_scroll(pending, size);
+        }
+
         let bg = Rect::new(0.0, 0.0, size.width as FP, size.height as FP);
 
         let rect = RenderRect {
@@ -205,6 +223,21 @@ impl<C: HasDrawComponents<RenderTree = RenderTree<C>, LayoutTree = RenderTree<C>
         self.dirty = true;
     }
 
+    fn scroll_position(&self) -> Point {
+        self.scene_transform
+            .as_ref()
+            .map(|x| Point::new(-x.tx(), -x.ty()))
+            .unwrap_or(Point::ZERO)
+    }
+
+    fn scroll_to(&mut self, position: Point) {
+        self.pending_scroll = Some(PendingScroll::Position(position));
+    }
+
+    fn scroll_to_fragment(&mut self, fragment: &str) {
+        self.pending_scroll = Some(PendingScroll::Fragment(fragment.to_string()));
+    }
+
     async fn from_url(url: Url, layouter: C::Layouter, debug: bool) -> Result<(Self, C::Document)> {
         let (rt, handle, fetcher) = load_html_rendertree::<C>(url.clone(), None).await?;
 
@@ -224,7 +257,7 @@ impl<C: HasDrawComponents<RenderTree = RenderTree<C>, LayoutTree = RenderTree<C>
         layouter: C::Layouter,
         debug: bool,
     ) -> Result<(Self, C::Document)> {
-        let (rt, handle) = load_html_rendertree_fetcher::<C>(url.clone(), &fetcher).await?;
+        let (rt, handle) = load_html_rendertree_fetcher::<C>(url.clone(), &fetcher, |_| {}).await?;
 
         Ok((Self::new(rt, layouter, fetcher, debug), handle))
     }
@@ -295,13 +328,14 @@ impl<C: HasDrawComponents<RenderTree = RenderTree<C>, LayoutTree = RenderTree<C>
         async move {
             info!("Reloading tab");
 
-            let (rt, handle) = match load_html_rendertree_fetcher::<C>(fetcher.base().clone(), &fetcher).await {
-                Ok(rt) => rt,
-                Err(e) => {
-                    error!("Failed to reload tab: {e}");
-                    return Err(e);
-                }
-            };
+            let (rt, handle) =
+                match load_html_rendertree_fetcher::<C>(fetcher.base(), &fetcher, |rt| el.show_partial(rt)).await {
+                    Ok(rt) => rt,
+                    Err(e) => {
+                        error!("Failed to reload tab: {e}");
+                        return Err(e);
+                    }
+                };
 
             el.reload_from(rt);
 
@@ -319,13 +353,14 @@ impl<C: HasDrawComponents<RenderTree = RenderTree<C>, LayoutTree = RenderTree<C>
         async move {
             info!("Navigating to {url}");
 
-            let (rt, handle) = match load_html_rendertree_fetcher::<C>(url.clone(), &fetcher).await {
-                Ok(rt) => rt,
-                Err(e) => {
-                    error!("Failed to navigate to {url}: {e}");
-                    return Err(e);
-                }
-            };
+            let (rt, handle) =
+                match load_html_rendertree_fetcher::<C>(url.clone(), &fetcher, |rt| el.show_partial(rt)).await {
+                    Ok(rt) => rt,
+                    Err(e) => {
+                        error!("Failed to navigate to {url}: {e}");
+                        return Err(e);
+                    }
+                };
 
             el.reload_from(rt);
 
@@ -334,7 +369,25 @@ impl<C: HasDrawComponents<RenderTree = RenderTree<C>, LayoutTree = RenderTree<C>
     }
 
     fn reload_from(&mut self, tree: C::RenderTree) {
-        self.tree = tree;
+        self.replace_tree(tree);
+    }
+
+    fn update_from(&mut self, doc: &C::Document, records: &[MutationRecord]) {
+        if !self.tree.update_from_document(doc, records) {
+            return;
+        }
+
+        self.last_hover = None;
+        self.debugger_scene = None;
+        self.tree_scene = None;
+        self.dirty = true;
+        if self.selected_element.is_some_and(|id| self.tree.get_node(id).is_none()) {
+            self.selected_element = None;
+        }
+    }
+
+    fn replace_tree(&mut self, tree: C::RenderTree) -> C::RenderTree {
+        let previous = std::mem::replace(&mut self.tree, tree);
         self.size = None;
         self.position = PositionTree::default();
         self.last_hover = None;
@@ -343,6 +396,9 @@ impl<C: HasDrawComponents<RenderTree = RenderTree<C>, LayoutTree = RenderTree<C>
         self.tree_scene = None;
         self.selected_element = None;
         self.scene_transform = None;
+        self.pending_scroll = None;
+
+        previous
     }
 }
 
@@ -354,7 +410,7 @@ struct Drawer<'s, 't, C: HasDrawComponents, EL: EventLoopHandle<C>> {
 }
 
 impl<
-        C: HasDrawComponents<LayoutTree = RenderTree<C>, RenderTree = RenderTree<C>> + HasHtmlParser,
+        C: HasDrawComponents<LayoutTree = RenderTree<C>, RenderTree = RenderTree<C>> + HasHtmlParser + HasScriptExecutor,
         EL: EventLoopHandle<C>,
     > Drawer<'_, '_, C, EL>
 {
@@ -836,6 +892,46 @@ impl Side {
 }
 
 impl<C: HasDrawComponents<RenderTree = RenderTree<C>, LayoutTree = RenderTree<C>>> TreeDrawerImpl<C> {
+    /// Applies a requested scroll position, now that the tree has been laid out for the given size
+    fn apply_scroll(&mut self, pending: PendingScroll, size: SizeU32) {
+        let position = match pending {
+            PendingScroll::Position(position) => position,
+            PendingScroll::Fragment(fragment) => {
+                // The element with the id is the target, or else the first anchor with the name
+                let target = self
+                    .find_fragment(self.tree.root(), &fragment, "id")
+                    .or_else(|| self.find_fragment(self.tree.root(), &fragment, "name"));
+                let Some((x, y)) = target.and_then(|id| self.position.position(id)) else {
+                    return;
+                };
+                Point::new(x, y)
+            }
+        };
+
+        let root_size = self.tree.get_root().layout.content();
+        let max_x = (root_size.width - size.width as f32).max(0.0);
+        let max_y = (root_size.height - size.height as f32).max(0.0);
+
+        let mut transform = self.scene_transform.take().unwrap_or(Transform::IDENTITY);
+        transform.set_xy(-position.x.min(max_x).max(0.0), -position.y.min(max_y).max(0.0));
+        self.scene_transform = Some(transform);
+    }
+
+    /// Returns the first element in tree order with the fragment as value of the attribute. Only anchors are
+    /// targets by their name.
+    fn find_fragment(&self, id: NodeId, fragment: &str, attribute: &str) -> Option<NodeId> {
+        let node = self.tree.get_node(id)?;
+        let value = node.element_attributes().and_then(|attrs| attrs.get(attribute));
+        if (attribute == "id" || node.name() == "a") && value.is_some_and(|value| value == fragment) {
+            return Some(id);
+        }
+
+        self.tree
+            .children(id)?
+            .into_iter()
+            .find_map(|child| self.find_fragment(child, fragment, attribute))
+    }
+
     fn debug_annotate(&mut self, e: NodeId) -> bool {
         let Some(node) = self.tree.get_node(e) else {
             return false;
@@ -67,7 +67,7 @@ async fn load_img<B: RenderBackend>(
     svg_renderer: Arc<Mutex<B::SVGRenderer>>,
     size: Option<SizeU32>,
 ) -> Result<ImageBuffer<B>> {
-    let res = fetcher.get_url(url).await?;
+    let res = fetcher.get_subresource(url).await?;
     if !res.is_ok() {
         return Err(anyhow!("Could not get url. Status code {}", res.status));
     }
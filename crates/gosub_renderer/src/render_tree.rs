@@ -1,17 +1,32 @@
 use anyhow::bail;
-use gosub_interface::config::{HasHtmlParser, HasRenderTree};
-use gosub_interface::css3::CssSystem;
+#[cfg(not(target_arch = "wasm32"))]
+use futures::channel::mpsc;
+#[cfg(not(target_arch = "wasm32"))]
+use futures::executor::block_on;
+#[cfg(target_arch = "wasm32")]
+use futures::stream;
+use futures::{SinkExt, Stream, StreamExt};
+use gosub_interface::config::{HasHtmlParser, HasRenderTree, HasScriptExecutor};
+use gosub_interface::css3::{CssOrigin, CssStylesheet, CssSystem};
 use gosub_interface::document::{Document, DocumentBuilder};
 
-use gosub_interface::html5::Html5Parser;
+use gosub_interface::html5::{Html5Parser, Html5StreamingParser, ParseState, ScriptExecutor};
 use gosub_net::http::fetcher::Fetcher;
+use gosub_net::http::response::BodyReader;
 use gosub_rendering::render_tree::generate_render_tree;
+use gosub_shared::async_executor::yield_now;
 use gosub_shared::byte_stream::{ByteStream, Encoding};
-use std::fs;
+use log::warn;
+use std::io::{self, ErrorKind, Read};
+#[cfg(target_arch = "wasm32")]
+use std::iter;
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread;
+use std::time::{Duration, Instant};
 use url::Url;
 
 /// Generates a render tree from the given URL... if the source is given, the URL is not loaded, but the source HTML is used instead
-pub async fn load_html_rendertree<C: HasRenderTree + HasHtmlParser>(
+pub async fn load_html_rendertree<C: HasRenderTree + HasHtmlParser + HasScriptExecutor>(
     url: Url,
     source: Option<&str>,
 ) -> gosub_shared::types::Result<(C::RenderTree, C::Document, Fetcher)> {
@@ -19,7 +34,7 @@ pub async fn load_html_rendertree<C: HasRenderTree + HasHtmlParser>(
 
     let (rt, handle) = match source {
         Some(source) => load_html_rendertree_source::<C>(url, source)?,
-        None => load_html_rendertree_fetcher::<C>(url, &fetcher).await?,
+        None => load_html_rendertree_fetcher::<C>(url, &fetcher, |_| {}).await?,
     };
 
     Ok((rt, handle, fetcher))
@@ -35,8 +50,29 @@ pub fn load_html_rendertree_source<C: HasRenderTree + HasHtmlParser>(
     stream.read_from_str(source_html, Some(Encoding::UTF8));
     stream.close();
 
+    load_html_rendertree_stream::<C>(url, &mut stream)
+}
+
+// Generate a render tree from the given undecoded HTML bytes. The encoding is sniffed from the bytes, unless the
+// transport layer already defines it.
+pub fn load_html_rendertree_bytes<C: HasRenderTree + HasHtmlParser>(
+    url: Url,
+    bytes: &[u8],
+    transport_encoding: Option<Encoding>,
+) -> gosub_shared::types::Result<(C::RenderTree, C::Document)> {
+    let mut stream = ByteStream::new(Encoding::UTF8, None);
+    stream.read_from_bytes(bytes)?;
+    stream.sniff_encoding(transport_encoding);
+
+    load_html_rendertree_stream::<C>(url, &mut stream)
+}
+
+fn load_html_rendertree_stream<C: HasRenderTree + HasHtmlParser>(
+    url: Url,
+    stream: &mut ByteStream,
+) -> gosub_shared::types::Result<(C::RenderTree, C::Document)> {
     let mut doc = C::DocumentBuilder::new_document(Some(url));
-    let parse_errors = C::HtmlParser::parse(&mut stream, &mut doc, None)?;
+    let parse_errors = C::HtmlParser::parse(stream, &mut doc, None)?;
 
     for error in parse_errors {
         eprintln!("Parse error: {:?}", error);
@@ -47,24 +83,356 @@ pub fn load_html_rendertree_source<C: HasRenderTree + HasHtmlParser>(
     Ok((generate_render_tree::<C>(&doc)?, doc))
 }
 
-/// Generates a render tree from the given URL. The complete HTML source is fetched from the URL async.
-pub async fn load_html_rendertree_fetcher<C: HasRenderTree + HasHtmlParser>(
+fn add_useragent_stylesheet<C: HasRenderTree + HasHtmlParser>(doc: &mut C::Document) {
+    if !doc
+        .stylesheets()
+        .iter()
+        .any(|sheet| sheet.origin() == CssOrigin::UserAgent)
+    {
+        doc.add_stylesheet(C::CssSystem::load_default_useragent_stylesheet());
+    }
+}
+
+/// Size of the chunks in which the body of a page is read and parsed
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Minimum time between two render trees of a page that is still loading. Generating the render tree takes longer the
+/// more of the page has been loaded, so it is not done for every chunk.
+const PARTIAL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Generates a render tree from the given URL. The HTML is parsed while it is fetched, and `on_partial` is called with
+/// the render tree of the document as far as it has been loaded. The scripts of the document are fetched with the same
+/// fetcher and executed while parsing.
+pub async fn load_html_rendertree_fetcher<C: HasRenderTree + HasHtmlParser + HasScriptExecutor>(
     url: Url,
     fetcher: &Fetcher,
+    mut on_partial: impl FnMut(C::RenderTree),
 ) -> gosub_shared::types::Result<(C::RenderTree, C::Document)> {
-    let html = if url.scheme() == "http" || url.scheme() == "https" {
-        // Fetch the html from the url
-        let response = fetcher.get(url.as_ref()).await?;
-        if response.status != 200 {
-            bail!(format!("Could not get url. Status code {}", response.status));
-        }
+    // Fetch the html from the url. Besides http and https, this also loads file, data, about and blob URLs
+    let (response, body) = fetcher.get_url_streaming(&url).await?;
+    if response.status != 200 {
+        bail!(format!("Could not get url. Status code {}", response.status));
+    }
+
+    // Relative URLs in the document are resolved against the URL it was redirected to
+    let url = response.url.clone().unwrap_or(url);
+    fetcher.set_base(url.clone());
 
-        String::from_utf8(response.body.clone())?
-    } else if url.scheme() == "file" {
-        fs::read_to_string(url.as_str().trim_start_matches("file://"))?
-    } else {
-        bail!("Unsupported url scheme: {}", url.scheme());
+    let mut chunks = body_chunks(body);
+
+    // The encoding is sniffed from the first chunk, unless the transport layer already defines it
+    let mut stream = ByteStream::new(Encoding::UTF8, None);
+    stream.append_bytes(&next_chunk(&mut chunks, &url).await?.unwrap_or_default());
+    stream.sniff_encoding(response.encoding());
+
+    // A page whose scripts cannot run is still shown
+    let mut script_executor = match C::ScriptExecutor::new(&url) {
+        Ok(executor) => Some(executor),
+        Err(e) => {
+            warn!("Could not create script executor for {url}: {e}");
+            None
+        }
     };
 
-    load_html_rendertree_source::<C>(url, &html)
+    let mut doc = C::DocumentBuilder::new_document(Some(url.clone()));
+    let mut parser = C::HtmlParser::parse_streaming(
+        &mut stream,
+        &mut doc,
+        None,
+        script_executor
+            .as_mut()
+            .map(|executor| executor as &mut dyn ScriptExecutor<C>),
+    );
+    let mut last_partial: Option<Instant> = None;
+    loop {
+        match parser.parse_available() {
+            ParseState::Finished => break,
+            ParseState::NeedsInput => {}
+            ParseState::NeedsScript(script_url) => {
+                parser.provide_script(fetch_script(fetcher, &script_url).await);
+                continue;
+            }
+        }
+
+        if !matches!(last_partial, Some(time) if time.elapsed() < PARTIAL_INTERVAL) {
+            // The document is started over when a meta element changes the encoding, which drops the stylesheet
+            let doc = parser.document_mut();
+            add_useragent_stylesheet::<C>(doc);
+            if let Ok(render_tree) = generate_render_tree::<C>(doc) {
+                on_partial(render_tree);
+            }
+            last_partial = Some(Instant::now());
+        }
+
+        match next_chunk(&mut chunks, &url).await? {
+            Some(chunk) => parser.append_bytes(&chunk),
+            None => parser.close(),
+        }
+
+        // Chunks that have arrived already are returned without waiting, so the other tasks get a turn here
+        yield_now().await;
+    }
+
+    for error in parser.errors() {
+        eprintln!("Parse error: {:?}", error);
+    }
+    drop(parser);
+
+    add_useragent_stylesheet::<C>(&mut doc);
+
+    Ok((generate_render_tree::<C>(&doc)?, doc))
+}
+
+/// Fetches the source of the external script at the URL. Returns `None` when it could not be loaded.
+async fn fetch_script(fetcher: &Fetcher, url: &Url) -> Option<String> {
+    match fetcher.get_subresource(url).await {
+        Ok(response) if (200..300).contains(&response.status) => {
+            Some(String::from_utf8_lossy(&response.body).into_owned())
+        }
+        Ok(response) => {
+            warn!("Could not load script from {url}. Status code {}", response.status);
+            None
+        }
+        Err(e) => {
+            warn!("Could not load script from {url}. Error: {e}");
+            None
+        }
+    }
+}
+
+/// Waits for the next chunk of the body of the page at the URL. Returns `None` at the end of the body.
+async fn next_chunk(
+    chunks: &mut (impl Stream<Item = io::Result<Vec<u8>>> + Unpin),
+    url: &Url,
+) -> gosub_shared::types::Result<Option<Vec<u8>>> {
+    match chunks.next().await {
+        Some(Ok(chunk)) => Ok(Some(chunk)),
+        Some(Err(e)) => bail!("Could not read {url}: {e}"),
+        None => Ok(None),
+    }
+}
+
+/// Reads the next chunk of the body. Returns `None` at the end of the body.
+fn read_chunk(body: &mut BodyReader) -> Option<io::Result<Vec<u8>>> {
+    let mut chunk = vec![0; CHUNK_SIZE];
+    loop {
+        match body.read(&mut chunk) {
+            Ok(0) => return None,
+            Ok(n) => {
+                chunk.truncate(n);
+                return Some(Ok(chunk));
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Some(Err(e)),
+        }
+    }
+}
+
+/// Returns the chunks of the body. Reading blocks while the body arrives over the network, so it is done on a thread
+/// of its own, which stops when the load is aborted.
+#[cfg(not(target_arch = "wasm32"))]
+fn body_chunks(mut body: BodyReader) -> impl Stream<Item = io::Result<Vec<u8>>> + Unpin {
+    let (mut tx, rx) = mpsc::channel(4);
+    thread::spawn(move || {
+        while let Some(chunk) = read_chunk(&mut body) {
+            let failed = chunk.is_err();
+            if block_on(tx.send(chunk)).is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    rx
+}
+
+/// Returns the chunks of the body, which has been loaded completely already
+#[cfg(target_arch = "wasm32")]
+fn body_chunks(mut body: BodyReader) -> impl Stream<Item = io::Result<Vec<u8>>> + Unpin {
+    stream::iter(iter::from_fn(move || read_chunk(&mut body)))
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use gosub_css3::system::Css3System;
+    use gosub_html5::document::builder::DocumentBuilderImpl;
+    use gosub_html5::document::document_impl::DocumentImpl;
+    use gosub_html5::document::fragment::DocumentFragmentImpl;
+    use gosub_html5::parser::Html5Parser;
+    use gosub_interface::config::{HasCssSystem, HasDocument, HasLayouter};
+    use gosub_interface::font::HasFontManager;
+    use gosub_interface::html5::Script;
+    use gosub_interface::node::Node as _;
+    use gosub_interface::render_tree::{RenderTree as _, RenderTreeNode as _};
+    use gosub_rendering::render_tree::RenderTree;
+    use gosub_taffy::TaffyLayouter;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Config;
+
+    impl HasCssSystem for Config {
+        type CssSystem = Css3System;
+    }
+    impl HasDocument for Config {
+        type Document = DocumentImpl<Self>;
+        type DocumentFragment = DocumentFragmentImpl<Self>;
+        type DocumentBuilder = DocumentBuilderImpl;
+    }
+    impl HasHtmlParser for Config {
+        type HtmlParser = Html5Parser<'static, Self>;
+    }
+    impl HasLayouter for Config {
+        type Layouter = TaffyLayouter;
+        type LayoutTree = RenderTree<Self>;
+    }
+    impl HasRenderTree for Config {
+        type RenderTree = RenderTree<Self>;
+    }
+    impl HasFontManager for Config {
+        type FontManager = gosub_fontmanager::FontManager;
+    }
+    impl HasScriptExecutor for Config {
+        type ScriptExecutor = TextInserter;
+    }
+
+    /// Executes a script by adding its source as text after the script element
+    struct TextInserter;
+
+    impl ScriptExecutor<Config> for TextInserter {
+        fn new(_url: &Url) -> gosub_shared::types::Result<Self> {
+            Ok(Self)
+        }
+
+        fn execute(&mut self, script: &Script, document: &mut DocumentImpl<Config>) -> gosub_shared::types::Result<()> {
+            let parent_id = document
+                .node_by_id(script.node_id)
+                .and_then(|node| node.parent_id())
+                .ok_or_else(|| anyhow::anyhow!("script element is not attached"))?;
+            let text = DocumentImpl::<Config>::new_text_node(&script.source, Default::default());
+            document.register_node_at(text, parent_id, None);
+            Ok(())
+        }
+    }
+
+    /// Serves the given number of requests, with the body for the path of each request
+    fn serve(listener: TcpListener, requests: usize, pages: &[(&str, &str)]) {
+        for _ in 0..requests {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let path = line.split(' ').nth(1).unwrap_or_default().to_string();
+            while line != "\r\n" {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+            }
+
+            let body = pages.iter().find(|(p, _)| *p == path).map(|(_, body)| *body);
+            let response = match body {
+                Some(body) => format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                ),
+                None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+            };
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    }
+
+    /// Returns the texts in the render tree, in document order
+    fn texts(tree: &RenderTree<Config>) -> Vec<String> {
+        let mut texts = Vec::new();
+        let mut stack = vec![tree.root()];
+        while let Some(id) = stack.pop() {
+            if let Some((text, _)) = tree.get_node(id).and_then(|node| node.text_data()) {
+                if !text.trim().is_empty() {
+                    texts.push(text.trim().to_string());
+                }
+            }
+            if let Some(children) = tree.get_children(id) {
+                stack.extend(children.iter().rev());
+            }
+        }
+
+        texts
+    }
+
+    #[test]
+    fn fetcher_streams_into_parser() {
+        // A server that sends the rest of the page only when the first part has been rendered
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/page", listener.local_addr().unwrap())).unwrap();
+        let (rendered, wait_rendered) = mpsc::channel::<()>();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while line != "\r\n" {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+            }
+
+            let first = "<html><body><p>first</p>";
+            let rest = "<p>second</p></body></html>";
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\n\r\n",
+                first.len() + rest.len()
+            );
+            stream.write_all(head.as_bytes()).unwrap();
+            stream.write_all(first.as_bytes()).unwrap();
+            stream.flush().unwrap();
+
+            // Without streaming the rest is sent after a timeout, and the first part is never rendered on its own
+            let _ = wait_rendered.recv_timeout(Duration::from_secs(5));
+            stream.write_all(rest.as_bytes()).unwrap();
+        });
+
+        let fetcher = Fetcher::with_cache(url.clone(), None).with_cookie_store(None);
+        let mut partials = Vec::new();
+        let (tree, _) = futures::executor::block_on(load_html_rendertree_fetcher::<Config>(url, &fetcher, |tree| {
+            let texts = texts(&tree);
+            if texts == ["first"] {
+                let _ = rendered.send(());
+            }
+            partials.push(texts);
+        }))
+        .unwrap();
+        server.join().unwrap();
+
+        assert!(partials.contains(&vec!["first".to_string()]));
+        assert_eq!(texts(&tree), ["first", "second"]);
+    }
+
+    #[test]
+    fn fetcher_runs_scripts() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/page", listener.local_addr().unwrap())).unwrap();
+        let server = thread::spawn(move || {
+            serve(
+                listener,
+                3,
+                &[
+                    (
+                        "/page",
+                        "<html><body><p>before</p><script src=\"/script.js\"></script><script>inline</script>\
+                         <script src=\"/missing.js\"></script><p>after</p></body></html>",
+                    ),
+                    ("/script.js", "external"),
+                ],
+            )
+        });
+
+        let fetcher = Fetcher::with_cache(url.clone(), None).with_cookie_store(None);
+        let (tree, _) =
+            futures::executor::block_on(load_html_rendertree_fetcher::<Config>(url, &fetcher, |_| {})).unwrap();
+        server.join().unwrap();
+
+        // The scripts ran when the parser reached them, before the rest of the document was parsed
+        assert_eq!(texts(&tree), ["before", "external", "inline", "after"]);
+    }
 }
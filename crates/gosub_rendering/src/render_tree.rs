@@ -1,11 +1,12 @@
 use cow_utils::CowUtils;
 use gosub_html5::document::document_impl::TreeIterator;
 use gosub_interface::config::{HasDocument, HasLayouter, HasRenderTree};
-use gosub_interface::css3::{CssProperty, CssPropertyMap, CssSystem};
+use gosub_interface::css3::{CssProperty, CssPropertyMap, CssStylesheet, CssSystem};
 use gosub_interface::document::Document;
 
 use gosub_interface::font::HasFontManager;
 use gosub_interface::layout::{HasTextLayout, Layout, LayoutCache, LayoutNode, LayoutTree, Layouter, TextLayout};
+use gosub_interface::mutation::{MutationRecord, MutationType};
 use gosub_interface::node::NodeData;
 use gosub_interface::node::{ElementDataType, Node as DocumentNode, TextDataType};
 use gosub_interface::render_tree;
@@ -18,6 +19,11 @@ use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
 
 mod desc;
+mod pseudo;
+
+/// Pseudo elements and anonymous boxes are not part of the document. Their IDs are taken from the upper half of the
+/// ID space, so they do not collide with the IDs of nodes that are added to the document later on.
+const FIRST_GENERATED_ID: usize = usize::MAX / 2;
 
 const INLINE_ELEMENTS: [&str; 31] = [
     "a", "abbr", "acronym", "b", "bdo", "big", "br", "button", "cite", "code", "dfn", "em", "i", "img", "input", "kbd",
@@ -295,15 +301,21 @@ impl<C: HasLayouter<LayoutTree = Self>> RenderTree<C> {
 
     /// Removes all unrenderable nodes from the render tree
     fn remove_unrenderable_nodes(&mut self) {
+        let ids = self.nodes.keys().copied().collect::<Vec<_>>();
+        self.remove_unrenderable_nodes_in(ids);
+    }
+
+    /// Removes the given nodes that are not renderable from the render tree
+    fn remove_unrenderable_nodes_in(&mut self, ids: Vec<NodeId>) {
         // There are more elements that are not renderable, but for now we only remove the most common ones
         let mut delete_list = Vec::new();
 
-        for id in self.nodes.keys() {
+        for id in ids {
             // Check CSS styles and remove if not renderable
-            if let Some(prop) = self.get_property(*id, "display") {
+            if let Some(prop) = self.get_property(id, "display") {
                 if prop.as_string() == Some("none") {
-                    delete_list.append(&mut self.get_child_node_ids(*id));
-                    delete_list.push(*id);
+                    delete_list.append(&mut self.get_child_node_ids(id));
+                    delete_list.push(id);
                     continue;
                 }
             }
@@ -452,13 +464,46 @@ impl<C: HasRenderTree<LayoutTree = Self, RenderTree = Self> + HasDocument> Rende
 
     fn generate_from(&mut self, doc: &C::Document) {
         // Iterate the complete document tree
-
         for current_node_id in TreeIterator::<C>::new(doc) {
-            let node = doc.node_by_id(current_node_id).unwrap();
+            self.generate_node(doc, current_node_id);
+        }
+
+        self.next_id = NodeId::from(FIRST_GENERATED_ID);
+
+        self.remove_unrenderable_nodes();
+
+        self.generate_pseudo_elements(doc);
+
+        <C::CssSystem as CssSystem>::inheritance::<C>(self, doc.media_environment());
+
+        if <C::Layouter as Layouter<C>>::COLLAPSE_INLINE {
+            self.collapse_inline(self.root);
+        }
+    }
 
-            let Some(properties) =
-                <C::CssSystem as CssSystem>::properties_from_node::<C>(node, doc.stylesheets(), doc, current_node_id)
-            else {
+    /// Adds the render node for the document node, when it is rendered. Its children are added to the render node
+    /// already, and are removed again when they turn out not to be rendered.
+    fn generate_node(&mut self, doc: &C::Document, current_node_id: NodeId) {
+        let node = doc.node_by_id(current_node_id).unwrap();
+
+        let Some(properties) =
+            <C::CssSystem as CssSystem>::properties_from_node::<C>(node, doc.stylesheets(), doc, current_node_id)
+        else {
+            if let Some(parent) = node.parent_id() {
+                if let Some(parent) = self.get_node_mut(parent) {
+                    parent.children.retain(|id| *id != current_node_id)
+                }
+            }
+
+            // doc.detach_node(current_node_id);
+            return;
+        };
+
+        let data = node.data();
+
+        let render_data = match RenderNodeData::from_node_data(&data) {
+            ControlFlow::Ok(data) => data,
+            ControlFlow::Drop => {
                 if let Some(parent) = node.parent_id() {
                     if let Some(parent) = self.get_node_mut(parent) {
                         parent.children.retain(|id| *id != current_node_id)
@@ -466,65 +511,144 @@ impl<C: HasRenderTree<LayoutTree = Self, RenderTree = Self> + HasDocument> Rende
                 }
 
                 // doc.detach_node(current_node_id);
-                continue;
-            };
+                return;
+            }
+            ControlFlow::Error(e) => {
+                log::error!("Failed to create node data for node: {current_node_id:?} ({e}");
+                return;
+            }
+        };
 
-            let data = node.data();
+        let mut namespace: Option<String> = None;
 
-            let render_data = match RenderNodeData::from_node_data(&data) {
-                ControlFlow::Ok(data) => data,
-                ControlFlow::Drop => {
-                    if let Some(parent) = node.parent_id() {
-                        if let Some(parent) = self.get_node_mut(parent) {
-                            parent.children.retain(|id| *id != current_node_id)
-                        }
-                    }
+        let name = match data {
+            NodeData::Element(data) => {
+                namespace = Some(data.namespace().to_string());
+                data.name().to_string()
+            }
+            NodeData::Text(_) => "#text".to_owned(),
+            NodeData::Document(_) => "#document".to_owned(),
+            _ => String::new(),
+        };
 
-                    // doc.detach_node(current_node_id);
-                    continue;
-                }
-                ControlFlow::Error(e) => {
-                    log::error!("Failed to create node data for node: {current_node_id:?} ({e}");
-                    continue;
-                }
+        let render_tree_node = RenderTreeNode {
+            id: current_node_id,
+            properties,
+            children: node.children().to_vec(),
+            parent: node.parent_id(),
+            name, // We might be able to move node into render_tree_node
+            namespace,
+            data: render_data,
+            cache: <C::Layouter as Layouter<C>>::Cache::default(),
+            layout: <C::Layouter as Layouter<C>>::Layout::default(),
+        };
+
+        self.nodes.insert(current_node_id, render_tree_node);
+    }
+
+    /// Updates the render tree for the mutations of the document. The subtrees that the mutations affect are
+    /// generated again, and only their layout and the layout of their ancestors is invalidated. When the
+    /// stylesheets have selectors or counters that reach outside these subtrees, the whole tree is generated again.
+    /// Returns true when the tree has changed.
+    pub fn update_from_document(&mut self, doc: &C::Document, records: &[MutationRecord]) -> bool {
+        if records.is_empty() {
+            return false;
+        }
+
+        if doc.stylesheets().iter().any(|sheet| sheet.has_nonlocal_dependencies()) {
+            *self = Self::from_document(doc);
+            return true;
+        }
+
+        let roots = self.restyle_roots(doc, records);
+
+        for root in &roots {
+            self.regenerate_subtree(doc, *root);
+        }
+
+        !roots.is_empty()
+    }
+
+    /// Returns the nodes whose subtrees have to be generated again for the mutations, leaving out nodes that are
+    /// inside the subtree of another one
+    fn restyle_roots(&self, doc: &C::Document, records: &[MutationRecord]) -> Vec<NodeId> {
+        let parent = |id: NodeId| doc.node_by_id(id).and_then(|node| node.parent_id());
+
+        let mut roots: Vec<NodeId> = Vec::new();
+
+        for record in records {
+            // The element whose own style can change. Changed children can make an element match :empty, and
+            // changed text can make its parent match :empty.
+            let element = match record.mutation_type {
+                MutationType::ChildList | MutationType::Attributes => record.target,
+                MutationType::CharacterData => parent(record.target).unwrap_or(record.target),
             };
 
-            let mut namespace: Option<String> = None;
+            // The parent of the element is restyled, as sibling combinators and structural pseudo classes can
+            // change the style of the siblings of the element as well
+            let root = parent(element).unwrap_or(element);
 
-            let name = match data {
-                NodeData::Element(data) => {
-                    namespace = Some(data.namespace().to_string());
-                    data.name().to_string()
+            // Mutations inside nodes that are not rendered are not visible
+            if self.nodes.contains_key(&root) && !roots.contains(&root) {
+                roots.push(root);
+            }
+        }
+
+        roots
+            .iter()
+            .copied()
+            .filter(|root| {
+                let mut parent = self.nodes.get(root).and_then(|node| node.parent);
+                while let Some(parent_id) = parent {
+                    if roots.contains(&parent_id) {
+                        return false;
+                    }
+                    parent = self.nodes.get(&parent_id).and_then(|node| node.parent);
                 }
-                NodeData::Text(_) => "#text".to_owned(),
-                NodeData::Document(_) => "#document".to_owned(),
-                _ => String::new(),
-            };
+                true
+            })
+            .collect()
+    }
 
-            let render_tree_node = RenderTreeNode {
-                id: current_node_id,
-                properties,
-                children: node.children().to_vec(),
-                parent: node.parent_id(),
-                name, // We might be able to move node into render_tree_node
-                namespace,
-                data: render_data,
-                cache: <C::Layouter as Layouter<C>>::Cache::default(),
-                layout: <C::Layouter as Layouter<C>>::Layout::default(),
-            };
+    /// Generates the descendants of the node again from the document. The node itself keeps its style.
+    fn regenerate_subtree(&mut self, doc: &C::Document, root: NodeId) {
+        for id in self.get_child_node_ids(root).into_iter().skip(1) {
+            self.nodes.remove(&id);
+        }
 
-            self.nodes.insert(current_node_id, render_tree_node);
+        let Some(doc_node) = doc.node_by_id(root) else {
+            return;
+        };
+        let children = doc_node.children().to_vec();
+        if let Some(node) = self.nodes.get_mut(&root) {
+            node.children.clone_from(&children);
         }
 
-        self.next_id = doc.peek_next_id();
+        let mut stack = children;
+        stack.reverse();
+        while let Some(id) = stack.pop() {
+            self.generate_node(doc, id);
 
-        self.remove_unrenderable_nodes();
+            if self.nodes.contains_key(&id) {
+                if let Some(node) = doc.node_by_id(id) {
+                    stack.extend(node.children().iter().rev());
+                }
+            }
+        }
 
-        <C::CssSystem as CssSystem>::inheritance::<C>(self);
+        let descendants = self.get_child_node_ids(root).into_iter().skip(1).collect::<Vec<_>>();
+        self.remove_unrenderable_nodes_in(descendants);
+
+        self.generate_pseudo_elements_from(doc, root);
+
+        <C::CssSystem as CssSystem>::inheritance_from::<C>(self, root, doc.media_environment());
 
         if <C::Layouter as Layouter<C>>::COLLAPSE_INLINE {
-            self.collapse_inline(self.root);
+            self.collapse_inline(root);
         }
+
+        self.mark_dirty(root);
+        self.layout_dirty_from(root);
     }
 }
 
@@ -548,6 +672,10 @@ impl<C: HasRenderTree<LayoutTree = Self, RenderTree = Self>> render_tree::Render
         self.get_children(id).cloned()
     }
 
+    fn get_parent(&self, id: Self::NodeId) -> Option<Self::NodeId> {
+        self.nodes.get(&id)?.parent
+    }
+
     fn get_layout(&self, id: Self::NodeId) -> Option<&<C::Layouter as Layouter<C>>::Layout> {
         Some(&LayoutTree::get_node(self, id)?.layout)
     }
@@ -558,6 +686,13 @@ impl<C: HasRenderTree<LayoutTree = Self, RenderTree = Self>> render_tree::Render
     {
         RenderTree::from_document(doc)
     }
+
+    fn update_from_document(&mut self, doc: &C::Document, records: &[MutationRecord]) -> bool
+    where
+        C: HasDocument,
+    {
+        RenderTree::update_from_document(self, doc, records)
+    }
 }
 
 impl<C: HasLayouter> render_tree::RenderTreeNode<C> for RenderTreeNode<C> {
@@ -831,3 +966,336 @@ pub fn generate_render_tree<C: HasDocument + HasRenderTree>(document: &C::Docume
 
     Ok(render_tree)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gosub_css3::system::Css3System;
+    use gosub_html5::document::builder::DocumentBuilderImpl;
+    use gosub_html5::document::document_impl::DocumentImpl;
+    use gosub_html5::document::fragment::DocumentFragmentImpl;
+    use gosub_html5::node::HTML_NAMESPACE;
+    use gosub_interface::config::HasCssSystem;
+    use gosub_interface::mutation::{MutationObserverId, MutationObserverInit};
+    use gosub_interface::node::ElementDataType;
+    use gosub_shared::byte_stream::Location;
+    use gosub_taffy::TaffyLayouter;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Config;
+
+    impl HasCssSystem for Config {
+        type CssSystem = Css3System;
+    }
+    impl HasDocument for Config {
+        type Document = DocumentImpl<Self>;
+        type DocumentFragment = DocumentFragmentImpl<Self>;
+        type DocumentBuilder = DocumentBuilderImpl;
+    }
+    impl HasLayouter for Config {
+        type Layouter = TaffyLayouter;
+        type LayoutTree = RenderTree<Self>;
+    }
+    impl HasRenderTree for Config {
+        type RenderTree = RenderTree<Self>;
+    }
+    impl HasFontManager for Config {
+        type FontManager = gosub_fontmanager::FontManager;
+    }
+
+    type Doc = DocumentImpl<Config>;
+
+    /// Parses the HTML, and observes all mutations of the document
+    fn observed(html: &str) -> (Doc, MutationObserverId) {
+        let mut doc = gosub_html5::html_compile::<Config>(html);
+        let observer = doc.create_mutation_observer();
+        let options = MutationObserverInit {
+            child_list: true,
+            subtree: true,
+            attributes: Some(true),
+            character_data: Some(true),
+            ..Default::default()
+        };
+        doc.observe(observer, NodeId::root(), options).unwrap();
+
+        (doc, observer)
+    }
+
+    /// Applies the mutations of the document to the tree, and checks that it is the same as a tree that is generated
+    /// from the document
+    fn assert_updated(tree: &mut RenderTree<Config>, doc: &mut Doc, observer: MutationObserverId) {
+        let records = doc.take_records(observer);
+        assert!(tree.update_from_document(doc, &records));
+
+        assert_eq!(describe(tree), describe(&RenderTree::from_document(doc)));
+    }
+
+    /// Describes the nodes of the tree in document order, with their name, text and properties. Generated nodes get
+    /// new ids when they are generated again, so their ids are left out.
+    fn describe(tree: &RenderTree<Config>) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut stack = vec![(tree.root, 0)];
+        while let Some((id, depth)) = stack.pop() {
+            let node = &tree.nodes[&id];
+            assert_eq!(
+                node.children
+                    .iter()
+                    .filter(|child| !tree.nodes.contains_key(child))
+                    .count(),
+                0
+            );
+
+            let id = match id.as_usize() >= FIRST_GENERATED_ID {
+                true => "generated".to_string(),
+                false => id.to_string(),
+            };
+            let text = match &node.data {
+                RenderNodeData::Text(data) => data.text.as_str(),
+                _ => "",
+            };
+            let mut props = node
+                .properties
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect::<Vec<_>>();
+            props.sort();
+
+            lines.push(format!(
+                "{}{} {id} {text:?} {}",
+                " ".repeat(depth),
+                node.name,
+                props.join(";")
+            ));
+            stack.extend(node.children.iter().rev().map(|child| (*child, depth + 1)));
+        }
+
+        lines
+    }
+
+    fn element(doc: &Doc, id: &str) -> NodeId {
+        doc.node_by_named_id(id).unwrap().id()
+    }
+
+    fn set_attribute(doc: &mut Doc, id: NodeId, name: &str, value: &str) {
+        let mut node = doc.cloned_node_by_id(id).unwrap();
+        node.get_element_data_mut().unwrap().add_attribute(name, value);
+        doc.update_node(node);
+    }
+
+    fn append_element(doc: &mut Doc, parent: NodeId, name: &str) -> NodeId {
+        let node = Doc::new_element_node(name, Some(HTML_NAMESPACE), HashMap::new(), Location::default());
+        doc.register_node_at(node, parent, None)
+    }
+
+    #[test]
+    fn update_empty_and_sibling_combinators() {
+        let (mut doc, observer) = observed(
+            r#"<html><head><style>
+                div:empty { color: red; }
+                div:empty + p { color: blue; }
+                .a + p, .a ~ span { color: green; }
+                span:last-child { font-size: 30px; }
+            </style></head><body>
+                <main><div id="d"></div><p>after the div</p></main>
+                <section id="s"><em id="e">first</em><p>next</p><span>later</span></section>
+            </body></html>"#,
+        );
+        let mut tree = RenderTree::<Config>::from_document(&doc);
+
+        // A child makes the div no longer empty, which changes the style of the div and of the paragraph after it
+        let d = element(&doc, "d");
+        append_element(&mut doc, d, "b");
+        assert_updated(&mut tree, &mut doc, observer);
+
+        // A class on an element changes the style of the siblings after it
+        let e = element(&doc, "e");
+        set_attribute(&mut doc, e, "class", "a");
+        assert_updated(&mut tree, &mut doc, observer);
+
+        // A new last child changes the style of the previous last child
+        let s = element(&doc, "s");
+        let span = append_element(&mut doc, s, "span");
+        let text = Doc::new_text_node("new", Location::default());
+        doc.register_node_at(text, span, None);
+        assert_updated(&mut tree, &mut doc, observer);
+    }
+
+    #[test]
+    fn update_text_and_moved_children() {
+        let (mut doc, observer) = observed(
+            r#"<html><head><style>
+                p:empty + span { color: red; }
+                li:first-child { color: blue; }
+                ol li::marker { color: green; }
+            </style></head><body>
+                <div><p id="p">text</p><span>after</span></div>
+                <ol id="ol"><li>a</li><li>b</li><li>c</li></ol>
+            </body></html>"#,
+        );
+        let mut tree = RenderTree::<Config>::from_document(&doc);
+
+        let p = element(&doc, "p");
+        let text = doc.node_by_id(p).unwrap().children()[0];
+        let mut node = doc.cloned_node_by_id(text).unwrap();
+        node.get_text_data_mut().unwrap().value_mut().clear();
+        doc.update_node(node);
+        assert_updated(&mut tree, &mut doc, observer);
+
+        // Moving the last item to the front changes the first child and the list numbers
+        let ol = element(&doc, "ol");
+        let mut node = doc.cloned_node_by_id(ol).unwrap();
+        let children = node.children().to_vec();
+        let last = children.len() - 1;
+        node.remove(children[last]);
+        node.insert(children[last], 0);
+        doc.update_node(node);
+        assert_updated(&mut tree, &mut doc, observer);
+    }
+
+    #[test]
+    fn update_nonlocal_dependencies() {
+        let (mut doc, observer) = observed(
+            r#"<html><head><style>
+                section:has(.x) { color: red; }
+                body { counter-reset: chapter; }
+                h2 { counter-increment: chapter; }
+                h2::before { content: counter(chapter) ". "; }
+            </style></head><body>
+                <section><div><p id="p">deep</p></div></section>
+                <section id="s"><h2>one</h2></section>
+                <section><h2>two</h2></section>
+            </body></html>"#,
+        );
+        let mut tree = RenderTree::<Config>::from_document(&doc);
+
+        // A class deep inside the section changes the style of the section
+        let p = element(&doc, "p");
+        set_attribute(&mut doc, p, "class", "x");
+        assert_updated(&mut tree, &mut doc, observer);
+
+        // A new chapter renumbers the chapters in the sections after it
+        let s = element(&doc, "s");
+        let h2 = append_element(&mut doc, s, "h2");
+        let text = Doc::new_text_node("inserted", Location::default());
+        doc.register_node_at(text, h2, None);
+        assert_updated(&mut tree, &mut doc, observer);
+    }
+
+    #[test]
+    fn update_unrendered() {
+        let (mut doc, observer) = observed(r#"<html><head><title>title</title></head><body><p>text</p></body></html>"#);
+        let mut tree = RenderTree::<Config>::from_document(&doc);
+
+        let title = doc.query_selector("title").unwrap().unwrap();
+        set_attribute(&mut doc, title, "lang", "en");
+
+        let records = doc.take_records(observer);
+        assert!(!records.is_empty());
+        assert!(!tree.update_from_document(&doc, &records));
+        assert!(!tree.update_from_document(&doc, &[]));
+    }
+
+    /// Describes the children of the element with their name and the text inside them, skipping whitespace only text
+    fn children(tree: &RenderTree<Config>, id: NodeId) -> Vec<String> {
+        fn text(tree: &RenderTree<Config>, id: NodeId) -> String {
+            let node = &tree.nodes[&id];
+            match &node.data {
+                RenderNodeData::Text(data) => data.text.clone(),
+                _ => node.children.iter().map(|child| text(tree, *child)).collect(),
+            }
+        }
+
+        // Anonymous inline boxes are left out, their children are described instead
+        fn flatten(tree: &RenderTree<Config>, id: NodeId) -> Vec<NodeId> {
+            tree.nodes[&id]
+                .children
+                .iter()
+                .flat_map(|child| match tree.nodes[child].data {
+                    RenderNodeData::AnonymousInline => flatten(tree, *child),
+                    _ => vec![*child],
+                })
+                .collect()
+        }
+
+        flatten(tree, id)
+            .into_iter()
+            .map(|child| (tree.nodes[&child].name.clone(), text(tree, child)))
+            .filter(|(name, text)| name != "#text" || !text.trim().is_empty())
+            .map(|(name, text)| format!("{name} {:?}", text.trim()))
+            .collect()
+    }
+
+    #[test]
+    fn generated_before_and_after() {
+        let doc = gosub_html5::html_compile::<Config>(
+            r#"<html><head><style>
+                p::before { content: "[" counter(para) "] "; }
+                p::after { content: "!"; }
+                p { counter-increment: para; }
+                #none::before { content: none; }
+                #normal::after { content: normal; }
+                #hidden::after { display: none; }
+            </style></head><body>
+                <p id="a">first <em>para</em></p>
+                <p id="none">second</p>
+                <p id="normal">third</p>
+                <p id="hidden">fourth</p>
+            </body></html>"#,
+        );
+        let tree = RenderTree::<Config>::from_document(&doc);
+
+        assert_eq!(
+            children(&tree, element(&doc, "a")),
+            vec!["::before \"[1]\"", "#text \"first\"", "em \"para\"", "::after \"!\""]
+        );
+        assert_eq!(
+            children(&tree, element(&doc, "none")),
+            vec!["#text \"second\"", "::after \"!\""]
+        );
+        assert_eq!(
+            children(&tree, element(&doc, "normal")),
+            vec!["::before \"[3]\"", "#text \"third\""]
+        );
+        assert_eq!(
+            children(&tree, element(&doc, "hidden")),
+            vec!["::before \"[4]\"", "#text \"fourth\""]
+        );
+    }
+
+    #[test]
+    fn generated_markers() {
+        let mut doc = gosub_html5::html_compile::<Config>(
+            r#"<html><head><style>
+                li::before { content: "("; }
+                #none::marker { content: none; }
+                #custom::marker { content: "*"; }
+                #ul { list-style-type: none; }
+            </style></head><body>
+                <ol id="ol" start="3"><li>a</li><li id="none">b</li><li id="custom">c</li></ol>
+                <ul id="ul"><li>d</li></ul>
+            </body></html>"#,
+        );
+        // List items and the list item counter are declared by the user agent stylesheet
+        doc.add_stylesheet(Css3System::load_default_useragent_stylesheet());
+        let tree = RenderTree::<Config>::from_document(&doc);
+
+        let items = |list: &str| {
+            tree.nodes[&element(&doc, list)]
+                .children
+                .iter()
+                .filter(|child| tree.nodes[*child].name == "li")
+                .map(|child| children(&tree, *child))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            items("ol"),
+            vec![
+                vec!["::marker \"3.\"", "::before \"(\"", "#text \"a\""],
+                vec!["::before \"(\"", "#text \"b\""],
+                vec!["::marker \"*\"", "::before \"(\"", "#text \"c\""],
+            ]
+        );
+        assert_eq!(items("ul"), vec![vec!["::before \"(\"", "#text \"d\""]]);
+    }
+}
@@ -0,0 +1,781 @@
+//! Generation of the boxes for the `::before`, `::after` and `::marker` pseudo elements.
+//!
+//! Pseudo elements are not part of the DOM, so they are generated after the render tree has been created from the
+//! document. While walking the tree we keep track of the CSS counters, so `counter()` and `counters()` in the
+//! `content` property and the list item numbers of markers can be resolved.
+use std::collections::HashMap;
+
+use cow_utils::CowUtils;
+
+use gosub_interface::config::{HasDocument, HasRenderTree};
+use gosub_interface::css3::{CssProperty, CssPropertyMap, CssSystem, CssValue};
+use gosub_interface::document::Document;
+use gosub_interface::node::Node as DocumentNode;
+use gosub_shared::node::NodeId;
+
+use crate::render_tree::{RenderNodeData, RenderTree, RenderTreeNode, TextData};
+
+/// Name of the counter that is automatically incremented by list items
+const LIST_ITEM_COUNTER: &str = "list-item";
+
+/// Single item of the `content` property
+#[derive(Debug, Clone, PartialEq)]
+enum ContentItem {
+    Text(String),
+    Counter {
+        name: String,
+        style: String,
+    },
+    Counters {
+        name: String,
+        separator: String,
+        style: String,
+    },
+    Url(String),
+}
+
+/// Inherited list style of the current element
+#[derive(Debug, Clone, PartialEq)]
+struct ListStyle {
+    kind: String,
+    image: Option<String>,
+}
+
+impl Default for ListStyle {
+    fn default() -> Self {
+        Self {
+            kind: "disc".to_string(),
+            image: None,
+        }
+    }
+}
+
+/// All counters that are in scope. Counters are kept on a stack, so nested counters with the same name (like
+/// nested lists) can be found with `counters()`.
+#[derive(Debug, Default)]
+struct Counters {
+    stack: Vec<(String, i32)>,
+}
+
+impl Counters {
+    /// Creates a new counter with the given name
+    fn reset(&mut self, name: &str, value: i32) {
+        self.stack.push((name.to_string(), value));
+    }
+
+    /// Sets the innermost counter with the given name, or creates a new counter when there is none
+    fn set(&mut self, name: &str, value: i32) {
+        match self.stack.iter_mut().rev().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value,
+            None => self.reset(name, value),
+        }
+    }
+
+    /// Increments the innermost counter with the given name, or creates a new counter when there is none
+    fn increment(&mut self, name: &str, value: i32) {
+        match self.stack.iter_mut().rev().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = v.saturating_add(value),
+            None => self.reset(name, value),
+        }
+    }
+
+    /// Returns the value of the innermost counter with the given name
+    fn get(&self, name: &str) -> i32 {
+        self.stack
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| *v)
+            .unwrap_or(0)
+    }
+
+    /// Returns the values of all counters with the given name, from the outermost to the innermost
+    fn all(&self, name: &str) -> Vec<i32> {
+        let values: Vec<i32> = self.stack.iter().filter(|(n, _)| n == name).map(|(_, v)| *v).collect();
+
+        if values.is_empty() {
+            vec![0]
+        } else {
+            values
+        }
+    }
+
+    fn scope(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Removes all counters that have been created since the given scope
+    fn close_scope(&mut self, scope: usize) {
+        self.stack.truncate(scope);
+    }
+}
+
+/// Counter changes of an element or pseudo element
+#[derive(Debug, Default)]
+struct CounterChanges {
+    reset: Vec<(String, i32)>,
+    increment: Vec<(String, i32)>,
+    set: Vec<(String, i32)>,
+}
+
+impl CounterChanges {
+    fn apply(&self, counters: &mut Counters) {
+        for (name, value) in &self.reset {
+            counters.reset(name, *value);
+        }
+        for (name, value) in &self.increment {
+            counters.increment(name, *value);
+        }
+        for (name, value) in &self.set {
+            counters.set(name, *value);
+        }
+    }
+}
+
+impl<C: HasRenderTree<LayoutTree = Self, RenderTree = Self> + HasDocument> RenderTree<C> {
+    /// Generates the pseudo element boxes for all elements in the render tree
+    pub(crate) fn generate_pseudo_elements(&mut self, doc: &C::Document) {
+        let mut counters = Counters::default();
+
+        self.generate_pseudo_elements_for(doc, self.root, &ListStyle::default(), &mut counters);
+    }
+
+    /// Generates the pseudo element boxes for the node and its descendants, which have no pseudo elements yet. The
+    /// counters are continued from the rest of the tree.
+    pub(crate) fn generate_pseudo_elements_from(&mut self, doc: &C::Document, id: NodeId) {
+        let mut counters = Counters::default();
+        let list_style = self
+            .replay_counters(self.root, id, &ListStyle::default(), &mut counters)
+            .unwrap_or_default();
+
+        self.generate_pseudo_elements_for(doc, id, &list_style, &mut counters);
+    }
+
+    /// Applies the counter changes of the node and its descendants, like generating the pseudo elements does.
+    /// Stops at the target, and returns the list style that the target inherits.
+    fn replay_counters(
+        &mut self,
+        id: NodeId,
+        target: NodeId,
+        list_style: &ListStyle,
+        counters: &mut Counters,
+    ) -> Option<ListStyle> {
+        if id == target {
+            return Some(list_style.clone());
+        }
+
+        let node = self.nodes.get_mut(&id)?;
+        if node.is_pseudo_element() {
+            counter_changes::<C>(&mut node.properties).apply(counters);
+            return None;
+        }
+
+        let children = node.children.clone();
+
+        // Anonymous boxes only wrap the children of their parent, and have no counter scope of their own
+        if matches!(node.data, RenderNodeData::AnonymousInline) {
+            return children
+                .into_iter()
+                .find_map(|child| self.replay_counters(child, target, list_style, counters));
+        }
+
+        let mut list_style = list_style.clone();
+        apply_element_counters(node, &mut list_style, counters);
+
+        let scope = counters.scope();
+        for child in children {
+            // The scopes of the ancestors of the target stay open
+            if let Some(found) = self.replay_counters(child, target, &list_style, counters) {
+                return Some(found);
+            }
+        }
+        counters.close_scope(scope);
+
+        None
+    }
+
+    fn generate_pseudo_elements_for(
+        &mut self,
+        doc: &C::Document,
+        id: NodeId,
+        list_style: &ListStyle,
+        counters: &mut Counters,
+    ) {
+        let Some(node) = self.nodes.get_mut(&id) else {
+            return;
+        };
+
+        let children = node.children.clone();
+        let mut list_style = list_style.clone();
+        let is_list_item = apply_element_counters(node, &mut list_style, counters);
+
+        // Counters created by the children are only visible inside this element
+        let scope = counters.scope();
+
+        let doc_node = doc.node_by_id(id).filter(|node| node.is_element_node());
+
+        if let Some(doc_node) = doc_node {
+            if let Some(before) = self.generate_pseudo_element(doc, doc_node, id, "before", counters) {
+                self.move_to_front(id, before);
+            }
+
+            if is_list_item {
+                if let Some(marker) = self.generate_marker(doc, doc_node, id, &list_style, counters) {
+                    self.move_to_front(id, marker);
+                }
+            }
+        }
+
+        for child in children {
+            self.generate_pseudo_elements_for(doc, child, &list_style, counters);
+        }
+
+        if let Some(doc_node) = doc_node {
+            self.generate_pseudo_element(doc, doc_node, id, "after", counters);
+        }
+
+        counters.close_scope(scope);
+    }
+
+    /// Generates the `::before` or `::after` pseudo element of the node. The new node is appended to the children
+    /// of the node.
+    fn generate_pseudo_element(
+        &mut self,
+        doc: &C::Document,
+        doc_node: &C::Node,
+        id: NodeId,
+        pseudo: &str,
+        counters: &mut Counters,
+    ) -> Option<NodeId> {
+        let mut props = <C::CssSystem as CssSystem>::pseudo_element_properties_from_node::<C>(
+            doc_node,
+            doc.stylesheets(),
+            doc,
+            id,
+            pseudo,
+        )?;
+
+        // Without a content property there is no box to generate
+        let content = computed::<C>(&mut props, "content").and_then(|prop| content_items::<C>(prop))?;
+
+        if computed_string::<C>(&mut props, "display").as_deref() == Some("none") {
+            return None;
+        }
+
+        counter_changes::<C>(&mut props).apply(counters);
+
+        Some(self.insert_pseudo_element(id, pseudo, props, &content, counters))
+    }
+
+    /// Generates the `::marker` pseudo element of a list item. The content of the marker is taken from the `content`
+    /// property of the marker, or otherwise from the list style of the list item.
+    fn generate_marker(
+        &mut self,
+        doc: &C::Document,
+        doc_node: &C::Node,
+        id: NodeId,
+        list_style: &ListStyle,
+        counters: &mut Counters,
+    ) -> Option<NodeId> {
+        let mut props = <C::CssSystem as CssSystem>::pseudo_element_properties_from_node::<C>(
+            doc_node,
+            doc.stylesheets(),
+            doc,
+            id,
+            "marker",
+        )
+        .unwrap_or_default();
+
+        let content = match computed::<C>(&mut props, "content") {
+            Some(prop) if is_keyword::<C>(prop, "none") => return None,
+            Some(prop) if !is_keyword::<C>(prop, "normal") => content_items::<C>(prop)?,
+            _ => marker_content(list_style)?,
+        };
+
+        counter_changes::<C>(&mut props).apply(counters);
+
+        Some(self.insert_pseudo_element(id, "marker", props, &content, counters))
+    }
+
+    /// Inserts a pseudo element with the given content as last child of the parent
+    fn insert_pseudo_element(
+        &mut self,
+        parent: NodeId,
+        pseudo: &str,
+        mut props: C::CssPropertyMap,
+        content: &[ContentItem],
+        counters: &Counters,
+    ) -> NodeId {
+        // Pseudo elements are inline, unless declared otherwise
+        if props.get("display").is_none() {
+            props.insert(
+                "display",
+                C::CssProperty::from(<C::CssSystem as CssSystem>::Value::new_string("inline")),
+            );
+        }
+
+        let id = self.insert_node_data(
+            parent,
+            format!("::{pseudo}"),
+            RenderNodeData::Element {
+                attributes: HashMap::new(),
+            },
+            props,
+        );
+
+        let mut text = String::new();
+
+        for item in content {
+            match item {
+                ContentItem::Text(str) => text.push_str(str),
+                ContentItem::Counter { name, style } => text.push_str(&format_counter(counters.get(name), style)),
+                ContentItem::Counters { name, separator, style } => {
+                    let values: Vec<String> = counters
+                        .all(name)
+                        .into_iter()
+                        .map(|value| format_counter(value, style))
+                        .collect();
+
+                    text.push_str(&values.join(separator));
+                }
+                ContentItem::Url(url) => {
+                    self.insert_pseudo_text(id, &mut text);
+
+                    let mut attributes = HashMap::new();
+                    attributes.insert("src".to_string(), url.clone());
+
+                    self.insert_node_data(
+                        id,
+                        "img".to_string(),
+                        RenderNodeData::Element { attributes },
+                        C::CssPropertyMap::default(),
+                    );
+                }
+            }
+        }
+
+        self.insert_pseudo_text(id, &mut text);
+
+        id
+    }
+
+    /// Inserts the collected text as text node, when there is any
+    fn insert_pseudo_text(&mut self, parent: NodeId, text: &mut String) {
+        if text.is_empty() {
+            return;
+        }
+
+        self.insert_node_data(
+            parent,
+            "#text".to_string(),
+            RenderNodeData::Text(Box::new(TextData {
+                text: std::mem::take(text),
+                layout: Vec::new(),
+            })),
+            C::CssPropertyMap::default(),
+        );
+    }
+
+    /// Moves the child to the front of the children of the parent
+    fn move_to_front(&mut self, parent: NodeId, child: NodeId) {
+        if let Some(parent) = self.nodes.get_mut(&parent) {
+            parent.children.retain(|id| *id != child);
+            parent.children.insert(0, child);
+        }
+    }
+}
+
+impl<C: HasRenderTree> RenderTreeNode<C> {
+    /// Returns true when this node has been generated for a pseudo element
+    pub fn is_pseudo_element(&self) -> bool {
+        self.name.starts_with("::")
+    }
+}
+
+/// Applies the counter changes of an element, and the list style it sets for its descendants. Returns true when
+/// the element is a list item.
+fn apply_element_counters<C: HasRenderTree>(
+    node: &mut RenderTreeNode<C>,
+    list_style: &mut ListStyle,
+    counters: &mut Counters,
+) -> bool {
+    if !node.is_element() {
+        return false;
+    }
+
+    let props = &mut node.properties;
+
+    let is_list_item = computed_string::<C>(props, "display").as_deref() == Some("list-item");
+
+    if let Some(kind) = computed_string::<C>(props, "list-style-type") {
+        list_style.kind = kind;
+    }
+    if let Some(prop) = computed::<C>(props, "list-style-image") {
+        list_style.image = prop
+            .as_function()
+            .and_then(|(name, args)| url_from_function::<C>(name, args));
+    }
+
+    let mut changes = counter_changes::<C>(props);
+
+    let attributes = match &node.data {
+        RenderNodeData::Element { attributes } => attributes.clone(),
+        _ => HashMap::new(),
+    };
+
+    // The html user agent stylesheet resets the list item counter for lists, and lets list items override
+    // their number with the value attribute.
+    if matches!(node.name.as_str(), "ol" | "ul" | "menu")
+        && !changes.reset.iter().any(|(name, _)| name == LIST_ITEM_COUNTER)
+    {
+        let start = attributes
+            .get("start")
+            .and_then(|start| start.trim().parse::<i32>().ok())
+            .unwrap_or(1);
+
+        changes
+            .reset
+            .push((LIST_ITEM_COUNTER.to_string(), start.saturating_sub(1)));
+    }
+
+    if is_list_item {
+        if !changes.increment.iter().any(|(name, _)| name == LIST_ITEM_COUNTER) {
+            changes.increment.push((LIST_ITEM_COUNTER.to_string(), 1));
+        }
+
+        if let Some(value) = attributes.get("value").and_then(|v| v.trim().parse::<i32>().ok()) {
+            changes.set.push((LIST_ITEM_COUNTER.to_string(), value));
+        }
+    }
+
+    changes.apply(counters);
+
+    is_list_item
+}
+
+/// Returns the property with its computed value
+fn computed<'a, C: HasRenderTree>(props: &'a mut C::CssPropertyMap, name: &str) -> Option<&'a C::CssProperty> {
+    let prop = props.get_mut(name)?;
+    prop.compute_value();
+
+    Some(prop)
+}
+
+fn computed_string<C: HasRenderTree>(props: &mut C::CssPropertyMap, name: &str) -> Option<String> {
+    computed::<C>(props, name)?.as_string().map(|s| s.to_string())
+}
+
+fn is_keyword<C: HasRenderTree>(prop: &C::CssProperty, keyword: &str) -> bool {
+    prop.as_string() == Some(keyword) || (keyword == "none" && prop.is_none())
+}
+
+/// Returns the url of an `url()` function
+fn url_from_function<C: HasRenderTree>(name: &str, args: &[<C::CssSystem as CssSystem>::Value]) -> Option<String> {
+    if name != "url" {
+        return None;
+    }
+
+    args.first().and_then(|arg| arg.as_string()).map(|url| url.to_string())
+}
+
+/// Converts the `content` property into a list of items. Returns `None` when no box should be generated.
+fn content_items<C: HasRenderTree>(prop: &C::CssProperty) -> Option<Vec<ContentItem>> {
+    if is_keyword::<C>(prop, "none") || is_keyword::<C>(prop, "normal") {
+        return None;
+    }
+
+    let mut items = Vec::new();
+
+    if let Some(list) = prop.as_list() {
+        for value in list {
+            collect_content_items::<C>(value, &mut items);
+        }
+    } else if let Some(str) = prop.as_string() {
+        items.push(ContentItem::Text(str.to_string()));
+    } else if let Some((name, args)) = prop.as_function() {
+        items.extend(content_function::<C>(name, args));
+    } else if let Some(num) = prop.as_number() {
+        items.push(ContentItem::Text(format_number(num)));
+    } else {
+        return None;
+    }
+
+    Some(items)
+}
+
+fn collect_content_items<C: HasRenderTree>(value: &<C::CssSystem as CssSystem>::Value, items: &mut Vec<ContentItem>) {
+    if let Some(list) = value.as_list() {
+        for value in list {
+            collect_content_items::<C>(value, items);
+        }
+    } else if let Some(str) = value.as_string() {
+        items.push(ContentItem::Text(str.to_string()));
+    } else if let Some((name, args)) = value.as_function() {
+        items.extend(content_function::<C>(name, args));
+    } else if let Some(num) = value.as_number() {
+        items.push(ContentItem::Text(format_number(num)));
+    }
+}
+
+fn content_function<C: HasRenderTree>(name: &str, args: &[<C::CssSystem as CssSystem>::Value]) -> Option<ContentItem> {
+    let mut args = args
+        .iter()
+        .filter(|arg| !arg.is_comma())
+        .filter_map(|arg| arg.as_string());
+
+    match name {
+        "counter" => Some(ContentItem::Counter {
+            name: args.next()?.to_string(),
+            style: args.next().unwrap_or("decimal").to_string(),
+        }),
+        "counters" => Some(ContentItem::Counters {
+            name: args.next()?.to_string(),
+            separator: args.next().unwrap_or("").to_string(),
+            style: args.next().unwrap_or("decimal").to_string(),
+        }),
+        "url" => Some(ContentItem::Url(args.next()?.to_string())),
+        _ => None,
+    }
+}
+
+/// Returns the counter changes (counter-reset, counter-increment and counter-set) of the properties
+fn counter_changes<C: HasRenderTree>(props: &mut C::CssPropertyMap) -> CounterChanges {
+    CounterChanges {
+        reset: counter_list::<C>(props, "counter-reset", 0),
+        increment: counter_list::<C>(props, "counter-increment", 1),
+        set: counter_list::<C>(props, "counter-set", 0),
+    }
+}
+
+/// Parses a list of counter names with optional values, like `item 2 other`
+fn counter_list<C: HasRenderTree>(props: &mut C::CssPropertyMap, name: &str, default: i32) -> Vec<(String, i32)> {
+    let Some(prop) = computed::<C>(props, name) else {
+        return Vec::new();
+    };
+
+    let mut result: Vec<(String, i32)> = Vec::new();
+    let mut add = |value: &<C::CssSystem as CssSystem>::Value| {
+        if let Some(name) = value.as_string() {
+            if name != "none" {
+                result.push((name.to_string(), default));
+            }
+        } else if let Some((_, args)) = value.as_function() {
+            // reversed(<counter-name>)
+            if let Some(name) = args.first().and_then(|arg| arg.as_string()) {
+                result.push((name.to_string(), default));
+            }
+        } else if let Some(num) = value.as_number() {
+            if let Some((_, value)) = result.last_mut() {
+                *value = num as i32;
+            }
+        }
+    };
+
+    if let Some(list) = prop.as_list() {
+        list.iter().for_each(&mut add);
+    } else if let Some(name) = prop.as_string() {
+        if name != "none" {
+            result.push((name.to_string(), default));
+        }
+    }
+
+    result
+}
+
+/// Returns the content of a marker for the given list style
+fn marker_content(list_style: &ListStyle) -> Option<Vec<ContentItem>> {
+    if let Some(image) = &list_style.image {
+        return Some(vec![
+            ContentItem::Url(image.clone()),
+            ContentItem::Text(" ".to_string()),
+        ]);
+    }
+
+    let content = match list_style.kind.as_str() {
+        "none" => return None,
+        "disc" | "circle" | "square" | "disclosure-open" | "disclosure-closed" => vec![
+            ContentItem::Counter {
+                name: LIST_ITEM_COUNTER.to_string(),
+                style: list_style.kind.clone(),
+            },
+            ContentItem::Text(" ".to_string()),
+        ],
+        kind if is_counter_style(kind) => vec![
+            ContentItem::Counter {
+                name: LIST_ITEM_COUNTER.to_string(),
+                style: kind.to_string(),
+            },
+            ContentItem::Text(". ".to_string()),
+        ],
+        // Unknown counter styles are seen as literal strings
+        text => vec![ContentItem::Text(text.to_string())],
+    };
+
+    Some(content)
+}
+
+fn is_counter_style(style: &str) -> bool {
+    matches!(
+        style,
+        "decimal"
+            | "decimal-leading-zero"
+            | "lower-alpha"
+            | "lower-latin"
+            | "upper-alpha"
+            | "upper-latin"
+            | "lower-roman"
+            | "upper-roman"
+            | "lower-greek"
+            | "disc"
+            | "circle"
+            | "square"
+            | "disclosure-open"
+            | "disclosure-closed"
+            | "none"
+    )
+}
+
+/// Formats the counter value in the given counter style. Unknown styles are formatted as decimal.
+fn format_counter(value: i32, style: &str) -> String {
+    match style {
+        "none" => String::new(),
+        "disc" => "•".to_string(),
+        "circle" => "◦".to_string(),
+        "square" => "▪".to_string(),
+        "disclosure-open" => "▾".to_string(),
+        "disclosure-closed" => "▸".to_string(),
+        "decimal-leading-zero" if (0..10).contains(&value) => format!("0{value}"),
+        "lower-alpha" | "lower-latin" => alphabetic(value, 'a', 26).unwrap_or_else(|| value.to_string()),
+        "upper-alpha" | "upper-latin" => alphabetic(value, 'A', 26).unwrap_or_else(|| value.to_string()),
+        "lower-greek" => alphabetic(value, 'α', 24).unwrap_or_else(|| value.to_string()),
+        "lower-roman" => roman(value).map_or_else(|| value.to_string(), |s| s.cow_to_ascii_lowercase().to_string()),
+        "upper-roman" => roman(value).unwrap_or_else(|| value.to_string()),
+        _ => value.to_string(),
+    }
+}
+
+/// Formats the value with an alphabetic counter style (a, b, ..., z, aa, ab, ...)
+fn alphabetic(value: i32, first: char, len: u32) -> Option<String> {
+    if value < 1 {
+        return None;
+    }
+
+    let mut value = value as u32;
+    let mut result = Vec::new();
+
+    while value > 0 {
+        value -= 1;
+        result.push(char::from_u32(first as u32 + value % len)?);
+        value /= len;
+    }
+
+    Some(result.iter().rev().collect())
+}
+
+/// Formats the value as (upper case) roman number. Only values between 1 and 3999 can be formatted.
+fn roman(value: i32) -> Option<String> {
+    const NUMERALS: [(i32, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+
+    if !(1..4000).contains(&value) {
+        return None;
+    }
+
+    let mut value = value;
+    let mut result = String::new();
+
+    for (num, numeral) in NUMERALS {
+        while value >= num {
+            result.push_str(numeral);
+            value -= num;
+        }
+    }
+
+    Some(result)
+}
+
+fn format_number(num: f32) -> String {
+    if num.fract() == 0.0 {
+        format!("{}", num as i64)
+    } else {
+        num.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counter_styles() {
+        assert_eq!(format_counter(3, "decimal"), "3");
+        assert_eq!(format_counter(3, "decimal-leading-zero"), "03");
+        assert_eq!(format_counter(1, "lower-alpha"), "a");
+        assert_eq!(format_counter(28, "upper-alpha"), "AB");
+        assert_eq!(format_counter(0, "lower-alpha"), "0");
+        assert_eq!(format_counter(1994, "upper-roman"), "MCMXCIV");
+        assert_eq!(format_counter(4, "lower-roman"), "iv");
+        assert_eq!(format_counter(2, "lower-greek"), "β");
+        assert_eq!(format_counter(5, "disc"), "•");
+        assert_eq!(format_counter(5, "none"), "");
+        assert_eq!(format_counter(5, "unknown-style"), "5");
+    }
+
+    #[test]
+    fn counter_scopes() {
+        let mut counters = Counters::default();
+        counters.reset("item", 0);
+        counters.increment("item", 1);
+
+        let scope = counters.scope();
+        counters.reset("item", 5);
+        counters.increment("item", 2);
+        assert_eq!(counters.get("item"), 7);
+        assert_eq!(counters.all("item"), vec![1, 7]);
+        counters.close_scope(scope);
+
+        assert_eq!(counters.get("item"), 1);
+        counters.increment("other", 3);
+        assert_eq!(counters.get("other"), 3);
+        counters.set("item", 10);
+        assert_eq!(counters.all("item"), vec![10]);
+        assert_eq!(counters.all("missing"), vec![0]);
+    }
+
+    #[test]
+    fn list_markers() {
+        let style = |kind: &str| ListStyle {
+            kind: kind.to_string(),
+            image: None,
+        };
+
+        assert_eq!(marker_content(&style("none")), None);
+        assert_eq!(
+            marker_content(&style("decimal")),
+            Some(vec![
+                ContentItem::Counter {
+                    name: LIST_ITEM_COUNTER.to_string(),
+                    style: "decimal".to_string()
+                },
+                ContentItem::Text(". ".to_string())
+            ])
+        );
+        assert_eq!(
+            marker_content(&style("-")),
+            Some(vec![ContentItem::Text("-".to_string())])
+        );
+    }
+}
@@ -1,4 +1,5 @@
-use std::future::Future;
+use std::future::{poll_fn, Future};
+use std::task::Poll;
 
 #[cfg(not(target_arch = "wasm32"))]
 pub trait WasmNotSend: Send {}
@@ -58,3 +59,18 @@ pub fn spawn_from<F: Future<Output = ()> + 'static>(f: impl FnOnce() -> F + 'sta
         });
     }
 }
+
+/// Yields to the executor once, so the other tasks on the thread can run before the current task continues
+pub async fn yield_now() {
+    let mut yielded = false;
+    poll_fn(|cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
@@ -1,5 +1,6 @@
 use std::cell::RefCell;
 use std::char::REPLACEMENT_CHARACTER;
+use std::collections::VecDeque;
 use std::fmt::{Debug, Display, Formatter};
 use std::io::Read;
 use std::{fmt, io};
@@ -8,7 +9,7 @@ pub const CHAR_LF: char = '\u{000A}';
 pub const CHAR_CR: char = '\u{000D}';
 
 /// Encoding defines the way the buffer stream is read, as what defines a "character".
-#[derive(PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Encoding {
     /// Unknown encoding. Won't read anything from the stream until the encoding is set
     UNKNOWN,
@@ -20,6 +21,60 @@ pub enum Encoding {
     UTF16LE,
     // Stream consists of 16-bit UTF characters (Big Endian)
     UTF16BE,
+    /// Stream is in any of the other (legacy) encodings of the WHATWG encoding standard, like windows-1252 or
+    /// Shift_JIS. The stream is decoded into UTF8 internally.
+    Legacy(&'static encoding_rs::Encoding),
+}
+
+impl Encoding {
+    /// Returns the encoding for the given label (like "utf-8", "latin1" or "sjis") as defined by the WHATWG
+    /// encoding standard, or None when the label is unknown.
+    pub fn for_label(label: &str) -> Option<Self> {
+        encoding_rs::Encoding::for_label(label.as_bytes()).map(Self::from)
+    }
+
+    /// Returns the canonical name of the encoding
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::UNKNOWN => "unknown",
+            Encoding::ASCII => "us-ascii",
+            Encoding::UTF8 => encoding_rs::UTF_8.name(),
+            Encoding::UTF16LE => encoding_rs::UTF_16LE.name(),
+            Encoding::UTF16BE => encoding_rs::UTF_16BE.name(),
+            Encoding::Legacy(encoding) => encoding.name(),
+        }
+    }
+
+    /// Returns true when the encoding is either UTF-16LE or UTF-16BE
+    pub fn is_utf16(&self) -> bool {
+        matches!(self, Encoding::UTF16LE | Encoding::UTF16BE)
+    }
+}
+
+impl From<&'static encoding_rs::Encoding> for Encoding {
+    fn from(encoding: &'static encoding_rs::Encoding) -> Self {
+        if encoding == encoding_rs::UTF_8 {
+            Encoding::UTF8
+        } else if encoding == encoding_rs::UTF_16LE {
+            Encoding::UTF16LE
+        } else if encoding == encoding_rs::UTF_16BE {
+            Encoding::UTF16BE
+        } else {
+            Encoding::Legacy(encoding)
+        }
+    }
+}
+
+/// The confidence of the encoding of a stream, as defined in the HTML spec. Only a tentative encoding can be changed
+/// by a `<meta charset>` element during parsing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Confidence {
+    /// Encoding is a guess (prescan or stream analysis) and may change during parsing
+    Tentative,
+    /// Encoding is known (byte order mark, transport layer or an earlier encoding change)
+    Certain,
+    /// Encoding does not matter, as the stream is not read from bytes
+    Irrelevant,
 }
 
 /// Defines a single character/element in the stream. This is either a UTF8 character, or
@@ -113,14 +168,21 @@ impl Default for Config {
 }
 
 pub struct ByteStream {
-    /// Actual buffer stream in u8 bytes
+    /// Actual buffer stream in u8 bytes. For legacy encodings this holds the stream decoded into UTF8.
     buffer: Vec<u8>,
+    /// Undecoded bytes of the stream when a legacy encoding is used, so the stream can be decoded again when the
+    /// encoding changes
+    source: Vec<u8>,
+    /// Decoder for a legacy encoding that is fed with incoming bytes
+    decoder: Option<encoding_rs::Decoder>,
     /// Current position in the stream
     buffer_pos: RefCell<usize>,
     /// True when the buffer is empty and not yet have a closed stream
     closed: bool,
     /// Current encoding
     encoding: Encoding,
+    /// Confidence of the current encoding
+    confidence: Confidence,
     // Configuration for the stream
     config: Config,
 }
@@ -181,6 +243,14 @@ impl Stream for ByteStream {
             *pos += len;
         }
 
+        // A CR at the end of an open stream could be followed by a LF that has not arrived yet, so it is read once
+        // more input is available
+        if (self.config.cr_lf_as_one || self.config.replace_cr_as_lf) && ch == Ch(CHAR_CR) && self.read() == StreamEmpty
+        {
+            self.move_back(1);
+            return StreamEmpty;
+        }
+
         // Make sure we skip the CR if it is followed by a LF
         if self.config.cr_lf_as_one && ch == Ch(CHAR_CR) && self.read() == Ch(CHAR_LF) {
             self.next();
@@ -199,7 +269,7 @@ impl Stream for ByteStream {
     /// (or back) in the stream.
     fn look_ahead(&self, offset: usize) -> Character {
         if self.buffer.is_empty() {
-            return StreamEnd;
+            return if self.closed { StreamEnd } else { StreamEmpty };
         }
 
         let original_pos = *self.buffer_pos.borrow();
@@ -286,7 +356,7 @@ impl Stream for ByteStream {
 
     /// Closes the stream so no more data can be added
     fn close(&mut self) {
-        self.closed = true;
+        ByteStream::close(self);
     }
 
     /// Returns true when the stream is closed and no more input can be read after this buffer
@@ -315,8 +385,11 @@ impl ByteStream {
             config: config.unwrap_or_default(),
             buffer_pos: RefCell::new(0),
             buffer: Vec::new(),
+            source: Vec::new(),
+            decoder: encoding_decoder(encoding),
             closed: false,
             encoding,
+            confidence: Confidence::Irrelevant,
         }
     }
 
@@ -349,12 +422,16 @@ impl ByteStream {
                     (Ch(self.buffer[*buf_pos] as char), 1)
                 }
             }
-            Encoding::UTF8 => {
+            Encoding::UTF8 | Encoding::Legacy(_) => {
                 let first_byte = self.buffer[*buf_pos];
                 let width = utf8_char_width(first_byte);
 
+                // The rest of the character has not arrived yet, or never will when the stream is closed
                 if *buf_pos + width > self.buffer.len() {
-                    return (StreamEmpty, self.buffer.len() - *buf_pos);
+                    if self.closed {
+                        return (Ch(REPLACEMENT_CHARACTER), self.buffer.len() - *buf_pos);
+                    }
+                    return (StreamEmpty, 0);
                 }
 
                 let ch = match width {
@@ -387,8 +464,10 @@ impl ByteStream {
                         char::from_u32(u32::from(code_unit)).map_or(Ch(REPLACEMENT_CHARACTER), Ch),
                         2,
                     )
+                } else if self.closed {
+                    (Ch(REPLACEMENT_CHARACTER), 1)
                 } else {
-                    (StreamEmpty, 1)
+                    (StreamEmpty, 0)
                 }
             }
             Encoding::UTF16BE => {
@@ -398,8 +477,10 @@ impl ByteStream {
                         char::from_u32(u32::from(code_unit)).map_or(Ch(REPLACEMENT_CHARACTER), Ch),
                         2,
                     )
+                } else if self.closed {
+                    (Ch(REPLACEMENT_CHARACTER), 1)
                 } else {
-                    (StreamEmpty, 1)
+                    (StreamEmpty, 0)
                 }
             }
         }
@@ -408,35 +489,77 @@ impl ByteStream {
     /// Populates the current buffer with the contents of given file f
     pub fn read_from_file(&mut self, mut f: impl Read) -> io::Result<()> {
         // First we read the u8 bytes into a buffer
-        f.read_to_end(&mut self.buffer).expect("uh oh");
-        self.close();
-        self.reset_stream();
-        self.close();
-        Ok(())
+        let mut bytes = Vec::new();
+        f.read_to_end(&mut bytes)?;
+        self.read_from_bytes(&bytes)
     }
 
     /// Populates the current buffer with the contents of the given string s
     pub fn read_from_str(&mut self, s: &str, _encoding: Option<Encoding>) {
-        self.buffer = Vec::from(s.as_bytes());
+        self.buffer.clear();
+        self.source.clear();
+        self.decoder = encoding_decoder(self.encoding);
+        self.append_str(s);
         self.reset_stream();
     }
 
+    /// Appends the given string to the buffer. Note that the string is already decoded, so when a legacy encoding
+    /// is used, it is encoded back into the undecoded source so the stream can still be decoded again.
     pub fn append_str(&mut self, s: &str) {
+        if let Encoding::Legacy(encoding) = self.encoding {
+            let (bytes, _, _) = encoding.encode(s);
+            self.source.extend_from_slice(&bytes);
+        }
         self.buffer.extend_from_slice(s.as_bytes());
     }
 
+    /// Appends raw bytes in the current encoding to the buffer
+    pub fn append_bytes(&mut self, bytes: &[u8]) {
+        if matches!(self.encoding, Encoding::Legacy(_)) {
+            self.source.extend_from_slice(bytes);
+            self.decode(bytes, false);
+        } else {
+            self.buffer.extend_from_slice(bytes);
+        }
+    }
+
     pub fn close(&mut self) {
+        if !self.closed && matches!(self.encoding, Encoding::Legacy(_)) {
+            // Flush any incomplete sequence that is left in the decoder
+            self.decode(&[], true);
+        }
         self.closed = true;
     }
 
     /// Read directly from bytes
     pub fn read_from_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
-        self.buffer = bytes.to_vec();
+        self.buffer.clear();
+        self.source.clear();
+        self.decoder = encoding_decoder(self.encoding);
+        self.closed = false;
+        self.append_bytes(bytes);
         self.close();
         self.reset_stream();
         Ok(())
     }
 
+    /// Decodes the given bytes of a legacy encoding and adds them to the buffer
+    fn decode(&mut self, bytes: &[u8], last: bool) {
+        let Some(decoder) = self.decoder.as_mut() else {
+            return;
+        };
+
+        let mut decoded = String::with_capacity(decoder.max_utf8_buffer_length(bytes.len()).unwrap_or(bytes.len() * 3));
+        let (_, _, _) = decoder.decode_to_string(bytes, &mut decoded, last);
+        self.buffer.extend_from_slice(decoded.as_bytes());
+    }
+
+    /// Returns the number of bytes in the buffer that have not been read yet. In an open stream, more bytes can
+    /// be appended later.
+    pub fn bytes_available(&self) -> usize {
+        self.buffer.len().saturating_sub(*self.buffer_pos.borrow())
+    }
+
     /// Returns the number of characters left in the buffer
     #[cfg(test)]
     fn chars_left(&self) -> usize {
@@ -455,7 +578,7 @@ impl ByteStream {
                     *pos = 0;
                 }
             }
-            Encoding::UTF8 => {
+            Encoding::UTF8 | Encoding::Legacy(_) => {
                 let mut n = n;
                 while n > 0 && *pos > 0 {
                     *pos -= 1;
@@ -487,20 +610,16 @@ impl ByteStream {
 impl ByteStream {
     /// Detect the given encoding from stream analysis
     pub fn detect_encoding(&self) -> Encoding {
-        let mut buf = self.buffer.as_slice();
+        let mut buf = self.raw_bytes();
 
         // Check for BOM
-        if buf.starts_with(b"\xEF\xBB\xBF") {
-            return Encoding::UTF8;
-        } else if buf.starts_with(b"\xFF\xFE") {
-            return Encoding::UTF16LE;
-        } else if buf.starts_with(b"\xFE\xFF") {
-            return Encoding::UTF16BE;
+        if let Some(encoding) = bom_encoding(buf) {
+            return encoding;
         }
 
         // Cap the buffer size we will check to max 64KB
         const MAX_BUF_SIZE: usize = 64 * 1024;
-        let mut complete = true;
+        let mut complete = self.closed;
         if buf.len() > MAX_BUF_SIZE {
             buf = &buf[..MAX_BUF_SIZE];
             complete = false;
@@ -509,25 +628,386 @@ impl ByteStream {
         let mut encoding_detector = chardetng::EncodingDetector::new();
         encoding_detector.feed(buf, complete);
 
-        let encoding = encoding_detector.guess(None, true);
-        if encoding == encoding_rs::UTF_8 {
-            Encoding::UTF8
-        } else if encoding == encoding_rs::UTF_16BE {
-            Encoding::UTF16BE
-        } else if encoding == encoding_rs::UTF_16LE {
-            Encoding::UTF16LE
+        Encoding::from(encoding_detector.guess(None, true))
+    }
+
+    /// Determines the encoding of the stream with the encoding sniffing algorithm of the HTML spec and switches the
+    /// stream to it. A byte order mark wins, followed by the charset of the transport layer (like the `Content-Type`
+    /// header), a `<meta charset>` found by prescanning the stream and finally a guess from stream analysis.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#encoding-sniffing-algorithm
+    pub fn sniff_encoding(&mut self, transport_encoding: Option<Encoding>) -> Encoding {
+        let bytes = self.raw_bytes();
+
+        let (encoding, confidence) = if let Some(encoding) = bom_encoding(bytes) {
+            (encoding, Confidence::Certain)
+        } else if let Some(encoding) = transport_encoding {
+            (encoding, Confidence::Certain)
+        } else if let Some(encoding) = prescan_encoding(bytes) {
+            (encoding, Confidence::Tentative)
         } else {
-            panic!("Unsupported encoding");
+            (self.detect_encoding(), Confidence::Tentative)
+        };
+
+        self.set_encoding(encoding);
+        self.confidence = confidence;
+
+        // The byte order mark itself is not part of the stream
+        if confidence == Confidence::Certain && bom_encoding(self.raw_bytes()) == Some(encoding) {
+            let bom_len = if encoding == Encoding::UTF8 { 3 } else { 2 };
+            self.seek_bytes(bom_len);
+        } else {
+            self.reset_stream();
         }
+
+        encoding
     }
 
     /// Changes the encoding that the decoder uses to read the buffer. Note that this does not reset
-    /// the buffer, so it might start on a non-valid character.
+    /// the buffer, so it might start on a non-valid character. When switching from or to a legacy encoding, the
+    /// stream is decoded again from its start, so the stream should be reset as the position is no longer valid.
     pub fn set_encoding(&mut self, e: Encoding) {
+        let was_legacy = matches!(self.encoding, Encoding::Legacy(_));
+        let is_legacy = matches!(e, Encoding::Legacy(_));
+
+        if was_legacy {
+            self.buffer = std::mem::take(&mut self.source);
+        }
+
         self.encoding = e;
+        self.decoder = encoding_decoder(e);
+
+        if is_legacy {
+            self.source = std::mem::take(&mut self.buffer);
+            let source = std::mem::take(&mut self.source);
+            self.decode(&source, self.closed);
+            self.source = source;
+        }
+    }
+
+    /// Returns the current encoding of the stream
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Returns the confidence of the current encoding
+    pub fn confidence(&self) -> Confidence {
+        self.confidence
+    }
+
+    /// Sets the confidence of the current encoding
+    pub fn set_confidence(&mut self, confidence: Confidence) {
+        self.confidence = confidence;
+    }
+
+    /// Changes the encoding while parsing, for instance when a `<meta charset>` element is found. Returns true when
+    /// the encoding has changed, in which case the stream is reset and the document must be parsed again from the
+    /// start. The encoding is only changed when its confidence is tentative.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#changing-the-encoding-while-parsing
+    pub fn change_encoding(&mut self, new_encoding: Encoding) -> bool {
+        if self.confidence != Confidence::Tentative {
+            return false;
+        }
+
+        // 1.
+        if self.encoding.is_utf16() {
+            self.confidence = Confidence::Certain;
+            return false;
+        }
+
+        // 2. / 3.
+        let new_encoding = match new_encoding {
+            Encoding::UTF16LE | Encoding::UTF16BE => Encoding::UTF8,
+            Encoding::Legacy(encoding) if encoding == encoding_rs::X_USER_DEFINED => {
+                Encoding::Legacy(encoding_rs::WINDOWS_1252)
+            }
+            encoding => encoding,
+        };
+
+        // 4.
+        if new_encoding == self.encoding {
+            self.confidence = Confidence::Certain;
+            return false;
+        }
+
+        // 5. - 7. We do not try to change the decoder on the fly, but always restart with the new encoding
+        self.set_encoding(new_encoding);
+        self.confidence = Confidence::Certain;
+        self.reset_stream();
+
+        true
+    }
+
+    /// Returns the undecoded bytes of the stream
+    fn raw_bytes(&self) -> &[u8] {
+        if matches!(self.encoding, Encoding::Legacy(_)) {
+            &self.source
+        } else {
+            &self.buffer
+        }
+    }
+}
+
+/// Returns a new decoder when the encoding is a legacy encoding that needs decoding into UTF8
+fn encoding_decoder(encoding: Encoding) -> Option<encoding_rs::Decoder> {
+    match encoding {
+        Encoding::Legacy(encoding) => Some(encoding.new_decoder_without_bom_handling()),
+        _ => None,
+    }
+}
+
+/// Returns the encoding of the byte order mark the bytes start with (if any)
+fn bom_encoding(bytes: &[u8]) -> Option<Encoding> {
+    if bytes.starts_with(b"\xEF\xBB\xBF") {
+        Some(Encoding::UTF8)
+    } else if bytes.starts_with(b"\xFF\xFE") {
+        Some(Encoding::UTF16LE)
+    } else if bytes.starts_with(b"\xFE\xFF") {
+        Some(Encoding::UTF16BE)
+    } else {
+        None
     }
 }
 
+/// Returns the encoding of the charset parameter of a `Content-Type` header value like `text/html; charset=utf-8`
+pub fn encoding_from_content_type(content_type: &str) -> Option<Encoding> {
+    extract_encoding_from_meta(content_type.as_bytes())
+}
+
+/// Prescans the first 1024 bytes of a byte stream for a `<meta charset>` or `<meta http-equiv="content-type">` element
+/// and returns its encoding.
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#prescan-a-byte-stream-to-determine-its-encoding
+pub fn prescan_encoding(bytes: &[u8]) -> Option<Encoding> {
+    const MAX_PRESCAN: usize = 1024;
+
+    let bytes = &bytes[..bytes.len().min(MAX_PRESCAN)];
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        let rest = &bytes[pos..];
+
+        if rest.starts_with(b"<!--") {
+            // Skip comments, the "-->" may overlap with the "<!--"
+            pos = match find(&bytes[pos + 2..], b"-->") {
+                Some(end) => pos + 2 + end + 3,
+                None => return None,
+            };
+            continue;
+        }
+
+        if rest.len() >= 6 && rest[..5].eq_ignore_ascii_case(b"<meta") && (is_whitespace(rest[5]) || rest[5] == b'/') {
+            pos += 6;
+
+            let mut got_pragma = false;
+            let mut need_pragma = None;
+            let mut charset = None;
+            let mut seen = Vec::new();
+
+            while let Some((name, value)) = get_attribute(bytes, &mut pos) {
+                if seen.contains(&name) {
+                    continue;
+                }
+
+                match name.as_slice() {
+                    b"http-equiv" => got_pragma = value.eq_ignore_ascii_case(b"content-type"),
+                    b"content" if charset.is_none() => {
+                        if let Some(encoding) = extract_encoding_from_meta(&value) {
+                            charset = Some(encoding);
+                            need_pragma = Some(true);
+                        }
+                    }
+                    b"charset" if charset.is_none() => {
+                        charset = encoding_rs::Encoding::for_label(&value).map(Encoding::from);
+                        need_pragma = Some(false);
+                    }
+                    _ => {}
+                }
+
+                seen.push(name);
+            }
+
+            match (need_pragma, charset) {
+                (None, _) | (_, None) => {}
+                (Some(true), Some(_)) if !got_pragma => {}
+                (_, Some(encoding)) => {
+                    return Some(match encoding {
+                        Encoding::UTF16LE | Encoding::UTF16BE => Encoding::UTF8,
+                        Encoding::Legacy(encoding) if encoding == encoding_rs::X_USER_DEFINED => {
+                            Encoding::Legacy(encoding_rs::WINDOWS_1252)
+                        }
+                        encoding => encoding,
+                    })
+                }
+            }
+            continue;
+        }
+
+        let is_tag = rest.len() > 2
+            && rest[0] == b'<'
+            && (rest[1].is_ascii_alphabetic() || (rest[1] == b'/' && rest[2].is_ascii_alphabetic()));
+        if is_tag {
+            // Skip the tag name and its attributes
+            while pos < bytes.len() && !is_whitespace(bytes[pos]) && bytes[pos] != b'>' {
+                pos += 1;
+            }
+            while get_attribute(bytes, &mut pos).is_some() {}
+            continue;
+        }
+
+        if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            pos = match rest.iter().position(|b| *b == b'>') {
+                Some(end) => pos + end + 1,
+                None => return None,
+            };
+            continue;
+        }
+
+        pos += 1;
+    }
+
+    None
+}
+
+/// Reads the next attribute of a tag during the prescan. Returns None when the end of the tag has been reached.
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#concept-get-attributes-when-sniffing
+fn get_attribute(bytes: &[u8], pos: &mut usize) -> Option<(Vec<u8>, Vec<u8>)> {
+    // 1.
+    while *pos < bytes.len() && (is_whitespace(bytes[*pos]) || bytes[*pos] == b'/') {
+        *pos += 1;
+    }
+
+    // 2.
+    if *pos >= bytes.len() || bytes[*pos] == b'>' {
+        *pos += 1;
+        return None;
+    }
+
+    // 3. - 5. Attribute name
+    let mut name = Vec::new();
+    loop {
+        let &b = bytes.get(*pos)?;
+        match b {
+            b'=' if !name.is_empty() => break,
+            b'/' | b'>' => return Some((name, Vec::new())),
+            b if is_whitespace(b) => {
+                // 6. / 7.
+                while *pos < bytes.len() && is_whitespace(bytes[*pos]) {
+                    *pos += 1;
+                }
+                if bytes.get(*pos) != Some(&b'=') {
+                    return Some((name, Vec::new()));
+                }
+                break;
+            }
+            b => name.push(b.to_ascii_lowercase()),
+        }
+        *pos += 1;
+    }
+
+    // 8. / 9. Skip the '=' and any whitespace after it
+    *pos += 1;
+    while *pos < bytes.len() && is_whitespace(bytes[*pos]) {
+        *pos += 1;
+    }
+
+    // 10. Attribute value
+    let mut value = Vec::new();
+    match bytes.get(*pos) {
+        None => return None,
+        Some(&quote @ (b'"' | b'\'')) => {
+            *pos += 1;
+            loop {
+                let &b = bytes.get(*pos)?;
+                *pos += 1;
+                if b == quote {
+                    return Some((name, value));
+                }
+                value.push(b.to_ascii_lowercase());
+            }
+        }
+        Some(b'>') => return Some((name, value)),
+        Some(_) => {}
+    }
+
+    // 11.
+    while let Some(&b) = bytes.get(*pos) {
+        if is_whitespace(b) || b == b'>' {
+            break;
+        }
+        value.push(b.to_ascii_lowercase());
+        *pos += 1;
+    }
+
+    Some((name, value))
+}
+
+/// Extracts the encoding of a `charset=` parameter, like in the `content` attribute of a meta element.
+///
+/// https://html.spec.whatwg.org/multipage/urls-and-fetching.html#algorithm-for-extracting-a-character-encoding-from-a-meta-element
+fn extract_encoding_from_meta(value: &[u8]) -> Option<Encoding> {
+    let mut pos = 0;
+
+    loop {
+        // 2.
+        let start = pos + find_ignore_ascii_case(&value[pos..], b"charset")?;
+        pos = start + b"charset".len();
+
+        // 3.
+        while pos < value.len() && is_whitespace(value[pos]) {
+            pos += 1;
+        }
+
+        // 4.
+        if value.get(pos) == Some(&b'=') {
+            pos += 1;
+            break;
+        }
+    }
+
+    // 5.
+    while pos < value.len() && is_whitespace(value[pos]) {
+        pos += 1;
+    }
+
+    // 6.
+    let label = match value.get(pos)? {
+        &quote @ (b'"' | b'\'') => {
+            let rest = &value[pos + 1..];
+            &rest[..rest.iter().position(|b| *b == quote)?]
+        }
+        _ => {
+            let rest = &value[pos..];
+            let end = rest
+                .iter()
+                .position(|b| is_whitespace(*b) || *b == b';')
+                .unwrap_or(rest.len());
+            &rest[..end]
+        }
+    };
+
+    encoding_rs::Encoding::for_label(label).map(Encoding::from)
+}
+
+/// Returns true when the byte is a whitespace byte as used during the prescan
+fn is_whitespace(b: u8) -> bool {
+    matches!(b, 0x09 | 0x0A | 0x0C | 0x0D | 0x20)
+}
+
+/// Returns the position of the needle in the haystack
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Returns the position of the needle in the haystack, ignoring ASCII case
+fn find_ignore_ascii_case(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|w| w.eq_ignore_ascii_case(needle))
+}
+
 /// Location holds the start position of the given element in the data source
 #[derive(Clone, PartialEq, Copy)]
 pub struct Location {
@@ -565,14 +1045,19 @@ impl Debug for Location {
     }
 }
 
+/// Number of line lengths the location handler remembers. Streams are only moved back a few characters, so only
+/// the last lines are needed, and the handler stays cheap to clone.
+const MAX_COLUMN_STACK: usize = 32;
+
 /// LocationHandler is a wrapper that will deal with line/column locations in the stream
+#[derive(Clone)]
 pub struct LocationHandler {
     /// The start offset of the location. Normally this is 0:0, but can be different in case of inline streams
     pub start_location: Location,
     /// The current location of the stream
     pub cur_location: Location,
-    /// Stack of all column size
-    column_stack: Vec<usize>,
+    /// Stack of the column sizes of the last lines
+    column_stack: VecDeque<usize>,
 }
 
 impl LocationHandler {
@@ -582,7 +1067,7 @@ impl LocationHandler {
         Self {
             start_location,
             cur_location: Location::default(),
-            column_stack: Vec::new(),
+            column_stack: VecDeque::new(),
         }
     }
 
@@ -599,7 +1084,7 @@ impl LocationHandler {
             self.cur_location.offset -= 1;
         } else if self.cur_location.line > 1 {
             self.cur_location.line -= 1;
-            self.cur_location.column = self.column_stack.pop().unwrap_or(1);
+            self.cur_location.column = self.column_stack.pop_back().unwrap_or(1);
             self.cur_location.offset -= 1;
         }
     }
@@ -608,7 +1093,10 @@ impl LocationHandler {
     pub fn inc(&mut self, ch: Character) {
         match ch {
             Ch(CHAR_LF) => {
-                self.column_stack.push(self.cur_location.column);
+                if self.column_stack.len() == MAX_COLUMN_STACK {
+                    self.column_stack.pop_front();
+                }
+                self.column_stack.push_back(self.cur_location.column);
                 self.cur_location.line += 1;
                 self.cur_location.column = 1;
                 self.cur_location.offset += 1;
@@ -791,6 +1279,36 @@ mod test {
         assert!(matches!(stream.read_and_next(), StreamEnd));
     }
 
+    #[test]
+    fn stream_chunk_boundaries() {
+        let mut stream = ByteStream::new(Encoding::UTF8, None);
+
+        // A character that is split over two chunks is read once it is complete
+        stream.append_bytes(&[b'a', 0xC3]);
+        assert_eq!(stream.read_and_next(), Ch('a'));
+        assert!(matches!(stream.read_and_next(), StreamEmpty));
+        assert_eq!(stream.bytes_available(), 1);
+        stream.append_bytes(&[0xA9]);
+        assert_eq!(stream.read_and_next(), Ch('é'));
+
+        // A CR at the end of a chunk waits for a possible LF
+        stream.append_str("b\r");
+        assert_eq!(stream.read_and_next(), Ch('b'));
+        assert!(matches!(stream.read_and_next(), StreamEmpty));
+        assert!(matches!(stream.look_ahead(0), Ch('\r')));
+        stream.append_str("\nc\r");
+        assert_eq!(stream.read_and_next(), Ch('\n'));
+        assert_eq!(stream.read_and_next(), Ch('c'));
+        assert!(matches!(stream.read_and_next(), StreamEmpty));
+
+        // Once the stream is closed, incomplete characters are replaced
+        stream.append_bytes(&[0xE2, 0x82]);
+        stream.close();
+        assert_eq!(stream.read_and_next(), Ch('\r'));
+        assert_eq!(stream.read_and_next(), Ch(REPLACEMENT_CHARACTER));
+        assert!(matches!(stream.read_and_next(), StreamEnd));
+    }
+
     #[test]
     fn advance() {
         let mut stream = ByteStream::new(Encoding::UTF8, None);
@@ -857,6 +1375,116 @@ mod test {
         assert_eq!(stream.read_and_next(), Ch('b'));
     }
 
+    fn read_all(stream: &ByteStream) -> String {
+        let mut s = String::new();
+        while let Ch(c) = stream.read_and_next() {
+            s.push(c);
+        }
+        s
+    }
+
+    #[test]
+    fn test_legacy_encodings() {
+        // "café €" in windows-1252
+        let mut stream = ByteStream::new(Encoding::for_label("windows-1252").unwrap(), None);
+        stream.read_from_bytes(b"caf\xE9 \x80").unwrap();
+        assert_eq!(read_all(&stream), "café €");
+        stream.prev_n(2);
+        assert_eq!(stream.read_and_next(), Ch(' '));
+
+        // "日本" in Shift_JIS, fed in chunks that split a character
+        let mut stream = ByteStream::new(Encoding::for_label("sjis").unwrap(), None);
+        stream.append_bytes(b"\x93\xfa\x96");
+        stream.append_bytes(b"\x7b");
+        stream.close();
+        assert_eq!(read_all(&stream), "日本");
+
+        // Switching back to UTF8 reads the original bytes again
+        stream.set_encoding(Encoding::UTF8);
+        stream.reset_stream();
+        assert_eq!(stream.chars_left(), 4);
+    }
+
+    #[test]
+    fn test_sniff_encoding() {
+        let mut stream = ByteStream::new(Encoding::UTF8, None);
+        stream.read_from_bytes(b"\xEF\xBB\xBFhi").unwrap();
+        assert_eq!(
+            stream.sniff_encoding(Encoding::for_label("windows-1252")),
+            Encoding::UTF8
+        );
+        assert_eq!(stream.confidence(), Confidence::Certain);
+        assert_eq!(read_all(&stream), "hi");
+
+        let mut stream = ByteStream::new(Encoding::UTF8, None);
+        stream.read_from_bytes(b"<p>\x83\x65\x83\x58\x83\x67</p>").unwrap();
+        assert_eq!(
+            stream.sniff_encoding(Encoding::for_label("shift_jis")).name(),
+            encoding_rs::SHIFT_JIS.name()
+        );
+        assert_eq!(stream.confidence(), Confidence::Certain);
+        assert_eq!(read_all(&stream), "<p>テスト</p>");
+
+        let mut stream = ByteStream::new(Encoding::UTF8, None);
+        stream
+            .read_from_bytes(b"<!-- <meta charset=utf-8> --><meta charset=\"latin1\"><p>\xE9</p>")
+            .unwrap();
+        assert_eq!(stream.sniff_encoding(None), Encoding::Legacy(encoding_rs::WINDOWS_1252));
+        assert_eq!(stream.confidence(), Confidence::Tentative);
+        assert!(read_all(&stream).ends_with("<p>é</p>"));
+    }
+
+    #[test]
+    fn test_change_encoding() {
+        let mut stream = ByteStream::new(Encoding::UTF8, None);
+        stream.read_from_bytes(b"<p>\xE9</p>").unwrap();
+        stream.set_confidence(Confidence::Tentative);
+
+        assert!(!stream.change_encoding(Encoding::UTF16LE));
+        assert_eq!(stream.confidence(), Confidence::Certain);
+
+        stream.set_confidence(Confidence::Tentative);
+        stream.next_n(3);
+        assert!(stream.change_encoding(Encoding::for_label("x-user-defined").unwrap()));
+        assert_eq!(stream.encoding(), Encoding::Legacy(encoding_rs::WINDOWS_1252));
+        assert_eq!(read_all(&stream), "<p>é</p>");
+
+        // Only tentative encodings can change
+        assert!(!stream.change_encoding(Encoding::UTF8));
+    }
+
+    #[test]
+    fn test_prescan_encoding() {
+        assert_eq!(prescan_encoding(b"<meta charset=utf-8>"), Some(Encoding::UTF8));
+        assert_eq!(prescan_encoding(b"<META CHARSET='UTF-16'>"), Some(Encoding::UTF8));
+        assert_eq!(
+            prescan_encoding(b"<html><meta http-equiv=\"Content-Type\" content=\"text/html; charset=Shift_JIS\">"),
+            Some(Encoding::Legacy(encoding_rs::SHIFT_JIS))
+        );
+        // content without http-equiv is ignored
+        assert_eq!(prescan_encoding(b"<meta content=\"text/html; charset=koi8-r\">"), None);
+        // attributes of other tags are skipped
+        assert_eq!(prescan_encoding(b"<div title=\"<meta charset=utf-8>\"></div>"), None);
+        assert_eq!(prescan_encoding(b"<meta charset=bogus>"), None);
+
+        let mut late = vec![b' '; 1024];
+        late.extend_from_slice(b"<meta charset=utf-8>");
+        assert_eq!(prescan_encoding(&late), None);
+    }
+
+    #[test]
+    fn test_encoding_from_content_type() {
+        assert_eq!(
+            encoding_from_content_type("text/html; charset=\"windows-1252\""),
+            Some(Encoding::Legacy(encoding_rs::WINDOWS_1252))
+        );
+        assert_eq!(
+            encoding_from_content_type("text/html;charset=UTF-8"),
+            Some(Encoding::UTF8)
+        );
+        assert_eq!(encoding_from_content_type("text/html"), None);
+    }
+
     #[test]
     fn test_character() {
         let ch = Ch('a');
@@ -997,7 +1625,7 @@ mod test {
     fn test_dec() {
         let mut handler = LocationHandler::new(Location::default());
         handler.cur_location = Location::new(2, 2, 4);
-        handler.column_stack = vec![3];
+        handler.column_stack = VecDeque::from([3]);
         handler.dec();
         assert_eq!(handler.cur_location, Location::new(2, 1, 3));
         handler.dec();
@@ -1,5 +1,6 @@
 use std::ops::{Deref, DerefMut};
 use std::vec::IntoIter;
+use taffy::util::ResolveOrZero;
 use taffy::{
     compute_block_layout, compute_cached_layout, compute_flexbox_layout, compute_grid_layout, compute_hidden_layout,
     compute_root_layout, AvailableSpace, Cache as TaffyCache, CacheTree, Display as TaffyDisplay,
@@ -14,7 +15,7 @@ use gosub_shared::geo::{Point, Rect, Size, SizeU32};
 use gosub_shared::types::Result;
 
 use crate::compute::inline::compute_inline_layout;
-use crate::style::get_style_from_node;
+use crate::style::{get_style_from_node, has_calc, resolve_calc_style};
 use crate::text::TextLayout;
 
 mod compute;
@@ -110,6 +111,8 @@ pub struct Cache {
     taffy: TaffyCache,
     style: Style,
     display: Display,
+    /// Any of the layout properties holds a math function that depends on the containing block
+    calc: bool,
 }
 
 impl Deref for Cache {
@@ -158,6 +161,15 @@ impl<B: HasLayouter<Layouter = TaffyLayouter> + HasFontManager> Layouter<B> for
         // for layouting into Taffy properties that are stored in a cache.
         Self::precompute_style(&mut tree, root);
 
+        // The containing block of the root is the viewport
+        tree.resolve_calc(
+            root,
+            taffy::Size {
+                width: Some(space.width as f32),
+                height: Some(space.height as f32),
+            },
+        );
+
         // Now let taffy compute the layout of the tree.
         compute_root_layout(&mut tree, TaffyId::from(root.into()), size);
 
@@ -239,10 +251,68 @@ impl<C: HasLayouter<Layouter = TaffyLayouter>> LayoutDocument<'_, C> {
         };
 
         let (style, display) = get_style_from_node(node);
+        let calc = has_calc(node);
 
         if let Some(cache) = self.0.get_cache_mut(node_id) {
             cache.style = style;
             cache.display = display;
+            cache.calc = calc;
+        }
+    }
+
+    /// Resolves the math functions in the style of the node against the size of its containing block
+    fn resolve_calc(
+        &mut self,
+        node_id: <C::LayoutTree as LayoutTree<C>>::NodeId,
+        containing_block: taffy::Size<Option<f32>>,
+    ) {
+        let Some(cache) = self.0.get_cache_mut(node_id) else {
+            return;
+        };
+
+        if !cache.calc {
+            return;
+        }
+
+        let mut style = std::mem::take(&mut cache.style);
+
+        if let Some(node) = self.0.get_node_mut(node_id) {
+            resolve_calc_style(node, &mut style, containing_block);
+        }
+
+        if let Some(cache) = self.0.get_cache_mut(node_id) {
+            cache.style = style;
+        }
+    }
+
+    /// Resolves the math functions in the styles of the children of the node. Their containing block is the content
+    /// box of the node, which is estimated from the layout inputs as the children are laid out by the node.
+    fn resolve_children_calc(&mut self, node_id: <C::LayoutTree as LayoutTree<C>>::NodeId, inputs: &LayoutInput) {
+        let Some(children) = self.0.children(node_id) else {
+            return;
+        };
+
+        let style = self.get_taffy_style_no_update(node_id);
+        let insets = style.padding.resolve_or_zero(inputs.parent_size.width)
+            + style.border.resolve_or_zero(inputs.parent_size.width);
+
+        let content_box = taffy::Size {
+            width: inputs
+                .known_dimensions
+                .width
+                .or(inputs.available_space.width.into_option())
+                .map(|width| width - insets.left - insets.right),
+            height: inputs
+                .known_dimensions
+                .height
+                .map(|height| height - insets.top - insets.bottom),
+        };
+
+        for child in children {
+            self.resolve_calc(
+                <C::LayoutTree as LayoutTree<C>>::NodeId::from(child.into()),
+                content_box,
+            );
         }
     }
 
@@ -354,6 +424,13 @@ impl<C: HasLayouter<Layouter = TaffyLayouter>> LayoutPartialTree for LayoutDocum
                 }
             }
 
+            if tree.0.style_dirty(node_id) {
+                tree.update_style(node_id);
+                tree.resolve_calc(node_id, inputs.parent_size);
+            }
+
+            tree.resolve_children_calc(node_id, &inputs);
+
             // let has_children = tree.0.child_count(node_id) > 0; //TODO: this isn't optimal, since we are now requesting the same node twice (up in get_cache and here)
             let style = tree.get_taffy_style(node_id);
 
@@ -1,7 +1,8 @@
-use taffy::Style;
+use taffy::{Dimension, LengthPercentage, LengthPercentageAuto, Style};
 
 use crate::Display;
 use gosub_interface::config::HasLayouter;
+use gosub_interface::css3::CssProperty;
 use gosub_interface::layout::LayoutNode;
 
 mod parse;
@@ -9,6 +10,43 @@ mod parse_properties;
 
 const SCROLLBAR_WIDTH: f32 = 16.0;
 
+/// Axis of the containing block that percentages of a property are resolved against
+#[derive(Clone, Copy)]
+enum Axis {
+    Width,
+    Height,
+}
+
+/// Properties that can hold a math function that depends on the size of the containing block. Note that
+/// percentages of margins and paddings always resolve against the width of the containing block.
+const CALC_PROPERTIES: [(&str, Axis); 25] = [
+    ("width", Axis::Width),
+    ("height", Axis::Height),
+    ("min-width", Axis::Width),
+    ("min-height", Axis::Height),
+    ("max-width", Axis::Width),
+    ("max-height", Axis::Height),
+    ("top", Axis::Height),
+    ("right", Axis::Width),
+    ("bottom", Axis::Height),
+    ("left", Axis::Width),
+    ("margin-top", Axis::Width),
+    ("margin-right", Axis::Width),
+    ("margin-bottom", Axis::Width),
+    ("margin-left", Axis::Width),
+    ("padding-top", Axis::Width),
+    ("padding-right", Axis::Width),
+    ("padding-bottom", Axis::Width),
+    ("padding-left", Axis::Width),
+    ("border-top-width", Axis::Width),
+    ("border-right-width", Axis::Width),
+    ("border-bottom-width", Axis::Width),
+    ("border-left-width", Axis::Width),
+    ("column-gap", Axis::Width),
+    ("row-gap", Axis::Height),
+    ("flex-basis", Axis::Width),
+];
+
 // This function will convert a node into a Style object with Taffy properties.
 pub fn get_style_from_node<C: HasLayouter>(node: &mut impl LayoutNode<C>) -> (Style, Display) {
     //TODO: theoretically we should limit this to the taffy layouter, since it doesn't make any sense otherwise
@@ -86,3 +124,79 @@ pub fn get_style_from_node<C: HasLayouter>(node: &mut impl LayoutNode<C>) -> (St
         disp,
     )
 }
+
+/// Returns true when any of the layout properties of the node holds a math function
+pub fn has_calc<C: HasLayouter>(node: &mut impl LayoutNode<C>) -> bool {
+    CALC_PROPERTIES.iter().any(|(name, _)| {
+        node.get_property(name)
+            .is_some_and(|prop| matches!(prop.as_function(), Some(("calc", _))))
+    })
+}
+
+/// Resolves the math functions in the layout properties of the node, like `width: calc(100% - 20px)`, against the
+/// size of its containing block. Taffy can only represent plain lengths and percentages, so this is done right
+/// before the node is laid out. Expressions with percentages of an unknown size stay unresolved, like when parsed.
+pub fn resolve_calc_style<C: HasLayouter>(
+    node: &mut impl LayoutNode<C>,
+    style: &mut Style,
+    containing_block: taffy::Size<Option<f32>>,
+) {
+    let font_size = parse::font_size(node);
+
+    for (name, axis) in CALC_PROPERTIES {
+        let basis = match axis {
+            Axis::Width => containing_block.width,
+            Axis::Height => containing_block.height,
+        };
+
+        let Some(prop) = node.get_property(name) else {
+            continue;
+        };
+
+        if !matches!(prop.as_function(), Some(("calc", _))) {
+            continue;
+        }
+
+        let (dimension, len_auto, len_percent) = match prop.resolve_calc(basis, font_size) {
+            Some(len) => (
+                Dimension::Length(len),
+                LengthPercentageAuto::Length(len),
+                LengthPercentage::Length(len),
+            ),
+            None => (
+                Dimension::Auto,
+                LengthPercentageAuto::Auto,
+                parse::parse_len(node, name),
+            ),
+        };
+
+        match name {
+            "width" => style.size.width = dimension,
+            "height" => style.size.height = dimension,
+            "min-width" => style.min_size.width = dimension,
+            "min-height" => style.min_size.height = dimension,
+            "max-width" => style.max_size.width = dimension,
+            "max-height" => style.max_size.height = dimension,
+            "top" => style.inset.top = len_auto,
+            "right" => style.inset.right = len_auto,
+            "bottom" => style.inset.bottom = len_auto,
+            "left" => style.inset.left = len_auto,
+            "margin-top" => style.margin.top = len_auto,
+            "margin-right" => style.margin.right = len_auto,
+            "margin-bottom" => style.margin.bottom = len_auto,
+            "margin-left" => style.margin.left = len_auto,
+            "padding-top" => style.padding.top = len_percent,
+            "padding-right" => style.padding.right = len_percent,
+            "padding-bottom" => style.padding.bottom = len_percent,
+            "padding-left" => style.padding.left = len_percent,
+            "border-top-width" => style.border.top = len_percent,
+            "border-right-width" => style.border.right = len_percent,
+            "border-bottom-width" => style.border.bottom = len_percent,
+            "border-left-width" => style.border.left = len_percent,
+            "column-gap" => style.gap.width = len_percent,
+            "row-gap" => style.gap.height = len_percent,
+            "flex-basis" => style.flex_basis = dimension,
+            _ => {}
+        }
+    }
+}
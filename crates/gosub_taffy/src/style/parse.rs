@@ -11,12 +11,34 @@ use gosub_interface::layout::LayoutNode;
 // Parse functions that will parse a CSS property and converts it into a Taffy type so it can be used
 // in the taffy layout engine. This step is needed since our CSS properties are not directly compatible
 // with the Taffy layout engine.
+//
+// Math functions like `calc(100% - 20px)` can't be represented in Taffy. When they hold percentages, the size of the
+// containing block is not known yet, so these are left unresolved here (as `auto`, or as the percentage part of the
+// expression), and resolved by `resolve_calc_style` once the size of the containing block is known.
+
+/// Returns the font size of the node in pixels, that font-relative units of its properties are resolved against
+pub fn font_size<C: HasLayouter>(node: &mut impl LayoutNode<C>) -> f32 {
+    node.get_property("font-size").map(|s| s.unit_to_px()).unwrap_or(16.0)
+}
 
 pub fn parse_len<C: HasLayouter>(node: &mut impl LayoutNode<C>, name: &str) -> LengthPercentage {
+    let font_size = font_size(node);
     let Some(property) = node.get_property(name) else {
         return LengthPercentage::Length(0.0);
     };
 
+    if let Some(len) = property.resolve_calc(None, font_size) {
+        return LengthPercentage::Length(len);
+    }
+
+    // There is no `auto` for these properties, so the expression is kept as its percentage until it is resolved
+    if let (Some(zero), Some(hundred)) = (
+        property.resolve_calc(Some(0.0), font_size),
+        property.resolve_calc(Some(100.0), font_size),
+    ) {
+        return LengthPercentage::Percent((hundred - zero) / 100.0);
+    }
+
     if let Some(percent) = property.as_percentage() {
         return LengthPercentage::Percent(percent / 100.0);
     }
@@ -25,6 +47,7 @@ pub fn parse_len<C: HasLayouter>(node: &mut impl LayoutNode<C>, name: &str) -> L
 }
 
 pub fn parse_len_auto<C: HasLayouter>(node: &mut impl LayoutNode<C>, name: &str) -> LengthPercentageAuto {
+    let font_size = font_size(node);
     let Some(property) = node.get_property(name) else {
         return LengthPercentageAuto::Length(0.0);
     };
@@ -35,6 +58,14 @@ pub fn parse_len_auto<C: HasLayouter>(node: &mut impl LayoutNode<C>, name: &str)
         }
     }
 
+    if let Some(len) = property.resolve_calc(None, font_size) {
+        return LengthPercentageAuto::Length(len);
+    }
+
+    if matches!(property.as_function(), Some(("calc", _))) {
+        return LengthPercentageAuto::Auto;
+    }
+
     if let Some(percent) = property.as_percentage() {
         return LengthPercentageAuto::Percent(percent / 100.0);
     }
@@ -43,6 +74,7 @@ pub fn parse_len_auto<C: HasLayouter>(node: &mut impl LayoutNode<C>, name: &str)
 }
 
 pub fn parse_dimension<C: HasLayouter>(node: &mut impl LayoutNode<C>, name: &str) -> Dimension {
+    let font_size = font_size(node);
     let Some(property) = node.get_property(name) else {
         return Dimension::Auto;
     };
@@ -53,6 +85,14 @@ pub fn parse_dimension<C: HasLayouter>(node: &mut impl LayoutNode<C>, name: &str
         }
     }
 
+    if let Some(len) = property.resolve_calc(None, font_size) {
+        return Dimension::Length(len);
+    }
+
+    if matches!(property.as_function(), Some(("calc", _))) {
+        return Dimension::Auto;
+    }
+
     if let Some(percent) = property.as_percentage() {
         return Dimension::Percent(percent / 100.0);
     }
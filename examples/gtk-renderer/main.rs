@@ -8,6 +8,7 @@ use gosub_fontmanager::FontManager;
 use gosub_html5::document::builder::DocumentBuilderImpl;
 use gosub_html5::document::document_impl::DocumentImpl;
 use gosub_html5::document::fragment::DocumentFragmentImpl;
+use gosub_html5::parser::scripts::JsScriptExecutor;
 use gosub_html5::parser::Html5Parser;
 use gosub_instance::{EngineInstance, InstanceMessage};
 use gosub_interface::chrome::ChromeHandle;
@@ -20,6 +21,7 @@ use gosub_interface::request::RequestServerHandle;
 use gosub_renderer::draw::TreeDrawerImpl;
 use gosub_rendering::render_tree::RenderTree;
 use gosub_taffy::TaffyLayouter;
+use gosub_v8::V8Engine;
 use gtk4::gio::{ApplicationCommandLine, ApplicationFlags};
 use gtk4::prelude::*;
 use gtk4::{glib, Application, ApplicationWindow, DrawingArea};
@@ -71,6 +73,10 @@ impl HasFontManager for Config {
     type FontManager = FontManager;
 }
 
+impl HasScriptExecutor for Config {
+    type ScriptExecutor = JsScriptExecutor<V8Engine>;
+}
+
 impl ModuleConfiguration for Config {}
 
 #[derive(Clone)]
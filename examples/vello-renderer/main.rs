@@ -8,15 +8,17 @@ use gosub_css3::system::Css3System;
 use gosub_html5::document::builder::DocumentBuilderImpl;
 use gosub_html5::document::document_impl::DocumentImpl;
 use gosub_html5::document::fragment::DocumentFragmentImpl;
+use gosub_html5::parser::scripts::JsScriptExecutor;
 use gosub_html5::parser::Html5Parser;
 use gosub_interface::config::{
-    HasChrome, HasCssSystem, HasDocument, HasHtmlParser, HasLayouter, HasRenderBackend, HasRenderTree, HasTreeDrawer,
-    ModuleConfiguration,
+    HasChrome, HasCssSystem, HasDocument, HasHtmlParser, HasLayouter, HasRenderBackend, HasRenderTree,
+    HasScriptExecutor, HasTreeDrawer, ModuleConfiguration,
 };
 use gosub_renderer::draw::TreeDrawerImpl;
 use gosub_rendering::render_tree::RenderTree;
 use gosub_shared::types::Result;
 use gosub_taffy::TaffyLayouter;
+use gosub_v8::V8Engine;
 use gosub_vello::VelloBackend;
 use log::LevelFilter;
 use simple_logger::SimpleLogger;
@@ -76,6 +78,10 @@ impl HasFontManager for Config {
     type FontManager = gosub_fontmanager::FontManager;
 }
 
+impl HasScriptExecutor for Config {
+    type ScriptExecutor = JsScriptExecutor<V8Engine>;
+}
+
 impl ModuleConfiguration for Config {}
 
 pub struct WinitEventLoopHandle<C: HasRenderBackend + 'static> {
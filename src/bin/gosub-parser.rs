@@ -6,7 +6,7 @@ use gosub_html5::document::fragment::DocumentFragmentImpl;
 use gosub_html5::parser::Html5Parser;
 use gosub_interface::config::{HasCssSystem, HasDocument, HasHtmlParser};
 use gosub_interface::document::DocumentBuilder;
-use gosub_shared::byte_stream::{ByteStream, Encoding};
+use gosub_shared::byte_stream::{encoding_from_content_type, ByteStream, Encoding};
 use gosub_shared::timing::Scale;
 use gosub_shared::timing_display;
 use gosub_shared::types::Result;
@@ -56,23 +56,28 @@ fn main() -> Result<()> {
 
     println!("Parsing url: {:?}", url);
 
-    let html = if url.scheme() == "http" || url.scheme() == "https" {
+    let (html, encoding) = if url.scheme() == "http" || url.scheme() == "https" {
         // Fetch the html from the url
         let mut response = ureq::get(url.as_ref()).call()?;
         if response.status() != 200 {
             bail!("Could not get url. Status code {}", response.status());
         }
-        response.body_mut().read_to_string()?
+        let encoding = response
+            .headers()
+            .get("content-type")
+            .and_then(|value| value.to_str().ok())
+            .and_then(encoding_from_content_type);
+        (response.body_mut().read_to_vec()?, encoding)
     } else if url.scheme() == "file" {
         // Get html from the file
-        fs::read_to_string(url.to_string().trim_start_matches("file://"))?
+        (fs::read(url.to_string().trim_start_matches("file://"))?, None)
     } else {
         bail("Invalid url scheme");
     };
 
     let mut stream = ByteStream::new(Encoding::UTF8, None);
-    stream.read_from_str(&html, Some(Encoding::UTF8));
-    stream.close();
+    stream.read_from_bytes(&html)?;
+    stream.sniff_encoding(encoding);
 
     // SimpleLogger::new().init().unwrap();
 
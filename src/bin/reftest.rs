@@ -0,0 +1,191 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::bail;
+use gosub_css3::system::Css3System;
+use gosub_headless::reftest::{discover, run_reftest, RefTestOptions, Tolerance};
+use gosub_headless::HeadlessBackend;
+use gosub_html5::document::builder::DocumentBuilderImpl;
+use gosub_html5::document::document_impl::DocumentImpl;
+use gosub_html5::document::fragment::DocumentFragmentImpl;
+use gosub_html5::parser::scripts::JsScriptExecutor;
+use gosub_html5::parser::Html5Parser;
+use gosub_interface::config::{
+    HasCssSystem, HasDocument, HasHtmlParser, HasLayouter, HasRenderBackend, HasRenderTree, HasScriptExecutor,
+    HasTreeDrawer,
+};
+use gosub_interface::font::HasFontManager;
+use gosub_renderer::draw::TreeDrawerImpl;
+use gosub_rendering::render_tree::RenderTree;
+use gosub_shared::geo::SizeU32;
+use gosub_shared::types::Result;
+use gosub_taffy::TaffyLayouter;
+use gosub_v8::V8Engine;
+use log::LevelFilter;
+use simple_logger::SimpleLogger;
+
+#[derive(Clone, Debug, PartialEq)]
+struct Config;
+
+impl HasCssSystem for Config {
+    type CssSystem = Css3System;
+}
+impl HasDocument for Config {
+    type Document = DocumentImpl<Self>;
+    type DocumentFragment = DocumentFragmentImpl<Self>;
+    type DocumentBuilder = DocumentBuilderImpl;
+}
+
+impl HasHtmlParser for Config {
+    type HtmlParser = Html5Parser<'static, Self>;
+}
+
+impl HasLayouter for Config {
+    type Layouter = TaffyLayouter;
+    type LayoutTree = RenderTree<Self>;
+}
+
+impl HasRenderTree for Config {
+    type RenderTree = RenderTree<Self>;
+}
+
+impl HasTreeDrawer for Config {
+    type TreeDrawer = TreeDrawerImpl<Self>;
+}
+
+impl HasRenderBackend for Config {
+    type RenderBackend = HeadlessBackend;
+}
+
+impl HasFontManager for Config {
+    type FontManager = gosub_fontmanager::FontManager;
+}
+
+impl HasScriptExecutor for Config {
+    type ScriptExecutor = JsScriptExecutor<V8Engine>;
+}
+
+fn main() -> Result<()> {
+    let matches = clap::Command::new("Gosub reftest runner")
+        .version("0.1.0")
+        .arg(
+            clap::Arg::new("path")
+                .help("Test file or directory with tests. Every name.html needs a name-ref.html or name-notref.html")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            clap::Arg::new("width")
+                .help("Width of the viewport")
+                .long("width")
+                .default_value("800")
+                .value_parser(clap::value_parser!(u32)),
+        )
+        .arg(
+            clap::Arg::new("height")
+                .help("Height of the viewport")
+                .long("height")
+                .default_value("600")
+                .value_parser(clap::value_parser!(u32)),
+        )
+        .arg(
+            clap::Arg::new("tolerance")
+                .help("Maximum difference of a color channel before a pixel is counted as different")
+                .short('t')
+                .long("tolerance")
+                .default_value("2")
+                .value_parser(clap::value_parser!(u8)),
+        )
+        .arg(
+            clap::Arg::new("max-pixels")
+                .help("Maximum number of different pixels before a test fails")
+                .long("max-pixels")
+                .default_value("0")
+                .value_parser(clap::value_parser!(u64)),
+        )
+        .arg(
+            clap::Arg::new("image-timeout")
+                .help("Milliseconds to wait for images referenced by the test files")
+                .long("image-timeout")
+                .default_value("500")
+                .value_parser(clap::value_parser!(u64)),
+        )
+        .arg(
+            clap::Arg::new("output")
+                .help("Directory where the rendered and diff images of failing tests are written")
+                .short('o')
+                .long("output"),
+        )
+        .arg(
+            clap::Arg::new("debug")
+                .help("Enable debug logging")
+                .short('d')
+                .long("debug")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .get_matches();
+
+    let level = if matches.get_flag("debug") {
+        LevelFilter::Debug
+    } else {
+        LevelFilter::Warn
+    };
+    SimpleLogger::new().with_level(level).init()?;
+
+    let path = PathBuf::from(matches.get_one::<String>("path").expect("path"));
+
+    let options = RefTestOptions {
+        size: SizeU32::new(
+            *matches.get_one::<u32>("width").expect("width"),
+            *matches.get_one::<u32>("height").expect("height"),
+        ),
+        tolerance: Tolerance {
+            max_channel_diff: *matches.get_one::<u8>("tolerance").expect("tolerance"),
+            max_differing_pixels: *matches.get_one::<u64>("max-pixels").expect("max-pixels"),
+        },
+        image_timeout: Duration::from_millis(*matches.get_one::<u64>("image-timeout").expect("image-timeout")),
+        output: matches.get_one::<String>("output").map(PathBuf::from),
+    };
+
+    let tests = discover(&path)?;
+    if tests.is_empty() {
+        bail!("No reftests found in {}", path.display());
+    }
+
+    let mut failed = 0;
+
+    for test in &tests {
+        match run_reftest::<Config>(test, TaffyLayouter, &options) {
+            Ok(result) if result.passed => {
+                println!("PASS {test}");
+            }
+            Ok(result) => {
+                failed += 1;
+                println!(
+                    "FAIL {test} ({} pixels differ, max channel difference {})",
+                    result.diff.differing_pixels, result.diff.max_channel_diff
+                );
+                if let Some(diff_path) = result.diff_path {
+                    println!("     diff image: {}", diff_path.display());
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                println!("ERROR {test}: {e}");
+            }
+        }
+    }
+
+    println!(
+        "All tests completed. {}/{} ({:.2}%) passed.",
+        tests.len() - failed,
+        tests.len(),
+        (tests.len() - failed) as f32 / tests.len() as f32 * 100_f32
+    );
+
+    if failed > 0 {
+        bail!("{failed} reftest(s) failed");
+    }
+
+    Ok(())
+}
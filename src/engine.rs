@@ -7,6 +7,7 @@ use {
     gosub_interface::html5::Html5Parser as Html5ParserT,
     gosub_net::dns::{Dns, ResolveType},
     gosub_net::errors::Error,
+    gosub_net::http::fetcher::Fetcher,
     gosub_net::http::headers::Headers,
     gosub_net::http::request::Request,
     gosub_net::http::response::Response,
@@ -16,12 +17,6 @@ use {
     url::Url,
 };
 
-#[allow(dead_code)]
-const USER_AGENT: &str = "Mozilla/5.0 (compatible; gosub/0.1; +https://gosub.io)";
-
-#[allow(dead_code)]
-const MAX_BYTES: u64 = 10_000_000;
-
 /// Response that is returned from the fetch function
 #[cfg(not(target_arch = "wasm32"))]
 pub struct FetchResponse<C: HasDocument> {
@@ -98,34 +93,14 @@ fn fetch_url<C: HasHtmlParser>(
     // Fetch the HTML document from the site
     let t_id = timing_start!("http.transfer", parts.host_str().unwrap());
 
-    let agent = ureq::agent();
-    let mut req = agent.get(url);
-    req = req.header("User-Agent", USER_AGENT);
-    for (key, value) in headers.sorted() {
-        req = req.header(key, value);
-    }
-
-    match req.call() {
-        Ok(mut resp) => {
-            fetch_response.response = Response::new();
-            fetch_response.response.status = resp.status().as_u16();
-            fetch_response.response.version = format!("{:?}", resp.version());
-            for (key, val) in resp.headers().iter() {
-                fetch_response
-                    .response
-                    .headers
-                    .set(key.as_str(), val.to_str().unwrap_or(""));
-            }
-            // TODO: cookies
-            // for cookie in resp.cookies() {
-            //     fetch_response.response.cookies.insert(cookie.name().to_string(), cookie.value().to_string());
-            // }
-            fetch_response.response.body = resp.body_mut().read_to_vec()?;
-        }
+    // The fetcher sends the request with the configured policy, and uses the shared HTTP cache and cookie store
+    let fetcher = Fetcher::new(parts.clone());
+    fetch_response.response = match futures::executor::block_on(fetcher.get_req(&fetch_response.request)) {
+        Ok(response) => response,
         Err(e) => {
             return Err(Error::Generic(format!("Failed to fetch URL: {}", e)).into());
         }
-    }
+    };
     timing_stop!(t_id);
 
     println!("resp: {:?}", fetch_response.response);
@@ -134,6 +109,7 @@ fn fetch_url<C: HasHtmlParser>(
 
     let mut stream = ByteStream::new(Encoding::UTF8, None);
     let _ = stream.read_from_bytes(&fetch_response.response.body);
+    stream.sniff_encoding(fetch_response.response.encoding());
     fetch_response.document = C::DocumentBuilder::new_document(Some(parts));
 
     match C::HtmlParser::parse(&mut stream, &mut fetch_response.document, None) {
@@ -160,6 +136,7 @@ mod tests {
     use gosub_html5::document::fragment::DocumentFragmentImpl;
     use gosub_html5::parser::Html5Parser;
     use gosub_interface::config::HasCssSystem;
+    use gosub_net::http::policy::FetchPolicy;
 
     #[derive(Clone, Debug, PartialEq)]
     struct Config;
@@ -181,7 +158,7 @@ mod tests {
     fn test_fetch_url() {
         let url = "https://gosub.io/";
         let mut headers = Headers::new();
-        headers.set("User-Agent", USER_AGENT);
+        headers.set("User-Agent", &FetchPolicy::default().user_agent);
         let cookies = CookieJar::new();
 
         let resp = fetch_url::<Config>("GET", url, headers, cookies);